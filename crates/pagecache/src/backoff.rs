@@ -0,0 +1,121 @@
+use std::{
+    cell::Cell,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::*;
+
+/// Configures how internal compare-and-swap loops
+/// back off after losing a race to a concurrent
+/// writer. The first `spin_limit` retries spin
+/// in place, after which the retrying thread sleeps
+/// for an exponentially increasing, jittered
+/// duration that is capped at `max_delay_us`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct BackoffConfig {
+    /// The number of retries that spin before sleeping.
+    pub spin_limit: u32,
+    /// The first sleep duration in microseconds,
+    /// doubled on each subsequent retry.
+    pub base_delay_us: u64,
+    /// The maximum sleep duration in microseconds.
+    pub max_delay_us: u64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> BackoffConfig {
+        BackoffConfig {
+            spin_limit: 6,
+            base_delay_us: 1,
+            max_delay_us: 1024,
+        }
+    }
+}
+
+/// Tracks the retries of a single contended CAS loop,
+/// backing off according to a `BackoffConfig`.
+#[derive(Debug)]
+pub struct CasBackoff {
+    config: BackoffConfig,
+    step: u32,
+}
+
+impl CasBackoff {
+    /// Create a new `CasBackoff` for a fresh CAS loop.
+    pub fn new(config: BackoffConfig) -> CasBackoff {
+        CasBackoff { config, step: 0 }
+    }
+
+    /// Back off before the next retry.
+    pub fn snooze(&mut self) {
+        if self.step < self.config.spin_limit {
+            for _ in 0..(1 << self.step.min(6)) {
+                std::hint::spin_loop();
+            }
+        } else if self.config.max_delay_us > 0 {
+            let exponent = (self.step - self.config.spin_limit).min(20);
+            let delay = self
+                .config
+                .base_delay_us
+                .saturating_mul(1 << exponent)
+                .min(self.config.max_delay_us)
+                .max(1);
+
+            // full jitter over the upper half of the delay
+            // prevents retrying threads from waking in lockstep.
+            let jittered = delay / 2 + jitter() % (delay / 2 + 1);
+
+            thread::sleep(Duration::from_micros(jittered));
+        } else {
+            thread::yield_now();
+        }
+
+        self.step = self.step.saturating_add(1);
+    }
+}
+
+// A cheap thread-local xorshift generator, which is
+// all we need for spreading out retries.
+fn jitter() -> u64 {
+    thread_local! {
+        static STATE: Cell<u64> = const { Cell::new(0) };
+    }
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        if x == 0 {
+            lazy_static! {
+                static ref START: Instant = Instant::now();
+            }
+            let seed = START.elapsed().subsec_nanos() as u64
+                ^ (state as *const Cell<u64> as u64);
+            x = seed | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    })
+}
+
+#[test]
+fn backoff_sleeps_are_capped() {
+    let config = BackoffConfig {
+        spin_limit: 1,
+        base_delay_us: 10,
+        max_delay_us: 40,
+    };
+    let mut backoff = CasBackoff::new(config);
+
+    let before = Instant::now();
+    for _ in 0..8 {
+        backoff.snooze();
+    }
+
+    // 7 sleeps of at most 40us each, with plenty of
+    // room for scheduler noise.
+    assert!(before.elapsed() < Duration::from_millis(500));
+    assert_eq!(backoff.step, 8);
+}
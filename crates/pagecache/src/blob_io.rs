@@ -1,35 +1,135 @@
-use std::io::{Read, Write};
+use std::{collections::BTreeMap, fs::File, io::Write, path::Path, sync::Arc};
+
+use parking_lot::Mutex;
 
 use super::*;
 
+/// Blob files that were opened for reading, kept open so that
+/// later reads of the same blob can skip reopening it. Once
+/// more than `max_blob_fds` are open, the least recently read
+/// one is closed.
+#[derive(Debug, Default)]
+pub(crate) struct BlobFds {
+    files: Mutex<OpenBlobs>,
+}
+
+#[derive(Debug, Default)]
+struct OpenBlobs {
+    // each open blob, with the tick of its last read
+    by_id: FastMap8<Lsn, (Arc<File>, u64)>,
+    // the open blobs by the tick of their last read,
+    // least recently read first
+    by_recency: BTreeMap<u64, Lsn>,
+    tick: u64,
+}
+
+impl OpenBlobs {
+    fn touch(&mut self, id: Lsn) -> Option<Arc<File>> {
+        let tick = self.tick;
+        let (f, last_read) = self.by_id.get_mut(&id)?;
+        self.by_recency.remove(last_read);
+        *last_read = tick;
+        let f = f.clone();
+        self.by_recency.insert(tick, id);
+        self.tick += 1;
+        Some(f)
+    }
+
+    fn insert(&mut self, id: Lsn, f: Arc<File>, max: usize) {
+        let tick = self.tick;
+        self.tick += 1;
+        if let Some((_, last_read)) = self.by_id.insert(id, (f, tick)) {
+            self.by_recency.remove(&last_read);
+        }
+        self.by_recency.insert(tick, id);
+
+        // a handle that is still being read from by
+        // another thread is closed when it is done
+        while self.by_id.len() > max {
+            let (&oldest, &id) = self.by_recency.iter().next().unwrap();
+            self.by_recency.remove(&oldest);
+            self.by_id.remove(&id);
+        }
+    }
+
+    fn remove(&mut self, id: Lsn) {
+        if let Some((_, last_read)) = self.by_id.remove(&id) {
+            self.by_recency.remove(&last_read);
+        }
+    }
+}
+
+impl BlobFds {
+    fn open(&self, id: Lsn, config: &Config) -> Result<Arc<File>> {
+        if let Some(f) = self.files.lock().touch(id) {
+            return Ok(f);
+        }
+
+        let path = config.blob_path(id);
+        let f = Arc::new(std::fs::OpenOptions::new().read(true).open(&path)?);
+
+        if config.max_blob_fds > 0 {
+            self.cache(id, &f, &path, config.max_blob_fds);
+        }
+
+        Ok(f)
+    }
+
+    // keeps a newly opened handle for later reads, unless
+    // the blob was removed after it was opened. blobs are
+    // only unlinked under the same lock, so checking for
+    // the path here can't race with a removal.
+    fn cache(&self, id: Lsn, f: &Arc<File>, path: &Path, max: usize) {
+        let mut files = self.files.lock();
+        if path.exists() {
+            files.insert(id, f.clone(), max);
+        }
+    }
+
+    // closes any handle kept for the blob and unlinks it
+    fn remove(&self, id: Lsn, path: &Path) -> std::io::Result<()> {
+        let mut files = self.files.lock();
+        files.remove(id);
+        std::fs::remove_file(path)
+    }
+
+    #[cfg(test)]
+    fn is_open(&self, id: Lsn) -> bool {
+        self.files.lock().by_id.contains_key(&id)
+    }
+}
+
 pub(crate) fn read_blob(
     blob_ptr: Lsn,
     config: &Config,
 ) -> Result<(MessageKind, Vec<u8>)> {
-    let path = config.blob_path(blob_ptr);
-    let f_res = std::fs::OpenOptions::new().read(true).open(&path);
+    let f_res = config.blob_fds.open(blob_ptr, config);
 
     if let Err(e) = &f_res {
         debug!("failed to open file for blob read at {}: {:?}", blob_ptr, e);
     }
 
-    let mut f = f_res?;
-
-    let mut crc_expected_bytes = [0u8; std::mem::size_of::<u32>()];
+    let f = f_res?;
 
-    if let Err(e) = f.read_exact(&mut crc_expected_bytes) {
-        debug!(
-            "failed to read the initial CRC bytes in the blob at {}: {:?}",
-            blob_ptr, e,
-        );
-        return Err(e.into());
-    }
+    // the handle may be shared with concurrent readers, so
+    // read at explicit offsets rather than from its cursor
+    let len = match f.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            debug!(
+                "failed to read the length of the blob at {}: {:?}",
+                blob_ptr, e,
+            );
+            return Err(e.into());
+        }
+    };
 
-    let crc_expected = arr_to_u32(&crc_expected_bytes);
+    // the crc followed by the kind byte
+    const HEADER_LEN: usize = std::mem::size_of::<u32>() + 1;
 
-    let mut kind_byte = [0u8];
+    let mut header = [0u8; HEADER_LEN];
 
-    if let Err(e) = f.read_exact(&mut kind_byte) {
+    if let Err(e) = f.pread_exact(&mut header, 0) {
         debug!(
             "failed to read the initial CRC bytes in the blob at {}: {:?}",
             blob_ptr, e,
@@ -37,8 +137,11 @@ pub(crate) fn read_blob(
         return Err(e.into());
     }
 
-    let mut buf = vec![];
-    if let Err(e) = f.read_to_end(&mut buf) {
+    let crc_expected = arr_to_u32(&header[..4]);
+    let kind_byte = [header[4]];
+
+    let mut buf = vec![0; len as usize - HEADER_LEN];
+    if let Err(e) = f.pread_exact(&mut buf, HEADER_LEN as LogId) {
         debug!(
             "failed to read data after the CRC bytes in blob at {}: {:?}",
             blob_ptr, e,
@@ -58,6 +161,17 @@ pub(crate) fn read_blob(
             at: DiskPtr::Blob(0, blob_ptr),
         })
     } else {
+        let buf = match config.encryption {
+            Some(ref encryption) => {
+                encryption.decrypt(&buf).ok_or_else(|| {
+                    warn!("blob {} failed to decrypt", blob_ptr);
+                    Error::Corruption {
+                        at: DiskPtr::Blob(0, blob_ptr),
+                    }
+                })?
+            }
+            None => buf,
+        };
         let buf = if config.use_compression {
             maybe_decompress(buf)?
         } else {
@@ -127,7 +241,7 @@ pub(crate) fn gc_blobs(config: &Config, stable_lsn: Lsn) -> Result<()> {
                  a higher lsn than our stable log: {:?}",
                 path, stable
             );
-            std::fs::remove_file(&path)?;
+            config.blob_fds.remove(lsn, &path)?;
         }
     }
 
@@ -137,7 +251,7 @@ pub(crate) fn gc_blobs(config: &Config, stable_lsn: Lsn) -> Result<()> {
 pub(crate) fn remove_blob(id: Lsn, config: &Config) -> Result<()> {
     let path = config.blob_path(id);
 
-    if let Err(e) = std::fs::remove_file(&path) {
+    if let Err(e) = config.blob_fds.remove(id, &path) {
         debug!("removing blob at {:?} failed: {}", path, e);
     } else {
         trace!("successfully removed blob at {:?}", path);
@@ -146,3 +260,24 @@ pub(crate) fn remove_blob(id: Lsn, config: &Config) -> Result<()> {
     // TODO return a future
     Ok(())
 }
+
+#[test]
+fn blobs_removed_while_opening_are_not_kept_open() {
+    let config = ConfigBuilder::new().temporary(true).build();
+
+    write_blob(&config, MessageKind::BlobAppend, 1, b"a").unwrap();
+    let path = config.blob_path(1);
+
+    // another thread removes the blob after a reader has
+    // opened it, but before the reader keeps its handle
+    let f = Arc::new(File::open(&path).unwrap());
+    remove_blob(1, &config).unwrap();
+    config.blob_fds.cache(1, &f, &path, 8);
+    assert!(!config.blob_fds.is_open(1));
+
+    write_blob(&config, MessageKind::BlobAppend, 2, b"b").unwrap();
+    read_blob(2, &config).unwrap();
+    assert!(config.blob_fds.is_open(2));
+    remove_blob(2, &config).unwrap();
+    assert!(!config.blob_fds.is_open(2));
+}
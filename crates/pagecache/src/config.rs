@@ -1,10 +1,10 @@
 use std::{
     fs,
-    io::{Read, Seek, Write},
+    io::{Read, Write},
     ops::Deref,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicPtr, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
         Arc,
     },
 };
@@ -32,6 +32,34 @@ impl PersistedConfig {
     }
 }
 
+/// Where a full node is split in two by sled.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SplitPolicy {
+    /// Split nodes in the middle, leaving both halves with
+    /// room for later inserts. Best for random inserts.
+    Center,
+    /// Split nodes near their high end, leaving most items
+    /// in the left node. Sequential inserts only ever land
+    /// in the right node, so this fills nodes densely for
+    /// time-series and log workloads, at the cost of more
+    /// frequent splits for random inserts.
+    AppendBiased,
+}
+
+/// What happens when an internal invariant is found to
+/// be violated, like a page holding an update that it
+/// can't accept, which may be caused by corruption.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum InvariantPolicy {
+    /// Panic, which crashes the process unless the panic
+    /// is caught.
+    Panic,
+    /// Fail the operation that found the violation with
+    /// `Error::Corruption`, leaving the rest of the
+    /// database usable.
+    Error,
+}
+
 impl Deref for ConfigInner {
     type Target = ConfigBuilder;
     fn deref(&self) -> &Self::Target {
@@ -64,6 +92,14 @@ pub struct ConfigBuilder {
     #[doc(hidden)]
     pub flush_every_ms: Option<u64>,
     #[doc(hidden)]
+    pub min_flush_interval_ms: Option<u64>,
+    #[doc(hidden)]
+    pub max_flush_delay_ms: Option<u64>,
+    #[doc(hidden)]
+    pub flush_on_idle: bool,
+    #[doc(hidden)]
+    pub group_commit_window_us: u64,
+    #[doc(hidden)]
     pub io_buf_size: usize,
     #[doc(hidden)]
     pub page_consolidation_threshold: usize,
@@ -78,6 +114,8 @@ pub struct ConfigBuilder {
     #[doc(hidden)]
     pub segment_mode: SegmentMode,
     #[doc(hidden)]
+    pub recycle_segments: bool,
+    #[doc(hidden)]
     pub snapshot_after_ops: u64,
     #[doc(hidden)]
     pub snapshot_path: Option<PathBuf>,
@@ -88,12 +126,53 @@ pub struct ConfigBuilder {
     #[doc(hidden)]
     pub compression_factor: i32,
     #[doc(hidden)]
+    #[serde(skip)]
+    pub encryption: Option<EncryptionConfig>,
+    #[doc(hidden)]
+    pub encryption_key_id: Option<String>,
+    #[doc(hidden)]
+    pub encryption_key_check: Option<Vec<u8>>,
+    #[doc(hidden)]
     pub print_profile_on_drop: bool,
     #[doc(hidden)]
     pub idgen_persist_interval: u64,
     #[doc(hidden)]
     pub async_io: bool,
     #[doc(hidden)]
+    pub cas_backoff: BackoffConfig,
+    #[doc(hidden)]
+    pub replication: bool,
+    #[doc(hidden)]
+    pub audit_log: Option<PathBuf>,
+    #[doc(hidden)]
+    pub separate_values: bool,
+    #[doc(hidden)]
+    pub value_checksums: bool,
+    #[doc(hidden)]
+    pub max_blob_fds: usize,
+    #[doc(hidden)]
+    pub scan_prefetch: usize,
+    #[doc(hidden)]
+    pub merge_operator_name: Option<String>,
+    #[doc(hidden)]
+    pub recovery_mode: RecoveryMode,
+    #[doc(hidden)]
+    pub split_policy: SplitPolicy,
+    #[doc(hidden)]
+    pub on_invariant_violation: InvariantPolicy,
+    #[doc(hidden)]
+    pub compaction_parallelism: usize,
+    #[doc(hidden)]
+    pub lazy_recovery: bool,
+    #[doc(hidden)]
+    pub use_bloom_filter: bool,
+    #[doc(hidden)]
+    pub bloom_filter_capacity: usize,
+    #[doc(hidden)]
+    pub track_hot_keys: usize,
+    #[doc(hidden)]
+    pub reserve_space: u64,
+    #[doc(hidden)]
     pub version: (usize, usize),
 }
 
@@ -109,16 +188,41 @@ impl Default for ConfigBuilder {
             cache_capacity: 1024 * 1024 * 1024, // 1gb
             use_compression: false,
             compression_factor: 5,
+            encryption: None,
+            encryption_key_id: None,
+            encryption_key_check: None,
             flush_every_ms: Some(500),
+            min_flush_interval_ms: None,
+            max_flush_delay_ms: None,
+            flush_on_idle: false,
+            group_commit_window_us: 0,
             snapshot_after_ops: 1_000_000,
             snapshot_path: None,
             segment_cleanup_threshold: 0.40,
             segment_cleanup_skew: 10,
             temporary: false,
             segment_mode: SegmentMode::Gc,
+            recycle_segments: true,
             print_profile_on_drop: false,
             idgen_persist_interval: 1_000_000,
             async_io: true,
+            cas_backoff: BackoffConfig::default(),
+            replication: false,
+            audit_log: None,
+            separate_values: false,
+            value_checksums: false,
+            max_blob_fds: 128,
+            scan_prefetch: 0,
+            merge_operator_name: None,
+            recovery_mode: RecoveryMode::Fast,
+            split_policy: SplitPolicy::Center,
+            on_invariant_violation: InvariantPolicy::Panic,
+            compaction_parallelism: 1,
+            lazy_recovery: false,
+            use_bloom_filter: false,
+            bloom_filter_capacity: 1_000_000,
+            track_hot_keys: 0,
+            reserve_space: 0,
             version: pagecache_crate_version(),
         }
     }
@@ -143,6 +247,40 @@ macro_rules! builder {
     }
 }
 
+// Generates the field-by-field encoding of the persisted
+// configuration. Each field is stored under its name, so
+// fields may be added to or removed from `ConfigBuilder`
+// without breaking databases created by other versions.
+macro_rules! persisted_fields {
+    ($($name:ident),*) => {
+        fn serialize_fields(&self) -> Vec<(String, Vec<u8>)> {
+            vec![$(
+                (stringify!($name).to_owned(), serialize(&self.$name).unwrap()),
+            )*]
+        }
+
+        fn deserialize_field(&mut self, name: &str, bytes: &[u8]) -> bool {
+            match name {
+                $(
+                    stringify!($name) => match deserialize(bytes) {
+                        Ok(value) => {
+                            self.$name = value;
+                            true
+                        }
+                        Err(_) => false,
+                    },
+                )*
+                // written by a version with fields we don't know
+                _ => true,
+            }
+        }
+    }
+}
+
+// Prefixes a configuration file written with the field-by-field
+// encoding. Older files are a bare bincode `ConfigBuilder`.
+const CONFIG_FORMAT_TAG: &[u8] = b"sled-conf-v1";
+
 impl ConfigBuilder {
     /// Returns a default `ConfigBuilder`
     pub fn new() -> ConfigBuilder {
@@ -155,6 +293,18 @@ impl ConfigBuilder {
         self
     }
 
+    /// Use the merge operator registered under `name` with
+    /// `sled::register_merge_operator` for every `Tree`.
+    /// The name is persisted, and reopening the database
+    /// with a different name is an error.
+    pub fn merge_operator_named<N: Into<String>>(
+        mut self,
+        name: N,
+    ) -> ConfigBuilder {
+        self.merge_operator_name = Some(name.into());
+        self
+    }
+
     /// Finalize the configuration.
     ///
     /// # Panics
@@ -163,9 +313,42 @@ impl ConfigBuilder {
     /// to open the files for performing database IO,
     /// or if the provided configuration fails some
     /// basic sanity checks.
-    pub fn build(mut self) -> Config {
+    pub fn build(self) -> Config {
+        let path = self.db_path();
+        self.try_build().unwrap_or_else(|e| {
+            panic!(
+                "should be able to open configured file at {:?}; {}",
+                path, e
+            );
+        })
+    }
+
+    /// Finalize the configuration, returning an error
+    /// instead of panicking if it is not possible to open
+    /// the files for performing database IO, or if the
+    /// provided configuration fails some basic sanity checks
+    /// or is incompatible with the existing database.
+    pub fn try_build(self) -> Result<Config> {
+        self.finalize(None)
+    }
+
+    /// Finalize the configuration like `try_build`, but use
+    /// the provided file for the database's data instead of
+    /// opening the `db` file under the configured path. This
+    /// allows a database to be started from a pre-opened file
+    /// descriptor (via `std::os::unix::io::FromRawFd`) in
+    /// sandboxed environments. The configuration, snapshot,
+    /// and blob files are still stored under the configured
+    /// path. The file is locked like a file opened by sled.
+    /// To reopen the same file later, pass a handle created
+    /// with `fs::File::try_clone`.
+    pub fn try_build_with_file(self, file: fs::File) -> Result<Config> {
+        self.finalize(Some(file))
+    }
+
+    fn finalize(mut self, file: Option<fs::File>) -> Result<Config> {
         // only validate, setup directory, and open file once
-        self.validate().unwrap();
+        self.validate()?;
 
         if self.temporary && self.path == PathBuf::from(DEFAULT_PATH) {
             #[cfg(unix)]
@@ -192,22 +375,37 @@ impl ConfigBuilder {
             self.path = PathBuf::from(tmp_path);
         }
 
-        let file = self.open_file().unwrap_or_else(|e| {
-            panic!(
-                "should be able to open configured file at {:?}; {}",
-                self.db_path(),
-                e,
-            );
-        });
+        self.encryption_key_id =
+            self.encryption.as_ref().map(|e| e.key_id().to_owned());
+        self.encryption_key_check =
+            self.encryption.as_ref().map(EncryptionConfig::key_check);
+
+        let file = match file {
+            Some(file) => {
+                self.prepare_directory(Some(&file))?;
+                self.lock_file(&file)?;
+                file
+            }
+            None => self.open_file()?,
+        };
+
+        if !self.read_only {
+            self.reserve_headroom();
+        }
+
+        let reserve_released =
+            self.reserve_space > 0 && self.reserve_released_path().exists();
 
         // seal config in a Config
-        Config(Arc::new(ConfigInner {
+        Ok(Config(Arc::new(ConfigInner {
             inner: self,
             file,
             global_error: AtomicPtr::default(),
+            reserve_released: AtomicBool::new(reserve_released),
+            blob_fds: BlobFds::default(),
             #[cfg(feature = "event_log")]
             event_log: crate::event_log::EventLog::default(),
-        }))
+        })))
     }
 
     builder!(
@@ -218,15 +416,37 @@ impl ConfigBuilder {
         (cache_capacity, u64, "maximum size for the system page cache"),
         (use_compression, bool, "whether to use zstd compression"),
         (compression_factor, i32, "the compression factor to use with zstd compression"),
+        (encryption, Option<EncryptionConfig>, "encrypt everything written to the log and blob files with this cipher, after compressing it. the key id is persisted, and reopening the database with a different key is an error"),
         (flush_every_ms, Option<u64>, "number of ms between IO buffer flushes"),
+        (min_flush_interval_ms, Option<u64>, "minimum number of ms between IO buffer flushes. once this has passed, a full IO buffer is flushed without waiting for the next scheduled flush"),
+        (max_flush_delay_ms, Option<u64>, "maximum number of ms that a write waits before being flushed. takes precedence over flush_every_ms"),
+        (flush_on_idle, bool, "flush as soon as writes pause, instead of waiting for the next scheduled flush. writes that keep arriving are still batched until the scheduled flush"),
+        (group_commit_window_us, u64, "the number of microseconds that a flush waits for concurrent writers to join it before writing their shared IO buffer, so that they are made durable by a single write and fsync. 0 writes immediately"),
         (snapshot_after_ops, u64, "number of operations between page table snapshots"),
         (segment_cleanup_threshold, f64, "the proportion of remaining valid pages in the segment before GC defragments it"),
         (segment_cleanup_skew, usize, "the cleanup threshold skew in percentage points between the first and last segments"),
         (segment_mode, SegmentMode, "the file segment selection mode"),
+        (recycle_segments, bool, "write new segments over freed ones instead of at the end of the file. when disabled, freed segments are only reclaimed by shrinking the file once they reach its end. has no effect in SegmentMode::Linear, which never recycles segments"),
         (snapshot_path, Option<PathBuf>, "snapshot file location"),
         (print_profile_on_drop, bool, "print a performance profile when the Config is dropped"),
         (idgen_persist_interval, u64, "generated IDs are persisted at this interval. during recovery we skip twice this number"),
-        (async_io, bool, "perform IO operations on a threadpool")
+        (async_io, bool, "perform IO operations on a threadpool"),
+        (cas_backoff, BackoffConfig, "how contended compare-and-swap loops back off between retries"),
+        (replication, bool, "write each change to a Tree to the log along with its whole key and the Tree that it was made to, so that Tree::log_entries_since and Tree::changes_since can read it back for as long as the log retains it. this makes every write larger"),
+        (audit_log, Option<PathBuf>, "an append-only file that every committed write is also recorded in, and which is never compacted. each entry is synced before the write returns"),
+        (separate_values, bool, "store every value in its own blob file, keeping only keys and blob ids in the log. this keeps the log small for large values, at the cost of a blob read for every value read"),
+        (value_checksums, bool, "store a checksum with every value and verify it whenever the value is read, reporting Error::Corruption on a mismatch. this catches values that are damaged in memory, which the checksums of the log can't"),
+        (max_blob_fds, usize, "the number of blob files kept open for reuse by later reads, closing the least recently read one when exceeded. 0 opens a blob's file anew for every read"),
+        (scan_prefetch, usize, "the number of leaves that forward scans read ahead of consumption. 0 disables read-ahead"),
+        (recovery_mode, RecoveryMode, "how thoroughly the recovered state is checked against the log at startup"),
+        (split_policy, SplitPolicy, "where full nodes are split. SplitPolicy::AppendBiased packs nodes densely when keys are inserted in increasing order"),
+        (on_invariant_violation, InvariantPolicy, "whether a violated internal invariant, like a page that can't accept one of its updates, panics or fails the operation with Error::Corruption"),
+        (compaction_parallelism, usize, "the number of threads that the background flusher uses to rewrite pages out of fragmented segments while it is idle. 1 cleans on the flusher's own thread"),
+        (lazy_recovery, bool, "rebuild in-memory state that only speeds up operations, like bloom filters, in the background after startup instead of before it returns. the log itself is always recovered before startup returns"),
+        (use_bloom_filter, bool, "keep an in-memory bloom filter of each Tree's keys, so that lookups of keys that were never written can skip the tree traversal. the filter is filled by reading every key of each Tree when it is opened, which takes a full scan unless lazy_recovery moves it to the background"),
+        (bloom_filter_capacity, usize, "the number of keys that each Tree's bloom filter is sized for, using 10 bits per key. past this many keys, more lookups of missing keys traverse the tree"),
+        (track_hot_keys, usize, "approximately count reads and writes of up to this many of the most frequently accessed keys of each Tree, for Tree::hot_keys. 0 disables tracking"),
+        (reserve_space, u64, "the number of bytes to set aside in a reserve file next to the database, which is deleted to free up room when the storage device fills up. 0 disables the reserve")
     );
 
     // panics if config options are outside of advised range
@@ -239,6 +459,13 @@ impl ConfigBuilder {
             self.io_buf_size <= 1 << 24,
             "io_buf_size should be <= 16mb"
         );
+        supported!(
+            self.io_buf_size.is_multiple_of(512),
+            format!(
+                "io_buf_size must be a multiple of 512, but it is {}",
+                self.io_buf_size
+            )
+        );
         supported!(
             self.page_consolidation_threshold >= 1,
             "must consolidate pages after a non-zero number of updates"
@@ -277,10 +504,38 @@ impl ConfigBuilder {
             self.idgen_persist_interval > 0,
             "idgen_persist_interval must be above 0"
         );
+        supported!(
+            self.cas_backoff.base_delay_us <= self.cas_backoff.max_delay_us,
+            "cas_backoff base_delay_us must be <= max_delay_us"
+        );
+        if let (Some(min), Some(max)) =
+            (self.min_flush_interval_ms, self.max_flush_delay_ms)
+        {
+            supported!(
+                min <= max,
+                "min_flush_interval_ms must be <= max_flush_delay_ms"
+            );
+        }
+        supported!(
+            self.compaction_parallelism > 0,
+            "compaction_parallelism must be above 0"
+        );
+        supported!(
+            self.bloom_filter_capacity > 0,
+            "bloom_filter_capacity must be above 0"
+        );
+        supported!(
+            !(self.replication && self.separate_values),
+            "replication can't be combined with separate_values, \
+             because the values that the log refers to may have \
+             been freed by the time that it is read back"
+        );
         Ok(())
     }
 
-    fn open_file(&mut self) -> Result<fs::File> {
+    // `file` is the data file that was provided instead of
+    // the one at `db_path`, if any
+    fn prepare_directory(&self, file: Option<&fs::File>) -> Result<()> {
         let path = self.db_path();
 
         // panic if we can't parse the path
@@ -304,14 +559,33 @@ impl ConfigBuilder {
         }
 
         if !dir.exists() {
-            let res: std::io::Result<()> = std::fs::create_dir_all(dir);
+            // the directories that create_dir_all is about to
+            // create, from the blobs directory upwards
+            let created: Vec<&Path> =
+                dir.ancestors().take_while(|dir| !dir.exists()).collect();
+
+            let res: std::io::Result<()> = std::fs::create_dir_all(&dir);
             res.map_err(|e: std::io::Error| {
                 let ret: Error = e.into();
                 ret
             })?;
+
+            // make the new directory entries durable by syncing
+            // each created directory and the existing one that
+            // holds the topmost of them.
+            for dir in &created {
+                fsync_dir(dir)?;
+            }
+            if let Some(topmost) = created.last() {
+                fsync_parent(topmost)?;
+            }
         }
 
-        self.verify_config_changes_ok()?;
+        self.verify_config_changes_ok(file)
+    }
+
+    fn open_file(&self) -> Result<fs::File> {
+        self.prepare_directory(None)?;
 
         // open the data file
         let mut options = fs::OpenOptions::new();
@@ -321,35 +595,127 @@ impl ConfigBuilder {
             options.write(true);
         }
 
-        match options.open(&path) {
-            Ok(file) => {
-                // try to exclusively lock the file
+        let path = self.db_path();
+        let created = !path.exists();
+        let file = options.open(&path)?;
+        self.lock_file(&file)?;
+        if created {
+            fsync_parent(&path)?;
+        }
+        Ok(file)
+    }
+
+    // Sets aside `reserve_space` bytes in the reserve file,
+    // so that they can be handed back to the database when
+    // the storage device fills up. The reserve is only
+    // created along with a new database, or later when the
+    // device clearly has room for it, and never while a
+    // released reserve is still waiting for writes to
+    // succeed again.
+    fn reserve_headroom(&self) {
+        if self.reserve_space == 0 {
+            return;
+        }
+
+        let path = self.reserve_path();
+        if path.exists() {
+            return;
+        }
+
+        if self.reserve_released_path().exists() {
+            debug!(
+                "not reserving space until writes succeed again \
+                 after the reserve was released"
+            );
+            return;
+        }
+
+        let first_creation = !self.config_path().exists();
+        if !first_creation && !self.has_room_for_reserve() {
+            return;
+        }
+
+        let res = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .and_then(|f| {
                 #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
                 {
-                    let lock_res = if self.read_only {
-                        file.try_lock_shared()
-                    } else {
-                        file.try_lock_exclusive()
-                    };
-                    if lock_res.is_err() {
-                        return Err(Error::Io(std::io::Error::new(
-                                    std::io::ErrorKind::Other,
-                                    format!(
-                                    "could not acquire appropriate file lock on {:?}",
-                                    path
-                                ),
-                            )));
+                    f.allocate(self.reserve_space)
+                }
+
+                #[cfg(not(any(
+                    windows,
+                    target_os = "linux",
+                    target_os = "macos"
+                )))]
+                {
+                    f.set_len(self.reserve_space)
+                }
+            });
+
+        if let Err(e) = res {
+            // a partial reserve would only take space
+            // away from the database, so give it back
+            warn!("failed to reserve space at {:?}: {}", path, e);
+            let _ = fs::remove_file(&path);
+        }
+    }
+
+    // Whether the device has room for the reserve with at
+    // least as much again to spare, so that recreating it
+    // does not bring the database close to running out.
+    fn has_room_for_reserve(&self) -> bool {
+        #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
+        {
+            match fs2::available_space(self.get_path()) {
+                Ok(available) => {
+                    let room = available / 2 >= self.reserve_space;
+                    if !room {
+                        debug!(
+                            "not reserving {} bytes with only {} available",
+                            self.reserve_space, available
+                        );
                     }
+                    room
                 }
+                Err(e) => {
+                    warn!("failed to check the available space: {}", e);
+                    false
+                }
+            }
+        }
+
+        #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+        {
+            false
+        }
+    }
 
-                Ok(file)
+    fn lock_file(&self, file: &fs::File) -> Result<()> {
+        // try to exclusively lock the file
+        #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
+        {
+            let lock_res = if self.read_only {
+                FileExt::try_lock_shared(file)
+            } else {
+                FileExt::try_lock_exclusive(file)
+            };
+            if lock_res.is_err() {
+                return Err(Error::Io(std::io::Error::other(format!(
+                    "could not acquire appropriate file lock on {:?}",
+                    self.db_path()
+                ))));
             }
-            Err(e) => Err(e.into()),
         }
+
+        Ok(())
     }
 
-    fn verify_config_changes_ok(&self) -> Result<()> {
-        match self.read_config() {
+    fn verify_config_changes_ok(&self, file: Option<&fs::File>) -> Result<()> {
+        match self.read_config(self.log_exists(file)) {
             Ok(Some(old)) => {
                 supported!(
                     self.use_compression == old.use_compression,
@@ -361,6 +727,29 @@ impl ConfigBuilder {
                     )
                 );
 
+                supported!(
+                    self.encryption_key_id == old.encryption_key_id,
+                    format!(
+                        "cannot change encryption keys across restarts. \
+                         old key id loaded from disk: {:?}, \
+                         currently set key id: {:?}.",
+                        old.encryption_key_id, self.encryption_key_id,
+                    )
+                );
+
+                if let (Some(encryption), Some(key_check)) =
+                    (&self.encryption, &old.encryption_key_check)
+                {
+                    supported!(
+                        encryption.verify_key_check(key_check),
+                        format!(
+                            "the configured key for key id {:?} \
+                             does not decrypt this database.",
+                            encryption.key_id(),
+                        )
+                    );
+                }
+
                 supported!(
                     self.io_buf_size == old.io_buf_size,
                     format!(
@@ -370,6 +759,36 @@ impl ConfigBuilder {
                     )
                 );
 
+                supported!(
+                    self.separate_values == old.separate_values,
+                    format!(
+                        "cannot change value separation across restarts. \
+                         old value of separate_values loaded from disk: {}, \
+                         currently set value: {}.",
+                        old.separate_values, self.separate_values,
+                    )
+                );
+
+                supported!(
+                    self.value_checksums == old.value_checksums,
+                    format!(
+                        "cannot change value checksums across restarts. \
+                         old value of value_checksums loaded from disk: {}, \
+                         currently set value: {}.",
+                        old.value_checksums, self.value_checksums,
+                    )
+                );
+
+                supported!(
+                    self.merge_operator_name == old.merge_operator_name,
+                    format!(
+                        "cannot change the merge operator across restarts. \
+                         old merge operator name loaded from disk: {:?}, \
+                         currently set name: {:?}.",
+                        old.merge_operator_name, self.merge_operator_name,
+                    )
+                );
+
                 supported!(
                     self.version == old.version,
                     format!(
@@ -387,31 +806,99 @@ impl ConfigBuilder {
                 Ok(())
             }
             Ok(None) => self.write_config(),
-            Err(e) => Err(e.into()),
+            Err(e) => Err(e),
         }
     }
 
+    // only the settings that the data on disk depends on,
+    // which `verify_config_changes_ok` checks on startup.
+    // the rest may change freely across restarts.
+    persisted_fields!(
+        io_buf_size,
+        use_compression,
+        encryption_key_id,
+        encryption_key_check,
+        separate_values,
+        value_checksums,
+        merge_operator_name,
+        version
+    );
+
+    fn encode_config(&self) -> Vec<u8> {
+        let mut bytes = CONFIG_FORMAT_TAG.to_vec();
+        bytes.extend(serialize(&self.serialize_fields()).unwrap());
+        bytes
+    }
+
+    // fields missing from the encoding keep their default values
+    fn decode_config(bytes: &[u8]) -> Option<ConfigBuilder> {
+        if !bytes.starts_with(CONFIG_FORMAT_TAG) {
+            return decode_legacy_config(bytes);
+        }
+
+        let fields: Vec<(String, Vec<u8>)> =
+            deserialize(&bytes[CONFIG_FORMAT_TAG.len()..]).ok()?;
+
+        let mut config = ConfigBuilder::default();
+        for (name, value) in fields {
+            if !config.deserialize_field(&name, &value) {
+                warn!("failed to decode persisted config field {}", name);
+                return None;
+            }
+        }
+        Some(config)
+    }
+
     fn write_config(&self) -> Result<()> {
-        let bytes = serialize(&*self).unwrap();
+        let bytes = self.encode_config();
         let crc: u32 = crc32(&*bytes);
         let crc_arr = u32_to_arr(crc);
 
         let path = self.config_path();
+        let tmp_path = path.with_extension("in-progress");
 
+        // write the new config beside the old one and then
+        // rename it into place, so that a crash leaves either
+        // the old or the new config, but never a torn one
         let mut f = std::fs::OpenOptions::new()
             .write(true)
             .create(true)
-            .open(path)?;
+            .truncate(true)
+            .open(&tmp_path)?;
 
         maybe_fail!("write_config bytes");
         f.write_all(&*bytes)?;
         maybe_fail!("write_config crc");
         f.write_all(&crc_arr)?;
+        f.sync_all()?;
+        drop(f);
+
+        fs::rename(&tmp_path, &path)?;
+        fsync_parent(&path)?;
         maybe_fail!("write_config post");
         Ok(())
     }
 
-    fn read_config(&self) -> std::io::Result<Option<ConfigBuilder>> {
+    // Whether the data file holds any log, which may depend on
+    // the settings in the config file. An unreadable data file
+    // is assumed to.
+    fn log_exists(&self, file: Option<&fs::File>) -> bool {
+        let metadata = match file {
+            Some(file) => file.metadata(),
+            None => fs::metadata(self.db_path()),
+        };
+        match metadata {
+            Ok(metadata) => metadata.len() > 0,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(_) => true,
+        }
+    }
+
+    // Reads the persisted config. One that was torn by a crash
+    // while it was written is treated as missing, so that a new
+    // one is written, but only if `log_exists` is false. Once
+    // there is a log, its settings can't be guessed.
+    fn read_config(&self, log_exists: bool) -> Result<Option<ConfigBuilder>> {
         let path = self.config_path();
 
         let f_res = std::fs::OpenOptions::new().read(true).open(&path);
@@ -421,37 +908,51 @@ impl ConfigBuilder {
                 return Ok(None);
             }
             Err(other) => {
-                return Err(other);
+                return Err(other.into());
             }
             Ok(f) => f,
         };
 
-        if f.metadata()?.len() <= 8 {
-            warn!("empty/corrupt configuration file found");
-            return Ok(None);
-        }
-
         let mut buf = vec![];
-        f.read_to_end(&mut buf).unwrap();
-        let len = buf.len();
-        buf.split_off(len - 4);
+        f.read_to_end(&mut buf)?;
 
-        let mut crc_arr = [0u8; 4];
-        f.seek(std::io::SeekFrom::End(-4)).unwrap();
-        f.read_exact(&mut crc_arr).unwrap();
-        let crc_expected = arr_to_u32(&crc_arr);
-
-        let crc_actual = crc32(&*buf);
+        let intact = buf.len() > 8 && {
+            let crc_arr = buf.split_off(buf.len() - 4);
+            arr_to_u32(&crc_arr) == crc32(&buf)
+        };
 
-        if crc_expected != crc_actual {
+        if !intact {
+            // nothing in an unverified config can be trusted
+            if log_exists {
+                error!(
+                    "crc for settings file {:?} failed, and \
+                     the existing log may depend on its settings",
+                    path
+                );
+                return Err(Error::Corruption {
+                    at: DiskPtr::Inline(0),
+                });
+            }
             warn!(
-                "crc for settings file {:?} failed! \
-                 can't verify that config is safe",
+                "crc for settings file {:?} failed! the database \
+                 is empty, so writing a new one",
                 path
             );
+            return Ok(None);
         }
 
-        Ok(deserialize::<ConfigBuilder>(&*buf).ok())
+        match ConfigBuilder::decode_config(&buf) {
+            Some(config) => Ok(Some(config)),
+            None => {
+                // its crc matched, so this isn't a torn write.
+                // overwriting it could silently change settings
+                // that the data on disk depends on.
+                error!("settings file {:?} could not be decoded", path);
+                Err(Error::Corruption {
+                    at: DiskPtr::Inline(0),
+                })
+            }
+        }
     }
 
     // Get the path of the database
@@ -478,6 +979,18 @@ impl ConfigBuilder {
         path.push("conf");
         path
     }
+
+    fn reserve_path(&self) -> PathBuf {
+        let mut path = self.get_path();
+        path.push("reserve");
+        path
+    }
+
+    fn reserve_released_path(&self) -> PathBuf {
+        let mut path = self.get_path();
+        path.push("reserve.released");
+        path
+    }
 }
 
 /// A finalized `ConfigBuilder` that can be use multiple times
@@ -498,6 +1011,10 @@ pub struct ConfigInner {
     inner: ConfigBuilder,
     pub(crate) file: fs::File,
     pub(crate) global_error: AtomicPtr<Error>,
+    // whether the reserve was released and no write has
+    // succeeded since
+    reserve_released: AtomicBool,
+    pub(crate) blob_fds: BlobFds,
     #[cfg(feature = "event_log")]
     /// an event log for concurrent debugging
     pub event_log: event_log::EventLog,
@@ -537,6 +1054,40 @@ impl Config {
         }
     }
 
+    // Deletes the reserve file set aside by `reserve_space`,
+    // freeing its space for the database to use. A marker
+    // file keeps later restarts from taking the space back
+    // before writes succeed again.
+    pub(crate) fn release_reserved_space(&self) {
+        if self.reserve_space == 0 {
+            return;
+        }
+        match fs::remove_file(self.reserve_path()) {
+            Ok(()) => warn!(
+                "ran out of space, released {} reserved bytes",
+                self.reserve_space
+            ),
+            Err(e) => debug!("no reserved space to release: {}", e),
+        }
+        self.reserve_released.store(true, Ordering::SeqCst);
+        if let Err(e) = fs::File::create(self.reserve_released_path()) {
+            warn!("failed to mark the reserve as released: {}", e);
+        }
+    }
+
+    // Called after a write reached the storage device, which
+    // allows a released reserve to be set aside again the
+    // next time the database is started with room for it.
+    pub(crate) fn writes_succeeded(&self) {
+        if self.reserve_released.load(Ordering::Relaxed)
+            && self.reserve_released.swap(false, Ordering::SeqCst)
+        {
+            if let Err(e) = fs::remove_file(self.reserve_released_path()) {
+                warn!("failed to clear the released reserve marker: {}", e);
+            }
+        }
+    }
+
     pub(crate) fn reset_global_error(&self) {
         self.global_error
             .store(std::ptr::null_mut(), Ordering::SeqCst);
@@ -611,6 +1162,103 @@ impl Config {
         Ok(snap_dir.read_dir()?.filter_map(filter).collect())
     }
 
+    /// Returns the completed snapshot files of this system,
+    /// ordered from oldest to newest. Recovery starts from
+    /// the newest one. Snapshots that are still being written
+    /// are not included.
+    pub fn snapshots(&self) -> std::io::Result<Vec<SnapshotInfo>> {
+        let mut snapshots = vec![];
+        for path in self.get_snapshot_files()? {
+            let lsn = match path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("snap."))
+                .and_then(|hex| Lsn::from_str_radix(hex, 16).ok())
+            {
+                Some(lsn) => lsn,
+                None => continue,
+            };
+
+            // snapshots may be removed concurrently
+            let size = match fs::metadata(&path) {
+                Ok(metadata) => metadata.len(),
+                Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+
+            snapshots.push(SnapshotInfo { path, lsn, size });
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.lsn);
+        Ok(snapshots)
+    }
+
+    /// Reads the messages in the log from `from_lsn` onward
+    /// without applying them, for inspecting a damaged or
+    /// misbehaving system. Messages that are still buffered
+    /// in memory are not included, so flush first.
+    pub fn dump_log(&self, from_lsn: Lsn) -> Result<RawLogIter> {
+        RawLogIter::new(self.clone(), from_lsn)
+    }
+
+    /// Removes all but the newest `keep` completed snapshot
+    /// files, returning the number removed. The newest one is
+    /// needed for recovery, so `keep` must be at least 1.
+    pub fn prune_snapshots(&self, keep: usize) -> Result<usize> {
+        supported!(
+            keep >= 1,
+            "the newest snapshot is needed for recovery, \
+             so at least 1 must be kept"
+        );
+
+        let snapshots = self.snapshots()?;
+        let n_old = snapshots.len().saturating_sub(keep);
+
+        let mut removed = 0;
+        for snapshot in &snapshots[..n_old] {
+            debug!("pruning old snapshot file {:?}", snapshot.path);
+            match fs::remove_file(&snapshot.path) {
+                Ok(()) => removed += 1,
+                Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Returns the number of bytes that the data file,
+    /// blobs, configuration file, and snapshots of this
+    /// system occupy on disk.
+    pub fn size_on_disk(&self) -> std::io::Result<u64> {
+        // blobs and snapshots may be removed concurrently
+        fn len_if_present(path: &Path) -> std::io::Result<u64> {
+            match fs::metadata(path) {
+                Ok(metadata) => Ok(metadata.len()),
+                Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+                Err(e) => Err(e),
+            }
+        }
+
+        let mut size = self.file.metadata()?.len();
+
+        size += len_if_present(&self.config_path())?;
+
+        let mut blob_dir = self.get_path();
+        blob_dir.push("blobs");
+        if blob_dir.exists() {
+            for entry in blob_dir.read_dir()? {
+                size += len_if_present(&entry?.path())?;
+            }
+        }
+
+        for snapshot_path in self.get_snapshot_files()? {
+            size += len_if_present(&snapshot_path)?;
+        }
+
+        Ok(size)
+    }
+
     #[doc(hidden)]
     pub fn verify_snapshot(&self) -> Result<()> {
         debug!("generating incremental snapshot");
@@ -707,3 +1355,354 @@ impl Config {
             .expect("should be able to truncate");
     }
 }
+
+// counted per thread, since tests run concurrently
+#[cfg(all(test, unix))]
+thread_local! {
+    static DIR_FSYNCS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Makes the entries of a directory durable, so that newly
+/// created files and subdirectories survive a crash. Only
+/// unix platforms support opening a directory to sync it,
+/// so this does nothing elsewhere.
+fn fsync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        // relative paths have an empty parent
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        fs::File::open(dir)?.sync_all()?;
+
+        #[cfg(test)]
+        DIR_FSYNCS.with(|n| n.set(n.get() + 1));
+    }
+
+    #[cfg(not(unix))]
+    let _ = dir;
+
+    Ok(())
+}
+
+fn fsync_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) => fsync_dir(dir),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn creating_a_database_syncs_its_directories() {
+    let before = DIR_FSYNCS.with(|n| n.get());
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let after = DIR_FSYNCS.with(|n| n.get());
+
+    // the new directories, the data file, and the config file
+    assert!(
+        after - before >= 5,
+        "only {} directory fsyncs",
+        after - before
+    );
+    drop(config);
+}
+
+#[cfg(unix)]
+#[test]
+fn only_created_directories_are_synced() {
+    let root = std::env::temp_dir().join(format!(
+        "pagecache.only_created_directories_are_synced.{}",
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&root);
+    let existing = root.join("existing");
+    fs::create_dir_all(existing.join("blobs")).unwrap();
+
+    // with the directories in place, only the new
+    // config file's directory is synced
+    let config = ConfigBuilder::new().path(&existing);
+    let before = DIR_FSYNCS.with(|n| n.get());
+    config.prepare_directory(None).unwrap();
+    assert_eq!(DIR_FSYNCS.with(|n| n.get()) - before, 1);
+
+    // a, b and blobs are created, so they and root are
+    // synced as well
+    let nested = root.join("a").join("b");
+    let config = ConfigBuilder::new().path(&nested);
+    let before = DIR_FSYNCS.with(|n| n.get());
+    config.prepare_directory(None).unwrap();
+    assert_eq!(DIR_FSYNCS.with(|n| n.get()) - before, 5);
+    assert!(nested.join("blobs").is_dir());
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn unaligned_io_buf_size_is_rejected() {
+    let res = ConfigBuilder::new()
+        .temporary(true)
+        .io_buf_size(100_001)
+        .try_build();
+    match res {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    }
+}
+
+#[test]
+fn replication_of_separated_values_is_rejected() {
+    let res = ConfigBuilder::new()
+        .temporary(true)
+        .replication(true)
+        .separate_values(true)
+        .try_build();
+    match res {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    }
+}
+
+#[test]
+fn configs_with_older_field_sets_are_readable() {
+    let path = "test_configs_with_older_field_sets";
+    let _ = fs::remove_dir_all(path);
+    let config = ConfigBuilder::new().path(path).build();
+    let version = config.version;
+    let conf_path = config.config_path();
+    drop(config);
+
+    // simulate a conf written by a version that had fewer
+    // fields, plus one written by a version that had more
+    let fields: Vec<(String, Vec<u8>)> = vec![
+        (
+            "io_buf_size".to_owned(),
+            serialize(&(2_usize << 20)).unwrap(),
+        ),
+        ("version".to_owned(), serialize(&version).unwrap()),
+        ("from_the_future".to_owned(), serialize(&42_u64).unwrap()),
+    ];
+    let mut bytes = CONFIG_FORMAT_TAG.to_vec();
+    bytes.extend(serialize(&fields).unwrap());
+    let crc = u32_to_arr(crc32(&bytes));
+    bytes.extend_from_slice(&crc);
+    fs::write(&conf_path, &bytes).unwrap();
+
+    let old = ConfigBuilder::new()
+        .path(path)
+        .read_config(true)
+        .unwrap()
+        .unwrap();
+    assert_eq!(old.io_buf_size, 2 << 20);
+    assert_eq!(old.merge_operator_name, None);
+    assert_eq!(old.segment_mode, SegmentMode::Gc);
+
+    // the persisted io_buf_size is still enforced
+    match ConfigBuilder::new().path(path).try_build() {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    }
+
+    let config = ConfigBuilder::new()
+        .path(path)
+        .io_buf_size(2 << 20)
+        .try_build()
+        .unwrap();
+
+    drop(config);
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn legacy_configs_are_decoded_from_their_own_layout() {
+    // the bytes of a bare ConfigBuilder as released versions
+    // wrote it. nested tuples encode like the struct did.
+    let v0 = serialize(&(
+        (
+            64_u64 << 20,
+            Some(250_u64),
+            4_usize << 20,
+            10_usize,
+            PathBuf::from("old.sled"),
+            false,
+            0.5_f64,
+            10_usize,
+            0_u32, // SegmentMode::Linear
+        ),
+        (
+            1_000_000_u64,
+            None::<PathBuf>,
+            false,
+            true,
+            5_i32,
+            false,
+            1_000_000_u64,
+            true,
+            (0_usize, 30_usize),
+        ),
+    ))
+    .unwrap();
+
+    let old = ConfigBuilder::decode_config(&v0).unwrap();
+    assert_eq!(old.cache_capacity, 64 << 20);
+    assert_eq!(old.flush_every_ms, Some(250));
+    assert_eq!(old.io_buf_size, 4 << 20);
+    assert_eq!(old.path, PathBuf::from("old.sled"));
+    assert_eq!(old.segment_cleanup_threshold, 0.5);
+    assert_eq!(old.segment_mode, SegmentMode::Linear);
+    assert!(old.use_compression);
+    assert_eq!(old.version, (0, 30));
+    // fields that didn't exist yet keep their defaults
+    assert_eq!(old.min_flush_interval_ms, None);
+    assert_eq!(old.merge_operator_name, None);
+    assert_eq!(old.recovery_mode, RecoveryMode::Fast);
+
+    // the last layout before the field by field encoding
+    let v1 = serialize(&(
+        (
+            64_u64 << 20,
+            Some(250_u64),
+            Some(5_u64),
+            None::<u64>,
+            4_usize << 20,
+            10_usize,
+            PathBuf::from("old.sled"),
+            false,
+            0.5_f64,
+            10_usize,
+            1_u32, // SegmentMode::Gc
+        ),
+        (
+            1_000_000_u64,
+            None::<PathBuf>,
+            false,
+            false,
+            5_i32,
+            false,
+            1_000_000_u64,
+            true,
+            (7_u32, 2_u64, 512_u64),
+        ),
+        (
+            64_usize,
+            32_usize << 20,
+            2_usize,
+            Some("concatenate".to_owned()),
+            1_u32, // RecoveryMode::Verify
+            true,
+            (0_usize, 31_usize),
+        ),
+    ))
+    .unwrap();
+
+    let old = ConfigBuilder::decode_config(&v1).unwrap();
+    assert_eq!(old.min_flush_interval_ms, Some(5));
+    assert_eq!(old.segment_mode, SegmentMode::Gc);
+    assert_eq!(old.cas_backoff.spin_limit, 7);
+    assert_eq!(old.cas_backoff.max_delay_us, 512);
+    assert!(old.replication);
+    assert_eq!(old.merge_operator_name, Some("concatenate".to_owned()));
+    assert_eq!(old.recovery_mode, RecoveryMode::Verify);
+    assert!(old.use_bloom_filter);
+    assert_eq!(old.version, (0, 31));
+
+    // anything else doesn't decode, including a truncated
+    // encoding that a longer layout would read past
+    assert_eq!(ConfigBuilder::decode_config(&v1[..v1.len() - 1]), None);
+    assert_eq!(ConfigBuilder::decode_config(&[1, 2, 3, 4, 5]), None);
+}
+
+#[test]
+fn only_format_settings_are_persisted() {
+    let dir = std::env::temp_dir().join("test_only_format_settings");
+    let path = dir.to_str().unwrap();
+    let _ = fs::remove_dir_all(path);
+    let config = ConfigBuilder::new()
+        .path(path)
+        .io_buf_size(2 << 20)
+        .cache_capacity(1 << 20)
+        .flush_every_ms(Some(10))
+        .build();
+    drop(config);
+
+    let read = ConfigBuilder::new()
+        .path(path)
+        .read_config(true)
+        .unwrap()
+        .unwrap();
+    assert_eq!(read.io_buf_size, 2 << 20);
+    assert_eq!(read.cache_capacity, ConfigBuilder::default().cache_capacity);
+    assert_eq!(read.flush_every_ms, ConfigBuilder::default().flush_every_ms);
+
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn undecodable_configs_are_an_error() {
+    let path = "test_undecodable_configs_are_an_error";
+    let _ = fs::remove_dir_all(path);
+    let config = ConfigBuilder::new().path(path).build();
+    let conf_path = config.config_path();
+    drop(config);
+
+    // a conf with a valid crc that no layout can decode
+    let mut bytes = b"neither tagged nor a known layout".to_vec();
+    let crc = u32_to_arr(crc32(&bytes));
+    bytes.extend_from_slice(&crc);
+    fs::write(&conf_path, &bytes).unwrap();
+
+    match ConfigBuilder::new().path(path).try_build() {
+        Err(Error::Corruption { .. }) => {}
+        other => panic!("expected Error::Corruption, got {:?}", other),
+    }
+
+    // and it is left in place rather than overwritten
+    assert_eq!(fs::read(&conf_path).unwrap(), bytes);
+
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn torn_configs_are_rewritten_while_the_log_is_empty() {
+    let dir = std::env::temp_dir().join("test_torn_configs_are_rewritten");
+    let path = dir.to_str().unwrap();
+    let _ = fs::remove_dir_all(path);
+    let config = ConfigBuilder::new().path(path).build();
+    let conf_path = config.config_path();
+    let db_path = config.db_path();
+    drop(config);
+
+    let intact = fs::read(&conf_path).unwrap();
+    assert!(!conf_path.with_extension("in-progress").exists());
+
+    // a crash partway through writing the config, or a
+    // flipped bit, leaves a config whose crc doesn't match
+    let mut flipped = intact.clone();
+    flipped[intact.len() / 2] ^= 1;
+    for damaged in [intact[..intact.len() / 2].to_vec(), flipped] {
+        fs::write(&conf_path, &damaged).unwrap();
+
+        let read = ConfigBuilder::new().path(path).read_config(false).unwrap();
+        assert_eq!(read, None);
+
+        // once there is a log, its settings can't be guessed
+        fs::write(&db_path, [1; 8]).unwrap();
+        match ConfigBuilder::new().path(path).try_build() {
+            Err(Error::Corruption { .. }) => {}
+            other => panic!("expected Error::Corruption, got {:?}", other),
+        }
+        assert_eq!(fs::read(&conf_path).unwrap(), damaged);
+
+        // but while it is empty, starting up writes
+        // a fresh one in its place
+        fs::write(&db_path, []).unwrap();
+        let config = ConfigBuilder::new().path(path).build();
+        drop(config);
+        assert_eq!(fs::read(&conf_path).unwrap(), intact);
+    }
+
+    fs::remove_dir_all(path).unwrap();
+}
@@ -8,6 +8,8 @@ pub struct Lru {
     shards: Vec<Mutex<Shard>>,
 }
 
+unsafe impl Send for Lru {}
+
 unsafe impl Sync for Lru {}
 
 impl Lru {
@@ -0,0 +1,132 @@
+use std::{fmt, sync::Arc};
+
+use super::*;
+
+/// The length of the nonces that a `Cipher` is called with.
+pub const NONCE_LEN: usize = 12;
+
+// Encrypts the persisted key check. Message nonces are
+// random, and are drawn again in the unlikely event that
+// they match it.
+const KEY_CHECK_NONCE: [u8; NONCE_LEN] = [0; NONCE_LEN];
+
+/// An authenticated cipher (AEAD) used to encrypt data
+/// at rest with `ConfigBuilder::encryption`. This is
+/// usually a thin wrapper around a cipher from a crate
+/// like `aes-gcm` or `chacha20poly1305`.
+pub trait Cipher: Send + Sync {
+    /// Encrypts `plaintext` with `nonce`, returning the
+    /// ciphertext along with its authentication tag.
+    fn encrypt(&self, nonce: &[u8; NONCE_LEN], plaintext: &[u8]) -> Vec<u8>;
+
+    /// Decrypts `ciphertext` that was produced by `encrypt`
+    /// with the same nonce, returning `None` if it fails
+    /// to authenticate, like when it was encrypted with a
+    /// different key or has been tampered with.
+    fn decrypt(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        ciphertext: &[u8],
+    ) -> Option<Vec<u8>>;
+}
+
+/// The cipher that data is encrypted with at rest, along
+/// with an identifier for its key. Every page is encrypted
+/// after it is serialized and compressed, so keys are
+/// encrypted along with values.
+///
+/// The key id and a value encrypted with the key are
+/// persisted, so that reopening a database with another
+/// key fails with `Error::Unsupported` instead of
+/// returning garbage.
+#[derive(Clone)]
+pub struct EncryptionConfig {
+    key_id: String,
+    cipher: Arc<dyn Cipher>,
+}
+
+impl EncryptionConfig {
+    /// Create an `EncryptionConfig` that encrypts data
+    /// with `cipher`, whose key is identified by `key_id`.
+    pub fn new<N, C>(key_id: N, cipher: C) -> EncryptionConfig
+    where
+        N: Into<String>,
+        C: Cipher + 'static,
+    {
+        EncryptionConfig {
+            key_id: key_id.into(),
+            cipher: Arc::new(cipher),
+        }
+    }
+
+    /// The identifier of the key used by this configuration.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Encrypts a message, prefixing it with its nonce. Nonces
+    /// are drawn at random from the operating system, so they
+    /// aren't reused across processes that write to the same
+    /// database, or after the clock moves backwards.
+    pub(crate) fn encrypt(&self, buf: &[u8]) -> Vec<u8> {
+        let _measure = Measure::new(&M.encrypt);
+
+        let mut nonce = KEY_CHECK_NONCE;
+        while nonce == KEY_CHECK_NONCE {
+            getrandom::getrandom(&mut nonce)
+                .expect("failed to draw a nonce from the operating system");
+        }
+
+        let ciphertext = self.cipher.encrypt(&nonce, buf);
+
+        let mut ret = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        ret.extend_from_slice(&nonce);
+        ret.extend_from_slice(&ciphertext);
+        ret
+    }
+
+    /// Decrypts a message written by `encrypt`, returning
+    /// `None` if it fails to authenticate.
+    pub(crate) fn decrypt(&self, buf: &[u8]) -> Option<Vec<u8>> {
+        let _measure = Measure::new(&M.decrypt);
+
+        if buf.len() < NONCE_LEN {
+            return None;
+        }
+
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&buf[..NONCE_LEN]);
+
+        self.cipher.decrypt(&nonce, &buf[NONCE_LEN..])
+    }
+
+    /// The key id encrypted with this configuration's key,
+    /// which is persisted to detect a changed key.
+    pub(crate) fn key_check(&self) -> Vec<u8> {
+        self.cipher
+            .encrypt(&KEY_CHECK_NONCE, self.key_id.as_bytes())
+    }
+
+    /// Whether `key_check` was produced by `key_check` with
+    /// the same key as this configuration.
+    pub(crate) fn verify_key_check(&self, key_check: &[u8]) -> bool {
+        self.cipher
+            .decrypt(&KEY_CHECK_NONCE, key_check)
+            .is_some_and(|plaintext| plaintext == self.key_id.as_bytes())
+    }
+}
+
+impl fmt::Debug for EncryptionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionConfig")
+            .field("key_id", &self.key_id)
+            .finish()
+    }
+}
+
+// the cipher is identified by its key id
+impl PartialEq for EncryptionConfig {
+    fn eq(&self, other: &EncryptionConfig) -> bool {
+        self.key_id == other.key_id
+    }
+}
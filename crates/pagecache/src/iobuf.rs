@@ -1,6 +1,9 @@
 use std::{
-    mem::size_of, sync::atomic::AtomicBool, sync::atomic::Ordering::SeqCst,
+    mem::size_of,
+    sync::atomic::AtomicBool,
+    sync::atomic::Ordering::{Relaxed, SeqCst},
     sync::Arc,
+    time::Duration,
 };
 
 use parking_lot::{Condvar, Mutex, RwLock};
@@ -65,6 +68,14 @@ pub(super) struct IoBufs {
     pub(crate) max_reserved_lsn: AtomicLsn,
     pub(crate) max_header_stable_lsn: Arc<AtomicLsn>,
     pub(crate) segment_accountant: Mutex<SegmentAccountant>,
+
+    // The lsn of the latest IO buffer that a flush has claimed to
+    // write once `group_commit_window_us` has passed. Flushes that
+    // find their buffer already claimed wait for that write.
+    group_commit_lsn: AtomicLsn,
+
+    // The number of IO buffers written to the log.
+    pub(crate) log_writes: AtomicU64,
 }
 
 /// `IoBufs` is a set of lock-free buffers for coordinating
@@ -177,6 +188,9 @@ impl IoBufs {
                 snapshot_max_header_stable_lsn,
             )),
             segment_accountant: Mutex::new(segment_accountant),
+
+            group_commit_lsn: AtomicLsn::new(-1),
+            log_writes: AtomicU64::new(0),
         })
     }
 
@@ -381,12 +395,31 @@ impl IoBufs {
 
         let f = &self.config.file;
         io_fail!(self, "buffer write");
-        f.pwrite_all(&data[..total_len], lid)?;
-        if !self.config.temporary {
-            f.sync_all()?;
+        let res = f.pwrite_all(&data[..total_len], lid).and_then(|_| {
+            if self.config.temporary {
+                Ok(())
+            } else {
+                f.sync_all()
+            }
+        });
+        if let Err(e) = res {
+            let e = Error::from(e);
+            if let Error::OutOfSpace = e {
+                self.config.release_reserved_space();
+            }
+
+            // stop writing later buffers, which would leave
+            // a hole in the log where this one belongs.
+            self.config.set_global_error(e.clone());
+            let _ = self.intervals.lock();
+            self.interval_updated.notify_all();
+            return Err(e);
         }
+        self.config.writes_succeeded();
         io_fail!(self, "buffer write post");
 
+        self.log_writes.fetch_add(1, Relaxed);
+
         if total_len > 0 {
             let complete_len = if maxed {
                 let lsn_idx = base_lsn / io_buf_size as Lsn;
@@ -420,6 +453,11 @@ impl IoBufs {
         guard.defer(move || {
             bump_atomic_lsn(&max_header_stable_lsn, stored_max_stable_lsn)
         });
+        // otherwise the bump waits in this thread's local
+        // garbage bag until enough other garbage piles up,
+        // which may never happen on a quiet io thread, and
+        // segments would stop being deactivated until then
+        guard.flush();
         drop(guard);
 
         let current_max_header_stable_lsn =
@@ -525,9 +563,30 @@ pub(crate) fn make_stable(iobufs: &Arc<IoBufs>, lsn: Lsn) -> Result<usize> {
 
         let iobuf = iobufs.current_iobuf();
         let header = iobuf.get_header();
+        let window = iobufs.config.group_commit_window_us;
         if offset(header) == 0 || is_sealed(header) || iobuf.lsn > lsn {
             // nothing to write, don't bother sealing
             // current IO buffer.
+        } else if window > 0
+            && iobufs.group_commit_lsn.fetch_max(iobuf.lsn, SeqCst) >= iobuf.lsn
+        {
+            // another flush is already waiting to write this
+            // buffer, so we wait for it below
+        } else if window > 0 {
+            // give concurrent writers a chance to reserve space
+            // in this buffer, so that a single write and fsync
+            // makes all of them durable
+            std::thread::sleep(Duration::from_micros(window));
+
+            // nobody else will seal this buffer for the flushes
+            // waiting on us, so retry if a reservation races us
+            let mut header = iobuf.get_header();
+            while !is_sealed(header) {
+                maybe_seal_and_write_iobuf(iobufs, &iobuf, header, false)?;
+                header = iobuf.get_header();
+            }
+            stable = iobufs.stable();
+            continue;
         } else {
             maybe_seal_and_write_iobuf(iobufs, &iobuf, header, false)?;
             stable = iobufs.stable();
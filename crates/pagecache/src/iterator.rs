@@ -2,7 +2,7 @@ use std::{collections::BTreeMap, io};
 
 use rayon::prelude::*;
 
-use self::reader::LogReader;
+use self::{logger::MessageHeader, reader::LogReader};
 
 use super::*;
 
@@ -214,6 +214,140 @@ impl LogIter {
     }
 }
 
+/// A message read from the log by `Config::dump_log`
+/// without being applied to any page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawLogEntry {
+    /// What the message records.
+    pub kind: MessageKind,
+    /// The page that the message belongs to.
+    pub pid: PageId,
+    /// The log sequence number that the message was written at.
+    pub lsn: Lsn,
+    /// Where the message begins in the log file.
+    pub lid: LogId,
+    /// The length of the message on disk, excluding its header.
+    pub len: usize,
+    /// Whether the message's checksum matched its contents.
+    /// The rest of a segment is skipped after a message that
+    /// fails its checksum, because its length can't be trusted.
+    pub checksum_valid: bool,
+}
+
+/// An iterator over the messages in the log, in lsn order,
+/// created by `Config::dump_log`. It only reads the log file,
+/// so it can be used on a damaged system without making the
+/// damage worse.
+pub struct RawLogIter {
+    config: Config,
+    from_lsn: Lsn,
+    file_len: u64,
+    segments: std::vec::IntoIter<(Lsn, LogId)>,
+    // the base of the segment being read, and the
+    // lsn of the next message in it
+    cursor: Option<(LogId, Lsn)>,
+}
+
+impl RawLogIter {
+    pub(crate) fn new(config: Config, from_lsn: Lsn) -> Result<RawLogIter> {
+        let segment_len = config.io_buf_size as LogId;
+        let file_len = config.file.metadata()?.len();
+
+        let mut segments = BTreeMap::new();
+        let mut base = 0;
+        while base + SEG_HEADER_LEN as LogId <= file_len {
+            let header = config.file.read_segment_header(base)?;
+            let ends_after_start = header.lsn + segment_len as Lsn > from_lsn;
+            if header.ok
+                && ends_after_start
+                && header.lsn % segment_len as Lsn == 0
+            {
+                segments.insert(header.lsn, base);
+            }
+            base += segment_len;
+        }
+
+        Ok(RawLogIter {
+            config,
+            from_lsn,
+            file_len,
+            segments: segments.into_iter().collect::<Vec<_>>().into_iter(),
+            cursor: None,
+        })
+    }
+}
+
+impl Iterator for RawLogIter {
+    type Item = Result<RawLogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let segment_len = self.config.io_buf_size;
+
+        loop {
+            let (base, lsn) = match self.cursor {
+                Some(cursor) => cursor,
+                None => {
+                    let (segment_lsn, base) = self.segments.next()?;
+                    self.cursor =
+                        Some((base, segment_lsn + SEG_HEADER_LEN as Lsn));
+                    continue;
+                }
+            };
+
+            // the last segment may not have been written out
+            // to its full length yet
+            let lid = base + (lsn % segment_len as Lsn) as LogId;
+            if !valid_entry_offset(lid, segment_len) || lid >= self.file_len {
+                self.cursor = None;
+                continue;
+            }
+
+            // whatever happens below, we only keep reading
+            // this segment after a message that is intact
+            self.cursor = None;
+
+            let f = &self.config.file;
+            let mut header_buf = [0; MSG_HEADER_LEN];
+            if let Err(e) = f.pread_exact(&mut header_buf, lid) {
+                return Some(Err(e.into()));
+            }
+            let header = MessageHeader::from(header_buf);
+
+            if header_buf == [0; MSG_HEADER_LEN] || header.lsn != lsn {
+                // the unwritten rest of the segment, or stale
+                // messages left over from before it was reused
+                continue;
+            }
+
+            let checksum_valid = match f.read_message(lid, lsn, &self.config) {
+                Ok(LogRead::Pad(_)) => continue,
+                Ok(LogRead::Corrupted(_)) => false,
+                Ok(_) => true,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let len = header.len as usize;
+            if checksum_valid {
+                let next_lsn = lsn + (MSG_HEADER_LEN + len) as Lsn;
+                self.cursor = Some((base, next_lsn));
+            }
+
+            if lsn < self.from_lsn {
+                continue;
+            }
+
+            return Some(Ok(RawLogEntry {
+                kind: header.kind,
+                pid: header.pid,
+                lsn,
+                lid,
+                len,
+                checksum_valid,
+            }));
+        }
+    }
+}
+
 fn valid_entry_offset(lid: LogId, segment_len: usize) -> bool {
     let seg_start = lid / segment_len as LogId * segment_len as LogId;
 
@@ -0,0 +1,182 @@
+//! The layouts of configuration files written before they
+//! were persisted field by field. These files are a bare
+//! bincode struct, so they can only be read with the exact
+//! field list and field types that wrote them. These
+//! definitions must never change, even when the
+//! `ConfigBuilder` fields or the types they use do.
+
+use std::path::PathBuf;
+
+use super::*;
+
+// bincode encodes an enum as the u32 index of its variant
+fn segment_mode(variant: u32) -> Option<SegmentMode> {
+    match variant {
+        0 => Some(SegmentMode::Linear),
+        1 => Some(SegmentMode::Gc),
+        _ => None,
+    }
+}
+
+fn recovery_mode(variant: u32) -> Option<RecoveryMode> {
+    match variant {
+        0 => Some(RecoveryMode::Fast),
+        1 => Some(RecoveryMode::Verify),
+        2 => Some(RecoveryMode::Repair),
+        _ => None,
+    }
+}
+
+/// The fields of every released version that wrote a
+/// bare `ConfigBuilder`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ConfigV0 {
+    cache_capacity: u64,
+    flush_every_ms: Option<u64>,
+    io_buf_size: usize,
+    page_consolidation_threshold: usize,
+    path: PathBuf,
+    read_only: bool,
+    segment_cleanup_threshold: f64,
+    segment_cleanup_skew: usize,
+    segment_mode: u32,
+    snapshot_after_ops: u64,
+    snapshot_path: Option<PathBuf>,
+    temporary: bool,
+    use_compression: bool,
+    compression_factor: i32,
+    print_profile_on_drop: bool,
+    idgen_persist_interval: u64,
+    async_io: bool,
+    version: (usize, usize),
+}
+
+/// The fields of the last bare `ConfigBuilder`, just before
+/// the field by field encoding was introduced.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ConfigV1 {
+    cache_capacity: u64,
+    flush_every_ms: Option<u64>,
+    min_flush_interval_ms: Option<u64>,
+    max_flush_delay_ms: Option<u64>,
+    io_buf_size: usize,
+    page_consolidation_threshold: usize,
+    path: PathBuf,
+    read_only: bool,
+    segment_cleanup_threshold: f64,
+    segment_cleanup_skew: usize,
+    segment_mode: u32,
+    snapshot_after_ops: u64,
+    snapshot_path: Option<PathBuf>,
+    temporary: bool,
+    use_compression: bool,
+    compression_factor: i32,
+    print_profile_on_drop: bool,
+    idgen_persist_interval: u64,
+    async_io: bool,
+    cas_backoff: BackoffV1,
+    replication_backlog: usize,
+    // no longer a setting, but still part of the layout
+    write_buffer_limit: usize,
+    scan_prefetch: usize,
+    merge_operator_name: Option<String>,
+    recovery_mode: u32,
+    use_bloom_filter: bool,
+    version: (usize, usize),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct BackoffV1 {
+    spin_limit: u32,
+    base_delay_us: u64,
+    max_delay_us: u64,
+}
+
+impl ConfigV0 {
+    fn into_config(self) -> Option<ConfigBuilder> {
+        Some(ConfigBuilder {
+            cache_capacity: self.cache_capacity,
+            flush_every_ms: self.flush_every_ms,
+            io_buf_size: self.io_buf_size,
+            page_consolidation_threshold: self.page_consolidation_threshold,
+            path: self.path,
+            read_only: self.read_only,
+            segment_cleanup_threshold: self.segment_cleanup_threshold,
+            segment_cleanup_skew: self.segment_cleanup_skew,
+            segment_mode: segment_mode(self.segment_mode)?,
+            snapshot_after_ops: self.snapshot_after_ops,
+            snapshot_path: self.snapshot_path,
+            temporary: self.temporary,
+            use_compression: self.use_compression,
+            compression_factor: self.compression_factor,
+            print_profile_on_drop: self.print_profile_on_drop,
+            idgen_persist_interval: self.idgen_persist_interval,
+            async_io: self.async_io,
+            version: self.version,
+            ..ConfigBuilder::default()
+        })
+    }
+}
+
+impl ConfigV1 {
+    fn into_config(self) -> Option<ConfigBuilder> {
+        Some(ConfigBuilder {
+            cache_capacity: self.cache_capacity,
+            flush_every_ms: self.flush_every_ms,
+            min_flush_interval_ms: self.min_flush_interval_ms,
+            max_flush_delay_ms: self.max_flush_delay_ms,
+            io_buf_size: self.io_buf_size,
+            page_consolidation_threshold: self.page_consolidation_threshold,
+            path: self.path,
+            read_only: self.read_only,
+            segment_cleanup_threshold: self.segment_cleanup_threshold,
+            segment_cleanup_skew: self.segment_cleanup_skew,
+            segment_mode: segment_mode(self.segment_mode)?,
+            snapshot_after_ops: self.snapshot_after_ops,
+            snapshot_path: self.snapshot_path,
+            temporary: self.temporary,
+            use_compression: self.use_compression,
+            compression_factor: self.compression_factor,
+            print_profile_on_drop: self.print_profile_on_drop,
+            idgen_persist_interval: self.idgen_persist_interval,
+            async_io: self.async_io,
+            cas_backoff: BackoffConfig {
+                spin_limit: self.cas_backoff.spin_limit,
+                base_delay_us: self.cas_backoff.base_delay_us,
+                max_delay_us: self.cas_backoff.max_delay_us,
+            },
+            replication: self.replication_backlog > 0,
+            scan_prefetch: self.scan_prefetch,
+            merge_operator_name: self.merge_operator_name,
+            recovery_mode: recovery_mode(self.recovery_mode)?,
+            use_bloom_filter: self.use_bloom_filter,
+            version: self.version,
+            ..ConfigBuilder::default()
+        })
+    }
+}
+
+// accepts `bytes` as a `T` only if they are exactly its
+// encoding, since a shorter layout may happen to decode
+// from the prefix of a longer one
+fn decode_exact<T>(bytes: &[u8]) -> Option<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let decoded: T = deserialize(bytes).ok()?;
+    if serialize(&decoded).ok()? == bytes {
+        Some(decoded)
+    } else {
+        None
+    }
+}
+
+/// Decodes a configuration file written before the field
+/// by field encoding, returning `None` if it matches none
+/// of the layouts that were ever written.
+pub(crate) fn decode_legacy_config(bytes: &[u8]) -> Option<ConfigBuilder> {
+    if let Some(v1) = decode_exact::<ConfigV1>(bytes) {
+        return v1.into_config();
+    }
+    decode_exact::<ConfigV0>(bytes)?.into_config()
+}
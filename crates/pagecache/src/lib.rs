@@ -18,13 +18,16 @@ macro_rules! maybe_fail {
     };
 }
 
+mod backoff;
 mod blob_io;
 mod config;
 mod constants;
 mod diskptr;
 mod ds;
+mod encryption;
 mod iobuf;
 mod iterator;
+mod legacy_config;
 mod map;
 mod materializer;
 mod meta;
@@ -76,11 +79,12 @@ use self::logger::{MessageHeader, SegmentHeader};
 use self::metrics::uptime;
 
 use self::{
-    blob_io::{gc_blobs, read_blob, remove_blob, write_blob},
+    blob_io::{gc_blobs, read_blob, remove_blob, write_blob, BlobFds},
     config::PersistedConfig,
     constants::{BATCH_MANIFEST_PID, CONFIG_PID, COUNTER_PID, META_PID},
     iobuf::{IoBuf, IoBufs},
     iterator::{raw_segment_iter_from, LogIter},
+    legacy_config::decode_legacy_config,
     metrics::{clock, measure},
     pagecache::Update,
     parallel_io::Pio,
@@ -91,9 +95,12 @@ use self::{
 };
 
 pub use self::{
-    config::{Config, ConfigBuilder},
+    backoff::{BackoffConfig, CasBackoff},
+    config::{Config, ConfigBuilder, InvariantPolicy, SplitPolicy},
     diskptr::DiskPtr,
     ds::{node_from_frag_vec, Lru, Node, PageTable, Stack, StackIter, VecSet},
+    encryption::{Cipher, EncryptionConfig, NONCE_LEN},
+    iterator::{RawLogEntry, RawLogIter},
     logger::{Log, LogRead},
     map::{FastMap1, FastMap4, FastMap8, FastSet1, FastSet4, FastSet8},
     materializer::Materializer,
@@ -103,6 +110,7 @@ pub use self::{
     reservation::Reservation,
     result::{CasResult, Error, Result},
     segment::SegmentMode,
+    snapshot::{RecoveryMode, SnapshotInfo},
     tx::{Tx, TxError, TxResult},
 };
 
@@ -132,10 +140,11 @@ pub type Lsn = i64;
 /// A page identifier.
 pub type PageId = u64;
 
-#[doc(hidden)]
+/// The kind of a message in the log, as reported
+/// by `Config::dump_log`.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(u8)]
-enum MessageKind {
+pub enum MessageKind {
     /// The EVIL_BYTE is written as a canary to help
     /// detect torn writes.
     Corrupted = 0,
@@ -255,7 +264,8 @@ impl From<MessageKind> for LogKind {
     }
 }
 
-pub(crate) fn crc32(buf: &[u8]) -> u32 {
+#[doc(hidden)]
+pub fn crc32(buf: &[u8]) -> u32 {
     let mut hasher = crc32fast::Hasher::new();
     hasher.update(&buf);
     hasher.finalize()
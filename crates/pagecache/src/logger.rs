@@ -114,6 +114,51 @@ impl Log {
         self.iobufs.stable()
     }
 
+    /// returns the number of reserved bytes that have
+    /// not yet been written to disk
+    pub fn unflushed_bytes(&self) -> u64 {
+        let max_reserved = self.iobufs.max_reserved_lsn.load(SeqCst);
+        let stable = self.iobufs.stable();
+        std::cmp::max(0, max_reserved - stable) as u64
+    }
+
+    /// returns the proportion of the current IO buffer's
+    /// room for writes that has been reserved, between 0
+    /// and 1. a sealed buffer counts as full, since writers
+    /// wait for the next one to be installed.
+    pub fn write_pressure(&self) -> f64 {
+        let iobuf = self.iobufs.current_iobuf();
+        let header = iobuf.get_header();
+        if iobuf::is_sealed(header) {
+            return 1.;
+        }
+
+        // the first buffer of a segment starts with its header
+        let start = if iobuf.lsn % self.config.io_buf_size as Lsn == 0 {
+            SEG_HEADER_LEN
+        } else {
+            0
+        };
+        let used = iobuf::offset(header).saturating_sub(start);
+        let room = iobuf.capacity.saturating_sub(start);
+        if room == 0 {
+            return 1.;
+        }
+
+        (used as f64 / room as f64).min(1.)
+    }
+
+    /// returns the highest log sequence number that has
+    /// been reserved, whether or not it is on disk yet
+    pub fn max_reserved_lsn(&self) -> Lsn {
+        self.iobufs.max_reserved_lsn.load(SeqCst)
+    }
+
+    /// returns the number of IO buffers written to the log
+    pub fn log_writes(&self) -> u64 {
+        self.iobufs.log_writes.load(SeqCst)
+    }
+
     /// blocks until the specified log sequence number has
     /// been made stable on disk. Returns the number of
     /// bytes written during this call.
@@ -140,7 +185,7 @@ impl Log {
         let lsn_buf: [u8; std::mem::size_of::<BlobPointer>()] =
             u64_to_arr(blob_ptr as u64);
 
-        self.reserve_inner(LogKind::Replace, pid, &lsn_buf, true)
+        self.reserve_inner(LogKind::Replace, pid, &lsn_buf, true, false, false)
     }
 
     /// Tries to claim a reservation for writing a buffer to a
@@ -154,6 +199,22 @@ impl Log {
         log_kind: LogKind,
         pid: PageId,
         raw_buf: &[u8],
+    ) -> Result<Reservation<'a>> {
+        self.reserve_as(log_kind, pid, raw_buf, false, false)
+    }
+
+    /// Like `reserve`, but if `force_blob` is set, the buffer
+    /// is written to a separate blob file even if it would
+    /// fit inline in the log. If `nonblocking` is set,
+    /// `Error::WouldBlock` is returned instead of waiting for
+    /// the current IO buffer to make room.
+    pub(super) fn reserve_as<'a>(
+        &'a self,
+        log_kind: LogKind,
+        pid: PageId,
+        raw_buf: &[u8],
+        force_blob: bool,
+        nonblocking: bool,
     ) -> Result<Reservation<'a>> {
         let mut _compressed: Option<Vec<u8>> = None;
         let mut buf = raw_buf;
@@ -173,7 +234,17 @@ impl Log {
             }
         }
 
-        self.reserve_inner(log_kind, pid, buf, false)
+        // batch manifests are filled in after they are
+        // reserved, so they are left unencrypted
+        let encrypted;
+        if let Some(ref encryption) = self.config.encryption {
+            if pid != BATCH_MANIFEST_PID {
+                encrypted = encryption.encrypt(buf);
+                buf = &encrypted;
+            }
+        }
+
+        self.reserve_inner(log_kind, pid, buf, false, force_blob, nonblocking)
     }
 
     fn reserve_inner<'a>(
@@ -182,6 +253,8 @@ impl Log {
         pid: PageId,
         buf: &[u8],
         is_blob_rewrite: bool,
+        force_blob: bool,
+        nonblocking: bool,
     ) -> Result<Reservation<'a>> {
         let _measure = Measure::new(&M.reserve_lat);
 
@@ -193,7 +266,7 @@ impl Log {
             / MINIMUM_ITEMS_PER_SEGMENT)
             - SEG_HEADER_LEN;
 
-        let over_blob_threshold = total_buf_len > max_buf_size;
+        let over_blob_threshold = force_blob || total_buf_len > max_buf_size;
 
         assert!(!(over_blob_threshold && is_blob_rewrite));
 
@@ -256,6 +329,10 @@ impl Log {
 
             // skip if already sealed
             if iobuf::is_sealed(header) {
+                if nonblocking {
+                    return Err(Error::WouldBlock);
+                }
+
                 // already sealed, start over and hope cur
                 // has already been bumped by sealer.
                 trace_once!("io buffer already sealed, spinning");
@@ -270,6 +347,13 @@ impl Log {
             let prospective_size = buf_offset + inline_buf_len;
             let would_overflow = prospective_size > iobuf.capacity;
             if would_overflow {
+                if nonblocking {
+                    // sealing the buffer may mean writing it
+                    // out, which is what the caller wants to
+                    // avoid waiting for
+                    return Err(Error::WouldBlock);
+                }
+
                 // This buffer is too full to accept our write!
                 // Try to seal the buffer, and maybe write it if
                 // there are zero writers.
@@ -289,6 +373,9 @@ impl Log {
 
             // check for maxed out IO buffer writers
             if iobuf::n_writers(bumped_offset) == iobuf::MAX_WRITERS {
+                if nonblocking {
+                    return Err(Error::WouldBlock);
+                }
                 trace_once!(
                     "spinning because our buffer has {} writers already",
                     iobuf::MAX_WRITERS
@@ -8,4 +8,39 @@ pub trait Materializer:
     /// Used to merge chains of partial pages into a form
     /// that is useful for the `PageCache` owner.
     fn merge(&mut self, other: &Self);
+
+    /// Used to check a fragment that was read back from
+    /// disk. Fragments for which this returns `false` are
+    /// reported as `Error::Corruption` rather than being
+    /// handed to the `PageCache` owner.
+    fn is_well_formed(&self) -> bool {
+        true
+    }
+
+    /// Used to check that `other` may be merged into this
+    /// fragment before calling `merge`. When this returns
+    /// `false` and `ConfigBuilder::on_invariant_violation` is
+    /// `InvariantPolicy::Error`, the merge is skipped and the
+    /// read that needed it fails with `Error::Corruption`.
+    fn can_merge(&self, _other: &Self) -> bool {
+        true
+    }
+
+    /// Fragments for which this returns `true` are always
+    /// written to a separate blob file when they are
+    /// allocated or replaced, rather than only when they
+    /// are too large to be written inline in the log.
+    fn stored_as_blob(&self) -> bool {
+        false
+    }
+
+    /// Fragments for which this returns `true` are always
+    /// written to the log as an append of their own when they
+    /// are linked, so that `PageCache::appends_since` can read
+    /// them back. When the page is due to be consolidated, it
+    /// is consolidated right after the append, rather than the
+    /// fragment only being written as part of the new page.
+    fn logged_as_append(&self) -> bool {
+        false
+    }
 }
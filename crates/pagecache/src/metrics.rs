@@ -109,6 +109,8 @@ pub struct Metrics {
     pub deserialize: Histo,
     pub compress: Histo,
     pub decompress: Histo,
+    pub encrypt: Histo,
+    pub decrypt: Histo,
     pub make_stable: Histo,
     pub assign_offset: Histo,
     pub assign_spinloop: Histo,
@@ -120,6 +122,7 @@ pub struct Metrics {
     pub written_bytes: Histo,
     pub read: Histo,
     pub tree_loops: CachePadded<AtomicUsize>,
+    pub cas_retries: CachePadded<AtomicUsize>,
     pub log_reservations: CachePadded<AtomicUsize>,
     pub log_reservation_attempts: CachePadded<AtomicUsize>,
     pub accountant_lock: Histo,
@@ -141,6 +144,11 @@ impl Metrics {
         self.tree_loops.fetch_add(1, Relaxed);
     }
 
+    #[inline]
+    pub fn cas_retried(&self) {
+        self.cas_retries.fetch_add(1, Relaxed);
+    }
+
     #[inline]
     pub fn log_reservation_attempted(&self) {
         self.log_reservation_attempts.fetch_add(1, Relaxed);
@@ -253,6 +261,7 @@ impl Metrics {
             lat("rev scan", &self.tree_reverse_scan),
         ]);
         println!("tree contention loops: {}", self.tree_loops.load(Acquire));
+        println!("page cas retries: {}", self.cas_retries.load(Acquire));
         println!(
             "tree split success rates: child({}/{}) parent({}/{}) root({}/{})",
             self.tree_child_split_success.load(Acquire),
@@ -277,12 +286,14 @@ impl Metrics {
         ]);
 
         println!("{}", std::iter::repeat("-").take(134).collect::<String>());
-        println!("serialization and compression:");
+        println!("serialization, compression, and encryption:");
         p(vec![
             lat("serialize", &self.serialize),
             lat("deserialize", &self.deserialize),
             lat("compress", &self.compress),
             lat("decompress", &self.decompress),
+            lat("encrypt", &self.encrypt),
+            lat("decrypt", &self.decrypt),
         ]);
 
         println!("{}", std::iter::repeat("-").take(134).collect::<String>());
@@ -354,6 +365,8 @@ impl Metrics {
 
     pub fn tree_looped(&self) {}
 
+    pub fn cas_retried(&self) {}
+
     pub fn log_looped(&self) {}
 
     pub fn print_profile(&self) {}
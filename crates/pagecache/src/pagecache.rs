@@ -1,6 +1,11 @@
-use std::{borrow::Cow, collections::BinaryHeap, ops::Deref, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{BinaryHeap, HashMap},
+    ops::Deref,
+    sync::{atomic::AtomicUsize, Arc},
+};
 
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 
 use super::*;
 
@@ -24,6 +29,11 @@ where
     pub fn last_lsn(&self) -> Lsn {
         unsafe { self.cached_ptr.deref().deref().1.lsn }
     }
+
+    /// The location in storage of the head of this page
+    pub fn last_disk_ptr(&self) -> DiskPtr {
+        unsafe { self.cached_ptr.deref().deref().1.ptr }
+    }
 }
 
 unsafe impl<'g, P> Send for PagePtr<'g, P> where P: Send {}
@@ -190,7 +200,17 @@ where
     free: Arc<Mutex<BinaryHeap<PageId>>>,
     log: Log,
     lru: Lru,
+    reserved_caches: RwLock<HashMap<PageId, Arc<Lru>>>,
     updates: AtomicU64,
+    cas_retries: AtomicU64,
+    prefetches: AtomicU64,
+    page_ins: AtomicU64,
+    rewrites: AtomicU64,
+    fetches: AtomicU64,
+    consolidations: AtomicU64,
+    freed: Arc<AtomicU64>,
+    flushes: AtomicU64,
+    consolidation_threshold: AtomicUsize,
     last_snapshot: Arc<Mutex<Option<Snapshot>>>,
     idgen: Arc<AtomicU64>,
     idgen_persists: Arc<AtomicU64>,
@@ -275,6 +295,7 @@ where
 
         let cache_capacity = config.cache_capacity;
         let lru = Lru::new(cache_capacity);
+        let consolidation_threshold = config.page_consolidation_threshold;
 
         let mut pc = PageCache {
             config: config.clone(),
@@ -283,7 +304,17 @@ where
             free: Arc::new(Mutex::new(BinaryHeap::new())),
             log: Log::start(config, snapshot.clone())?,
             lru,
+            reserved_caches: RwLock::new(HashMap::new()),
             updates: AtomicU64::new(0),
+            cas_retries: AtomicU64::new(0),
+            prefetches: AtomicU64::new(0),
+            page_ins: AtomicU64::new(0),
+            rewrites: AtomicU64::new(0),
+            fetches: AtomicU64::new(0),
+            consolidations: AtomicU64::new(0),
+            freed: Arc::new(AtomicU64::new(0)),
+            flushes: AtomicU64::new(0),
+            consolidation_threshold: AtomicUsize::new(consolidation_threshold),
             last_snapshot: Arc::new(Mutex::new(Some(snapshot))),
             idgen_persist_mu: Arc::new(Mutex::new(())),
             idgen: Arc::new(AtomicU64::new(0)),
@@ -410,7 +441,11 @@ where
     /// Flushes any pending IO buffers to disk to ensure durability.
     /// Returns the number of bytes written during this call.
     pub fn flush(&self) -> Result<usize> {
-        self.log.flush()
+        let written = self.log.flush()?;
+        if written > 0 {
+            self.flushes.fetch_add(1, Relaxed);
+        }
+        Ok(written)
     }
 
     /// Begins a transaction.
@@ -584,8 +619,15 @@ where
         let new_ptr = self.cas_page(pid, old, Update::Free, false, tx)?;
 
         if new_ptr.is_ok() {
+            if self.reserved_caches.read().contains_key(&pid) {
+                self.reserved_caches.write().remove(&pid);
+            }
+
             let free = self.free.clone();
+            let freed = self.freed.clone();
             tx.guard.defer(move || {
+                freed.fetch_add(1, Relaxed);
+
                 let mut free = free.lock();
                 // panic if we double-freed a page
                 if free.iter().any(|e| e == &pid) {
@@ -604,10 +646,34 @@ where
     /// `Err(None)` if the page no longer exists. Returns `Err(Some(actual_key))`
     /// if the atomic append fails.
     pub fn link<'g>(
+        &'g self,
+        pid: PageId,
+        old: PagePtr<'g, P>,
+        new: P,
+        tx: &'g Tx<P>,
+    ) -> Result<CasResult<'g, P, P>> {
+        self.link_inner(pid, old, new, false, tx)
+    }
+
+    /// Like `link`, but returns `Error::WouldBlock` instead of
+    /// waiting when the current IO buffer has no room for the
+    /// new fragment, in which case the page is left unchanged.
+    pub fn try_link<'g>(
+        &'g self,
+        pid: PageId,
+        old: PagePtr<'g, P>,
+        new: P,
+        tx: &'g Tx<P>,
+    ) -> Result<CasResult<'g, P, P>> {
+        self.link_inner(pid, old, new, true, tx)
+    }
+
+    fn link_inner<'g>(
         &'g self,
         pid: PageId,
         mut old: PagePtr<'g, P>,
         new: P,
+        nonblocking: bool,
         tx: &'g Tx<P>,
     ) -> Result<CasResult<'g, P, P>> {
         let _measure = Measure::new(&M.link_page);
@@ -622,7 +688,9 @@ where
         let head = unsafe { head_ptr.deref().head(&tx.guard) };
         let stack_iter = StackIter::from_ptr(head, &tx.guard);
         let stack_len = stack_iter.size_hint().1.unwrap();
-        if stack_len >= self.config.page_consolidation_threshold {
+        let consolidate =
+            stack_len >= self.consolidation_threshold.load(Relaxed);
+        if consolidate && !new.logged_as_append() {
             let current_frag =
                 if let Some((current_ptr, frag, _sz)) = self.get(pid, tx)? {
                     if old.ts != current_ptr.ts
@@ -641,15 +709,21 @@ where
             let update: P = {
                 let _measure = Measure::new(&M.merge_page);
 
+                self.check_merge(current_frag, &new, old.last_disk_ptr())?;
                 let mut update = current_frag.clone();
                 update.merge(&new);
                 update
             };
 
-            return self.replace(pid, old, update, tx);
+            let res = self.replace_inner(pid, old, update, nonblocking, tx)?;
+            if res.is_ok() {
+                self.consolidations.fetch_add(1, Relaxed);
+            }
+            return Ok(res);
         }
 
         let bytes = measure(&M.serialize, || serialize(&new).unwrap());
+        let force_blob = new.stored_as_blob();
 
         let mut new = {
             let update = Update::Append(new);
@@ -673,9 +747,16 @@ where
             Some(Owned::new(node))
         };
 
+        let mut backoff = CasBackoff::new(self.config.cas_backoff);
+
         loop {
-            let log_reservation =
-                self.log.reserve(LogKind::Append, pid, &bytes)?;
+            let log_reservation = self.log.reserve_as(
+                LogKind::Append,
+                pid,
+                &bytes,
+                force_blob,
+                nonblocking,
+            )?;
 
             let lsn = log_reservation.lsn();
             let ptr = log_reservation.ptr();
@@ -762,11 +843,17 @@ where
                         self.advance_snapshot()?;
                     }
 
-                    return Ok(Ok(PagePtr { cached_ptr, ts }));
+                    let linked = PagePtr { cached_ptr, ts };
+                    if consolidate {
+                        self.consolidate(pid, nonblocking, tx)?;
+                    }
+
+                    return Ok(Ok(linked));
                 }
                 Err((actual_ptr, returned_new)) => {
                     trace!("link of pid {} failed", pid);
                     log_reservation.abort()?;
+                    self.record_cas_retry();
                     let actual_ts = unsafe { actual_ptr.deref().1.ts };
                     if actual_ts != old.ts {
                         let returned_update = returned_new.0.clone().unwrap();
@@ -784,12 +871,45 @@ where
                             cached_ptr: actual_ptr,
                             ts: actual_ts,
                         };
+                        backoff.snooze();
                     }
                 }
             }
         }
     }
 
+    // consolidates a page that a fragment which is logged as
+    // an append of its own was just linked to. the link has
+    // already succeeded, so losing a race or finding no room
+    // in the IO buffers just leaves the page to be
+    // consolidated by a later link.
+    fn consolidate(
+        &self,
+        pid: PageId,
+        nonblocking: bool,
+        tx: &Tx<P>,
+    ) -> Result<()> {
+        let (current_ptr, current_frag) = match self.get(pid, tx)? {
+            Some((ptr, frag, _sz)) => (ptr, frag.clone()),
+            None => return Ok(()),
+        };
+
+        match self.replace_inner(
+            pid,
+            current_ptr,
+            current_frag,
+            nonblocking,
+            tx,
+        ) {
+            Ok(Ok(_)) => {
+                self.consolidations.fetch_add(1, Relaxed);
+                Ok(())
+            }
+            Ok(Err(_)) | Err(Error::WouldBlock) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Replace an existing page with a different set of `PageFrag`s.
     /// Returns `Ok(new_key)` if the operation was successful. Returns
     /// `Err(None)` if the page no longer exists. Returns `Err(Some(actual_key))`
@@ -800,13 +920,30 @@ where
         old: PagePtr<'g, P>,
         new: P,
         tx: &'g Tx<P>,
+    ) -> Result<CasResult<'g, P, P>> {
+        self.replace_inner(pid, old, new, false, tx)
+    }
+
+    fn replace_inner<'g>(
+        &self,
+        pid: PageId,
+        old: PagePtr<'g, P>,
+        new: P,
+        nonblocking: bool,
+        tx: &'g Tx<P>,
     ) -> Result<CasResult<'g, P, P>> {
         let _measure = Measure::new(&M.replace_page);
 
         trace!("replacing pid {} with {:?}", pid, new);
 
-        let result =
-            self.cas_page(pid, old, Update::Compact(new), false, tx)?;
+        let result = self.cas_page_inner(
+            pid,
+            old,
+            Update::Compact(new),
+            false,
+            nonblocking,
+            tx,
+        )?;
 
         let to_clean = self.log.with_sa(|sa| sa.clean(pid));
 
@@ -848,6 +985,8 @@ where
             Some(p) => p,
         };
 
+        self.rewrites.fetch_add(1, Relaxed);
+
         debug_delay();
         let head = unsafe { head_ptr.deref().head(&tx.guard) };
         let stack_iter = StackIter::from_ptr(head, &tx.guard);
@@ -1002,11 +1141,23 @@ where
     }
 
     fn cas_page<'g>(
+        &self,
+        pid: PageId,
+        old: PagePtr<'g, P>,
+        update: Update<P>,
+        is_rewrite: bool,
+        tx: &'g Tx<P>,
+    ) -> Result<CasResult<'g, P, Update<P>>> {
+        self.cas_page_inner(pid, old, update, is_rewrite, false, tx)
+    }
+
+    fn cas_page_inner<'g>(
         &self,
         pid: PageId,
         mut old: PagePtr<'g, P>,
         update: Update<P>,
         is_rewrite: bool,
+        nonblocking: bool,
         tx: &'g Tx<P>,
     ) -> Result<CasResult<'g, P, Update<P>>> {
         trace!(
@@ -1033,10 +1184,23 @@ where
             other => serialize(other.as_frag()).unwrap(),
         };
         drop(serialize_latency);
+        let force_blob = match &update {
+            Update::Append(frag) | Update::Compact(frag) => {
+                frag.stored_as_blob()
+            }
+            _ => false,
+        };
         let mut update_opt = Some(update);
+        let mut backoff = CasBackoff::new(self.config.cas_backoff);
 
         loop {
-            let log_reservation = self.log.reserve(log_kind, pid, &bytes)?;
+            let log_reservation = self.log.reserve_as(
+                log_kind,
+                pid,
+                &bytes,
+                force_blob,
+                nonblocking,
+            )?;
             let lsn = log_reservation.lsn();
             let new_ptr = log_reservation.ptr();
 
@@ -1094,6 +1258,7 @@ where
                 Err((actual_ptr, returned_entry)) => {
                     trace!("cas_page failed on pid {}", pid);
                     log_reservation.abort()?;
+                    self.record_cas_retry();
 
                     let returned_update =
                         returned_entry.into_box().inner.0.take().unwrap();
@@ -1119,6 +1284,7 @@ where
                         ts: old.ts,
                     };
                     update_opt = Some(returned_update);
+                    backoff.snooze();
                 }
             } // match cas result
         } // loop
@@ -1285,6 +1451,8 @@ where
             ));
         }
 
+        self.fetches.fetch_add(1, Relaxed);
+
         let head_ptr = match self.inner.get(pid, &tx.guard) {
             None => return Ok(None),
             Some(p) => p,
@@ -1329,8 +1497,12 @@ where
                 if let Some(base_idx) = base_idx {
                     let mut base =
                         entries[base_idx].0.as_ref().unwrap().as_frag().clone();
-                    for (append, _) in entries[0..base_idx].iter().rev() {
-                        base.merge(append.as_ref().unwrap().as_frag());
+                    for (append, cache_info) in
+                        entries[0..base_idx].iter().rev()
+                    {
+                        let frag = append.as_ref().unwrap().as_frag();
+                        self.check_merge(&base, frag, cache_info.ptr)?;
+                        base.merge(frag);
                     }
                     Some(base)
                 } else {
@@ -1381,8 +1553,11 @@ where
 
             let mut base = successes.pop().unwrap().into_owned();
 
-            while let Some(frag) = successes.pop() {
-                base.merge(&frag);
+            for (frag, (_, cache_info)) in
+                successes.iter().rev().zip(entries.iter().rev().skip(1))
+            {
+                self.check_merge(&base, frag, cache_info.ptr)?;
+                base.merge(frag);
             }
 
             base
@@ -1408,8 +1583,16 @@ where
         if let Ok(new_ptr) = res {
             trace!("fix-up for pid {} succeeded", pid);
 
-            // possibly evict an item now that our cache has grown
-            let to_evict = self.lru.accessed(pid, total_page_size);
+            self.page_ins.fetch_add(1, Relaxed);
+
+            // possibly evict an item now that our cache has grown.
+            // pages with a reserved cache only compete with each
+            // other for space.
+            let reserved = self.reserved_caches.read().get(&pid).cloned();
+            let to_evict = match reserved {
+                Some(lru) => lru.accessed(pid, total_page_size),
+                None => self.lru.accessed(pid, total_page_size),
+            };
             trace!("accessed pid {} -> paging out pids {:?}", pid, to_evict);
             if !to_evict.is_empty() {
                 self.page_out(to_evict, tx)?;
@@ -1442,6 +1625,28 @@ where
         self.log.stable_offset()
     }
 
+    /// The highest Lsn that has been reserved in the log,
+    /// which a flush would need to make stable for all
+    /// writes so far to be durable.
+    pub fn max_reserved_lsn(&self) -> Lsn {
+        self.log.max_reserved_lsn()
+    }
+
+    /// Returns the number of logged bytes that are
+    /// awaiting a flush.
+    pub fn unflushed_bytes(&self) -> u64 {
+        self.log.unflushed_bytes()
+    }
+
+    /// Returns the proportion of the current IO buffer
+    /// that is taken up by writes, between 0 and 1. Once
+    /// a write no longer fits, it has to wait for the
+    /// buffer to be sealed and replaced, and `try_link`
+    /// returns `Error::WouldBlock` instead.
+    pub fn write_pressure(&self) -> f64 {
+        self.log.write_pressure()
+    }
+
     /// Blocks until the provided Lsn is stable on disk,
     /// triggering necessary flushes in the process.
     /// Returns the number of bytes written during
@@ -1450,6 +1655,225 @@ where
         self.log.make_stable(lsn)
     }
 
+    /// Returns the fragments that were linked to pages by
+    /// appends written to the log after `lsn`, in log order,
+    /// along with the lsn and location of each append. Every
+    /// link that completed before this is called is included,
+    /// but only fragments that return `true` from
+    /// `Materializer::logged_as_append` are guaranteed to be
+    /// written as appends of their own.
+    ///
+    /// Returns an `Error::Unsupported` if any of the log after
+    /// `lsn` has already been reclaimed for reuse.
+    pub fn appends_since(&self, lsn: Lsn) -> Result<Vec<(Lsn, DiskPtr, P)>> {
+        let tip = self.max_reserved_lsn();
+        if lsn >= tip {
+            return Ok(vec![]);
+        }
+        self.make_stable(tip)?;
+
+        let reclaimed = || {
+            Error::Unsupported(format!(
+                "the log after lsn {} has been partly \
+                 reclaimed, so it can't be read back",
+                lsn
+            ))
+        };
+
+        let segment_len = self.config.io_buf_size as Lsn;
+        let segment_start = |lsn: Lsn| lsn / segment_len * segment_len;
+
+        // each message starts where the last one ended, or
+        // at the start of the next segment if the rest of
+        // its segment was padded out
+        let first_segment = segment_start(lsn + 1);
+        let mut next = first_segment + SEG_HEADER_LEN as Lsn;
+        let follows = |next: Lsn, lsn: Lsn| {
+            let next_segment = segment_start(next + segment_len - 1);
+            lsn == next || lsn == next_segment + SEG_HEADER_LEN as Lsn
+        };
+
+        let mut appends = vec![];
+        for entry in RawLogIter::new(self.config.clone(), first_segment)? {
+            let entry = entry?;
+            if entry.lsn > tip {
+                break;
+            }
+            if !entry.checksum_valid || !follows(next, entry.lsn) {
+                return Err(reclaimed());
+            }
+            next = entry.lsn + (MSG_HEADER_LEN + entry.len) as Lsn;
+
+            let is_append = matches!(
+                entry.kind,
+                MessageKind::InlineAppend | MessageKind::BlobAppend
+            );
+            if entry.lsn <= lsn || !is_append {
+                continue;
+            }
+
+            let ptr = DiskPtr::Inline(entry.lid);
+            let (bytes, ptr) = match self.log.read(entry.pid, entry.lsn, ptr)? {
+                LogRead::Inline(_, buf, _) => (buf, ptr),
+                LogRead::Blob(_, buf, blob_ptr) => {
+                    (buf, DiskPtr::Blob(entry.lid, blob_ptr))
+                }
+                // the blob was removed along with the
+                // page that the append belonged to
+                _ => return Err(reclaimed()),
+            };
+            let frag = measure(&M.deserialize, || deserialize::<P>(&bytes))
+                .map_err(|_| Error::Corruption { at: ptr })?;
+            appends.push((entry.lsn, ptr, frag));
+        }
+
+        if next < tip {
+            return Err(reclaimed());
+        }
+
+        Ok(appends)
+    }
+
+    /// Returns the number of times that an attempt to
+    /// install a page update lost a race against a
+    /// concurrent writer and had to be retried.
+    pub fn cas_retries(&self) -> u64 {
+        self.cas_retries.load(Acquire)
+    }
+
+    /// Reads a page into the cache ahead of an anticipated
+    /// `get`, counting it towards `prefetched_pages`.
+    pub fn prefetch<'g>(
+        &self,
+        pid: PageId,
+        tx: &'g Tx<P>,
+    ) -> Result<Option<(PagePtr<'g, P>, &'g P, u64)>> {
+        let res = self.get(pid, tx)?;
+        if res.is_some() {
+            self.prefetches.fetch_add(1, Relaxed);
+        }
+        Ok(res)
+    }
+
+    /// Returns the number of pages that have been
+    /// read ahead of use with `prefetch`.
+    pub fn prefetched_pages(&self) -> u64 {
+        self.prefetches.load(Acquire)
+    }
+
+    /// Accounts for a page in the provided `Lru` rather
+    /// than the shared cache from now on, so that it is only
+    /// paged out to make room for other pages reserved in the
+    /// same `Lru`. A page that is already cached remains
+    /// accounted for in the shared cache until it is next
+    /// paged out. The reservation is released when the page
+    /// is freed.
+    pub fn reserve_cache(&self, pid: PageId, lru: &Arc<Lru>) {
+        if let Some(reserved) = self.reserved_caches.read().get(&pid) {
+            if Arc::ptr_eq(reserved, lru) {
+                return;
+            }
+        }
+        self.reserved_caches.write().insert(pid, lru.clone());
+    }
+
+    /// Returns the number of pages that have been
+    /// read into the cache from disk.
+    pub fn page_ins(&self) -> u64 {
+        self.page_ins.load(Acquire)
+    }
+
+    /// Returns the number of times that a page
+    /// has been requested with `get`.
+    pub fn page_fetches(&self) -> u64 {
+        self.fetches.load(Acquire)
+    }
+
+    /// Returns the number of times that a page was
+    /// rewritten to relocate it out of a segment that
+    /// was being cleaned.
+    pub fn rewritten_pages(&self) -> u64 {
+        self.rewrites.load(Acquire)
+    }
+
+    /// Returns the number of calls to `flush` that
+    /// had data to write.
+    pub fn flushes(&self) -> u64 {
+        self.flushes.load(Acquire)
+    }
+
+    /// Returns the number of IO buffers written to the log,
+    /// each of which is followed by an fsync unless the
+    /// config is temporary. Concurrent flushes share writes
+    /// when `ConfigBuilder::group_commit_window_us` is set.
+    pub fn log_writes(&self) -> u64 {
+        self.log.log_writes()
+    }
+
+    /// Returns the number of times that a page's chain of
+    /// updates was consolidated into a single fragment.
+    pub fn consolidated_pages(&self) -> u64 {
+        self.consolidations.load(Acquire)
+    }
+
+    /// Returns the number of freed pages whose ids have been
+    /// reclaimed for reuse, which happens once every thread
+    /// that was pinned when a page was freed has unpinned.
+    pub fn freed_pages(&self) -> u64 {
+        self.freed.load(Acquire)
+    }
+
+    /// Returns the number of fragments currently linked into
+    /// the page's chain, including its base, or 0 if the page
+    /// does not exist. Each `link` grows the chain by one
+    /// until it reaches the consolidation threshold, after
+    /// which the page is replaced by a single fragment.
+    pub fn chain_len(&self, pid: PageId, tx: &Tx<P>) -> usize {
+        match self.inner.get(pid, &tx.guard) {
+            None => 0,
+            Some(head_ptr) => {
+                let head = unsafe { head_ptr.deref().head(&tx.guard) };
+                StackIter::from_ptr(head, &tx.guard).count()
+            }
+        }
+    }
+
+    /// Returns `true` if any fragment in the page's chain was
+    /// too large to be written inline in the log, and was
+    /// stored in a separate blob file instead.
+    pub fn has_blob_frag(&self, pid: PageId, tx: &Tx<P>) -> bool {
+        match self.inner.get(pid, &tx.guard) {
+            None => false,
+            Some(head_ptr) => {
+                let head = unsafe { head_ptr.deref().head(&tx.guard) };
+                StackIter::from_ptr(head, &tx.guard)
+                    .any(|(_, cache_info)| cache_info.ptr.is_blob())
+            }
+        }
+    }
+
+    /// Sets the number of updates that may be linked onto a
+    /// page before it is consolidated, overriding the
+    /// configured `page_consolidation_threshold` until the
+    /// `PageCache` is restarted. Pages with longer chains
+    /// are consolidated on their next update.
+    pub fn set_consolidation_threshold(&self, threshold: usize) -> Result<()> {
+        if !(1..1 << 20).contains(&threshold) {
+            return Err(Error::Unsupported(format!(
+                "page consolidation threshold must be at least 1 \
+                 and fewer than 1 million updates, but it is {}",
+                threshold
+            )));
+        }
+        self.consolidation_threshold.store(threshold, Relaxed);
+        Ok(())
+    }
+
+    fn record_cas_retry(&self) {
+        M.cas_retried();
+        self.cas_retries.fetch_add(1, Relaxed);
+    }
+
     /// Returns `true` if the database was
     /// recovered from a previous process.
     /// Note that database state is only
@@ -1718,10 +2142,29 @@ where
             Update::Free => Err(Error::ReportableBug(
                 "non-append/compact found in pull".to_owned(),
             )),
+            Update::Append(ref frag) | Update::Compact(ref frag)
+                if !frag.is_well_formed() =>
+            {
+                debug!("read malformed page fragment at {}", ptr);
+                Err(Error::Corruption { at: ptr })
+            }
             update => Ok(update),
         }
     }
 
+    // under `InvariantPolicy::Error`, reports a merge that the
+    // materializer rejects as corruption of the fragment at `at`
+    // instead of letting the merge panic
+    fn check_merge(&self, base: &P, frag: &P, at: DiskPtr) -> Result<()> {
+        if self.config.on_invariant_violation == InvariantPolicy::Error
+            && !base.can_merge(frag)
+        {
+            error!("refusing to merge fragment at {} into its page", at);
+            return Err(Error::Corruption { at });
+        }
+        Ok(())
+    }
+
     // caller is expected to have instantiated self.last_snapshot
     // in recovery already.
     fn advance_snapshot(&self) -> Result<()> {
@@ -187,6 +187,17 @@ impl LogReader for File {
             | MessageKind::Free
             | MessageKind::Counter => {
                 trace!("read a successful inline message");
+                let buf = match config.encryption {
+                    Some(ref encryption) => {
+                        encryption.decrypt(&buf).ok_or_else(|| {
+                            warn!("message at lid {} failed to decrypt", lid);
+                            Error::Corruption {
+                                at: DiskPtr::Inline(lid),
+                            }
+                        })?
+                    }
+                    None => buf,
+                };
                 let buf = if config.use_compression {
                     maybe_decompress(buf)?
                 } else {
@@ -20,7 +20,12 @@ impl<'a> Drop for Reservation<'a> {
     fn drop(&mut self) {
         // We auto-abort if the user never uses a reservation.
         if !self.flushed {
-            self.flush(false).unwrap();
+            // this happens while unwinding from errors like a failed
+            // write, which are already set as the log's global error
+            // and reported by later operations, so don't panic here
+            if let Err(e) = self.flush(false) {
+                error!("failed to abort dropped reservation: {:?}", e);
+            }
         }
     }
 }
@@ -35,6 +35,13 @@ pub enum Error {
         /// The file location that corrupted data was found at.
         at: DiskPtr,
     },
+    /// The storage device ran out of space while writing to the log.
+    /// Data that was already written can still be read, but further
+    /// writes will fail until the database is restarted.
+    OutOfSpace,
+    /// A write that was asked not to block would have had to
+    /// wait for the IO buffers to make room for it.
+    WouldBlock,
     // a failpoint has been triggered for testing purposes
     #[doc(hidden)]
     #[cfg(feature = "failpoints")]
@@ -53,6 +60,8 @@ impl Clone for Error {
             Unsupported(why) => Unsupported(why.clone()),
             ReportableBug(what) => ReportableBug(what.clone()),
             Corruption { at } => Corruption { at: *at },
+            OutOfSpace => OutOfSpace,
+            WouldBlock => WouldBlock,
             #[cfg(feature = "failpoints")]
             FailPoint => FailPoint,
         }
@@ -100,6 +109,8 @@ impl PartialEq for Error {
                     false
                 }
             }
+            OutOfSpace => matches!(*other, OutOfSpace),
+            WouldBlock => matches!(*other, WouldBlock),
             Io(_) => false,
         }
     }
@@ -108,6 +119,12 @@ impl PartialEq for Error {
 impl From<io::Error> for Error {
     #[inline]
     fn from(io_error: io::Error) -> Error {
+        #[cfg(unix)]
+        {
+            if io_error.raw_os_error() == Some(libc::ENOSPC) {
+                return Error::OutOfSpace;
+            }
+        }
         Error::Io(io_error)
     }
 }
@@ -122,6 +139,8 @@ impl StdError for Error {
             FailPoint => "Fail point has been triggered.",
             Io(ref e) => e.description(),
             Corruption { .. } => "Read corrupted data.",
+            OutOfSpace => "Ran out of space on the storage device.",
+            WouldBlock => "The IO buffers had no room for the write.",
         }
     }
 }
@@ -148,6 +167,8 @@ impl Display for Error {
             Corruption { at } => {
                 write!(f, "Read corrupted data at file offset {}", at)
             }
+            OutOfSpace => write!(f, "Ran out of space on the storage device"),
+            WouldBlock => write!(f, "The IO buffers had no room for the write"),
         }
     }
 }
@@ -405,9 +405,7 @@ impl SegmentAccountant {
             deferred_free_segments_after: 0,
         };
 
-        if let SegmentMode::Linear = ret.config.segment_mode {
-            // this is a hack to prevent segments from being overwritten
-            // when operating without a `PageCache`
+        if !ret.recycles_segments() {
             ret.pause_rewriting();
         }
 
@@ -642,12 +640,19 @@ impl SegmentAccountant {
 
     /// Re-enables segment rewriting after iteration is complete.
     pub(super) fn resume_rewriting(&mut self) {
-        // we never want to resume segment rewriting in Linear mode
-        if self.config.segment_mode != SegmentMode::Linear {
+        if self.recycles_segments() {
             self.pause_rewriting = false;
         }
     }
 
+    // whether freed segments may be overwritten by new ones.
+    // Linear mode never does this, to prevent segments from
+    // being overwritten when operating without a `PageCache`
+    fn recycles_segments(&self) -> bool {
+        self.config.segment_mode != SegmentMode::Linear
+            && self.config.recycle_segments
+    }
+
     /// Called by the `PageCache` when a page has been rewritten completely.
     /// We mark all of the old segments that contained the previous state
     /// from the page, and if the old segments are empty or clear enough to
@@ -678,17 +683,21 @@ impl SegmentAccountant {
         assert!(!self.to_clean.contains(&new_segment_start));
 
         // Do we need to schedule any blob cleanups?
-        // Not if we just moved the pointer without changing
-        // the underlying blob, as is the case with a single Blob
-        // with nothing else.
-        let schedule_rm_blob = !(old_ptrs.len() == 1 && old_ptrs[0].is_blob());
+        // Not for a blob that the new pointer still refers to,
+        // as is the case when we just moved the pointer to a
+        // single Blob without changing the underlying blob.
+        let kept_blob = if new_ptr.is_blob() {
+            Some(new_ptr.blob().1)
+        } else {
+            None
+        };
 
         let mut deferred_replacements = FastSet8::default();
 
         for old_ptr in old_ptrs {
             let old_lid = old_ptr.lid();
 
-            if schedule_rm_blob && old_ptr.is_blob() {
+            if old_ptr.is_blob() && Some(old_ptr.blob().1) != kept_blob {
                 trace!(
                     "queueing blob removal for {} in our own segment",
                     old_ptr
@@ -774,6 +783,11 @@ impl SegmentAccountant {
     /// segments elligible for cleaning that it should
     /// try to rewrite elsewhere.
     pub(super) fn clean(&mut self, ignore_pid: PageId) -> Option<PageId> {
+        if self.config.segment_mode == SegmentMode::Linear {
+            // live data is never relocated in Linear mode
+            return None;
+        }
+
         let seg_offset = if self.to_clean.is_empty() || self.to_clean.len() == 1
         {
             0
@@ -915,7 +929,7 @@ impl SegmentAccountant {
             self.segments[idx]
         );
 
-        let replacements = if self.segments[idx].state == Draining {
+        let replacements = if self.segments[idx].state == Active {
             self.segments[idx].active_to_inactive(lsn, false, &self.config)?
         } else {
             Default::default()
@@ -1199,13 +1213,23 @@ impl SegmentAccountant {
 /// the constraints of the system using it.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum SegmentMode {
-    /// Write to the end of the log, always.
+    /// Write to the end of the log, always, and
+    /// never relocate live data. Segments are only
+    /// reclaimed once every page in them has been
+    /// replaced or freed by normal writes, and the
+    /// file is only shrunk when that happens at its
+    /// end. Appropriate for append-mostly workloads
+    /// and raw `Log` use, where rewriting live data
+    /// would cost more than the space it frees.
     Linear,
     /// Keep track of segment utilization, and
     /// reuse segments when their contents are
     /// fully relocated elsewhere.
     /// Will try to copy data out of segments
     /// once they reach a configurable threshold.
+    /// Appropriate for workloads that update or
+    /// remove data, where keeping the file compact
+    /// is worth the extra writes. This is the default.
     Gc,
 }
 
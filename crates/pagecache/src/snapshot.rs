@@ -1,4 +1,7 @@
-use std::io::{Read, Write};
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+};
 
 #[cfg(feature = "zstd")]
 use zstd::block::{compress, decompress};
@@ -20,6 +23,34 @@ pub struct Snapshot {
     pub max_header_stable_lsn: Lsn,
 }
 
+/// A snapshot file on disk, as listed by `Config::snapshots`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotInfo {
+    /// The location of the snapshot file
+    pub path: PathBuf,
+    /// The lsn of the last log message the snapshot covers
+    pub lsn: Lsn,
+    /// The size of the snapshot file in bytes
+    pub size: u64,
+}
+
+/// How thoroughly the state loaded from the latest
+/// snapshot is checked against the log during recovery.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum RecoveryMode {
+    /// Trust the latest snapshot, and only replay the
+    /// log written after it.
+    Fast,
+    /// Also regenerate the snapshot from the entire log,
+    /// and fail with `Error::Corruption` if it differs from
+    /// the one loaded from disk.
+    Verify,
+    /// Like `Verify`, but instead of failing, recover from
+    /// the regenerated snapshot, dropping any state that
+    /// can't be replayed from the log.
+    Repair,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum PageState {
     Present(Vec<(Lsn, DiskPtr, usize)>),
@@ -125,10 +156,22 @@ pub(super) fn advance_snapshot(
 ) -> Result<Snapshot> {
     let _measure = Measure::new(&M.advance_snapshot);
 
-    trace!("building on top of old snapshot: {:?}", snapshot);
-
     let old_lsn = snapshot.last_lsn;
 
+    snapshot = replay(iter, snapshot);
+
+    if snapshot.last_lsn != old_lsn {
+        write_snapshot(config, &snapshot)?;
+    }
+
+    trace!("generated new snapshot: {:?}", snapshot);
+
+    Ok(snapshot)
+}
+
+fn replay(iter: LogIter, mut snapshot: Snapshot) -> Snapshot {
+    trace!("building on top of old snapshot: {:?}", snapshot);
+
     for (log_kind, pid, lsn, ptr, sz) in iter {
         trace!(
             "in advance_snapshot looking at item with lsn {} ptr {}",
@@ -155,17 +198,13 @@ pub(super) fn advance_snapshot(
         snapshot.apply(log_kind, pid, lsn, ptr, sz);
     }
 
-    if snapshot.last_lsn != old_lsn {
-        write_snapshot(config, &snapshot)?;
-    }
-
-    trace!("generated new snapshot: {:?}", snapshot);
-
-    Ok(snapshot)
+    snapshot
 }
 
 /// Read a `Snapshot` or generate a default, then advance it to
-/// the tip of the data file, if present.
+/// the tip of the data file, if present. Depending on the
+/// configured `RecoveryMode`, the result is then checked
+/// against a snapshot regenerated from the entire log.
 pub fn read_snapshot_or_default(config: &Config) -> Result<Snapshot> {
     let mut last_snap =
         read_snapshot(config)?.unwrap_or_else(Snapshot::default);
@@ -175,7 +214,56 @@ pub fn read_snapshot_or_default(config: &Config) -> Result<Snapshot> {
 
     last_snap.max_header_stable_lsn = max_header_stable_lsn;
 
-    advance_snapshot(log_iter, last_snap, config)
+    let snapshot = advance_snapshot(log_iter, last_snap, config)?;
+
+    if config.recovery_mode == RecoveryMode::Fast {
+        return Ok(snapshot);
+    }
+
+    debug!("regenerating snapshot from the log to verify recovery");
+
+    let (log_iter, max_header_stable_lsn) = raw_segment_iter_from(0, config)?;
+    let regenerated = Snapshot {
+        max_header_stable_lsn,
+        ..Snapshot::default()
+    };
+    let regenerated = replay(log_iter, regenerated);
+
+    match divergence(&snapshot, &regenerated) {
+        None => Ok(snapshot),
+        Some(at) if config.recovery_mode == RecoveryMode::Repair => {
+            warn!(
+                "recovered snapshot diverges from the log at {}, \
+                 continuing from a snapshot regenerated from the log",
+                at
+            );
+            write_snapshot(config, &regenerated)?;
+            Ok(regenerated)
+        }
+        Some(at) => Err(Error::Corruption { at }),
+    }
+}
+
+// returns a location where a snapshot differs
+// from one regenerated from the log, if any.
+fn divergence(snapshot: &Snapshot, regenerated: &Snapshot) -> Option<DiskPtr> {
+    for (pid, state) in &snapshot.pt {
+        if regenerated.pt.get(pid) != Some(state) {
+            return state.iter().map(|(_, ptr, _)| ptr).last();
+        }
+    }
+
+    for (pid, state) in &regenerated.pt {
+        if !snapshot.pt.contains_key(pid) {
+            return state.iter().map(|(_, ptr, _)| ptr).last();
+        }
+    }
+
+    if snapshot.last_lsn != regenerated.last_lsn {
+        return Some(DiskPtr::new_inline(snapshot.last_lid));
+    }
+
+    None
 }
 
 /// Read a `Snapshot` from disk.
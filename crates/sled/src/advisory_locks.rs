@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+use pagecache::FastMap8;
+use parking_lot::Mutex;
+
+use super::*;
+
+/// The advisory locks of a `Tree`, claimed with
+/// `Tree::try_lock`. They are only held in memory, so they
+/// stay out of the `Tree`'s keyspace, and are all released
+/// when the database is closed.
+#[derive(Default)]
+pub(crate) struct AdvisoryLocks {
+    // each key's holder and when its claim expires
+    held: Mutex<FastMap8<IVec, (u64, Instant)>>,
+}
+
+impl AdvisoryLocks {
+    /// Claims `key` for `owner` if nobody holds it or the
+    /// holder's claim has expired, returning whether `owner`
+    /// now holds it.
+    pub(crate) fn try_lock(
+        &self,
+        key: &[u8],
+        owner: u64,
+        ttl: Duration,
+    ) -> bool {
+        let now = Instant::now();
+        let mut held = self.held.lock();
+
+        match held.get(key) {
+            Some(&(holder, expires_at))
+                if holder != owner && expires_at > now =>
+            {
+                return false;
+            }
+            Some(_) => {}
+            // claims that were never released would otherwise
+            // pile up, so expired ones are dropped as new keys
+            // are claimed
+            None => held.retain(|_, &mut (_, expires_at)| expires_at > now),
+        }
+
+        held.insert(IVec::from(key), (owner, now + ttl));
+        true
+    }
+
+    /// Releases `key` if `owner` holds it, returning whether
+    /// it did.
+    pub(crate) fn unlock(&self, key: &[u8], owner: u64) -> bool {
+        let mut held = self.held.lock();
+        match held.get(key) {
+            Some(&(holder, _)) if holder == owner => {
+                held.remove(key);
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    convert::TryInto,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering::SeqCst},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+
+use super::*;
+
+/// A committed write recorded in the audit log configured
+/// with `ConfigBuilder::audit_log`. Unlike the main log,
+/// the audit log is only ever appended to, so it retains
+/// every write even after it is overwritten or removed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The name of the `Tree` that was written to.
+    pub tree: IVec,
+    /// The log sequence number of this write.
+    pub lsn: Lsn,
+    /// When the write was committed, in milliseconds
+    /// since the unix epoch.
+    pub timestamp_ms: u64,
+    /// The write itself.
+    pub op: LogOp,
+}
+
+/// An append-only file of `AuditEntry`s, shared by
+/// every `Tree` in a `Db`. Each entry is framed by
+/// its length as a little-endian u64.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+    // a second handle for fsync, so that appenders
+    // don't wait on each other's syncs
+    sync_file: File,
+    // the length of the entries that have been
+    // completely written
+    len: AtomicU64,
+}
+
+impl AuditLog {
+    pub(crate) fn open(path: &Path, read_only: bool) -> Result<AuditLog> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(!read_only)
+            .create(!read_only)
+            .open(path)?;
+
+        let mut bytes = vec![];
+        (&file).read_to_end(&mut bytes)?;
+        let (_, len) = decode_entries(&bytes);
+
+        if len < bytes.len() as u64 && !read_only {
+            // a crash while appending left a partial entry
+            // behind, which would hide every later one
+            warn!(
+                "truncating torn audit log tail of {} bytes",
+                bytes.len() as u64 - len
+            );
+            file.set_len(len)?;
+            file.sync_all()?;
+        }
+
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            sync_file: file.try_clone()?,
+            file: Mutex::new(file),
+            len: AtomicU64::new(len),
+        })
+    }
+
+    /// Appends an entry and syncs it, so that it is at
+    /// least as durable as the write that it records.
+    pub(crate) fn append(
+        &self,
+        tree: &[u8],
+        lsn: Lsn,
+        op: &LogOp,
+    ) -> Result<()> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let entry = AuditEntry {
+            tree: IVec::from(tree),
+            lsn,
+            timestamp_ms,
+            op: op.clone(),
+        };
+        let encoded = bincode::serialize(&entry).unwrap();
+
+        let mut buf = Vec::with_capacity(8 + encoded.len());
+        buf.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&encoded);
+
+        {
+            // a single write keeps each entry contiguous
+            // when several trees append concurrently
+            let mut file = self.file.lock();
+            if let Err(e) = file.write_all(&buf) {
+                // drop whatever part of the entry was
+                // written, so that later entries still
+                // follow a complete one
+                let _ = file.set_len(self.len.load(SeqCst));
+                return Err(e.into());
+            }
+            self.len.fetch_add(buf.len() as u64, SeqCst);
+        }
+
+        self.sync_file.sync_data()?;
+
+        Ok(())
+    }
+
+    pub(crate) fn since(
+        &self,
+        tree: &[u8],
+        lsn: Lsn,
+    ) -> Result<Vec<AuditEntry>> {
+        // only read the entries that were completely
+        // written, without blocking appenders
+        let len = self.len.load(SeqCst);
+        let mut bytes = Vec::with_capacity(len as usize);
+        File::open(&self.path)?.take(len).read_to_end(&mut bytes)?;
+
+        let (entries, _) = decode_entries(&bytes);
+
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.lsn > lsn && &*entry.tree == tree)
+            .collect())
+    }
+}
+
+// decodes entries up to a torn or corrupt tail, returning
+// them along with the length of the bytes they occupy
+fn decode_entries(bytes: &[u8]) -> (Vec<AuditEntry>, u64) {
+    let mut entries = vec![];
+    let mut buf = bytes;
+    while buf.len() >= 8 {
+        let len = u64::from_le_bytes(buf[..8].try_into().unwrap()) as usize;
+        if buf.len() - 8 < len {
+            // torn by a crash while appending
+            break;
+        }
+        let entry: AuditEntry = match bincode::deserialize(&buf[8..8 + len]) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("stopping at corrupt audit log entry: {}", e);
+                break;
+            }
+        };
+        buf = &buf[8 + len..];
+        entries.push(entry);
+    }
+
+    (entries, (bytes.len() - buf.len()) as u64)
+}
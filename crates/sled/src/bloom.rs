@@ -0,0 +1,165 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::Hasher,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering::SeqCst},
+        Arc,
+    },
+};
+
+use parking_lot::Mutex;
+
+use super::*;
+
+// about a 1% false positive rate at the configured capacity
+const BLOOM_BITS_PER_KEY: u64 = 10;
+const BLOOM_HASHES: u64 = 7;
+
+/// An in-memory bloom filter over the keys that have
+/// been written to a `Tree`. Keys are never removed,
+/// so a deleted key keeps passing the filter until
+/// the filter is rebuilt during the next recovery.
+pub(crate) struct BloomFilter {
+    words: Vec<AtomicU64>,
+    n_bits: u64,
+    // set while the keys that were already in the `Tree`
+    // are being recorded in the background
+    filling: AtomicBool,
+    // set once every key in the `Tree` has been recorded.
+    // until then, every key passes the filter
+    filled: AtomicBool,
+    // why filling in the background failed, if it did
+    fill_error: Mutex<Option<Error>>,
+}
+
+impl BloomFilter {
+    /// Returns an empty filter sized for
+    /// `bloom_filter_capacity` keys if the configuration
+    /// enables `use_bloom_filter`.
+    pub(crate) fn for_config(config: &Config) -> Option<Arc<BloomFilter>> {
+        if config.use_bloom_filter {
+            Some(Arc::new(BloomFilter::new(config.bloom_filter_capacity)))
+        } else {
+            None
+        }
+    }
+
+    fn new(capacity: usize) -> BloomFilter {
+        let n_words = (capacity as u64 * BLOOM_BITS_PER_KEY).div_ceil(64);
+        BloomFilter {
+            words: (0..n_words).map(|_| AtomicU64::new(0)).collect(),
+            n_bits: n_words * 64,
+            filling: AtomicBool::new(false),
+            filled: AtomicBool::new(true),
+            fill_error: Mutex::new(None),
+        }
+    }
+
+    /// Records a key, which must happen before it
+    /// becomes visible to readers.
+    pub(crate) fn insert(&self, key: &[u8]) {
+        for bit in bits(key, self.n_bits) {
+            self.words[(bit / 64) as usize].fetch_or(1 << (bit % 64), SeqCst);
+        }
+    }
+
+    /// Records every key currently in `tree`, which reads
+    /// every leaf of the `Tree`. With `lazy_recovery`, this
+    /// happens in the background, and the filter passes
+    /// every key until it's done.
+    pub(crate) fn fill(self: Arc<Self>, tree: &Tree) -> Result<()> {
+        fn record_keys(bloom_filter: &BloomFilter, tree: &Tree) -> Result<()> {
+            for key in tree.iter().keys() {
+                bloom_filter.insert(&key?);
+            }
+            Ok(())
+        }
+
+        if !tree.context.lazy_recovery {
+            return record_keys(&self, tree);
+        }
+
+        self.filled.store(false, SeqCst);
+        self.filling.store(true, SeqCst);
+        let tree = tree.clone();
+        rayon::spawn(move || {
+            let res = record_keys(&self, &tree);
+            if let Err(ref e) = res {
+                error!(
+                    "failed to fill the bloom filter of tree {:?}, \
+                     which will pass every key: {:?}",
+                    tree.tree_id, e
+                );
+            }
+            self.finish_fill(res);
+        });
+        Ok(())
+    }
+
+    // a filter that failed to fill keeps passing every key,
+    // since it is missing some of the keys in the `Tree`
+    fn finish_fill(&self, res: Result<()>) {
+        match res {
+            Ok(()) => self.filled.store(true, SeqCst),
+            Err(e) => *self.fill_error.lock() = Some(e),
+        }
+        self.filling.store(false, SeqCst);
+    }
+
+    /// Returns `true` while the filter is being filled
+    /// in the background.
+    pub(crate) fn is_filling(&self) -> bool {
+        self.filling.load(SeqCst)
+    }
+
+    /// Returns the error that stopped the filter from
+    /// being filled in the background, if any.
+    pub(crate) fn fill_error(&self) -> Option<Error> {
+        self.fill_error.lock().clone()
+    }
+
+    /// Returns `false` if the key was definitely never
+    /// recorded.
+    pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+        if !self.filled.load(SeqCst) {
+            return true;
+        }
+        bits(key, self.n_bits).all(|bit| {
+            self.words[(bit / 64) as usize].load(SeqCst) & (1 << (bit % 64))
+                != 0
+        })
+    }
+}
+
+// derives all of a key's bits from two halves of
+// a single hash, as in Kirsch and Mitzenmacher.
+fn bits(key: &[u8], n_bits: u64) -> impl Iterator<Item = u64> {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(key);
+    let hash = hasher.finish();
+    let (h1, h2) = (hash & 0xFFFF_FFFF, hash >> 32);
+
+    (0..BLOOM_HASHES).map(move |i| h1.wrapping_add(i * h2) % n_bits)
+}
+
+#[test]
+fn failed_fills_stop_pending_and_pass_every_key() {
+    let bloom_filter = BloomFilter::new(100);
+    bloom_filter.filled.store(false, SeqCst);
+    bloom_filter.filling.store(true, SeqCst);
+    assert!(bloom_filter.may_contain(b"missing"));
+
+    let error = Error::ReportableBug("failed to read a leaf".to_owned());
+    bloom_filter.finish_fill(Err(error.clone()));
+    assert!(!bloom_filter.is_filling());
+    assert!(bloom_filter.may_contain(b"missing"));
+    assert_eq!(bloom_filter.fill_error(), Some(error));
+
+    let bloom_filter = BloomFilter::new(100);
+    bloom_filter.filled.store(false, SeqCst);
+    bloom_filter.filling.store(true, SeqCst);
+    bloom_filter.finish_fill(Ok(()));
+    assert!(!bloom_filter.is_filling());
+    assert!(!bloom_filter.may_contain(b"missing"));
+    assert_eq!(bloom_filter.fill_error(), None);
+}
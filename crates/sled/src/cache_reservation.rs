@@ -0,0 +1,37 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering::Acquire, Ordering::Release},
+    Arc,
+};
+
+use parking_lot::RwLock;
+
+use super::*;
+
+/// The cache reserved for a `Tree` by
+/// `Db::open_tree_with_cache_capacity`. Most trees never
+/// reserve one, so they only pay for an atomic load on each
+/// page access rather than taking a lock.
+#[derive(Default)]
+pub(crate) struct CacheReservation {
+    reserved: AtomicBool,
+    lru: RwLock<Option<Arc<Lru>>>,
+}
+
+impl CacheReservation {
+    /// Replaces any earlier reservation with a new `Lru`
+    /// of `cache_capacity` bytes.
+    pub(crate) fn set(&self, cache_capacity: u64) {
+        *self.lru.write() = Some(Arc::new(Lru::new(cache_capacity)));
+        self.reserved.store(true, Release);
+    }
+
+    /// Accounts for `pid` in the reserved `Lru`, if any.
+    pub(crate) fn reserve(&self, pid: PageId, pagecache: &PageCache<Frag>) {
+        if !self.reserved.load(Acquire) {
+            return;
+        }
+        if let Some(ref lru) = *self.lru.read() {
+            pagecache.reserve_cache(pid, lru);
+        }
+    }
+}
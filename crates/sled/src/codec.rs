@@ -0,0 +1,220 @@
+use std::ops::{Bound, RangeBounds};
+
+use super::*;
+
+/// Converts the keys and values of a `Tree` to and from
+/// bytes, so that a `CodecView` can work with typed keys and
+/// values. Range scans follow the order of the encoded keys,
+/// so key encodings should preserve the order of the keys
+/// themselves for scans to be meaningful.
+pub trait Codec {
+    /// The type of keys.
+    type Key;
+    /// The type of values.
+    type Value;
+
+    /// Encodes a key into the bytes stored in the `Tree`.
+    fn encode_key(&self, key: &Self::Key) -> Vec<u8>;
+
+    /// Decodes a key from the bytes stored in the `Tree`.
+    fn decode_key(&self, bytes: &[u8]) -> Result<Self::Key>;
+
+    /// Encodes a value into the bytes stored in the `Tree`.
+    fn encode_value(&self, value: &Self::Value) -> Vec<u8>;
+
+    /// Decodes a value from the bytes stored in the `Tree`.
+    fn decode_value(&self, bytes: &[u8]) -> Result<Self::Value>;
+}
+
+/// The default `Codec`, which stores keys and values unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Identity;
+
+impl Codec for Identity {
+    type Key = Vec<u8>;
+    type Value = Vec<u8>;
+
+    fn encode_key(&self, key: &Vec<u8>) -> Vec<u8> {
+        key.clone()
+    }
+
+    fn decode_key(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+
+    fn encode_value(&self, value: &Vec<u8>) -> Vec<u8> {
+        value.clone()
+    }
+
+    fn decode_value(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// A view of a `Tree` that passes all keys and values
+/// through a `Codec`.
+pub struct CodecView<'a, C> {
+    pub(super) tree: &'a Tree,
+    pub(super) codec: C,
+}
+
+impl<'a, C: Codec> CodecView<'a, C> {
+    /// Returns the `Codec` that this view uses.
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Retrieve a value for a key if it exists.
+    pub fn get(&self, key: &C::Key) -> Result<Option<C::Value>> {
+        self.decode_opt(self.tree.get(self.codec.encode_key(key))?)
+    }
+
+    /// Insert a key to a new value, returning
+    /// the last value if it was set.
+    pub fn insert(
+        &self,
+        key: &C::Key,
+        value: &C::Value,
+    ) -> Result<Option<C::Value>> {
+        let last = self.tree.insert(
+            self.codec.encode_key(key),
+            self.codec.encode_value(value),
+        )?;
+        self.decode_opt(last)
+    }
+
+    /// Delete the value for a key, returning
+    /// the old value if it existed.
+    pub fn remove(&self, key: &C::Key) -> Result<Option<C::Value>> {
+        self.decode_opt(self.tree.remove(self.codec.encode_key(key))?)
+    }
+
+    /// Returns `true` if the view contains a value
+    /// for the specified key.
+    pub fn contains_key(&self, key: &C::Key) -> Result<bool> {
+        self.tree.contains_key(self.codec.encode_key(key))
+    }
+
+    /// Create a double-ended iterator over the keys and
+    /// values in this view, in the order of their encoded keys.
+    pub fn iter(
+        &self,
+    ) -> impl '_ + DoubleEndedIterator<Item = Result<(C::Key, C::Value)>> {
+        self.decode_iter(self.tree.iter())
+    }
+
+    /// Create a double-ended iterator over the keys and
+    /// values in a range of keys, in the order of their
+    /// encoded keys.
+    pub fn range<R>(
+        &self,
+        range: R,
+    ) -> impl '_ + DoubleEndedIterator<Item = Result<(C::Key, C::Value)>>
+    where
+        R: RangeBounds<C::Key>,
+    {
+        let encode = |bound: Bound<&C::Key>| match bound {
+            Bound::Included(key) => Bound::Included(self.codec.encode_key(key)),
+            Bound::Excluded(key) => Bound::Excluded(self.codec.encode_key(key)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let bounds = (encode(range.start_bound()), encode(range.end_bound()));
+
+        self.decode_iter(self.tree.range::<Vec<u8>, _>(bounds))
+    }
+
+    fn decode_opt(&self, value: Option<IVec>) -> Result<Option<C::Value>> {
+        match value {
+            Some(value) => self.codec.decode_value(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn decode_iter(
+        &self,
+        iter: Iter<'a>,
+    ) -> impl '_ + DoubleEndedIterator<Item = Result<(C::Key, C::Value)>> {
+        let codec = &self.codec;
+        iter.map(move |res| {
+            let (k, v) = res?;
+            Ok((codec.decode_key(&k)?, codec.decode_value(&v)?))
+        })
+    }
+}
+
+#[test]
+fn codec_view_round_trips_and_orders() {
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        admin: bool,
+    }
+
+    // order-preserving keys and bincode-encoded values
+    struct UserCodec;
+
+    impl Codec for UserCodec {
+        type Key = u64;
+        type Value = User;
+
+        fn encode_key(&self, key: &u64) -> Vec<u8> {
+            key.to_be_bytes().to_vec()
+        }
+
+        fn decode_key(&self, bytes: &[u8]) -> Result<u64> {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(bytes);
+            Ok(u64::from_be_bytes(buf))
+        }
+
+        fn encode_value(&self, value: &User) -> Vec<u8> {
+            bincode::serialize(value).unwrap()
+        }
+
+        fn decode_value(&self, bytes: &[u8]) -> Result<User> {
+            bincode::deserialize(bytes)
+                .map_err(|e| Error::Unsupported(e.to_string()))
+        }
+    }
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let db = Db::start(config).unwrap();
+    let users = db.with_codec(UserCodec);
+
+    let user = |i: u64| User {
+        name: format!("user {}", i),
+        admin: i.is_multiple_of(7),
+    };
+
+    // insert out of order, across byte boundaries
+    for i in (0..334).map(|j| j * 97 % 334 * 3) {
+        assert_eq!(users.insert(&i, &user(i)), Ok(None));
+    }
+
+    assert_eq!(users.get(&999), Ok(Some(user(999))));
+    assert_eq!(users.get(&998), Ok(None));
+    assert_eq!(users.insert(&999, &user(1)), Ok(Some(user(999))));
+    assert_eq!(users.remove(&999), Ok(Some(user(1))));
+    assert_eq!(users.contains_key(&999), Ok(false));
+
+    let ids: Vec<u64> = users.iter().map(|res| res.unwrap().0).collect();
+    let expected: Vec<u64> = (0..999).filter(|i| i % 3 == 0).collect();
+    assert_eq!(ids, expected);
+
+    let in_range: Vec<(u64, User)> =
+        users.range(250..=260).map(|res| res.unwrap()).collect();
+    assert_eq!(
+        in_range,
+        vec![(252, user(252)), (255, user(255)), (258, user(258))]
+    );
+
+    let last = users.range(..300).next_back().unwrap().unwrap();
+    assert_eq!(last, (297, user(297)));
+
+    // the identity codec leaves bytes untouched
+    let raw = db.with_codec(Identity);
+    assert_eq!(
+        raw.get(&3_u64.to_be_bytes().to_vec()),
+        Ok(Some(bincode::serialize(&user(3)).unwrap()))
+    );
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{atomic::AtomicU64, Arc};
 
 use parking_lot::Mutex;
 
@@ -16,7 +16,18 @@ pub(crate) struct Context {
     /// should trigger all background threads to clean
     /// up synchronously.
     pub(crate) _flusher: Arc<Mutex<Option<flusher::Flusher>>>,
+    /// Runs the flushes scheduled by `Tree::flush_async`.
+    pub(crate) async_flusher: Arc<flusher::AsyncFlusher>,
     pub(crate) pagecache: Arc<PageCache<Frag>>,
+    /// The merge operator selected by name in the
+    /// configuration, which new `Tree`s start with.
+    pub(crate) merge_operator: Option<MergeOperator>,
+    /// The append-only record of every committed write,
+    /// if `ConfigBuilder::audit_log` is set.
+    pub(crate) audit_log: Option<Arc<AuditLog>>,
+    /// The number of `Iter` calls that resumed from the leaf
+    /// that the previous call returned a record from.
+    pub(crate) iter_resumes: Arc<AtomicU64>,
 }
 
 impl std::ops::Deref for Context {
@@ -43,20 +54,38 @@ impl Context {
     pub(crate) fn start(config: Config) -> Result<Context> {
         trace!("starting context");
 
+        // the other recovery modes check the snapshot themselves
         #[cfg(any(test, feature = "check_snapshot_integrity"))]
-        match config.verify_snapshot() {
-            Ok(_) => {}
-            #[cfg(feature = "failpoints")]
-            Err(Error::FailPoint) => {}
-            other => panic!("failed to verify snapshot: {:?}", other),
+        {
+            if config.recovery_mode == RecoveryMode::Fast {
+                match config.verify_snapshot() {
+                    Ok(_) => {}
+                    #[cfg(feature = "failpoints")]
+                    Err(Error::FailPoint) => {}
+                    other => panic!("failed to verify snapshot: {:?}", other),
+                }
+            }
         }
 
+        let merge_operator = configured_merge_operator(&config)?;
+
+        let audit_log = match config.audit_log {
+            Some(ref path) => {
+                Some(Arc::new(AuditLog::open(path, config.read_only)?))
+            }
+            None => None,
+        };
+
         let pagecache = Arc::new(PageCache::start(config.clone())?);
 
         Ok(Context {
             config,
             pagecache,
+            merge_operator,
+            audit_log,
+            iter_resumes: Arc::new(AtomicU64::new(0)),
             _flusher: Arc::new(Mutex::new(None)),
+            async_flusher: Arc::new(flusher::AsyncFlusher::default()),
         })
     }
 
@@ -26,6 +26,19 @@ impl Data {
         }
     }
 
+    /// Returns `false` if any key is not a valid encoding
+    /// relative to the node's `lo` prefix.
+    pub(crate) fn is_well_formed(&self, prefix: &[u8]) -> bool {
+        match self {
+            Data::Index(ref ptrs) => {
+                ptrs.iter().all(|(k, _)| prefix_is_valid(prefix, k))
+            }
+            Data::Leaf(ref items) => {
+                items.iter().all(|(k, _)| prefix_is_valid(prefix, k))
+            }
+        }
+    }
+
     pub(crate) fn len(&self) -> usize {
         match *self {
             Data::Index(ref ptrs) => ptrs.len(),
@@ -33,15 +46,28 @@ impl Data {
         }
     }
 
-    pub(crate) fn split(&self, lhs_prefix: &[u8]) -> (IVec, Data) {
+    pub(crate) fn split(
+        &self,
+        lhs_prefix: &[u8],
+        policy: SplitPolicy,
+    ) -> (IVec, Data) {
         fn split_inner<T>(
             xs: &[(IVec, T)],
             lhs_prefix: &[u8],
+            policy: SplitPolicy,
         ) -> (IVec, Vec<(IVec, T)>)
         where
             T: Clone + Ord,
         {
-            let (_lhs, rhs) = xs.split_at(xs.len() / 2 + 1);
+            let at = match policy {
+                SplitPolicy::Center => xs.len() / 2 + 1,
+                // leave an eighth of the items, and at least
+                // one, for the right side
+                SplitPolicy::AppendBiased => {
+                    xs.len() - std::cmp::max(1, xs.len() / 8)
+                }
+            };
+            let (_lhs, rhs) = xs.split_at(at);
             let split = prefix_decode(lhs_prefix, &rhs[0].0);
 
             let mut rhs_data = Vec::with_capacity(rhs.len());
@@ -55,11 +81,11 @@ impl Data {
 
         match *self {
             Data::Index(ref ptrs) => {
-                let (split, rhs) = split_inner(ptrs, lhs_prefix);
+                let (split, rhs) = split_inner(ptrs, lhs_prefix, policy);
                 (split, Data::Index(rhs))
             }
             Data::Leaf(ref items) => {
-                let (split, rhs) = split_inner(items, lhs_prefix);
+                let (split, rhs) = split_inner(items, lhs_prefix, policy);
                 (split, Data::Leaf(rhs))
             }
         }
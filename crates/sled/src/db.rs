@@ -1,6 +1,9 @@
 use std::{
     ops::Deref,
-    sync::{atomic::AtomicU64, Arc},
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
 };
 
 use pagecache::FastMap8;
@@ -17,6 +20,40 @@ pub struct Db {
     tenants: Arc<RwLock<FastMap8<Vec<u8>, Arc<Tree>>>>,
 }
 
+/// Opens a `Db` directly from a configuration, as a
+/// shorthand for building it and passing it to `Db::start`.
+///
+/// # Examples
+///
+/// ```
+/// use sled::{ConfigBuilder, Open};
+///
+/// # fn main() -> sled::Result<()> {
+/// let db = ConfigBuilder::new().temporary(true).open()?;
+/// db.insert(b"k", vec![1])?;
+/// assert_eq!(db.get(b"k")?, Some(sled::IVec::from(vec![1])));
+/// # Ok(())
+/// # }
+/// ```
+pub trait Open {
+    /// Start a `Db` with this configuration.
+    fn open(self) -> Result<Db>;
+}
+
+impl Open for Config {
+    fn open(self) -> Result<Db> {
+        Db::start(self)
+    }
+}
+
+impl Open for ConfigBuilder {
+    /// Start a `Db` with this configuration, returning
+    /// an error instead of panicking if it can't be built.
+    fn open(self) -> Result<Db> {
+        Db::start(self.try_build()?)
+    }
+}
+
 unsafe impl Send for Db {}
 
 unsafe impl Sync for Db {}
@@ -62,11 +99,24 @@ impl Db {
 
         if !context.read_only {
             let flusher_pagecache = context.pagecache.clone();
-            let flusher = context.flush_every_ms.map(move |fem| {
+            let flush_every_ms =
+                context.max_flush_delay_ms.or(context.flush_every_ms);
+            let min_flush_interval_ms =
+                context.min_flush_interval_ms.unwrap_or(0);
+            let flush_threshold = context.io_buf_size as u64;
+            let flush_on_idle = context.flush_on_idle;
+            let compaction_parallelism = context.compaction_parallelism;
+            let flusher = flush_every_ms.map(move |fem| {
                 flusher::Flusher::new(
                     "log flusher".to_owned(),
                     flusher_pagecache,
-                    fem,
+                    flusher::FlushSchedule {
+                        flush_every_ms: fem,
+                        min_flush_interval_ms,
+                        flush_threshold,
+                        flush_on_idle,
+                        compaction_parallelism,
+                    },
                 )
             });
             *context._flusher.lock() = flusher;
@@ -89,14 +139,30 @@ impl Db {
         let mut tenants = ret.tenants.write();
 
         for (id, root) in context.pagecache.meta(&tx)?.tenants().into_iter() {
+            // share the default tree so that its bloom filter
+            // sees writes made through either handle
+            if id == DEFAULT_TREE_ID {
+                tenants.insert(id, ret.default.clone());
+                continue;
+            }
+
             let tree = Tree {
                 tree_id: id.clone(),
                 subscriptions: Arc::new(Subscriptions::default()),
                 context: context.clone(),
                 root: Arc::new(AtomicU64::new(root)),
                 concurrency_control: Arc::new(RwLock::new(())),
-                merge_operator: Arc::new(RwLock::new(None)),
+                merge_operator: Arc::new(RwLock::new(context.merge_operator)),
+                cache_reservation: Arc::new(CacheReservation::default()),
+                bloom_filter: BloomFilter::for_config(&context),
+                hot_keys: HotKeys::for_config(&context),
+                loads: Arc::new(Loads::default()),
+                locks: Arc::new(AdvisoryLocks::default()),
+                generation: Arc::new(AtomicU64::new(lsn_generation(
+                    context.pagecache.max_reserved_lsn(),
+                ))),
             };
+            tree.fill_bloom_filter()?;
             tenants.insert(id, Arc::new(tree));
         }
 
@@ -128,6 +194,30 @@ impl Db {
         Ok(tree)
     }
 
+    /// Open or create a new disk-backed Tree like `open_tree`,
+    /// reserving `cache_capacity` bytes of cache for its pages
+    /// in addition to the shared `ConfigBuilder::cache_capacity`.
+    /// Pages of this `Tree` are then only paged out to make room
+    /// for each other, so scans of other trees can't evict them.
+    /// Pages are moved into the reservation as they are accessed,
+    /// and reopening a `Tree` with a new capacity replaces its
+    /// earlier reservation. The reservation is not persisted.
+    pub fn open_tree_with_cache_capacity<V: AsRef<[u8]>>(
+        &self,
+        name: V,
+        cache_capacity: u64,
+    ) -> Result<Arc<Tree>> {
+        if cache_capacity < 256 {
+            return Err(Error::Unsupported(
+                "a Tree's cache capacity must be at least 256 bytes".into(),
+            ));
+        }
+
+        let tree = self.open_tree(name)?;
+        tree.cache_reservation.set(cache_capacity);
+        Ok(tree)
+    }
+
     /// Remove a disk-backed collection.
     pub fn drop_tree(&self, name: &[u8]) -> Result<bool> {
         if name == DEFAULT_TREE_ID {
@@ -290,6 +380,109 @@ impl Db {
         }
     }
 
+    /// Returns the number of times that a write lost a
+    /// compare-and-swap race against a concurrent writer
+    /// and had to be retried since this `Db` was started.
+    /// A rapidly growing count indicates heavy contention
+    /// on a small set of keys.
+    pub fn cas_retries(&self) -> u64 {
+        self.context.pagecache.cas_retries()
+    }
+
+    /// Returns the number of leaves that forward scans have
+    /// read ahead of consumption since this `Db` was started.
+    /// See the `ConfigBuilder::scan_prefetch` option.
+    pub fn prefetched_pages(&self) -> u64 {
+        self.context.pagecache.prefetched_pages()
+    }
+
+    /// Returns the number of times that an iterator resumed
+    /// from the leaf of the record it last returned, rather
+    /// than searching for its position from the root, since
+    /// this `Db` was started. Iterators only resume when the
+    /// leaf has not been written to since.
+    pub fn iter_resumes(&self) -> u64 {
+        self.context.iter_resumes.load(Relaxed)
+    }
+
+    /// Returns the number of pages that have been read
+    /// into the cache from disk since this `Db` was started.
+    pub fn page_ins(&self) -> u64 {
+        self.context.pagecache.page_ins()
+    }
+
+    /// Returns the number of times that a page was fetched
+    /// from the page cache since this `Db` was started,
+    /// whether or not it had to be read from disk.
+    pub fn page_fetches(&self) -> u64 {
+        self.context.pagecache.page_fetches()
+    }
+
+    /// Returns the number of times that a page was rewritten
+    /// to relocate it out of a segment being cleaned since
+    /// this `Db` was started. This is always 0 with
+    /// `SegmentMode::Linear`.
+    pub fn rewritten_pages(&self) -> u64 {
+        self.context.pagecache.rewritten_pages()
+    }
+
+    /// Returns the number of flushes that wrote data since
+    /// this `Db` was started, whether they were run by the
+    /// background flusher or by `Tree::flush`.
+    pub fn flushes(&self) -> u64 {
+        self.context.pagecache.flushes()
+    }
+
+    /// Returns `true` while in-memory state whose recovery was
+    /// deferred by `ConfigBuilder::lazy_recovery` is still being
+    /// rebuilt in the background. The `Db` can be used as usual
+    /// in the meantime, although lookups of missing keys can't
+    /// use the bloom filters yet.
+    pub fn recovery_pending(&self) -> bool {
+        let pending = |tree: &Tree| {
+            tree.bloom_filter.as_ref().is_some_and(|b| b.is_filling())
+        };
+        pending(&self.default)
+            || self.tenants.read().values().any(|t| pending(t))
+    }
+
+    /// Returns the error that stopped in-memory state from being
+    /// rebuilt in the background with `ConfigBuilder::lazy_recovery`,
+    /// if any. The affected bloom filters keep passing every key, so
+    /// lookups stay correct, but can't skip missing keys.
+    pub fn recovery_error(&self) -> Option<Error> {
+        let error = |tree: &Tree| {
+            tree.bloom_filter.as_ref().and_then(|b| b.fill_error())
+        };
+        error(&self.default)
+            .or_else(|| self.tenants.read().values().find_map(|t| error(t)))
+    }
+
+    /// Returns the number of IO buffers written to the log
+    /// since this `Db` was started, each of which is followed
+    /// by an fsync unless the config is temporary. Concurrent
+    /// flushes share writes when
+    /// `ConfigBuilder::group_commit_window_us` is set.
+    pub fn log_writes(&self) -> u64 {
+        self.context.pagecache.log_writes()
+    }
+
+    /// Returns the number of times that a page's chain of
+    /// updates was consolidated since this `Db` was started.
+    /// See `Tree::set_consolidation_threshold`.
+    pub fn consolidated_pages(&self) -> u64 {
+        self.context.pagecache.consolidated_pages()
+    }
+
+    /// Returns the number of pages freed by merging nodes
+    /// whose ids have since been reclaimed for reuse. A page
+    /// is only reclaimed once no reader that was pinned when
+    /// it was freed is still pinned, so this stalls while a
+    /// reader holds an epoch open.
+    pub fn freed_pages(&self) -> u64 {
+        self.context.pagecache.freed_pages()
+    }
+
     /// Traverses all files and calculates their total physical
     /// size, then traverses all pages and calculates their
     /// total logical size, then divides the physical size
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use parking_lot::{Condvar, Mutex};
 
@@ -15,22 +15,25 @@ pub(crate) enum ShutdownState {
 
 impl ShutdownState {
     fn is_running(self) -> bool {
-        if let ShutdownState::Running = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, ShutdownState::Running)
     }
 
     fn is_shutdown(self) -> bool {
-        if let ShutdownState::ShutDown = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, ShutdownState::ShutDown)
     }
 }
 
+/// When a `Flusher` flushes, and how it cleans
+/// segments while there is nothing to flush.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FlushSchedule {
+    pub(crate) flush_every_ms: u64,
+    pub(crate) min_flush_interval_ms: u64,
+    pub(crate) flush_threshold: u64,
+    pub(crate) flush_on_idle: bool,
+    pub(crate) compaction_parallelism: usize,
+}
+
 #[derive(Debug)]
 pub(crate) struct Flusher {
     shutdown: Arc<Mutex<ShutdownState>>,
@@ -39,11 +42,19 @@ pub(crate) struct Flusher {
 }
 
 impl Flusher {
-    /// Spawns a thread that periodically calls `callback` until dropped.
+    /// Spawns a thread that periodically calls `callback` until dropped,
+    /// following `schedule`. Once `min_flush_interval_ms` has passed
+    /// since the last flush, the thread flushes early if at least
+    /// `flush_threshold` bytes are awaiting a flush, rather than
+    /// waiting out the rest of `flush_every_ms`. A `min_flush_interval_ms` of 0 disables
+    /// early flushes. With `flush_on_idle`, the thread also
+    /// flushes early once writes stop arriving. When there is
+    /// nothing to flush, the thread cleans fragmented segments
+    /// using `compaction_parallelism` threads.
     pub(crate) fn new(
         name: String,
         pagecache: Arc<PageCache<Frag>>,
-        flush_every_ms: u64,
+        schedule: FlushSchedule,
     ) -> Flusher {
         #[allow(clippy::mutex_atomic)] // mutex used in CondVar below
         let shutdown = Arc::new(Mutex::new(ShutdownState::Running));
@@ -54,7 +65,7 @@ impl Flusher {
             .spawn({
                 let shutdown = shutdown.clone();
                 let sc = sc.clone();
-                move || run(shutdown, sc, pagecache, flush_every_ms)
+                move || run(shutdown, sc, pagecache, schedule)
             })
             .unwrap();
 
@@ -70,13 +81,21 @@ fn run(
     shutdown: Arc<Mutex<ShutdownState>>,
     sc: Arc<Condvar>,
     pagecache: Arc<PageCache<Frag>>,
-    flush_every_ms: u64,
+    schedule: FlushSchedule,
 ) {
+    let FlushSchedule {
+        flush_every_ms,
+        min_flush_interval_ms,
+        flush_threshold,
+        flush_on_idle,
+        compaction_parallelism,
+    } = schedule;
     let flush_every = Duration::from_millis(flush_every_ms);
+    let min_flush_interval = Duration::from_millis(min_flush_interval_ms);
     let mut shutdown = shutdown.lock();
     let mut wrote_data = false;
     while shutdown.is_running() || wrote_data {
-        let before = std::time::Instant::now();
+        let before = Instant::now();
         match pagecache.flush() {
             Ok(0) => {
                 wrote_data = false;
@@ -88,24 +107,21 @@ fn run(
                 // cleaning up the file. try not to
                 // spend more than half of our sleep
                 // time rewriting pages though.
-                while before.elapsed() < flush_every / 2 {
-                    match pagecache.attempt_gc() {
-                        Err(e) => {
-                            error!(
-                                "failed to clean file from async flush thread: {}",
-                                e
-                            );
-
-                            #[cfg(feature = "failpoints")]
-                            pagecache.set_failpoint(e);
-
-                            *shutdown = ShutdownState::ShutDown;
-                            sc.notify_all();
-                            return;
-                        }
-                        Ok(false) => break,
-                        Ok(true) => {}
-                    }
+                let deadline = before + flush_every / 2;
+                if let Err(e) =
+                    clean(&pagecache, compaction_parallelism, deadline)
+                {
+                    error!(
+                        "failed to clean file from async flush thread: {}",
+                        e
+                    );
+
+                    #[cfg(feature = "failpoints")]
+                    pagecache.set_failpoint(e);
+
+                    *shutdown = ShutdownState::ShutDown;
+                    sc.notify_all();
+                    return;
                 }
             }
             Ok(_) => {
@@ -130,13 +146,178 @@ fn run(
         let sleep_duration = flush_every
             .checked_sub(before.elapsed())
             .unwrap_or(Duration::from_millis(1));
+        let deadline = Instant::now() + sleep_duration;
 
-        sc.wait_for(&mut shutdown, sleep_duration);
+        if flush_on_idle {
+            // check several times per interval whether writes
+            // have paused, and flush right away if they have.
+            // writes that keep arriving wait for the deadline.
+            let tick = std::cmp::max(flush_every / 8, Duration::from_millis(1));
+            let mut last_lsn = pagecache.max_reserved_lsn();
+            loop {
+                let wake = std::cmp::min(Instant::now() + tick, deadline);
+                sc.wait_until(&mut shutdown, wake);
+                if !shutdown.is_running() || wake >= deadline {
+                    break;
+                }
+                let lsn = pagecache.max_reserved_lsn();
+                let idle = lsn == last_lsn && pagecache.unflushed_bytes() > 0;
+                let full = min_flush_interval_ms != 0
+                    && before.elapsed() >= min_flush_interval
+                    && pagecache.unflushed_bytes() >= flush_threshold;
+                if idle || full {
+                    break;
+                }
+                last_lsn = lsn;
+            }
+            continue;
+        }
+
+        if min_flush_interval_ms == 0 {
+            sc.wait_until(&mut shutdown, deadline);
+            continue;
+        }
+
+        // after the minimum interval, keep checking whether
+        // enough writes have piled up to be worth flushing
+        // before the deadline.
+        let mut wake = std::cmp::min(before + min_flush_interval, deadline);
+        loop {
+            sc.wait_until(&mut shutdown, wake);
+            if !shutdown.is_running()
+                || wake >= deadline
+                || pagecache.unflushed_bytes() >= flush_threshold
+            {
+                break;
+            }
+            wake = std::cmp::min(Instant::now() + min_flush_interval, deadline);
+        }
     }
     *shutdown = ShutdownState::ShutDown;
     sc.notify_all();
 }
 
+// rewrites pages out of the segments being cleaned until
+// `deadline` passes or nothing is left to clean. with a
+// `parallelism` above 1, that many cleaners run at once on
+// the rayon pool. each rewrite is installed with a CAS while
+// its cleaner is pinned, so cleaners that pick the same page
+// don't interfere, and the pages they replace are only
+// reclaimed once no cleaner can still be reading them.
+fn clean(
+    pagecache: &PageCache<Frag>,
+    parallelism: usize,
+    deadline: Instant,
+) -> Result<()> {
+    let clean_until_deadline = || -> Result<()> {
+        while Instant::now() < deadline {
+            if !pagecache.attempt_gc()? {
+                break;
+            }
+        }
+        Ok(())
+    };
+
+    if parallelism <= 1 {
+        return clean_until_deadline();
+    }
+
+    let results = Mutex::new(Vec::with_capacity(parallelism));
+    rayon::scope(|s| {
+        for _ in 0..parallelism {
+            s.spawn(|_| results.lock().push(clean_until_deadline()));
+        }
+    });
+
+    results.into_inner().into_iter().collect()
+}
+
+/// Runs the flushes scheduled by `Tree::flush_async` on a
+/// single background thread. Requests that arrive while a
+/// flush is running are coalesced into the next one, so
+/// callers never spawn a thread each. The thread is started
+/// on demand, exits after a second without requests, and
+/// only holds a weak reference to the `PageCache` while idle,
+/// so it never keeps a dropped database open.
+#[derive(Debug, Default)]
+pub(crate) struct AsyncFlusher {
+    state: Mutex<AsyncFlushState>,
+    requested: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct AsyncFlushState {
+    // the highest lsn that a caller asked to make stable
+    lsn: Lsn,
+    running: bool,
+}
+
+impl AsyncFlusher {
+    /// Asks the background thread to make `lsn` stable,
+    /// starting the thread if it is not running.
+    pub(crate) fn request(
+        self: &Arc<Self>,
+        pagecache: &Arc<PageCache<Frag>>,
+        lsn: Lsn,
+    ) {
+        let mut state = self.state.lock();
+        state.lsn = std::cmp::max(state.lsn, lsn);
+        if state.running {
+            self.requested.notify_one();
+            return;
+        }
+
+        let spawned = thread::Builder::new()
+            .name("sled flush_async".into())
+            .spawn({
+                let this = self.clone();
+                let pagecache = Arc::downgrade(pagecache);
+                move || this.run(pagecache)
+            });
+
+        match spawned {
+            Ok(_) => state.running = true,
+            // the caller's `FlushHandle::wait` does the flush
+            Err(e) => {
+                warn!("failed to spawn a thread for flush_async: {:?}", e)
+            }
+        }
+    }
+
+    fn run(&self, pagecache: std::sync::Weak<PageCache<Frag>>) {
+        let mut flushed = -1;
+        let mut state = self.state.lock();
+        loop {
+            if state.lsn <= flushed {
+                let timeout =
+                    self.requested.wait_for(&mut state, Duration::from_secs(1));
+                if timeout.timed_out() && state.lsn <= flushed {
+                    state.running = false;
+                    return;
+                }
+                continue;
+            }
+
+            let lsn = state.lsn;
+            let pagecache = match pagecache.upgrade() {
+                Some(pagecache) => pagecache,
+                None => {
+                    state.running = false;
+                    return;
+                }
+            };
+
+            drop(state);
+            if let Err(e) = pagecache.make_stable(lsn) {
+                error!("failed to flush from flush_async thread: {}", e);
+            }
+            drop(pagecache);
+            flushed = lsn;
+            state = self.state.lock();
+        }
+    }
+}
+
 impl Drop for Flusher {
     fn drop(&mut self) {
         let mut shutdown = self.shutdown.lock();
@@ -16,4 +16,10 @@ pub(crate) enum Frag {
     ParentMergeIntention(PageId),
     ParentMergeConfirm,
     ChildMergeCap,
+    /// A value stored in its own page, when
+    /// `ConfigBuilder::separate_values` is set.
+    Value(IVec),
+    /// A `Set` or `Del` that can be read back out of the
+    /// log, when `ConfigBuilder::replication` is set.
+    Logged(LoggedWrite),
 }
@@ -0,0 +1,157 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::Hasher,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+};
+
+use parking_lot::Mutex;
+
+use super::*;
+
+const SKETCH_DEPTH: u64 = 4;
+
+/// Approximate access counts for the most frequently
+/// accessed keys of a `Tree`. Accesses are counted in a
+/// count-min sketch of atomic counters, so recording one
+/// never takes a lock. Counts may be overestimated when
+/// keys collide in every row of the sketch, but never
+/// underestimated. At most `capacity` keys are tracked.
+/// A key's count is checked against the coldest tracked
+/// key each time it reaches a power of two, so the lock on
+/// the tracked keys is only taken a logarithmic number of
+/// times per key, and a key that becomes hotter than the
+/// coldest tracked key replaces it.
+pub(crate) struct HotKeys {
+    capacity: usize,
+    sketch: Vec<AtomicU64>,
+    width: u64,
+    // the lowest count among the tracked keys, once
+    // `capacity` keys are tracked, and 0 before
+    threshold: AtomicU64,
+    tracked: Mutex<HashMap<IVec, ()>>,
+}
+
+impl HotKeys {
+    /// Returns an empty sketch if the configuration
+    /// enables `track_hot_keys`.
+    pub(crate) fn for_config(config: &Config) -> Option<Arc<HotKeys>> {
+        if config.track_hot_keys > 0 {
+            Some(Arc::new(HotKeys::new(config.track_hot_keys)))
+        } else {
+            None
+        }
+    }
+
+    fn new(capacity: usize) -> HotKeys {
+        let width = std::cmp::max(capacity * 16, 1024).next_power_of_two();
+        HotKeys {
+            capacity,
+            sketch: (0..width as u64 * SKETCH_DEPTH)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            width: width as u64,
+            threshold: AtomicU64::new(0),
+            tracked: Mutex::new(HashMap::with_capacity(capacity)),
+        }
+    }
+
+    /// Counts an access to a key.
+    pub(crate) fn record(&self, key: &[u8]) {
+        let count = self
+            .cells(key)
+            .map(|cell| self.sketch[cell].fetch_add(1, Relaxed) + 1)
+            .min()
+            .unwrap();
+
+        if count.is_power_of_two() && count > self.threshold.load(Relaxed) {
+            self.track(key);
+        }
+    }
+
+    fn track(&self, key: &[u8]) {
+        let mut tracked = self.tracked.lock();
+
+        if !tracked.contains_key(key) {
+            if tracked.len() >= self.capacity {
+                let (coldest, min) = tracked
+                    .keys()
+                    .map(|tracked| (tracked.clone(), self.estimate(tracked)))
+                    .min_by_key(|(_, count)| *count)
+                    .unwrap();
+                if min >= self.estimate(key) {
+                    self.threshold.store(min, Relaxed);
+                    return;
+                }
+                tracked.remove(&coldest);
+            }
+            tracked.insert(IVec::from(key), ());
+        }
+
+        if tracked.len() >= self.capacity {
+            let min = tracked.keys().map(|k| self.estimate(k)).min().unwrap();
+            self.threshold.store(min, Relaxed);
+        }
+    }
+
+    // the count of a key is the lowest of its counters
+    fn estimate(&self, key: &[u8]) -> u64 {
+        self.cells(key)
+            .map(|cell| self.sketch[cell].load(Relaxed))
+            .min()
+            .unwrap()
+    }
+
+    // derives a counter in each row from two halves of
+    // a single hash, like the bloom filter does
+    fn cells(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(key);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash & 0xFFFF_FFFF, hash >> 32);
+        let width = self.width;
+
+        (0..SKETCH_DEPTH).map(move |row| {
+            let col = h1.wrapping_add(row * h2) % width;
+            (row * width + col) as usize
+        })
+    }
+
+    /// Returns the tracked keys and their counts,
+    /// most frequently accessed first.
+    pub(crate) fn hottest(&self) -> Vec<(IVec, u64)> {
+        let mut hottest: Vec<(IVec, u64)> = self
+            .tracked
+            .lock()
+            .keys()
+            .map(|key| (key.clone(), self.estimate(key)))
+            .collect();
+        hottest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        hottest
+    }
+}
+
+#[test]
+fn hotter_keys_replace_the_coldest() {
+    let hot_keys = HotKeys::new(2);
+
+    for _ in 0..3 {
+        hot_keys.record(b"a");
+    }
+    hot_keys.record(b"b");
+
+    // c is no hotter than b yet
+    hot_keys.record(b"c");
+    assert_eq!(
+        hot_keys.hottest(),
+        vec![(IVec::from(b"a"), 3), (IVec::from(b"b"), 1)]
+    );
+
+    hot_keys.record(b"c");
+    assert_eq!(
+        hot_keys.hottest(),
+        vec![(IVec::from(b"a"), 3), (IVec::from(b"c"), 2)]
+    );
+}
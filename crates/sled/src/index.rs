@@ -0,0 +1,123 @@
+use super::*;
+
+/// Prefix under which all index entries are stored.
+const INDEX_PREFIX: &[u8] = b"__sled__index/";
+
+/// A secondary index over the rows of a `Tree`.
+///
+/// Rows written through the `Index` keep an inverted
+/// index entry, under a reserved prefix in the same
+/// `Tree`, for the value returned by the extraction
+/// closure. The row and its index entries are written
+/// atomically with respect to other writers and crashes.
+///
+/// Rows written directly to the `Tree` are not indexed,
+/// and iterating over the whole `Tree` will also yield
+/// the index entries, which all start with
+/// `__sled__index/`.
+pub struct Index<'a, F> {
+    pub(super) tree: &'a Tree,
+    pub(super) prefix: Vec<u8>,
+    pub(super) extract: F,
+}
+
+impl<'a, F> Index<'a, F>
+where
+    F: Fn(&[u8]) -> Option<Vec<u8>>,
+{
+    pub(super) fn new(tree: &'a Tree, name: &[u8], extract: F) -> Self {
+        let mut prefix = INDEX_PREFIX.to_vec();
+        prefix.extend_from_slice(&(name.len() as u64).to_be_bytes());
+        prefix.extend_from_slice(name);
+        Index {
+            tree,
+            prefix,
+            extract,
+        }
+    }
+
+    /// Set a row, updating its index entry, and
+    /// return the last value if it was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        let key = key.as_ref();
+        let value = IVec::from(value);
+
+        let peg = self.tree.context.pin_log()?;
+        let cc = self.tree.concurrency_control.write();
+
+        let last = self.tree.get_inner(key)?;
+        let old_entry = last.as_ref().and_then(|old| (self.extract)(old));
+        let new_entry = (self.extract)(&value);
+
+        if old_entry != new_entry {
+            if let Some(indexed) = old_entry {
+                self.tree.remove_inner(self.entry_key(&indexed, key))?;
+            }
+            if let Some(indexed) = new_entry {
+                self.tree.insert_inner::<_, &[u8]>(
+                    self.entry_key(&indexed, key),
+                    &[],
+                )?;
+            }
+        }
+        self.tree.insert_inner::<_, IVec>(key, value)?;
+        drop(cc);
+
+        peg.seal_batch()?;
+
+        Ok(last)
+    }
+
+    /// Delete a row and its index entry, returning
+    /// the old value if it existed.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let key = key.as_ref();
+
+        let peg = self.tree.context.pin_log()?;
+        let cc = self.tree.concurrency_control.write();
+
+        let last = self.tree.remove_inner(key)?;
+        if let Some(indexed) = last.as_ref().and_then(|old| (self.extract)(old))
+        {
+            self.tree.remove_inner(self.entry_key(&indexed, key))?;
+        }
+        drop(cc);
+
+        peg.seal_batch()?;
+
+        Ok(last)
+    }
+
+    /// Returns the primary keys of all rows whose
+    /// indexed value equals `indexed`, in key order.
+    pub fn lookup<V: AsRef<[u8]>>(
+        &self,
+        indexed: V,
+    ) -> impl '_ + DoubleEndedIterator<Item = Result<IVec>> {
+        let prefix = self.value_prefix(indexed.as_ref());
+        let len = prefix.len();
+        self.tree
+            .scan_prefix(prefix)
+            .keys()
+            .map(move |res| res.map(|key| IVec::from(&key[len..])))
+    }
+
+    // the indexed value is length-prefixed so that no
+    // value's entries can be mistaken for another's.
+    fn value_prefix(&self, indexed: &[u8]) -> Vec<u8> {
+        let mut prefix = self.prefix.clone();
+        prefix.extend_from_slice(&(indexed.len() as u64).to_be_bytes());
+        prefix.extend_from_slice(indexed);
+        prefix
+    }
+
+    fn entry_key(&self, indexed: &[u8], key: &[u8]) -> Vec<u8> {
+        let mut entry = self.value_prefix(indexed);
+        entry.extend_from_slice(key);
+        entry
+    }
+}
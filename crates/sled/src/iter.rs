@@ -1,4 +1,4 @@
-use std::ops::Bound;
+use std::{collections::VecDeque, ops::Bound, sync::atomic::Ordering::Relaxed};
 
 use pagecache::{Measure, M};
 
@@ -25,6 +25,61 @@ fn possible_predecessor(s: &[u8]) -> Option<Vec<u8>> {
     }
 }
 
+// whether a leaf beginning at `lo` may contain
+// keys below the `hi` bound of a scan
+pub(crate) fn below_bound(lo: &[u8], hi: &Bound<IVec>) -> bool {
+    if lo.is_empty() {
+        // the leaf is unbounded on the right
+        return false;
+    }
+    match hi {
+        Bound::Unbounded => true,
+        Bound::Included(ref h) => lo <= h.as_ref(),
+        Bound::Excluded(ref h) => lo < h.as_ref(),
+    }
+}
+
+fn prefetch_leaves(
+    tree: &Tree,
+    mut pid: PageId,
+    leaves: usize,
+    hi: &Bound<IVec>,
+) {
+    let tx = match tree.context.pagecache.begin() {
+        Ok(tx) => tx,
+        Err(_) => return,
+    };
+
+    // don't read ahead more than the cache can hold,
+    // which would evict the pages we read earliest.
+    let mut budget = tree.context.cache_capacity;
+
+    for _ in 0..leaves {
+        tree.reserve_cache(pid);
+        let node = match tree.context.pagecache.prefetch(pid, &tx) {
+            Ok(Some((_, Frag::Base(node), size))) if size <= budget => {
+                budget -= size;
+                node
+            }
+            // read-ahead is advisory, so stop quietly on
+            // errors and leave them for the scan to hit.
+            _ => return,
+        };
+
+        if !below_bound(&node.hi, hi) {
+            return;
+        }
+        pid = match node.next {
+            Some(next) => next,
+            None => return,
+        };
+    }
+}
+
+// a predicate on decoded keys that is checked before
+// a record's value is read out of its leaf
+pub(crate) type KeyFilter<'a> = dyn 'a + Fn(&[u8]) -> bool;
+
 macro_rules! iter_try {
     ($e:expr) => {
         match $e {
@@ -39,14 +94,31 @@ pub struct Iter<'a> {
     pub(super) tree: &'a Tree,
     pub(super) hi: Bound<IVec>,
     pub(super) lo: Bound<IVec>,
-    pub(super) cached_node: Option<(PageId, &'a Node)>,
-    pub(super) tx: Result<Tx<'a, Frag>>,
+    // the leaf that the last record was returned from, the
+    // lsn of its page at the time, and the record's index.
+    // the next call resumes there if the page is unchanged.
+    // nothing else is kept between calls, so an idle `Iter`
+    // doesn't hold back epoch reclamation.
+    pub(super) cursor: Option<(PageId, Lsn, usize)>,
     pub(super) going_forward: bool,
+    // the last leaf that we read ahead of
+    pub(super) prefetched_from: Option<PageId>,
+    // skips records whose keys don't match, set by
+    // `Tree::scan_filtered`
+    pub(super) key_filter: Option<Box<KeyFilter<'a>>>,
+    // skips reading separated values, set by `Iter::keys`
+    pub(super) keys_only: bool,
+    // the number of records left to return, set by
+    // `Tree::scan_limited`
+    pub(super) remaining: Option<usize>,
 }
 
 impl<'a> Iter<'a> {
     /// Iterate over the keys of this Tree
-    pub fn keys(self) -> impl 'a + DoubleEndedIterator<Item = Result<IVec>> {
+    pub fn keys(
+        mut self,
+    ) -> impl 'a + DoubleEndedIterator<Item = Result<IVec>> {
+        self.keys_only = true;
         self.map(|r| r.map(|(k, _v)| k))
     }
 
@@ -55,6 +127,35 @@ impl<'a> Iter<'a> {
         self.map(|r| r.map(|(_k, v)| v))
     }
 
+    /// Group the keys and values of this iterator into batches
+    /// of `n` items, except for the last batch which may be
+    /// smaller. If an error is encountered, it is returned in
+    /// place of the batch that was being filled, and no further
+    /// batches are returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// for i in 0..5u8 {
+    ///     t.insert(vec![i], vec![i]).unwrap();
+    /// }
+    ///
+    /// let sizes: Vec<usize> =
+    ///     t.iter().batches(2).map(|batch| batch.unwrap().len()).collect();
+    /// assert_eq!(sizes, vec![2, 2, 1]);
+    /// ```
+    pub fn batches(self, n: usize) -> Batches<Self> {
+        Batches::new(self, n)
+    }
+
     fn bounds_collapsed(&self) -> bool {
         match (&self.lo, &self.hi) {
             (Bound::Included(ref start), Bound::Included(ref end))
@@ -74,6 +175,29 @@ impl<'a> Iter<'a> {
         }
     }
 
+    // Reads the leaves following this one into the cache
+    // so that forward iteration doesn't stall on each one.
+    fn prefetch_after(&self, node: &Node) {
+        let leaves = self.tree.context.scan_prefetch;
+        if leaves == 0 || !below_bound(&node.hi, &self.hi) {
+            return;
+        }
+        let next = match node.next {
+            Some(next) => next,
+            None => return,
+        };
+
+        let tree = self.tree.clone();
+        let hi = self.hi.clone();
+        let read_ahead = move || prefetch_leaves(&tree, next, leaves, &hi);
+
+        if self.tree.context.async_io {
+            rayon::spawn(read_ahead);
+        } else {
+            read_ahead();
+        }
+    }
+
     fn high_key(&self) -> &[u8] {
         const MAX_KEY: &[u8] = &[255; 1024 * 1024];
         match self.hi {
@@ -87,61 +211,149 @@ impl<'a> Iterator for Iter<'a> {
     type Item = Result<(IVec, IVec)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let _measure = Measure::new(&M.tree_scan);
-        let _ = self.tree.concurrency_control.read();
+        if self.remaining == Some(0) {
+            return None;
+        }
 
-        let tx: &'a Tx<'a, _> = match self.tx {
-            Ok(ref tx) => {
-                let tx_ref = tx as *const Tx<'a, _>;
-                unsafe { &*tx_ref as &'a Tx<'a, _> }
+        loop {
+            let (key, stored) = iter_try!(self.next_stored()?);
+            if let Some(item) = iter_try!(self.load(key, stored)) {
+                self.count_returned();
+                return Some(Ok(item));
             }
-            Err(ref e) => return Some(Err(e.clone())),
-        };
+        }
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+
+        loop {
+            let (key, stored) = iter_try!(self.next_back_stored()?);
+            if let Some(item) = iter_try!(self.load(key, stored)) {
+                self.count_returned();
+                return Some(Ok(item));
+            }
+        }
+    }
+}
+
+impl<'a> Iter<'a> {
+    fn count_returned(&mut self) {
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= 1;
+        }
+    }
+
+    // reads the value that a record refers to, when values
+    // are separated. returns `None` if the key was removed
+    // since its leaf was read.
+    fn load(&self, key: IVec, stored: IVec) -> Result<Option<(IVec, IVec)>> {
+        if self.keys_only || !self.tree.context.separate_values {
+            return Ok(Some((key, stored)));
+        }
 
-        let (mut pid, mut node) =
-            match (self.going_forward, self.cached_node.take()) {
-                (true, Some((pid, node))) => (pid, node),
-                _ => {
-                    let view =
-                        iter_try!(self.tree.node_for_key(self.low_key(), &tx));
-                    (view.pid, view.node)
+        let tx = self.tree.context.pagecache.begin()?;
+
+        if let Some(value) = self.tree.load_separated_value(&stored, &tx)? {
+            return Ok(Some((key, value)));
+        }
+
+        // the value was replaced after we read its leaf
+        Ok(self.tree.get_inner(&key)?.map(|value| (key, value)))
+    }
+
+    fn next_stored(&mut self) -> Option<Result<(IVec, IVec)>> {
+        let _measure = Measure::new(&M.tree_scan);
+
+        // we only pin the epoch while reading, so a slow consumer
+        // doesn't hold back reclamation of the pages that it has
+        // already passed
+        let tx = &iter_try!(self.tree.context.pagecache.begin());
+
+        let resumed = match (self.going_forward, self.cursor.take()) {
+            (true, Some((pid, lsn, idx))) => {
+                match iter_try!(self.tree.view_for_pid(pid, tx)) {
+                    // the page may have changed, or even been
+                    // freed and reused, since we last read it
+                    Some(view) if view.ptr.last_lsn() == lsn => {
+                        Some((view, idx))
+                    }
+                    _ => None,
                 }
-            };
+            }
+            _ => None,
+        };
+        let (mut view, mut hint) = match resumed {
+            Some((view, idx)) => {
+                self.tree.context.iter_resumes.fetch_add(1, Relaxed);
+                (view, Some(idx))
+            }
+            None => {
+                (iter_try!(self.tree.node_for_key(self.low_key(), tx)), None)
+            }
+        };
 
         for _ in 0..MAX_LOOPS {
             if self.bounds_collapsed() {
                 return None;
             }
 
-            if !node.contains_upper_bound(&self.lo) {
+            if !view.contains_upper_bound(&self.lo) {
                 // view too low (maybe merged, maybe exhausted?)
-                let next_pid = node.next?;
-                assert_ne!(pid, next_pid);
-                let view = if let Some(view) =
-                    iter_try!(self.tree.view_for_pid(next_pid, &tx))
+                let next_pid = view.next?;
+                assert_ne!(view.pid, next_pid);
+                view = if let Some(view) =
+                    iter_try!(self.tree.view_for_pid(next_pid, tx))
                 {
                     view
                 } else {
-                    iter_try!(self.tree.node_for_key(self.low_key(), &tx))
+                    iter_try!(self.tree.node_for_key(self.low_key(), tx))
                 };
 
-                pid = view.pid;
-                node = view.node;
+                hint = None;
                 continue;
-            } else if !node.contains_lower_bound(&self.lo, true) {
+            } else if !view.contains_lower_bound(&self.lo, true) {
                 // view too high (maybe split, maybe exhausted?)
-                let seek_key = possible_predecessor(&node.lo)?;
-                let view = iter_try!(self.tree.node_for_key(seek_key, &tx));
-                pid = view.pid;
-                node = view.node;
+                let seek_key = possible_predecessor(&view.lo)?;
+                view = iter_try!(self.tree.node_for_key(seek_key, tx));
+                hint = None;
                 continue;
             }
 
-            if let Some((key, value)) = node.successor(&self.lo) {
+            if let Some((idx, key, value)) =
+                view.successor(&self.lo, self.key_filter.as_deref(), hint)
+            {
+                // a limited scan that ends in this leaf has
+                // nothing to read ahead for
+                let ends_here = self.remaining.is_some_and(|remaining| {
+                    remaining <= view.data.len() - idx
+                });
+                if self.prefetched_from != Some(view.pid) && !ends_here {
+                    self.prefetched_from = Some(view.pid);
+                    self.prefetch_after(&view);
+                }
+
                 self.lo = Bound::Excluded(key.clone());
-                self.cached_node = Some((pid, node));
+                self.cursor = Some((view.pid, view.ptr.last_lsn(), idx));
                 self.going_forward = true;
 
+                // values that are stored in the leaf are checked
+                // here, while we know which page they came from
+                let value =
+                    if self.keys_only || self.tree.context.separate_values {
+                        value
+                    } else {
+                        iter_try!(self.tree.unframe_value(&value, &view.ptr))
+                    };
+
                 match self.hi {
                     Bound::Unbounded => return Some(Ok((key, value))),
                     Bound::Included(ref h) if *h >= key => {
@@ -153,81 +365,102 @@ impl<'a> Iterator for Iter<'a> {
                     _ => return None,
                 }
             } else {
-                if node.hi.is_empty() {
+                if view.hi.is_empty() {
                     return None;
                 }
-                self.lo = Bound::Included(node.hi.clone());
+                self.lo = Bound::Included(view.hi.clone());
                 continue;
             }
         }
+        if self.tree.context.on_invariant_violation == InvariantPolicy::Error {
+            error!(
+                "tree traversal next({:?}) failed to terminate on {:?}",
+                self.lo, self.tree
+            );
+            return Some(Err(Error::Corruption {
+                at: view.ptr.last_disk_ptr(),
+            }));
+        }
         panic!(
             "fucked up tree traversal next({:?}) on {:?}",
             self.lo, self.tree
         );
     }
 
-    fn last(mut self) -> Option<Self::Item> {
-        self.next_back()
-    }
-}
-
-impl<'a> DoubleEndedIterator for Iter<'a> {
-    fn next_back(&mut self) -> Option<Self::Item> {
+    fn next_back_stored(&mut self) -> Option<Result<(IVec, IVec)>> {
         let _measure = Measure::new(&M.tree_reverse_scan);
-        let _ = self.tree.concurrency_control.read();
 
-        let tx: &'a Tx<'a, _> = match self.tx {
-            Ok(ref tx) => {
-                let tx_ref = tx as *const Tx<'a, _>;
-                unsafe { &*tx_ref as &'a Tx<'a, _> }
-            }
-            Err(ref e) => return Some(Err(e.clone())),
-        };
+        // we only pin the epoch while reading, so a slow consumer
+        // doesn't hold back reclamation of the pages that it has
+        // already passed
+        let tx = &iter_try!(self.tree.context.pagecache.begin());
 
-        let (mut pid, mut node) =
-            match (self.going_forward, self.cached_node.take()) {
-                (false, Some((pid, node))) => (pid, node),
-                _ => {
-                    let view =
-                        iter_try!(self.tree.node_for_key(self.high_key(), &tx));
-                    (view.pid, view.node)
+        let resumed = match (self.going_forward, self.cursor.take()) {
+            (false, Some((pid, lsn, idx))) => {
+                match iter_try!(self.tree.view_for_pid(pid, tx)) {
+                    // the page may have changed, or even been
+                    // freed and reused, since we last read it
+                    Some(view) if view.ptr.last_lsn() == lsn => {
+                        Some((view, idx))
+                    }
+                    _ => None,
                 }
-            };
+            }
+            _ => None,
+        };
+        let (mut view, mut hint) = match resumed {
+            Some((view, idx)) => {
+                self.tree.context.iter_resumes.fetch_add(1, Relaxed);
+                (view, Some(idx))
+            }
+            None => {
+                (iter_try!(self.tree.node_for_key(self.high_key(), tx)), None)
+            }
+        };
 
         for _ in 0..MAX_LOOPS {
             if self.bounds_collapsed() {
                 return None;
             }
 
-            if !node.contains_upper_bound(&self.hi) {
+            if !view.contains_upper_bound(&self.hi) {
                 // node too low (maybe merged, maybe exhausted?)
-                let next_pid = node.next?;
-                assert_ne!(pid, next_pid);
-                let view = if let Some(view) =
-                    iter_try!(self.tree.view_for_pid(next_pid, &tx))
+                let next_pid = view.next?;
+                assert_ne!(view.pid, next_pid);
+                view = if let Some(view) =
+                    iter_try!(self.tree.view_for_pid(next_pid, tx))
                 {
                     view
                 } else {
-                    iter_try!(self.tree.node_for_key(self.high_key(), &tx))
+                    iter_try!(self.tree.node_for_key(self.high_key(), tx))
                 };
 
-                pid = view.pid;
-                node = view.node;
+                hint = None;
                 continue;
-            } else if !node.contains_lower_bound(&self.hi, false) {
+            } else if !view.contains_lower_bound(&self.hi, false) {
                 // view too high (maybe split, maybe exhausted?)
-                let seek_key = possible_predecessor(&node.lo)?;
-                let view = iter_try!(self.tree.node_for_key(seek_key, &tx));
-                pid = view.pid;
-                node = view.node;
+                let seek_key = possible_predecessor(&view.lo)?;
+                view = iter_try!(self.tree.node_for_key(seek_key, tx));
+                hint = None;
                 continue;
             }
 
-            if let Some((key, value)) = node.predecessor(&self.hi) {
+            if let Some((idx, key, value)) =
+                view.predecessor(&self.hi, self.key_filter.as_deref(), hint)
+            {
                 self.hi = Bound::Excluded(key.clone());
-                self.cached_node = Some((pid, node));
+                self.cursor = Some((view.pid, view.ptr.last_lsn(), idx));
                 self.going_forward = false;
 
+                // values that are stored in the leaf are checked
+                // here, while we know which page they came from
+                let value =
+                    if self.keys_only || self.tree.context.separate_values {
+                        value
+                    } else {
+                        iter_try!(self.tree.unframe_value(&value, &view.ptr))
+                    };
+
                 match self.lo {
                     Bound::Unbounded => return Some(Ok((key, value))),
                     Bound::Included(ref l) if *l <= key => {
@@ -239,13 +472,22 @@ impl<'a> DoubleEndedIterator for Iter<'a> {
                     _ => return None,
                 }
             } else {
-                if node.lo.is_empty() {
+                if view.lo.is_empty() {
                     return None;
                 }
-                self.hi = Bound::Excluded(node.lo.clone());
+                self.hi = Bound::Excluded(view.lo.clone());
                 continue;
             }
         }
+        if self.tree.context.on_invariant_violation == InvariantPolicy::Error {
+            error!(
+                "tree traversal next_back({:?}) failed to terminate on {:?}",
+                self.hi, self.tree
+            );
+            return Some(Err(Error::Corruption {
+                at: view.ptr.last_disk_ptr(),
+            }));
+        }
         panic!(
             "fucked up tree traversal next_back({:?}) on {:?}",
             self.hi, self.tree
@@ -253,6 +495,329 @@ impl<'a> DoubleEndedIterator for Iter<'a> {
     }
 }
 
+/// An iterator over keys and values in a `Tree` that owns
+/// a handle to it, created by `Tree::owned_range`. It has no
+/// lifetime, so it can be returned from functions and sent to
+/// other threads.
+///
+/// Each item is found with a fresh lookup from the last key
+/// returned, so iterating is slower than with `Iter`, and
+/// concurrent writes to keys that haven't been reached yet
+/// are observed.
+#[derive(Clone)]
+pub struct OwnedIter {
+    pub(super) tree: Tree,
+    pub(super) lo: Bound<IVec>,
+    pub(super) hi: Bound<IVec>,
+}
+
+impl OwnedIter {
+    fn bounds(&self) -> (Bound<IVec>, Bound<IVec>) {
+        (self.lo.clone(), self.hi.clone())
+    }
+}
+
+impl Iterator for OwnedIter {
+    type Item = Result<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.tree.range::<IVec, _>(self.bounds()).next()?;
+        if let Ok((ref key, _)) = item {
+            self.lo = Bound::Excluded(key.clone());
+        }
+        Some(item)
+    }
+}
+
+impl DoubleEndedIterator for OwnedIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.tree.range::<IVec, _>(self.bounds()).next_back()?;
+        if let Ok((ref key, _)) = item {
+            self.hi = Bound::Excluded(key.clone());
+        }
+        Some(item)
+    }
+}
+
+/// An iterator over keys in a `Tree` that defers reading
+/// their values, created by `Tree::scan_lazy`.
+pub struct LazyIter<'a> {
+    pub(super) iter: Iter<'a>,
+}
+
+/// A value yielded by `LazyIter`, which is only read
+/// when `LazyValue::load` is called.
+#[derive(Debug, Clone)]
+pub struct LazyValue<'a> {
+    tree: &'a Tree,
+    key: IVec,
+    // the value itself, unless values are separated
+    stored: IVec,
+}
+
+impl<'a> LazyValue<'a> {
+    /// Reads the value, returning `None` if its key has
+    /// been removed since it was scanned. When values are
+    /// separated with `ConfigBuilder::separate_values`, this
+    /// looks the key up again, so it may return a value that
+    /// was written after the key was scanned.
+    pub fn load(&self) -> Result<Option<IVec>> {
+        if !self.tree.context.separate_values {
+            return Ok(Some(self.stored.clone()));
+        }
+
+        // the page that held the value when it was scanned
+        // may have been freed and reused since then
+        self.tree.get(&self.key)
+    }
+}
+
+impl<'a> Iterator for LazyIter<'a> {
+    type Item = Result<(IVec, LazyValue<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, stored) = iter_try!(self.iter.next_stored()?);
+        Some(Ok(self.lazy(key, stored)))
+    }
+}
+
+impl<'a> DoubleEndedIterator for LazyIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, stored) = iter_try!(self.iter.next_back_stored()?);
+        Some(Ok(self.lazy(key, stored)))
+    }
+}
+
+impl<'a> LazyIter<'a> {
+    fn lazy(&self, key: IVec, stored: IVec) -> (IVec, LazyValue<'a>) {
+        let value = LazyValue {
+            tree: self.iter.tree,
+            key: key.clone(),
+            stored,
+        };
+        (key, value)
+    }
+}
+
+/// A record yielded by `RawIter`, with its key as it
+/// is physically stored in its leaf.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawRecord {
+    /// The `lo` bound of the leaf that the record was read
+    /// from, which its key is encoded relative to.
+    pub lo: IVec,
+    /// The prefix-encoded key: the number of leading bytes
+    /// that it shares with `lo`, followed by the rest of
+    /// the key. `prefix_decode(&lo, &key)` recovers it.
+    pub key: IVec,
+    /// The value.
+    pub value: IVec,
+}
+
+/// An iterator over the records of a `Tree` with their
+/// keys left prefix-encoded, created by `Tree::scan_raw`.
+///
+/// Each leaf is read in full when the iterator reaches it,
+/// so its records reflect a single version of that leaf,
+/// but concurrent writes to later leaves are observed.
+pub struct RawIter<'a> {
+    pub(super) tree: &'a Tree,
+    // the lowest key of the leaf to read once `records` is
+    // drained, or `None` after the last leaf was read
+    pub(super) from: Option<IVec>,
+    pub(super) records: VecDeque<RawRecord>,
+}
+
+impl<'a> Iterator for RawIter<'a> {
+    type Item = Result<RawRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.records.pop_front() {
+                return Some(Ok(record));
+            }
+            let from = self.from.take()?;
+            iter_try!(self.read_leaf(&from));
+        }
+    }
+}
+
+impl<'a> RawIter<'a> {
+    // returns the buffered records of one leaf, reading
+    // leaves until one holds records or there are no more
+    pub(crate) fn next_leaf(&mut self) -> Result<Option<Vec<RawRecord>>> {
+        while self.records.is_empty() {
+            match self.from.take() {
+                Some(from) => self.read_leaf(&from)?,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(self.records.drain(..).collect()))
+    }
+
+    // buffers the records at or above `from` in the
+    // leaf that contains it
+    fn read_leaf(&mut self, from: &[u8]) -> Result<()> {
+        let _measure = Measure::new(&M.tree_scan);
+        let tx = self.tree.context.pagecache.begin()?;
+        let view = self.tree.node_for_key(from, &tx)?;
+
+        let items = match view.data {
+            Data::Leaf(ref items) => items,
+            Data::Index(_) => {
+                return Err(Error::ReportableBug(format!(
+                    "expected pid {} to be a leaf",
+                    view.pid
+                )));
+            }
+        };
+
+        for (key, stored) in items {
+            let decoded = prefix_decode(&view.lo, key);
+            if &*decoded < from {
+                continue;
+            }
+            let value = match self.tree.load_value(stored, &view.ptr, &tx)? {
+                Some(value) => value,
+                // the value was replaced after we read its leaf
+                None => match self.tree.get_inner(&decoded)? {
+                    Some(value) => value,
+                    None => continue,
+                },
+            };
+            self.records.push_back(RawRecord {
+                lo: view.lo.clone(),
+                key: key.clone(),
+                value,
+            });
+        }
+
+        if !view.hi.is_empty() {
+            self.from = Some(view.hi.clone());
+        }
+        Ok(())
+    }
+}
+
+/// A scan over keys and values that is consumed with
+/// `for_each_ok`, which handles errors once for the whole
+/// scan rather than once per record. Created by
+/// `Tree::try_scan`.
+pub struct TryIter<'a> {
+    pub(super) iter: Iter<'a>,
+    pub(super) done: bool,
+}
+
+impl<'a> TryIter<'a> {
+    /// Calls `f` with each remaining key and value until `f`
+    /// returns `false` or the scan is exhausted. A later call
+    /// resumes after the last record passed to `f`. If reading
+    /// a record fails, the scan stops and the error is
+    /// returned, and later calls return `Ok` without calling
+    /// `f`.
+    pub fn for_each_ok<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(IVec, IVec) -> bool,
+    {
+        if self.done {
+            return Ok(());
+        }
+
+        for item in &mut self.iter {
+            match item {
+                Ok((key, value)) => {
+                    if !f(key, value) {
+                        return Ok(());
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Err(e);
+                }
+            }
+        }
+
+        self.done = true;
+        Ok(())
+    }
+}
+
+/// An iterator over batches of keys and values,
+/// created by `Iter::batches`.
+pub struct Batches<I> {
+    iter: I,
+    n: usize,
+    done: bool,
+}
+
+impl<I> Batches<I> {
+    fn new(iter: I, n: usize) -> Batches<I> {
+        assert!(n > 0, "batches must hold at least one item");
+        Batches {
+            iter,
+            n,
+            done: false,
+        }
+    }
+}
+
+impl<I> Iterator for Batches<I>
+where
+    I: Iterator<Item = Result<(IVec, IVec)>>,
+{
+    type Item = Result<Vec<(IVec, IVec)>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut batch = Vec::with_capacity(self.n);
+        while batch.len() < self.n {
+            match self.iter.next() {
+                Some(Ok(item)) => batch.push(item),
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
+    }
+}
+
+#[test]
+fn batches_stop_at_the_first_error() {
+    let item = |i: u8| Ok((IVec::from(vec![i]), IVec::from(vec![i])));
+    let items = vec![
+        item(0),
+        item(1),
+        item(2),
+        Err(Error::ReportableBug("injected".into())),
+        item(3),
+    ];
+
+    let mut batches = Batches::new(items.into_iter(), 2);
+    assert_eq!(
+        batches.next(),
+        Some(Ok(vec![item(0).unwrap(), item(1).unwrap()]))
+    );
+    assert_eq!(
+        batches.next(),
+        Some(Err(Error::ReportableBug("injected".into())))
+    );
+    assert_eq!(batches.next(), None);
+}
+
 #[test]
 fn test_possible_predecessor() {
     assert_eq!(possible_predecessor(b""), None);
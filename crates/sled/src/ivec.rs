@@ -235,3 +235,41 @@ fn ivec_usage() {
     let iv2 = IVec::from(&[4; 128][..]);
     assert_eq!(iv2, vec![4; 128]);
 }
+
+#[test]
+fn ivec_conversions() {
+    let small: &[u8] = &[1, 2, 3];
+    let large = vec![7; 256];
+
+    for bytes in &[small.to_vec(), large] {
+        let from_vec = IVec::from(bytes.clone());
+        let from_slice = IVec::from(&bytes[..]);
+        let from_arc = IVec::from(Arc::<[u8]>::from(&bytes[..]));
+
+        assert_eq!(from_vec, from_slice);
+        assert_eq!(from_vec, from_arc);
+        assert_eq!(&*from_vec, &bytes[..]);
+        assert_eq!(from_vec.as_ref(), &bytes[..]);
+        assert!(from_vec == bytes[..]);
+
+        let arc: Arc<[u8]> = from_vec.clone().into();
+        assert_eq!(&*arc, &bytes[..]);
+    }
+
+    assert_eq!(IVec::from("abc"), IVec::from(b"abc"));
+    assert_eq!(IVec::default(), IVec::from(&[]));
+}
+
+#[test]
+fn ivec_serde_round_trip() {
+    for len in &[0, 3, 255] {
+        let iv = IVec::from(vec![9; *len]);
+        let serialized = bincode::serialize(&iv).unwrap();
+        let deserialized: IVec = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(iv, deserialized);
+
+        // IVec serializes identically to a byte buffer
+        let as_bytes = serde_bytes::ByteBuf::from(vec![9; *len]);
+        assert_eq!(serialized, bincode::serialize(&as_bytes).unwrap());
+    }
+}
@@ -34,53 +34,93 @@
 #![cfg_attr(test, deny(clippy::rust_2018_compatibility))]
 #![cfg_attr(test, deny(clippy::rust_2018_idioms))]
 
+mod advisory_locks;
+mod audit;
 mod batch;
 mod binary_search;
+mod bloom;
+mod cache_reservation;
+mod codec;
 mod context;
 mod data;
 mod db;
 mod flusher;
 mod frag;
+mod hot_keys;
+mod index;
 mod iter;
 mod ivec;
+mod loads;
 mod materializer;
+mod merge_operators;
 mod meta;
 mod node;
 mod prefix;
+mod prefix_view;
+mod replication;
 mod subscription;
+mod transaction;
 mod tree;
 
 const DEFAULT_TREE_ID: &[u8] = b"__sled__default";
 
 pub use {
     self::{
+        audit::AuditEntry,
         batch::Batch,
-        db::Db,
-        iter::Iter,
+        codec::{Codec, CodecView, Identity},
+        db::{Db, Open},
+        index::Index,
+        iter::{
+            Batches, Iter, LazyIter, LazyValue, OwnedIter, RawIter, RawRecord,
+            TryIter,
+        },
         ivec::IVec,
+        merge_operators::{max_u64, min_u64, register_merge_operator},
+        prefix::{prefix_decode, prefix_reencode},
+        prefix_view::PrefixView,
+        replication::{LogEntry, LogOp},
         subscription::{Event, Subscriber},
-        tree::Tree,
+        transaction::{
+            ConflictableResult, TransactionError, TransactionalTree,
+        },
+        tree::{
+            CasOutcome, FlushHandle, KeyStats, MultiCasError, Tree, ValueMeta,
+        },
+    },
+    pagecache::{
+        BackoffConfig, Cipher, Config, ConfigBuilder, EncryptionConfig, Error,
+        InvariantPolicy, MessageKind, RawLogEntry, RawLogIter, RecoveryMode,
+        Result, SnapshotInfo, SplitPolicy, NONCE_LEN,
     },
-    pagecache::{Config, ConfigBuilder, Error, Result},
 };
 
 use {
     self::{
+        advisory_locks::AdvisoryLocks,
+        audit::AuditLog,
         binary_search::binary_search_lub,
+        bloom::BloomFilter,
+        cache_reservation::CacheReservation,
         context::Context,
         data::Data,
         frag::Frag,
+        hot_keys::HotKeys,
+        iter::KeyFilter,
+        loads::Loads,
+        merge_operators::{configured_merge_operator, decode_u64},
         node::Node,
         prefix::{
-            prefix_cmp, prefix_cmp_encoded, prefix_decode, prefix_encode,
-            prefix_reencode,
+            prefix_cmp, prefix_cmp_encoded, prefix_encode, prefix_is_valid,
         },
+        replication::LoggedWrite,
         subscription::Subscriptions,
+        tree::lsn_generation,
     },
-    log::{debug, error, trace},
+    log::{debug, error, trace, warn},
     pagecache::{
-        debug_delay, Materializer, Measure, PageCache, PageId, RecoveryGuard,
-        Tx, M,
+        debug_delay, CasBackoff, Lru, Lsn, Materializer, Measure, PageCache,
+        PageId, RecoveryGuard, Tx, M,
     },
     serde::{Deserialize, Serialize},
 };
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+
+use parking_lot::{Condvar, Mutex};
+
+use super::*;
+
+/// The keys of a `Tree` whose values are being loaded by
+/// `Tree::get_or_load`, so that callers that miss the same
+/// key at once wait for a single load instead of each
+/// running their own loader.
+#[derive(Default)]
+pub(crate) struct Loads {
+    loading: Mutex<HashSet<IVec>>,
+    finished: Condvar,
+}
+
+impl Loads {
+    /// Waits until no other caller is loading `key`, then
+    /// marks it as being loaded until the returned guard
+    /// is dropped.
+    pub(crate) fn start(&self, key: &[u8]) -> LoadGuard<'_> {
+        let key = IVec::from(key);
+        let mut loading = self.loading.lock();
+        while loading.contains(&key) {
+            self.finished.wait(&mut loading);
+        }
+        loading.insert(key.clone());
+
+        LoadGuard { loads: self, key }
+    }
+}
+
+/// Marks a key as being loaded, created by `Loads::start`.
+/// Dropping it wakes the callers waiting to load the key,
+/// including when the loader panicked.
+pub(crate) struct LoadGuard<'a> {
+    loads: &'a Loads,
+    key: IVec,
+}
+
+impl<'a> Drop for LoadGuard<'a> {
+    fn drop(&mut self) {
+        self.loads.loading.lock().remove(&self.key);
+        self.loads.finished.notify_all();
+    }
+}
@@ -8,4 +8,98 @@ impl Materializer for Frag {
             panic!("expected base to be the first node");
         }
     }
+
+    fn is_well_formed(&self) -> bool {
+        match self {
+            Frag::Base(node) => node.data.is_well_formed(&node.lo),
+            Frag::Set(k, _) | Frag::Del(k) => !k.is_empty(),
+            _ => true,
+        }
+    }
+
+    fn can_merge(&self, other: &Frag) -> bool {
+        let node = match self {
+            Frag::Base(node) if !node.merging => node,
+            _ => return false,
+        };
+
+        match other {
+            Frag::Set(k, _) | Frag::Del(k) => {
+                !node.data.is_index()
+                    && (node.hi.is_empty()
+                        || prefix_cmp_encoded(k, &node.hi, &node.lo)
+                            == std::cmp::Ordering::Less)
+            }
+            Frag::Logged(write) => self.can_merge(&write.frag(&node.lo)),
+            Frag::Base(_) | Frag::Value(_) => false,
+            Frag::ParentMergeIntention(_) => node.merging_child.is_none(),
+            Frag::ParentMergeConfirm => {
+                node.data.is_index() && node.merging_child.is_some()
+            }
+            Frag::ChildMergeCap => true,
+        }
+    }
+
+    fn stored_as_blob(&self) -> bool {
+        matches!(self, Frag::Value(_))
+    }
+
+    fn logged_as_append(&self) -> bool {
+        matches!(self, Frag::Logged(_))
+    }
+}
+
+#[test]
+fn corrupt_prefix_lengths_are_not_well_formed() {
+    let node = |key: Vec<u8>| {
+        Frag::Base(Node {
+            data: Data::Leaf(vec![(IVec::from(key), IVec::from(vec![]))]),
+            next: None,
+            lo: IVec::from(b"cat"),
+            hi: IVec::from(vec![]),
+            merging_child: None,
+            merging: false,
+        })
+    };
+
+    assert!(node(vec![3, b's']).is_well_formed());
+    assert!(!node(vec![4, b's']).is_well_formed());
+    assert!(!node(vec![]).is_well_formed());
+    assert!(!Frag::Set(IVec::from(vec![]), IVec::from(vec![])).is_well_formed());
+}
+
+#[test]
+fn frags_that_would_panic_cannot_merge() {
+    let node = |hi: &[u8]| {
+        Frag::Base(Node {
+            data: Data::Leaf(vec![]),
+            next: None,
+            lo: IVec::from(vec![]),
+            hi: IVec::from(hi),
+            merging_child: None,
+            merging: false,
+        })
+    };
+    let set = |k: &[u8]| Frag::Set(prefix_encode(b"", k), IVec::from(vec![]));
+    let logged = |k: &[u8]| {
+        Frag::Logged(LoggedWrite {
+            tree_id: IVec::from(b"tree"),
+            key: IVec::from(k),
+            stored: None,
+            operand: None,
+        })
+    };
+
+    assert!(node(b"").can_merge(&set(b"a")));
+    assert!(node(b"m").can_merge(&set(b"a")));
+    assert!(!node(b"m").can_merge(&set(b"z")));
+    assert!(node(b"m").can_merge(&logged(b"a")));
+    assert!(!node(b"m").can_merge(&logged(b"z")));
+    assert!(!node(b"").can_merge(&Frag::ParentMergeConfirm));
+    assert!(!node(b"").can_merge(&Frag::Value(IVec::from(vec![]))));
+    assert!(!set(b"a").can_merge(&set(b"b")));
+
+    let mut merged = node(b"");
+    merged.merge(&Frag::ChildMergeCap);
+    assert!(!merged.can_merge(&set(b"a")));
 }
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use parking_lot::RwLock;
+
+use super::*;
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: RwLock<HashMap<String, MergeOperator>> = {
+        let mut registry = HashMap::new();
+        registry.insert("max_u64".to_owned(), max_u64 as MergeOperator);
+        registry.insert("min_u64".to_owned(), min_u64 as MergeOperator);
+        RwLock::new(registry)
+    };
+}
+
+/// A merge operator that keeps the larger of the stored
+/// value and the merged value, both encoded as big-endian
+/// `u64`s. A stored value that isn't 8 bytes long is
+/// replaced. This is registered under the name `max_u64`,
+/// and is used by `Tree::update_max`.
+pub fn max_u64(
+    _key: &[u8],
+    old_value: Option<&[u8]>,
+    merged_bytes: &[u8],
+) -> Option<Vec<u8>> {
+    extreme_u64(old_value, merged_bytes, std::cmp::max)
+}
+
+/// A merge operator that keeps the smaller of the stored
+/// value and the merged value, both encoded as big-endian
+/// `u64`s. A stored value that isn't 8 bytes long is
+/// replaced. This is registered under the name `min_u64`,
+/// and is used by `Tree::update_min`.
+pub fn min_u64(
+    _key: &[u8],
+    old_value: Option<&[u8]>,
+    merged_bytes: &[u8],
+) -> Option<Vec<u8>> {
+    extreme_u64(old_value, merged_bytes, std::cmp::min)
+}
+
+fn extreme_u64(
+    old_value: Option<&[u8]>,
+    merged_bytes: &[u8],
+    pick: fn(u64, u64) -> u64,
+) -> Option<Vec<u8>> {
+    let merged = match decode_u64(merged_bytes) {
+        Some(merged) => merged,
+        // leave the stored value alone
+        None => return old_value.map(<[u8]>::to_vec),
+    };
+
+    let extreme = match old_value.and_then(decode_u64) {
+        Some(old) => pick(old, merged),
+        None => merged,
+    };
+
+    Some(extreme.to_be_bytes().to_vec())
+}
+
+pub(crate) fn decode_u64(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() != 8 {
+        return None;
+    }
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    Some(u64::from_be_bytes(buf))
+}
+
+/// Registers a merge operator under a name, so that it can
+/// be selected with `ConfigBuilder::merge_operator_named`.
+/// Only the name is persisted, so every process that opens
+/// the database must register the same operator under it
+/// before starting the `Db`. Registering a name again
+/// replaces the previous operator. The operators `max_u64`
+/// and `min_u64` are registered by default.
+///
+/// # Examples
+///
+/// ```
+/// use sled::{ConfigBuilder, Db, IVec};
+///
+/// fn concatenate_merge(
+///   _key: &[u8],               // the key being merged
+///   old_value: Option<&[u8]>,  // the previous value, if one existed
+///   merged_bytes: &[u8]        // the new bytes being merged in
+/// ) -> Option<Vec<u8>> {       // set the new value, return None to delete
+///   let mut ret = old_value
+///     .map(|ov| ov.to_vec())
+///     .unwrap_or_else(|| vec![]);
+///
+///   ret.extend_from_slice(merged_bytes);
+///
+///   Some(ret)
+/// }
+///
+/// sled::register_merge_operator("concatenate", concatenate_merge);
+///
+/// let config = ConfigBuilder::new()
+///   .temporary(true)
+///   .merge_operator_named("concatenate")
+///   .build();
+///
+/// let tree = Db::start(config).unwrap();
+///
+/// tree.merge(b"k1", vec![1]).unwrap();
+/// tree.merge(b"k1", vec![2]).unwrap();
+/// assert_eq!(tree.get(b"k1"), Ok(Some(IVec::from(vec![1, 2]))));
+/// ```
+pub fn register_merge_operator<N: Into<String>>(
+    name: N,
+    merge_operator: MergeOperator,
+) {
+    REGISTRY.write().insert(name.into(), merge_operator);
+}
+
+/// Looks up the merge operator named in the configuration.
+pub(crate) fn configured_merge_operator(
+    config: &Config,
+) -> Result<Option<MergeOperator>> {
+    let name = match config.merge_operator_name {
+        Some(ref name) => name,
+        None => return Ok(None),
+    };
+
+    match REGISTRY.read().get(name) {
+        Some(merge_operator) => Ok(Some(*merge_operator)),
+        None => Err(Error::Unsupported(format!(
+            "no merge operator has been registered under the name {:?}. \
+             use sled::register_merge_operator before starting the Db",
+            name
+        ))),
+    }
+}
+
+#[test]
+fn extremes_ignore_malformed_values() {
+    let five = 5_u64.to_be_bytes();
+    let nine = 9_u64.to_be_bytes();
+
+    assert_eq!(max_u64(b"k", Some(&five), &nine), Some(nine.to_vec()));
+    assert_eq!(max_u64(b"k", Some(&nine), &five), Some(nine.to_vec()));
+    assert_eq!(min_u64(b"k", Some(&nine), &five), Some(five.to_vec()));
+    assert_eq!(min_u64(b"k", None, &nine), Some(nine.to_vec()));
+
+    // a malformed stored value is replaced, and a
+    // malformed merged value is ignored
+    assert_eq!(max_u64(b"k", Some(b"x"), &five), Some(five.to_vec()));
+    assert_eq!(max_u64(b"k", Some(&five), b"x"), Some(five.to_vec()));
+    assert_eq!(min_u64(b"k", None, b"x"), None);
+}
@@ -16,14 +16,26 @@ pub(crate) fn open_tree<'a>(
     loop {
         match context.pagecache.meta_pid_for_name(&name, tx) {
             Ok(root_id) => {
-                return Ok(Tree {
+                let tree = Tree {
                     tree_id: name,
                     context: context.clone(),
                     subscriptions: Arc::new(Subscriptions::default()),
                     root: Arc::new(AtomicU64::new(root_id)),
                     concurrency_control: Arc::new(RwLock::new(())),
-                    merge_operator: Arc::new(RwLock::new(None)),
-                });
+                    merge_operator: Arc::new(RwLock::new(
+                        context.merge_operator,
+                    )),
+                    cache_reservation: Arc::new(CacheReservation::default()),
+                    bloom_filter: BloomFilter::for_config(&context),
+                    hot_keys: HotKeys::for_config(&context),
+                    loads: Arc::new(Loads::default()),
+                    locks: Arc::new(AdvisoryLocks::default()),
+                    generation: Arc::new(AtomicU64::new(lsn_generation(
+                        context.pagecache.max_reserved_lsn(),
+                    ))),
+                };
+                tree.fill_bloom_filter()?;
+                return Ok(tree);
             }
             Err(Error::CollectionNotFound(_)) => {}
             Err(other) => return Err(other),
@@ -92,7 +104,15 @@ pub(crate) fn open_tree<'a>(
             context: context.clone(),
             root: Arc::new(AtomicU64::new(root_id)),
             concurrency_control: Arc::new(RwLock::new(())),
-            merge_operator: Arc::new(RwLock::new(None)),
+            merge_operator: Arc::new(RwLock::new(context.merge_operator)),
+            cache_reservation: Arc::new(CacheReservation::default()),
+            bloom_filter: BloomFilter::for_config(&context),
+            hot_keys: HotKeys::for_config(&context),
+            loads: Arc::new(Loads::default()),
+            locks: Arc::new(AdvisoryLocks::default()),
+            generation: Arc::new(AtomicU64::new(lsn_generation(
+                context.pagecache.max_reserved_lsn(),
+            ))),
         });
     }
 }
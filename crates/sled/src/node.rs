@@ -69,7 +69,12 @@ impl Node {
                     panic!("tried to consolidate del at key <= hi")
                 }
             }
+            Logged(ref write) => {
+                let frag = write.frag(&self.lo);
+                self.apply(&frag);
+            }
             Base(_) => panic!("trying to apply a Base to frag {:?}", self),
+            Value(_) => panic!("trying to apply a Value to node {:?}", self),
             ParentMergeIntention(pid) => {
                 assert!(
                     self.merging_child.is_none(),
@@ -173,8 +178,8 @@ impl Node {
         }
     }
 
-    pub(crate) fn split(mut self) -> (Node, Node) {
-        let (split, right_data) = self.data.split(&self.lo);
+    pub(crate) fn split(mut self, policy: SplitPolicy) -> (Node, Node) {
+        let (split, right_data) = self.data.split(&self.lo, policy);
         let rhs = Node {
             data: right_data,
             next: self.next,
@@ -232,36 +237,54 @@ impl Node {
         }
     }
 
+    // returns the first record above `bound` and its index.
+    // `hint` is the index of the record that `bound` last
+    // excluded, which lets us skip the search while that
+    // record is still in place.
     pub(crate) fn successor(
         &self,
         bound: &Bound<IVec>,
-    ) -> Option<(IVec, IVec)> {
+        key_filter: Option<&KeyFilter<'_>>,
+        hint: Option<usize>,
+    ) -> Option<(usize, IVec, IVec)> {
         assert!(!self.data.is_index());
 
-        // This encoding happens this way because
-        // keys cannot be lower than the node's lo key.
-        let predecessor_key = match bound {
-            Bound::Unbounded => prefix_encode(&self.lo, &self.lo),
-            Bound::Included(b) => {
-                let max = std::cmp::max(b, &self.lo);
-                prefix_encode(&self.lo, max)
-            }
-            Bound::Excluded(b) => {
-                let max = std::cmp::max(b, &self.lo);
-                prefix_encode(&self.lo, max)
+        let records = self.data.leaf_ref().unwrap();
+
+        let idx = match (bound, hint) {
+            (Bound::Excluded(b), Some(hint))
+                if hint < records.len()
+                    && prefix_cmp_encoded(&records[hint].0, b, &self.lo)
+                        == std::cmp::Ordering::Equal =>
+            {
+                hint + 1
             }
-        };
+            _ => {
+                // This encoding happens this way because
+                // keys cannot be lower than the node's lo key.
+                let predecessor_key = match bound {
+                    Bound::Unbounded => prefix_encode(&self.lo, &self.lo),
+                    Bound::Included(b) => {
+                        let max = std::cmp::max(b, &self.lo);
+                        prefix_encode(&self.lo, max)
+                    }
+                    Bound::Excluded(b) => {
+                        let max = std::cmp::max(b, &self.lo);
+                        prefix_encode(&self.lo, max)
+                    }
+                };
 
-        let records = self.data.leaf_ref().unwrap();
-        let search =
-            records.binary_search_by(|(k, _)| prefix_cmp(k, &predecessor_key));
+                let search = records
+                    .binary_search_by(|(k, _)| prefix_cmp(k, &predecessor_key));
 
-        let idx = match search {
-            Ok(idx) => idx,
-            Err(idx) => idx,
+                match search {
+                    Ok(idx) => idx,
+                    Err(idx) => idx,
+                }
+            }
         };
 
-        for (k, v) in &records[idx..] {
+        for (i, (k, v)) in records.iter().enumerate().skip(idx) {
             match bound {
                 Bound::Excluded(b)
                     if prefix_cmp_encoded(k, b, &self.lo)
@@ -274,16 +297,25 @@ impl Node {
                 _ => {}
             }
             let decoded_key = prefix_decode(&self.lo, &k);
-            return Some((IVec::from(decoded_key), v.clone()));
+            if let Some(key_filter) = key_filter {
+                if !key_filter(&decoded_key) {
+                    continue;
+                }
+            }
+            return Some((i, IVec::from(decoded_key), v.clone()));
         }
 
         None
     }
 
+    // returns the last record below `bound` and its index.
+    // see `successor` for `hint`.
     pub(crate) fn predecessor(
         &self,
         bound: &Bound<IVec>,
-    ) -> Option<(IVec, IVec)> {
+        key_filter: Option<&KeyFilter<'_>>,
+        hint: Option<usize>,
+    ) -> Option<(usize, IVec, IVec)> {
         assert!(!self.data.is_index());
 
         lazy_static::lazy_static! {
@@ -294,46 +326,58 @@ impl Node {
             };
         }
 
-        // This encoding happens this way because
-        // the rightmost (unbounded) node has
-        // a hi key represented by the empty slice
-        let successor_key = match bound {
-            Bound::Unbounded => {
-                if self.hi.is_empty() {
-                    MAX_IVEC.clone()
-                } else {
-                    prefix_encode(&self.lo, &self.hi)
-                }
-            }
-            Bound::Included(b) => {
-                let min = if self.hi.is_empty() {
-                    b
-                } else {
-                    std::cmp::min(b, &self.hi)
-                };
-                prefix_encode(&self.lo, min)
+        let records = self.data.leaf_ref().unwrap();
+
+        let end = match (bound, hint) {
+            (Bound::Excluded(b), Some(hint))
+                if hint < records.len()
+                    && prefix_cmp_encoded(&records[hint].0, b, &self.lo)
+                        == std::cmp::Ordering::Equal =>
+            {
+                hint
             }
-            Bound::Excluded(b) => {
-                let min = if self.hi.is_empty() {
-                    b
-                } else {
-                    std::cmp::min(b, &self.hi)
+            _ => {
+                // This encoding happens this way because
+                // the rightmost (unbounded) node has
+                // a hi key represented by the empty slice
+                let successor_key = match bound {
+                    Bound::Unbounded => {
+                        if self.hi.is_empty() {
+                            MAX_IVEC.clone()
+                        } else {
+                            prefix_encode(&self.lo, &self.hi)
+                        }
+                    }
+                    Bound::Included(b) => {
+                        let min = if self.hi.is_empty() {
+                            b
+                        } else {
+                            std::cmp::min(b, &self.hi)
+                        };
+                        prefix_encode(&self.lo, min)
+                    }
+                    Bound::Excluded(b) => {
+                        let min = if self.hi.is_empty() {
+                            b
+                        } else {
+                            std::cmp::min(b, &self.hi)
+                        };
+                        prefix_encode(&self.lo, min)
+                    }
                 };
-                prefix_encode(&self.lo, min)
-            }
-        };
 
-        let records = self.data.leaf_ref().unwrap();
-        let search =
-            records.binary_search_by(|(k, _)| prefix_cmp(k, &successor_key));
+                let search = records
+                    .binary_search_by(|(k, _)| prefix_cmp(k, &successor_key));
 
-        let idx = match search {
-            Ok(idx) => idx,
-            Err(idx) if idx > 0 => idx - 1,
-            _ => return None,
+                match search {
+                    Ok(idx) => idx + 1,
+                    Err(idx) if idx > 0 => idx,
+                    _ => return None,
+                }
+            }
         };
 
-        for (k, v) in records[0..=idx].iter().rev() {
+        for (i, (k, v)) in records[..end].iter().enumerate().rev() {
             match bound {
                 Bound::Excluded(b)
                     if prefix_cmp_encoded(k, b, &self.lo)
@@ -346,7 +390,12 @@ impl Node {
                 _ => {}
             }
             let decoded_key = prefix_decode(&self.lo, &k);
-            return Some((IVec::from(decoded_key), v.clone()));
+            if let Some(key_filter) = key_filter {
+                if !key_filter(&decoded_key) {
+                    continue;
+                }
+            }
+            return Some((i, IVec::from(decoded_key), v.clone()));
         }
         None
     }
@@ -414,3 +463,47 @@ impl Node {
         (index, records[index].1)
     }
 }
+
+#[test]
+fn append_biased_splits_pack_sequential_inserts() {
+    // the leaf size that triggers splits without lock_free_delays
+    const MAX_LEAF_LEN: usize = 16;
+    const N: u64 = 10_000;
+
+    fn leaves_after_sequential_inserts(policy: SplitPolicy) -> u64 {
+        let mut node = Node {
+            data: Data::Leaf(vec![]),
+            next: None,
+            lo: IVec::from(vec![]),
+            hi: IVec::from(vec![]),
+            merging_child: None,
+            merging: false,
+        };
+        let mut leaves = 1;
+        for i in 0..N {
+            let key = prefix_encode(&node.lo, &i.to_be_bytes());
+            node.set_leaf(key, IVec::from(vec![]));
+            if node.data.len() > MAX_LEAF_LEN {
+                // sequential inserts only land in the right node
+                let (_lhs, rhs) = node.split(policy);
+                node = rhs;
+                leaves += 1;
+            }
+        }
+        leaves
+    }
+
+    let center = leaves_after_sequential_inserts(SplitPolicy::Center);
+    let append_biased =
+        leaves_after_sequential_inserts(SplitPolicy::AppendBiased);
+
+    assert!(
+        append_biased < center,
+        "AppendBiased used {} leaves, Center used {}",
+        append_biased,
+        center
+    );
+    // Center leaves keep 9 of 17 items, AppendBiased ones 15
+    assert!(N / center < 10);
+    assert!(N / append_biased >= 14);
+}
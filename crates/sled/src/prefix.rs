@@ -23,28 +23,54 @@ pub(crate) fn prefix_encode(prefix: &[u8], buf: &[u8]) -> IVec {
     IVec::from(ret)
 }
 
-pub(crate) fn prefix_decode(prefix: &[u8], buf: &[u8]) -> Vec<u8> {
-    assert!(!buf.is_empty());
+/// Returns `false` if an encoded key is empty or claims to
+/// share more bytes with the prefix than the prefix has, which
+/// can only happen if it was corrupted.
+pub(crate) fn prefix_is_valid(prefix: &[u8], buf: &[u8]) -> bool {
+    !buf.is_empty() && buf[0] as usize <= prefix.len()
+}
 
-    let prefix_len = buf[0] as usize;
-    let mut ret = Vec::with_capacity(prefix_len + buf.len() - 1);
+// splits an encoded key into the part of the prefix that it
+// shares and its suffix. malformed keys are clamped to the
+// prefix rather than panicking, so they still have a defined
+// order, and are rejected as corrupt when read from disk.
+fn prefix_split<'a, 'b>(
+    prefix: &'a [u8],
+    buf: &'b [u8],
+) -> (&'a [u8], &'b [u8]) {
+    match buf.split_first() {
+        Some((&len, suffix)) => {
+            (&prefix[..std::cmp::min(len as usize, prefix.len())], suffix)
+        }
+        None => (&[], &[]),
+    }
+}
 
-    ret.extend_from_slice(&prefix[..prefix_len]);
-    ret.extend_from_slice(&buf[1..]);
+/// Reconstructs a key from its encoding relative to `prefix`,
+/// which is the `lo` bound of the leaf that it is stored in.
+/// An encoded key is the number of leading bytes that it
+/// shares with the prefix, followed by the rest of the key.
+/// See `Tree::scan_raw`.
+pub fn prefix_decode(prefix: &[u8], buf: &[u8]) -> Vec<u8> {
+    let (prefix, suffix) = prefix_split(prefix, buf);
+    let mut ret = Vec::with_capacity(prefix.len() + suffix.len());
+
+    ret.extend_from_slice(prefix);
+    ret.extend_from_slice(suffix);
 
     ret
 }
 
-pub(crate) fn prefix_reencode(
+/// Re-encodes a key that is encoded relative to `old_prefix`
+/// so that it is encoded relative to `new_prefix` instead,
+/// as happens when a key moves to a leaf with a different
+/// `lo` bound. See `prefix_decode`.
+pub fn prefix_reencode(
     old_prefix: &[u8],
     new_prefix: &[u8],
     buf: &[u8],
 ) -> IVec {
-    assert!(!buf.is_empty());
-
-    let old_prefix_len = buf[0] as usize;
-    let old_prefix = &old_prefix[..old_prefix_len];
-    let old_suffix = &buf[1..];
+    let (old_prefix, old_suffix) = prefix_split(old_prefix, buf);
 
     let decoded_key = old_prefix.iter().chain(old_suffix.iter());
     let max_prefix_len = u8::max_value() as usize;
@@ -91,27 +117,23 @@ pub(crate) fn prefix_cmp(a: &[u8], b: &[u8]) -> Ordering {
 /// Compare `a` and `b`, assuming that `a` is prefix encoded and `b` is not.
 pub(crate) fn prefix_cmp_encoded(
     a: &[u8],
-    mut b: &[u8],
-    mut prefix: &[u8],
+    b: &[u8],
+    prefix: &[u8],
 ) -> Ordering {
-    assert!(!a.is_empty() && a[0] as usize <= prefix.len());
-
-    let mut a_prefix_len = a[0];
-    let a_suffix = &a[1..];
+    let (a_prefix, a_suffix) = prefix_split(prefix, a);
 
-    while a_prefix_len > 0 {
-        if b.is_empty() || prefix[0] > b[0] {
-            return Ordering::Greater;
-        } else if prefix[0] < b[0] {
-            return Ordering::Less;
+    if b.len() < a_prefix.len() {
+        match a_prefix[..b.len()].cmp(b) {
+            Ordering::Equal => Ordering::Greater,
+            other => other,
+        }
+    } else {
+        let (b_prefix, b_suffix) = b.split_at(a_prefix.len());
+        match a_prefix.cmp(b_prefix) {
+            Ordering::Equal => a_suffix.cmp(b_suffix),
+            other => other,
         }
-
-        a_prefix_len -= 1;
-        b = &b[1..];
-        prefix = &prefix[1..];
     }
-
-    a_suffix.cmp(b)
 }
 
 #[test]
@@ -181,3 +203,36 @@ fn test_prefix_cmp_encoded() {
     assert_pce(&[1, 3], &[1, 1], prefix, Ordering::Greater);
     assert_pce(&[1, 1], &[3, 3], prefix, Ordering::Less);
 }
+
+#[test]
+fn malformed_prefix_encodings_do_not_panic() {
+    let prefix = b"cat";
+
+    assert!(prefix_is_valid(prefix, &[3, b's']));
+    assert!(!prefix_is_valid(prefix, &[4, b's']));
+    assert!(!prefix_is_valid(prefix, &[]));
+
+    // over-long prefix lengths are clamped to the prefix
+    for len in 4..=u8::MAX {
+        let corrupt = [len, b's'];
+        assert_eq!(prefix_decode(prefix, &corrupt), b"cats".to_vec());
+        assert_eq!(
+            prefix_cmp_encoded(&corrupt, b"cats", prefix),
+            Ordering::Equal
+        );
+        assert_eq!(
+            prefix_cmp_encoded(&corrupt, b"dog", prefix),
+            Ordering::Less
+        );
+        assert_eq!(
+            prefix_reencode(prefix, b"ca", &corrupt),
+            IVec::from(vec![2, b't', b's'])
+        );
+    }
+
+    // empty buffers decode to the empty key
+    assert_eq!(prefix_decode(prefix, &[]), Vec::<u8>::new());
+    assert_eq!(prefix_cmp_encoded(&[], b"", prefix), Ordering::Equal);
+    assert_eq!(prefix_cmp_encoded(&[], b"a", prefix), Ordering::Less);
+    assert_eq!(prefix_reencode(prefix, b"ca", &[]), IVec::from(vec![0]));
+}
@@ -0,0 +1,75 @@
+use super::*;
+
+/// A view of the keys in a `Tree` that start with a
+/// shared prefix. Keys passed to and returned from a
+/// `PrefixView` are relative to the prefix, which is
+/// prepended and stripped automatically. Unlike a `Tree`
+/// opened with `Db::open_tree`, a `PrefixView` shares its
+/// keyspace with the underlying `Tree`.
+pub struct PrefixView<'a> {
+    pub(super) tree: &'a Tree,
+    pub(super) prefix: Vec<u8>,
+}
+
+impl<'a> PrefixView<'a> {
+    /// Returns the prefix that this view is scoped to.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Retrieve a value for a relative key if it exists.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.tree.get(self.absolute(key))
+    }
+
+    /// Insert a relative key to a new value, returning
+    /// the last value if it was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        self.tree.insert(self.absolute(key), value)
+    }
+
+    /// Delete the value for a relative key, returning
+    /// the old value if it existed.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.tree.remove(self.absolute(key))
+    }
+
+    /// Returns `true` if the view contains a value for
+    /// the specified relative key.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.tree.contains_key(self.absolute(key))
+    }
+
+    /// Create a double-ended iterator over the relative keys
+    /// and values in this view.
+    pub fn iter(
+        &self,
+    ) -> impl 'a + DoubleEndedIterator<Item = Result<(IVec, IVec)>> {
+        self.scan_prefix(&[])
+    }
+
+    /// Create a double-ended iterator over the relative keys
+    /// and values in this view that start with the given
+    /// relative prefix.
+    pub fn scan_prefix<P: AsRef<[u8]>>(
+        &self,
+        prefix: P,
+    ) -> impl 'a + DoubleEndedIterator<Item = Result<(IVec, IVec)>> {
+        let strip = self.prefix.len();
+        self.tree
+            .scan_prefix(self.absolute(prefix))
+            .map(move |res| res.map(|(k, v)| (IVec::from(&k[strip..]), v)))
+    }
+
+    fn absolute<K: AsRef<[u8]>>(&self, key: K) -> Vec<u8> {
+        let key = key.as_ref();
+        let mut absolute = Vec::with_capacity(self.prefix.len() + key.len());
+        absolute.extend_from_slice(&self.prefix);
+        absolute.extend_from_slice(key);
+        absolute
+    }
+}
@@ -0,0 +1,53 @@
+use super::*;
+
+/// A committed write to a `Tree`, tagged with the log
+/// sequence number that it was written to the log at.
+/// Entries can be shipped to another `Tree` and applied
+/// with `Tree::apply_log_entries` to replicate writes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// The log sequence number of this write.
+    pub lsn: Lsn,
+    /// The write itself.
+    pub op: LogOp,
+}
+
+/// The operation recorded in a `LogEntry`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LogOp {
+    /// A key was set to a new value.
+    Set(IVec, IVec),
+    /// A key was removed.
+    Del(IVec),
+    /// An operand was merged into a key with the
+    /// `Tree`'s merge operator.
+    Merge(IVec, IVec),
+}
+
+/// A leaf update that is written to the log in place of
+/// `Frag::Set` and `Frag::Del` when
+/// `ConfigBuilder::replication` is set. It records the
+/// whole key and the `Tree` that it was made to, which the
+/// leaf doesn't, so that it can be read back out of the log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LoggedWrite {
+    pub(crate) tree_id: IVec,
+    pub(crate) key: IVec,
+    // what the leaf stores for the key afterwards,
+    // or `None` if it was removed
+    pub(crate) stored: Option<IVec>,
+    // the operand, if this write was a merge
+    pub(crate) operand: Option<IVec>,
+}
+
+impl LoggedWrite {
+    /// The `Frag::Set` or `Frag::Del` that this write makes
+    /// to the leaf whose low bound is `lo`.
+    pub(crate) fn frag(&self, lo: &[u8]) -> Frag {
+        let encoded_key = prefix_encode(lo, &self.key);
+        match self.stored {
+            Some(ref stored) => Frag::Set(encoded_key, stored.clone()),
+            None => Frag::Del(encoded_key),
+        }
+    }
+}
@@ -1,10 +1,14 @@
 use std::{
     collections::BTreeMap,
     sync::{
-        atomic::{AtomicUsize, Ordering::Relaxed},
-        mpsc::{sync_channel, Receiver, SyncSender},
-        Arc, RwLock,
+        atomic::{
+            AtomicU64, AtomicUsize,
+            Ordering::{Relaxed, SeqCst},
+        },
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
     },
+    time::{Duration, Instant},
 };
 
 use futures::{
@@ -15,6 +19,8 @@ use futures::{
     },
 };
 
+use parking_lot::{Condvar, Mutex, RwLock};
+
 use crate::ivec::IVec;
 
 static ID_GEN: AtomicUsize = AtomicUsize::new(0);
@@ -62,11 +68,30 @@ pub struct Subscriber {
 
 impl Drop for Subscriber {
     fn drop(&mut self) {
-        let mut w_senders = self.home.write().unwrap();
+        let mut w_senders = self.home.write();
         w_senders.retain(|(id, _)| *id != self.id);
     }
 }
 
+impl Subscriber {
+    /// Blocks until the next `Event` arrives or `timeout`
+    /// passes, whichever comes first.
+    pub fn next_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Event, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let future_rx = self.rx.recv_timeout(remaining)?;
+            match future_rx.wait() {
+                Ok(event) => return Ok(event),
+                Err(_cancelled) => continue,
+            }
+        }
+    }
+}
+
 impl Iterator for Subscriber {
     type Item = Event;
 
@@ -84,29 +109,96 @@ impl Iterator for Subscriber {
 #[derive(Default)]
 pub(crate) struct Subscriptions {
     watched: RwLock<BTreeMap<Vec<u8>, Arc<RwLock<Senders>>>>,
+    insert_waiters: InsertWaiters,
+}
+
+// wakes callers of `Tree::pop_min_blocking` when a key is set.
+// writers only take the mutex while someone is waiting.
+#[derive(Default)]
+struct InsertWaiters {
+    inserts: AtomicU64,
+    waiting: AtomicUsize,
+    mu: Mutex<()>,
+    inserted: Condvar,
+}
+
+/// Waits for keys to be set, created by
+/// `Subscriptions::insert_waiter`.
+pub(crate) struct InsertWaiter<'a> {
+    waiters: &'a InsertWaiters,
+}
+
+impl<'a> InsertWaiter<'a> {
+    /// Returns the number of keys set so far, to pass
+    /// to `wait` after checking for a key.
+    pub(crate) fn inserts(&self) -> u64 {
+        self.waiters.inserts.load(SeqCst)
+    }
+
+    /// Blocks until a key is set after `seen` keys were,
+    /// or `deadline` passes. Returns `false` on timeout.
+    pub(crate) fn wait(&self, seen: u64, deadline: Instant) -> bool {
+        let mut mu = self.waiters.mu.lock();
+        while self.inserts() == seen {
+            if self
+                .waiters
+                .inserted
+                .wait_until(&mut mu, deadline)
+                .timed_out()
+            {
+                return self.inserts() != seen;
+            }
+        }
+        true
+    }
+}
+
+impl<'a> Drop for InsertWaiter<'a> {
+    fn drop(&mut self) {
+        self.waiters.waiting.fetch_sub(1, SeqCst);
+    }
 }
 
 impl Subscriptions {
+    pub(crate) fn insert_waiter(&self) -> InsertWaiter<'_> {
+        self.insert_waiters.waiting.fetch_add(1, SeqCst);
+        InsertWaiter {
+            waiters: &self.insert_waiters,
+        }
+    }
+
+    /// Called after a key is set, once the write is visible.
+    pub(crate) fn inserted(&self) {
+        let waiters = &self.insert_waiters;
+        waiters.inserts.fetch_add(1, SeqCst);
+        if waiters.waiting.load(SeqCst) > 0 {
+            // a waiter that saw the old count is either
+            // still holding the mutex or already waiting
+            let _mu = waiters.mu.lock();
+            waiters.inserted.notify_all();
+        }
+    }
+
     pub(crate) fn register(&self, prefix: Vec<u8>) -> Subscriber {
         let r_mu = {
-            let r_mu = self.watched.read().unwrap();
+            let r_mu = self.watched.read();
             if r_mu.contains_key(&prefix) {
                 r_mu
             } else {
                 drop(r_mu);
-                let mut w_mu = self.watched.write().unwrap();
+                let mut w_mu = self.watched.write();
                 if !w_mu.contains_key(&prefix) {
                     w_mu.insert(prefix.clone(), Arc::new(RwLock::new(vec![])));
                 }
                 drop(w_mu);
-                self.watched.read().unwrap()
+                self.watched.read()
             }
         };
 
         let (tx, rx) = sync_channel(1024);
 
         let arc_senders = &r_mu[&prefix];
-        let mut w_senders = arc_senders.write().unwrap();
+        let mut w_senders = arc_senders.write();
 
         let id = ID_GEN.fetch_add(1, Relaxed);
 
@@ -123,13 +215,13 @@ impl Subscriptions {
         &self,
         key: R,
     ) -> Option<ReservedBroadcast> {
-        let r_mu = self.watched.read().unwrap();
+        let r_mu = self.watched.read();
         let prefixes = r_mu.iter().filter(|(k, _)| key.as_ref().starts_with(k));
 
         let mut subscribers = vec![];
 
         for (_, subs_rwl) in prefixes {
-            let subs = subs_rwl.read().unwrap();
+            let subs = subs_rwl.read();
 
             for (_id, sender) in subs.iter() {
                 let (tx, rx) = future_channel();
@@ -229,3 +321,32 @@ fn basic_subscription() {
 
     assert_eq!(s4.next().unwrap().key(), &*k8);
 }
+
+#[test]
+fn subscriptions_survive_panics() {
+    let subs = Arc::new(Subscriptions::default());
+    let mut s1 = subs.register([0].to_vec());
+
+    // a thread that panics while holding the registry
+    // lock must not wedge later writers and subscribers.
+    let subs2 = subs.clone();
+    let res = std::thread::spawn(move || {
+        let _w_mu = subs2.watched.write();
+        panic!("panicking while holding the subscription lock");
+    })
+    .join();
+    assert!(res.is_err());
+
+    let mut s2 = subs.register([0].to_vec());
+
+    let k = vec![0, 1];
+    let r = subs.reserve(&k).unwrap();
+    r.complete(Event::Del(k.clone()));
+
+    assert_eq!(s1.next().unwrap().key(), &*k);
+    assert_eq!(s2.next().unwrap().key(), &*k);
+
+    drop(s1);
+    drop(s2);
+    assert!(subs.reserve(&k).is_none());
+}
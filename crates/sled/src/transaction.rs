@@ -0,0 +1,133 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use super::*;
+
+/// The reason that an attempt to run a `Tree::transaction`
+/// closure did not complete.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionError {
+    /// A value read by the transaction was changed by
+    /// another writer, so the closure will be retried.
+    Conflict,
+    /// An error from the underlying storage, which
+    /// aborts the transaction.
+    Storage(Error),
+}
+
+impl From<Error> for TransactionError {
+    fn from(error: Error) -> TransactionError {
+        TransactionError::Storage(error)
+    }
+}
+
+/// The result of an operation within a `Tree::transaction`.
+/// Use `?` to propagate a `TransactionError` out of the
+/// transaction closure.
+pub type ConflictableResult<T> = std::result::Result<T, TransactionError>;
+
+/// A view of a `Tree` passed to a `Tree::transaction`
+/// closure, or returned by `Tree::begin_transaction`.
+/// Reads observe the writes staged earlier in the same
+/// transaction, and writes are only applied to the `Tree`
+/// when the transaction commits and none of the values it
+/// read have changed since.
+pub struct TransactionalTree<'a> {
+    tree: &'a Tree,
+    reads: RefCell<HashMap<IVec, Option<IVec>>>,
+    writes: RefCell<HashMap<IVec, Option<IVec>>>,
+}
+
+impl<'a> TransactionalTree<'a> {
+    pub(super) fn new(tree: &'a Tree) -> Self {
+        TransactionalTree {
+            tree,
+            reads: RefCell::new(HashMap::new()),
+            writes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Retrieve a value, as of the start of the
+    /// transaction or its last staged write.
+    pub fn get<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> ConflictableResult<Option<IVec>> {
+        let key = key.as_ref();
+        if let Some(staged) = self.writes.borrow().get(key) {
+            return Ok(staged.clone());
+        }
+        if let Some(read) = self.reads.borrow().get(key) {
+            return Ok(read.clone());
+        }
+
+        let value = self.tree.get(key)?;
+        self.reads
+            .borrow_mut()
+            .insert(IVec::from(key), value.clone());
+        Ok(value)
+    }
+
+    /// Stage a write of a value, returning the last
+    /// value if it was set.
+    pub fn insert<K, V>(
+        &self,
+        key: K,
+        value: V,
+    ) -> ConflictableResult<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        self.stage(key.as_ref(), Some(IVec::from(value)))
+    }
+
+    /// Stage the removal of a key, returning the old
+    /// value if it existed.
+    pub fn remove<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> ConflictableResult<Option<IVec>> {
+        self.stage(key.as_ref(), None)
+    }
+
+    fn stage(
+        &self,
+        key: &[u8],
+        value: Option<IVec>,
+    ) -> ConflictableResult<Option<IVec>> {
+        let last = self.get(key)?;
+        self.writes.borrow_mut().insert(IVec::from(key), value);
+        Ok(last)
+    }
+
+    /// Atomically applies the staged writes if none of the
+    /// values read by the transaction have changed since they
+    /// were read, returning `TransactionError::Conflict`
+    /// without writing anything if one has. Dropping a
+    /// transaction without committing it discards its writes.
+    pub fn commit(self) -> ConflictableResult<()> {
+        let peg = self.tree.context.pin_log()?;
+        let cc = self.tree.concurrency_control.write();
+
+        // no other writer can proceed while we hold the write
+        // lock, so these values can't change before we apply.
+        for (key, read) in self.reads.into_inner() {
+            if self.tree.get_inner(&key)? != read {
+                return Err(TransactionError::Conflict);
+            }
+        }
+
+        for (key, write) in self.writes.into_inner() {
+            if let Some(value) = write {
+                self.tree.insert_inner(key, value)?;
+            } else {
+                self.tree.remove_inner(key)?;
+            }
+        }
+        drop(cc);
+
+        peg.seal_batch()?;
+
+        Ok(())
+    }
+}
@@ -1,16 +1,115 @@
 use std::{
+    collections::VecDeque,
     fmt::{self, Debug},
     ops::{self, RangeBounds},
     sync::{
-        atomic::{AtomicU64, Ordering::SeqCst},
+        atomic::{
+            AtomicU64,
+            Ordering::{Acquire, Release, SeqCst},
+        },
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use parking_lot::RwLock;
 
 use super::*;
 
+/// The reason that a `Tree::multi_cas` was not applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiCasError {
+    /// The first key whose value did not match
+    /// the expected value.
+    pub key: IVec,
+    /// The current value of that key.
+    pub current: Option<IVec>,
+}
+
+/// The result of a `Tree::cas_outcome`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CasOutcome {
+    /// The expected value matched, and the new value
+    /// was written.
+    Applied,
+    /// A value was expected, but the key was absent.
+    AbsentButExpectedPresent,
+    /// The key was expected to be absent, but it was present.
+    PresentButExpectedAbsent,
+    /// The key was present, but with a different value
+    /// than expected. Carries the current value.
+    ValueMismatch(IVec),
+}
+
+/// A value returned by `Tree::get_meta`, along with
+/// information about how it is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueMeta {
+    /// The value itself.
+    pub value: IVec,
+    /// The log sequence number of the latest write to the
+    /// leaf that holds the value. This is at least the lsn
+    /// that the value was written at, and changes whenever
+    /// the value does, so it can be used as a version token.
+    /// It also changes when other keys in the leaf are written.
+    pub lsn: Lsn,
+    /// The length of the value in bytes.
+    pub len: usize,
+    /// Whether part of the leaf that holds the value was too
+    /// large to be written inline in the log, and is stored
+    /// in a separate blob file. This is always the case when
+    /// `ConfigBuilder::separate_values` is set.
+    pub fragmented: bool,
+}
+
+/// Statistics about the lengths of the keys in a `Tree`,
+/// returned by `Tree::key_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct KeyStats {
+    /// The number of keys.
+    pub count: usize,
+    /// The length of the shortest key in bytes,
+    /// or 0 if there are no keys.
+    pub min_len: usize,
+    /// The length of the longest key in bytes,
+    /// or 0 if there are no keys.
+    pub max_len: usize,
+    /// The mean length of the keys in bytes,
+    /// or 0.0 if there are no keys.
+    pub mean_len: f64,
+}
+
+/// A flush scheduled by `Tree::flush_async`, covering
+/// every write that had been issued when it was scheduled.
+#[derive(Clone)]
+pub struct FlushHandle {
+    pagecache: Arc<PageCache<Frag>>,
+    lsn: Lsn,
+}
+
+impl FlushHandle {
+    /// Returns `true` if the writes covered by this
+    /// flush are already durable, without blocking.
+    pub fn is_done(&self) -> bool {
+        self.pagecache.stable_lsn() >= self.lsn
+    }
+
+    /// Blocks until the writes covered by this flush
+    /// are durable, flushing them if the background
+    /// flush has not gotten to them yet.
+    pub fn wait(self) -> Result<()> {
+        self.pagecache.make_stable(self.lsn).map(|_| ())
+    }
+}
+
+impl Debug for FlushHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlushHandle")
+            .field("lsn", &self.lsn)
+            .finish()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct View<'g> {
     pub ptr: TreePtr<'g>,
@@ -38,6 +137,10 @@ impl<'a> IntoIterator for &'a Tree {
 
 /// A flash-sympathetic persistent lock-free B+ tree
 ///
+/// Keys are ordered lexicographically by their bytes. The
+/// empty key is a valid key like any other, and sorts before
+/// every other key.
+///
 /// # Examples
 ///
 /// ```
@@ -71,6 +174,13 @@ pub struct Tree {
     pub(crate) root: Arc<AtomicU64>,
     pub(crate) concurrency_control: Arc<RwLock<()>>,
     pub(crate) merge_operator: Arc<RwLock<Option<MergeOperator>>>,
+    pub(crate) cache_reservation: Arc<CacheReservation>,
+    pub(crate) bloom_filter: Option<Arc<BloomFilter>>,
+    pub(crate) hot_keys: Option<Arc<HotKeys>>,
+    pub(crate) loads: Arc<Loads>,
+    pub(crate) locks: Arc<AdvisoryLocks>,
+    // the lsn of the last write, see `Tree::generation`
+    pub(crate) generation: Arc<AtomicU64>,
 }
 
 unsafe impl Send for Tree {}
@@ -101,7 +211,10 @@ impl Tree {
     }
 
     /// Insert a key to a new value, returning the last value if it
-    /// was set.
+    /// was set. The last value is read and replaced in a single
+    /// atomic step, so this can be used as an atomic exchange:
+    /// concurrent inserts to the same key each return a distinct
+    /// predecessor.
     ///
     /// # Examples
     ///
@@ -118,15 +231,140 @@ impl Tree {
         K: AsRef<[u8]>,
         IVec: From<V>,
     {
-        let _ = self.concurrency_control.read();
+        if let Some(ref hot_keys) = self.hot_keys {
+            hot_keys.record(key.as_ref());
+        }
+        let _cc = self.concurrency_control.read();
         self.insert_inner(key, value)
     }
 
+    /// Insert a key to a new value, unless the current IO
+    /// buffer has no room left for it, in which case the key
+    /// is left unchanged and `Ok(false)` is returned instead
+    /// of waiting for the buffer to be written out. This lets
+    /// servers shed load instead of stalling request threads.
+    /// The buffer makes room again once it is flushed. See
+    /// `Tree::write_pressure`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// assert_eq!(t.try_set(&[1,2,3], vec![0]), Ok(true));
+    /// assert_eq!(t.get(&[1,2,3]), Ok(Some(IVec::from(&[0]))));
+    /// ```
+    pub fn try_set<K, V>(&self, key: K, value: V) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        if let Some(ref hot_keys) = self.hot_keys {
+            hot_keys.record(key.as_ref());
+        }
+        let _cc = self.concurrency_control.read();
+        match self.set_inner(key, value, true) {
+            Ok(_) => Ok(true),
+            Err(Error::WouldBlock) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the proportion of the current IO buffer that
+    /// is taken up by writes awaiting a flush, between 0
+    /// and 1. As this nears 1, `Tree::try_set` starts
+    /// rejecting the writes that no longer fit.
+    pub fn write_pressure(&self) -> f64 {
+        self.context.pagecache.write_pressure()
+    }
+
+    /// Returns the number of bytes that the database
+    /// occupies on disk, including its data file, blobs,
+    /// configuration file, and snapshots. This is shared by
+    /// all trees in the same `Db`.
+    pub fn size_on_disk(&self) -> std::io::Result<u64> {
+        self.context.size_on_disk()
+    }
+
+    /// Returns the snapshot files of the database, ordered from
+    /// oldest to newest. Old snapshots are normally removed
+    /// after a new one is written, but may be left behind if
+    /// that fails. This is shared by all trees in the same `Db`.
+    pub fn snapshots(&self) -> std::io::Result<Vec<SnapshotInfo>> {
+        self.context.snapshots()
+    }
+
+    /// Removes all but the newest `keep` snapshot files of the
+    /// database, returning the number removed. This is shared
+    /// by all trees in the same `Db`.
+    ///
+    /// Returns `Error::Unsupported` if `keep` is 0, because
+    /// the newest snapshot is needed for recovery.
+    pub fn prune_snapshots(&self, keep: usize) -> Result<usize> {
+        self.context.prune_snapshots(keep)
+    }
+
+    /// Sets the number of updates that may be linked onto
+    /// a page before it is consolidated, overriding the
+    /// configured `page_consolidation_threshold` until the
+    /// `Db` is restarted. This is shared by all trees in the
+    /// same `Db`. Raising it makes bulk loads cheaper, and
+    /// lowering it afterwards shortens the chains that reads
+    /// have to walk.
+    ///
+    /// Returns `Error::Unsupported` if the threshold is 0 or
+    /// at least 1 million, like `ConfigBuilder::build`.
+    pub fn set_consolidation_threshold(&self, threshold: usize) -> Result<()> {
+        self.context
+            .pagecache
+            .set_consolidation_threshold(threshold)
+    }
+
+    /// Returns the number of fragments in the chain of updates
+    /// for the leaf that holds `key`, including its base. Reads
+    /// walk this whole chain while the leaf is being paged in,
+    /// so this is useful for relating read latency to the
+    /// consolidation threshold. The chain is shortened to a
+    /// single fragment when the leaf is consolidated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// t.insert(b"k", vec![1]).unwrap();
+    /// assert!(t.fragment_chain_len(b"k").unwrap() >= 1);
+    /// ```
+    pub fn fragment_chain_len<K: AsRef<[u8]>>(&self, key: K) -> Result<usize> {
+        let tx = self.context.pagecache.begin()?;
+        let view = self.node_for_key(key.as_ref(), &tx)?;
+        Ok(self.context.pagecache.chain_len(view.pid, &tx))
+    }
+
     pub(crate) fn insert_inner<K, V>(
         &self,
         key: K,
         value: V,
     ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        self.set_inner(key, value, false)
+    }
+
+    // if `nonblocking` is set, an `Error::WouldBlock` is
+    // returned without writing anything when the current
+    // IO buffer has no room for the write.
+    fn set_inner<K, V>(
+        &self,
+        key: K,
+        value: V,
+        nonblocking: bool,
+    ) -> Result<Option<IVec>>
     where
         K: AsRef<[u8]>,
         IVec: From<V>,
@@ -141,25 +379,45 @@ impl Tree {
         }
 
         let value = IVec::from(value);
+        let mut backoff = CasBackoff::new(self.context.cas_backoff);
+
+        if let Some(ref bloom_filter) = self.bloom_filter {
+            bloom_filter.insert(key.as_ref());
+        }
+
+        let stored = self.store_value(&value)?;
+        let discard = |e| self.discard_value(Some(&stored), e);
 
         loop {
-            let tx = self.context.pagecache.begin()?;
+            let tx = self.context.pagecache.begin().map_err(discard)?;
             let View { ptr, pid, node, .. } =
-                self.node_for_key(key.as_ref(), &tx)?;
-            let encoded_key = prefix_encode(&node.lo, key.as_ref());
+                self.node_for_key(key.as_ref(), &tx).map_err(discard)?;
 
             let mut subscriber_reservation = self.subscriptions.reserve(&key);
 
-            let last_value = node.leaf_value_for_key(key.as_ref());
-            let frag = Frag::Set(encoded_key, value.clone());
-            let link = self.context.pagecache.link(
-                pid,
-                ptr.clone(),
-                frag.clone(),
-                &tx,
-            )?;
-            if let Ok(_new_cas_key) = link {
-                // success
+            let last_stored = node.leaf_value_for_key(key.as_ref());
+            let frag =
+                self.write_frag(node, key.as_ref(), Some(stored.clone()), None);
+            let pagecache = &self.context.pagecache;
+            let link = if nonblocking {
+                pagecache.try_link(pid, ptr.clone(), frag.clone(), &tx)
+            } else {
+                pagecache.link(pid, ptr.clone(), frag.clone(), &tx)
+            }
+            .map_err(discard)?;
+            if let Ok(new_cas_key) = link {
+                // success. a damaged last value is reported
+                // after the write is recorded, since it has
+                // been replaced either way.
+                let last_value = last_stored
+                    .map(|last_stored| self.take_value(last_stored, &ptr, &tx))
+                    .transpose();
+
+                self.subscriptions.inserted();
+                self.record_write(new_cas_key.last_lsn(), || {
+                    LogOp::Set(key.as_ref().to_vec().into(), value.clone())
+                });
+
                 if let Some(res) = subscriber_reservation.take() {
                     let event =
                         subscription::Event::Set(key.as_ref().to_vec(), value);
@@ -167,9 +425,10 @@ impl Tree {
                     res.complete(event);
                 }
 
-                return Ok(last_value.cloned());
+                return last_value;
             }
             M.tree_looped();
+            backoff.snooze();
         }
     }
 
@@ -199,107 +458,358 @@ impl Tree {
         }
     }
 
-    /// Retrieve a value from the `Tree` if it exists.
+    /// Returns a number that increases whenever a write to
+    /// this `Tree` is committed, and stays the same otherwise,
+    /// so that state derived from the `Tree` only needs to be
+    /// recomputed once it changes. It is the log sequence
+    /// number of the latest write, so it keeps increasing
+    /// across restarts.
     ///
     /// # Examples
     ///
     /// ```
-    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// let before = t.generation();
+    /// t.insert(b"k", vec![1]).unwrap();
+    /// let after = t.generation();
+    /// assert!(after > before);
+    ///
+    /// t.get(b"k").unwrap();
+    /// assert_eq!(t.generation(), after);
+    /// ```
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Acquire)
+    }
+
+    /// Returns the writes committed to this `Tree` after the
+    /// provided log sequence number, in the order that they
+    /// were written to the log, by reading them back out of
+    /// it. Merges are returned as their operands, so a replica
+    /// needs the same merge operator to apply them. This
+    /// requires `ConfigBuilder::replication`, and returns an
+    /// `Error::Unsupported` if it isn't set, if it wasn't set
+    /// for some of the writes after `lsn`, or if the part of the
+    /// log that holds them has since been reclaimed. A replica
+    /// must then be fully resynchronized.
+    ///
+    /// Reapplying entries in order is idempotent, except for
+    /// merges with operators that aren't.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db};
+    /// let config = ConfigBuilder::new()
+    ///     .temporary(true)
+    ///     .replication(true)
+    ///     .build();
+    /// let primary = Db::start(config).unwrap();
+    ///
     /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let replica = Db::start(config).unwrap();
+    ///
+    /// primary.insert(b"a", vec![1]).unwrap();
+    /// primary.remove(b"a").unwrap();
+    /// primary.insert(b"b", vec![2]).unwrap();
+    ///
+    /// let entries: Vec<_> = primary.log_entries_since(0).unwrap().collect();
+    /// replica.apply_log_entries(&entries).unwrap();
+    ///
+    /// assert_eq!(replica.get(b"a"), Ok(None));
+    /// assert_eq!(replica.get(b"b"), Ok(Some(vec![2].into())));
+    /// ```
+    pub fn log_entries_since(
+        &self,
+        lsn: Lsn,
+    ) -> Result<impl Iterator<Item = LogEntry>> {
+        let writes = self.logged_writes_since(lsn)?;
+
+        Ok(writes.into_iter().map(|(lsn, write)| {
+            let op = match (write.operand, write.stored) {
+                (Some(operand), _) => LogOp::Merge(write.key, operand),
+                (None, Some(value)) => LogOp::Set(write.key, value),
+                (None, None) => LogOp::Del(write.key),
+            };
+            LogEntry { lsn, op }
+        }))
+    }
+
+    // reads the writes made to this `Tree` after `lsn` back out
+    // of the log, with the checksums of their values stripped.
+    fn logged_writes_since(&self, lsn: Lsn) -> Result<Vec<(Lsn, LoggedWrite)>> {
+        if !self.context.replication {
+            return Err(Error::Unsupported(
+                "writes can only be read back out of the log \
+                 when ConfigBuilder::replication is set"
+                    .to_owned(),
+            ));
+        }
+
+        let mut writes = vec![];
+        for (at, ptr, frag) in self.context.pagecache.appends_since(lsn)? {
+            let mut write = match frag {
+                Frag::Logged(write) => write,
+                // we can't tell which tree or key these were for
+                Frag::Set(..) | Frag::Del(_) => {
+                    return Err(Error::Unsupported(format!(
+                        "some writes after lsn {} were made without \
+                         ConfigBuilder::replication set",
+                        lsn
+                    )));
+                }
+                _ => continue,
+            };
+            if *write.tree_id != *self.tree_id {
+                continue;
+            }
+
+            if let Some(stored) = write.stored.take() {
+                match self.checked_value(&stored) {
+                    Some(value) => write.stored = Some(value),
+                    None => return Err(Error::Corruption { at: ptr }),
+                }
+            }
+            writes.push((at, write));
+        }
+
+        Ok(writes)
+    }
+
+    /// Returns the writes to this `Tree` recorded in the
+    /// audit log after the provided log sequence number,
+    /// in the order that they were recorded. The audit log
+    /// is never compacted, so every write since the audit
+    /// log was configured is retained, including ones that
+    /// have since been overwritten or removed. Merges are
+    /// recorded as the value that they produced.
+    ///
+    /// Returns an `Error::Unsupported` if
+    /// `ConfigBuilder::audit_log` is not set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, LogOp};
+    /// # let _ = std::fs::remove_file("audit_doctest.log");
+    /// let config = ConfigBuilder::new()
+    ///     .temporary(true)
+    ///     .audit_log(Some("audit_doctest.log".into()))
+    ///     .build();
     /// let t = Db::start(config).unwrap();
     ///
-    /// t.insert(&[0], vec![0]).unwrap();
-    /// assert_eq!(t.get(&[0]), Ok(Some(IVec::from(vec![0]))));
-    /// assert_eq!(t.get(&[1]), Ok(None));
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// t.remove(b"a").unwrap();
+    ///
+    /// let ops: Vec<_> =
+    ///     t.audit_entries_since(0).unwrap().map(|e| e.op).collect();
+    /// assert_eq!(
+    ///     ops,
+    ///     vec![LogOp::Set(b"a".into(), vec![1].into()), LogOp::Del(b"a".into())]
+    /// );
+    /// # drop(t);
+    /// # let _ = std::fs::remove_file("audit_doctest.log");
     /// ```
-    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
-        let _ = self.concurrency_control.read();
-        let _measure = Measure::new(&M.tree_get);
-        trace!("getting key {:?}", key.as_ref());
+    pub fn audit_entries_since(
+        &self,
+        lsn: Lsn,
+    ) -> Result<impl Iterator<Item = AuditEntry>> {
+        match self.context.audit_log {
+            Some(ref audit_log) => audit_log
+                .since(&self.tree_id, lsn)
+                .map(IntoIterator::into_iter),
+            None => Err(Error::Unsupported(
+                "no audit log is configured. set \
+                 ConfigBuilder::audit_log to record writes"
+                    .to_owned(),
+            )),
+        }
+    }
 
-        let tx = self.context.pagecache.begin()?;
+    /// Reads the raw messages in the log from `from_lsn`
+    /// onward without applying them, reporting each one's
+    /// kind, page, lsn, length and whether its checksum is
+    /// valid. A torn or corrupt tail is reported rather than
+    /// treated as an error. Call `flush` first to include
+    /// recent writes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, MessageKind};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// t.flush().unwrap();
+    ///
+    /// let entries: Vec<_> =
+    ///     t.dump_log(0).unwrap().collect::<sled::Result<_>>().unwrap();
+    /// assert!(entries.iter().all(|e| e.checksum_valid));
+    /// assert!(entries.iter().any(|e| e.kind == MessageKind::InlineAppend));
+    /// ```
+    pub fn dump_log(&self, from_lsn: Lsn) -> Result<RawLogIter> {
+        self.context.dump_log(from_lsn)
+    }
+
+    // records a committed write in the audit log, if one
+    // is configured. the write has already been applied,
+    // so failing to audit it is logged rather than
+    // reported to the caller.
+    fn record_write<F>(&self, lsn: Lsn, op: F)
+    where
+        F: FnOnce() -> LogOp,
+    {
+        // concurrent writes may be recorded out of lsn order
+        self.generation.fetch_max(lsn_generation(lsn), Release);
+
+        if let Some(ref audit_log) = self.context.audit_log {
+            let op = op();
+            if let Err(e) = audit_log.append(&self.tree_id, lsn, &op) {
+                error!(
+                    "failed to record write at lsn {} in audit log: {}",
+                    lsn, e
+                );
+            }
+        }
+    }
 
-        let View { node, .. } = self.node_for_key(key.as_ref(), &tx)?;
+    /// Applies writes shipped from another `Tree` with
+    /// `Tree::log_entries_since`, in the order provided.
+    /// The entries are recovered atomically after a crash,
+    /// just like a `Batch`. Merges are applied with this
+    /// `Tree`'s merge operator, and return an
+    /// `Error::Unsupported` if it has none.
+    pub fn apply_log_entries(&self, entries: &[LogEntry]) -> Result<()> {
+        let peg = self.context.pin_log()?;
+        let cc = self.concurrency_control.write();
+        for entry in entries {
+            match entry.op {
+                LogOp::Set(ref k, ref v) => {
+                    self.insert_inner(k, v.clone())?;
+                }
+                LogOp::Del(ref k) => {
+                    self.remove_inner(k)?;
+                }
+                LogOp::Merge(ref k, ref operand) => {
+                    self.merge_inner(k, operand)?;
+                }
+            }
+        }
+        drop(cc);
 
-        Ok(node.leaf_value_for_key(key.as_ref()).cloned())
+        peg.seal_batch()
     }
 
-    /// Delete a value, returning the old value if it existed.
+    /// Returns the net state of each key written after the
+    /// provided log sequence number, in key order: `Some` with
+    /// the latest value for keys that were set or merged, and
+    /// `None` for keys that were removed. Intermediate writes to
+    /// the same key are coalesced, so a backup only needs to
+    /// ship one change per key. Like `Tree::log_entries_since`,
+    /// this reads the writes back out of the log segments on
+    /// disk, which requires `ConfigBuilder::replication`, so
+    /// writes from before a restart are included for as long
+    /// as the log retains them. Rather than silently leaving
+    /// changes out, it yields an `Error::Unsupported` in the same
+    /// cases that `Tree::log_entries_since` returns one.
     ///
     /// # Examples
     ///
     /// ```
-    /// let config = sled::ConfigBuilder::new().temporary(true).build();
-    /// let t = sled::Db::start(config).unwrap();
-    /// t.insert(&[1], vec![1]);
-    /// assert_eq!(t.del(&[1]), Ok(Some(sled::IVec::from(vec![1]))));
-    /// assert_eq!(t.del(&[1]), Ok(None));
+    /// use sled::{ConfigBuilder, Db};
+    /// let config = ConfigBuilder::new()
+    ///     .temporary(true)
+    ///     .replication(true)
+    ///     .build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// t.insert(b"a", vec![2]).unwrap();
+    /// t.insert(b"b", vec![3]).unwrap();
+    /// t.remove(b"b").unwrap();
+    ///
+    /// let changes: Vec<_> =
+    ///     t.changes_since(0).collect::<Result<_, _>>().unwrap();
+    /// assert_eq!(
+    ///     changes,
+    ///     vec![(b"a".into(), Some(vec![2].into())), (b"b".into(), None)]
+    /// );
     /// ```
-    #[deprecated(since = "0.24.2", note = "replaced by `Tree::remove`")]
-    pub fn del<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
-        self.remove(key)
+    pub fn changes_since<'a>(
+        &'a self,
+        lsn: Lsn,
+    ) -> impl 'a + Iterator<Item = Result<(IVec, Option<IVec>)>> {
+        let mut net: std::collections::BTreeMap<IVec, Option<IVec>> =
+            std::collections::BTreeMap::new();
+
+        let (err, writes) = match self.logged_writes_since(lsn) {
+            Ok(writes) => (None, writes),
+            Err(e) => (Some(Err(e)), vec![]),
+        };
+
+        for (_lsn, write) in writes {
+            net.insert(write.key, write.stored);
+        }
+
+        err.into_iter().chain(net.into_iter().map(Ok))
     }
 
-    /// Delete a value, returning the old value if it existed.
+    /// Retrieve a value from the `Tree` if it exists.
     ///
     /// # Examples
     ///
     /// ```
-    /// let config = sled::ConfigBuilder::new().temporary(true).build();
-    /// let t = sled::Db::start(config).unwrap();
-    /// t.set(&[1], vec![1]);
-    /// assert_eq!(t.remove(&[1]), Ok(Some(sled::IVec::from(vec![1]))));
-    /// assert_eq!(t.remove(&[1]), Ok(None));
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(&[0], vec![0]).unwrap();
+    /// assert_eq!(t.get(&[0]), Ok(Some(IVec::from(vec![0]))));
+    /// assert_eq!(t.get(&[1]), Ok(None));
     /// ```
-    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
-        let _ = self.concurrency_control.read();
-        self.remove_inner(key)
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        if let Some(ref hot_keys) = self.hot_keys {
+            hot_keys.record(key.as_ref());
+        }
+        self.get_inner(key)
     }
 
-    pub(crate) fn remove_inner<K: AsRef<[u8]>>(
+    pub(crate) fn get_inner<K: AsRef<[u8]>>(
         &self,
         key: K,
     ) -> Result<Option<IVec>> {
-        let _measure = Measure::new(&M.tree_del);
+        let _measure = Measure::new(&M.tree_get);
+        trace!("getting key {:?}", key.as_ref());
 
-        if self.context.read_only {
-            return Ok(None);
+        if let Some(ref bloom_filter) = self.bloom_filter {
+            if !bloom_filter.may_contain(key.as_ref()) {
+                return Ok(None);
+            }
         }
 
         loop {
             let tx = self.context.pagecache.begin()?;
 
-            let View { ptr, pid, node, .. } =
+            let View { ptr, node, .. } =
                 self.node_for_key(key.as_ref(), &tx)?;
-            let existing_val = node.leaf_value_for_key(key.as_ref());
-
-            let mut subscriber_reservation = self.subscriptions.reserve(&key);
-
-            let encoded_key = prefix_encode(&node.lo, key.as_ref());
 
-            let frag = Frag::Del(encoded_key);
-
-            let link =
-                self.context.pagecache.link(pid, ptr.clone(), frag, &tx)?;
-
-            if link.is_ok() {
-                // success
-                if let Some(res) = subscriber_reservation.take() {
-                    let event = subscription::Event::Del(key.as_ref().to_vec());
-
-                    res.complete(event);
-                }
+            let stored = match node.leaf_value_for_key(key.as_ref()) {
+                Some(stored) => stored,
+                None => return Ok(None),
+            };
 
-                return Ok(existing_val.cloned());
+            if let Some(value) = self.load_value(stored, &ptr, &tx)? {
+                return Ok(Some(value));
             }
+            M.tree_looped();
         }
     }
 
-    /// Compare and swap. Capable of unique creation, conditional modification,
-    /// or deletion. If old is None, this will only set the value if it doesn't
-    /// exist yet. If new is None, will delete the value if old is correct.
-    /// If both old and new are Some, will modify the value if old is correct.
-    /// If Tree is read-only, will do nothing.
+    /// Retrieve a value from the `Tree` if it exists, along with
+    /// the lsn of the latest write to its leaf and whether it is
+    /// stored in a blob file. See `ValueMeta`.
     ///
     /// # Examples
     ///
@@ -307,527 +817,2671 @@ impl Tree {
     /// let config = sled::ConfigBuilder::new().temporary(true).build();
     /// let t = sled::Db::start(config).unwrap();
     ///
-    /// // unique creation
-    /// assert_eq!(t.cas(&[1], None as Option<&[u8]>, Some(&[10])), Ok(Ok(())));
+    /// t.insert(b"k", vec![1]).unwrap();
+    /// let first = t.get_meta(b"k").unwrap().unwrap();
+    /// assert_eq!(first.len, 1);
     ///
-    /// // conditional modification
-    /// assert_eq!(t.cas(&[1], Some(&[10]), Some(&[20])), Ok(Ok(())));
+    /// t.insert(b"k", vec![2, 2]).unwrap();
+    /// let second = t.get_meta(b"k").unwrap().unwrap();
+    /// assert!(second.lsn > first.lsn);
     ///
-    /// // conditional deletion
-    /// assert_eq!(t.cas(&[1], Some(&[20]), None as Option<&[u8]>), Ok(Ok(())));
-    /// assert_eq!(t.get(&[1]), Ok(None));
+    /// assert_eq!(t.get_meta(b"missing"), Ok(None));
     /// ```
-    pub fn cas<K, OV, NV>(
+    pub fn get_meta<K: AsRef<[u8]>>(
         &self,
         key: K,
-        old: Option<OV>,
-        new: Option<NV>,
-    ) -> Result<std::result::Result<(), Option<IVec>>>
-    where
-        K: AsRef<[u8]>,
-        OV: AsRef<[u8]>,
-        IVec: From<NV>,
-    {
-        trace!("casing key {:?}", key.as_ref());
-        let _measure = Measure::new(&M.tree_cas);
-
-        let _ = self.concurrency_control.read();
-
-        if self.context.read_only {
-            return Err(Error::Unsupported(
-                "can not perform a cas on a read-only Tree".into(),
-            ));
+    ) -> Result<Option<ValueMeta>> {
+        let key = key.as_ref();
+        if let Some(ref hot_keys) = self.hot_keys {
+            hot_keys.record(key);
         }
 
-        let new = new.map(IVec::from);
-
-        // we need to retry caps until old != cur, since just because
-        // cap fails it doesn't mean our value was changed.
         loop {
             let tx = self.context.pagecache.begin()?;
-            let View { ptr, pid, node, .. } =
-                self.node_for_key(key.as_ref(), &tx)?;
-            let cur = node.leaf_value_for_key(key.as_ref());
+            let view = self.node_for_key(key, &tx)?;
 
-            let matches = match (&old, &cur) {
-                (None, None) => true,
-                (Some(ref o), Some(ref c)) => o.as_ref() == &***c,
-                _ => false,
+            let stored = match view.node.leaf_value_for_key(key) {
+                Some(stored) => stored,
+                None => return Ok(None),
             };
 
-            if !matches {
-                return Ok(Err(cur.cloned()));
-            }
+            let value = match self.load_value(stored, &view.ptr, &tx)? {
+                Some(value) => value,
+                None => continue,
+            };
 
-            let mut subscriber_reservation = self.subscriptions.reserve(&key);
+            return Ok(Some(ValueMeta {
+                len: value.len(),
+                value,
+                lsn: view.ptr.last_lsn(),
+                fragmented: self.context.separate_values
+                    || self.context.pagecache.has_blob_frag(view.pid, &tx),
+            }));
+        }
+    }
 
-            let encoded_key = prefix_encode(&node.lo, key.as_ref());
-            let frag = if let Some(ref new) = new {
-                Frag::Set(encoded_key, new.clone())
+    /// Flips a bit of the value of `key` as it is held in the
+    /// page cache, leaving any checksum from
+    /// `ConfigBuilder::value_checksums` as it was. This is only
+    /// useful for testing that such damage is detected, and is
+    /// only available with the `testing` feature.
+    #[cfg(feature = "testing")]
+    pub fn corrupt_value<K: AsRef<[u8]>>(&self, key: K) -> Result<()> {
+        let key = key.as_ref();
+
+        loop {
+            let tx = self.context.pagecache.begin()?;
+            let View { ptr, pid, node, .. } = self.node_for_key(key, &tx)?;
+
+            let stored = match node.leaf_value_for_key(key) {
+                Some(stored) => stored,
+                None => {
+                    return Err(Error::Unsupported(format!(
+                        "no value to corrupt for key {:?}",
+                        key
+                    )));
+                }
+            };
+
+            let mut framed = if self.context.separate_values {
+                match self.value_page(stored, &tx)? {
+                    Some((_, framed)) => framed.to_vec(),
+                    None => continue,
+                }
             } else {
-                Frag::Del(encoded_key)
+                stored.to_vec()
+            };
+            if let Some(byte) = framed.first_mut() {
+                *byte ^= 1;
+            }
+            let corrupted = self.store_framed_value(IVec::from(framed))?;
+
+            let frag =
+                self.write_frag(node, key, Some(corrupted.clone()), None);
+            if self.context.pagecache.link(pid, ptr, frag, &tx)?.is_ok() {
+                return self.free_value(stored, &tx);
+            }
+            self.free_value(&corrupted, &tx)?;
+        }
+    }
+
+    // with `ConfigBuilder::separate_values`, leaves hold the
+    // id of a page that holds each value, rather than the
+    // value itself. this writes a value to its own page, and
+    // returns what should be stored in the leaf.
+    fn store_value(&self, value: &IVec) -> Result<IVec> {
+        if !self.context.value_checksums {
+            return self.store_framed_value(value.clone());
+        }
+
+        let mut framed = Vec::with_capacity(value.len() + 4);
+        framed.extend_from_slice(value);
+        framed.extend_from_slice(&pagecache::crc32(value).to_le_bytes());
+
+        self.store_framed_value(IVec::from(framed))
+    }
+
+    fn store_framed_value(&self, framed: IVec) -> Result<IVec> {
+        if !self.context.separate_values {
+            return Ok(framed);
+        }
+
+        let tx = self.context.pagecache.begin()?;
+        let (pid, _ptr) =
+            self.context.pagecache.allocate(Frag::Value(framed), &tx)?;
+
+        Ok(IVec::from(&pid.to_be_bytes()[..]))
+    }
+
+    // the leaf update that sets `key` to `stored` in `node`, or
+    // removes it if `stored` is `None`. with
+    // `ConfigBuilder::replication`, it records enough to be read
+    // back out of the log, including the operand of the merge
+    // that produced it, if any.
+    fn write_frag(
+        &self,
+        node: &Node,
+        key: &[u8],
+        stored: Option<IVec>,
+        operand: Option<&[u8]>,
+    ) -> Frag {
+        if !self.context.replication {
+            let encoded_key = prefix_encode(&node.lo, key);
+            return match stored {
+                Some(stored) => Frag::Set(encoded_key, stored),
+                None => Frag::Del(encoded_key),
+            };
+        }
+
+        Frag::Logged(LoggedWrite {
+            tree_id: IVec::from(&*self.tree_id),
+            key: IVec::from(key),
+            stored,
+            operand: operand.map(IVec::from),
+        })
+    }
+
+    // with `ConfigBuilder::value_checksums`, values are followed
+    // by a crc32 of their bytes, which is checked and stripped
+    // here. `at` is the page that the value was read from.
+    pub(crate) fn unframe_value(
+        &self,
+        framed: &IVec,
+        at: &TreePtr<'_>,
+    ) -> Result<IVec> {
+        self.checked_value(framed).ok_or_else(|| {
+            warn!("value failed its checksum at {:?}", at.last_disk_ptr());
+            Error::Corruption {
+                at: at.last_disk_ptr(),
+            }
+        })
+    }
+
+    // strips the checksum that `ConfigBuilder::value_checksums`
+    // adds to values, or returns `None` if it doesn't match
+    fn checked_value(&self, framed: &IVec) -> Option<IVec> {
+        if !self.context.value_checksums {
+            return Some(framed.clone());
+        }
+
+        if framed.len() >= 4 {
+            let (value, crc) = framed.split_at(framed.len() - 4);
+            let mut crc_arr = [0; 4];
+            crc_arr.copy_from_slice(crc);
+            if pagecache::crc32(value) == u32::from_le_bytes(crc_arr) {
+                return Some(IVec::from(value));
+            }
+        }
+
+        None
+    }
+
+    // reads the value that a leaf refers to. returns `None`
+    // if the value was freed after the leaf was read, in which
+    // case the caller should read the leaf again.
+    pub(crate) fn load_value<'g>(
+        &self,
+        stored: &IVec,
+        leaf: &TreePtr<'g>,
+        tx: &'g Tx<'g, Frag>,
+    ) -> Result<Option<IVec>> {
+        if !self.context.separate_values {
+            return self.unframe_value(stored, leaf).map(Some);
+        }
+
+        self.load_separated_value(stored, tx)
+    }
+
+    // reads a value from the page that it was separated into
+    pub(crate) fn load_separated_value<'g>(
+        &self,
+        stored: &IVec,
+        tx: &'g Tx<'g, Frag>,
+    ) -> Result<Option<IVec>> {
+        match self.value_page(stored, tx)? {
+            Some((ptr, framed)) => self.unframe_value(framed, &ptr).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn value_page<'g>(
+        &self,
+        stored: &IVec,
+        tx: &'g Tx<'g, Frag>,
+    ) -> Result<Option<(TreePtr<'g>, &'g IVec)>> {
+        let pid = value_pid(stored)?;
+        match self.context.pagecache.get(pid, tx)? {
+            Some((ptr, Frag::Value(value), _)) => Ok(Some((ptr, value))),
+            None => Ok(None),
+            Some((_, other, _)) => Err(Error::ReportableBug(format!(
+                "expected pid {} to hold a value, but found {:?}",
+                pid, other
+            ))),
+        }
+    }
+
+    // reads and frees the value that a leaf referred to
+    // before it was replaced by a write that we linked.
+    fn take_value<'g>(
+        &self,
+        stored: &IVec,
+        leaf: &TreePtr<'g>,
+        tx: &'g Tx<'g, Frag>,
+    ) -> Result<IVec> {
+        match self.load_value(stored, leaf, tx) {
+            Ok(None) => Err(Error::ReportableBug(format!(
+                "value {:?} was freed before the write \
+                 that replaced it completed",
+                stored
+            ))),
+            loaded => {
+                // the page is no longer referred to even
+                // if the value in it is damaged
+                self.free_value(stored, tx)?;
+                loaded.map(Option::unwrap)
+            }
+        }
+    }
+
+    // frees the page that holds a value, once no leaf refers to it
+    pub(crate) fn free_value<'g>(
+        &self,
+        stored: &IVec,
+        tx: &'g Tx<'g, Frag>,
+    ) -> Result<()> {
+        if !self.context.separate_values {
+            return Ok(());
+        }
+
+        let pid = value_pid(stored)?;
+        loop {
+            let ptr = match self.context.pagecache.get(pid, tx)? {
+                Some((ptr, _, _)) => ptr,
+                None => return Ok(()),
             };
-            let link = self.context.pagecache.link(pid, ptr, frag, &tx)?;
+            if self.context.pagecache.free(pid, ptr, tx)?.is_ok() {
+                return Ok(());
+            }
+        }
+    }
+
+    // frees the page that `store_value` wrote for a write
+    // that failed before linking it, which nothing else would
+    // free, and passes on the error that the write failed with.
+    fn discard_value(&self, stored: Option<&IVec>, error: Error) -> Error {
+        if let (Some(stored), Ok(tx)) = (stored, self.context.pagecache.begin())
+        {
+            if let Err(e) = self.free_value(stored, &tx) {
+                warn!("failed to free the value of a failed write: {:?}", e);
+            }
+        }
+        error
+    }
+
+    /// Delete a value, returning the old value if it existed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    /// t.insert(&[1], vec![1]);
+    /// assert_eq!(t.del(&[1]), Ok(Some(sled::IVec::from(vec![1]))));
+    /// assert_eq!(t.del(&[1]), Ok(None));
+    /// ```
+    #[deprecated(since = "0.24.2", note = "replaced by `Tree::remove`")]
+    pub fn del<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.remove(key)
+    }
+
+    /// Delete a value, returning the old value if it existed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    /// t.set(&[1], vec![1]);
+    /// assert_eq!(t.remove(&[1]), Ok(Some(sled::IVec::from(vec![1]))));
+    /// assert_eq!(t.remove(&[1]), Ok(None));
+    /// ```
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let _cc = self.concurrency_control.read();
+        self.remove_inner(key)
+    }
+
+    /// Atomically delete a set of keys, returning the number
+    /// that existed. Keys that don't exist are skipped. Like
+    /// `Batch::apply`, either every deletion is recovered
+    /// after a crash or none of them are. The keys are removed
+    /// in sorted order, so that keys sharing a leaf are removed
+    /// one after another while it is still cached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// t.insert(b"b", vec![2]).unwrap();
+    /// t.insert(b"c", vec![3]).unwrap();
+    ///
+    /// assert_eq!(t.bulk_delete(vec![b"c", b"a", b"z"]), Ok(2));
+    /// assert_eq!(t.len(), 1);
+    /// ```
+    pub fn bulk_delete<K, I>(&self, keys: I) -> Result<usize>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = K>,
+    {
+        let mut keys: Vec<IVec> =
+            keys.into_iter().map(|k| IVec::from(k.as_ref())).collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        let peg = self.context.pin_log()?;
+        let cc = self.concurrency_control.write();
+        let mut deleted = 0;
+        for key in keys {
+            if self.remove_inner(key)?.is_some() {
+                deleted += 1;
+            }
+        }
+        drop(cc);
+
+        // when the peg drops, it ensures all updates
+        // written to the log since its creation are
+        // recovered atomically
+        peg.seal_batch()?;
+
+        Ok(deleted)
+    }
+
+    /// Removes every record for which `f` returns `false`,
+    /// returning the number removed, like `Vec::retain`.
+    /// The `Tree` is read one leaf at a time, and the records
+    /// removed from each leaf are removed atomically with
+    /// `Tree::bulk_delete`, so a crash leaves each leaf's
+    /// removals either fully applied or not at all.
+    ///
+    /// Records are judged by the values that they held when
+    /// their leaf was read, so a record that is overwritten
+    /// concurrently may be removed for its old value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    /// for i in 0..10u8 {
+    ///     t.insert(&[i], vec![i]).unwrap();
+    /// }
+    ///
+    /// let removed = t.retain(|_k, v| v[0] < 4).unwrap();
+    /// assert_eq!(removed, 6);
+    /// assert_eq!(t.len(), 4);
+    /// ```
+    pub fn retain<F>(&self, mut f: F) -> Result<usize>
+    where
+        F: FnMut(&[u8], &[u8]) -> bool,
+    {
+        if self.context.read_only {
+            return Err(Error::Unsupported(
+                "the database is in read-only mode".to_owned(),
+            ));
+        }
+
+        let mut leaves = self.scan_raw(b"");
+        let mut removed = 0;
+        while let Some(records) = leaves.next_leaf()? {
+            let doomed: Vec<Vec<u8>> = records
+                .iter()
+                .map(|record| prefix_decode(&record.lo, &record.key))
+                .zip(&records)
+                .filter(|(key, record)| !f(key, &record.value))
+                .map(|(key, _)| key)
+                .collect();
+
+            if !doomed.is_empty() {
+                removed += self.bulk_delete(doomed)?;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    pub(crate) fn remove_inner<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>> {
+        let _measure = Measure::new(&M.tree_del);
+
+        if self.context.read_only {
+            return Ok(None);
+        }
+
+        let mut backoff = CasBackoff::new(self.context.cas_backoff);
+
+        loop {
+            let tx = self.context.pagecache.begin()?;
+
+            let View { ptr, pid, node, .. } =
+                self.node_for_key(key.as_ref(), &tx)?;
+            let existing_stored = node.leaf_value_for_key(key.as_ref());
+
+            let mut subscriber_reservation = self.subscriptions.reserve(&key);
+
+            let frag = self.write_frag(node, key.as_ref(), None, None);
+
+            let link =
+                self.context.pagecache.link(pid, ptr.clone(), frag, &tx)?;
+
+            if let Ok(new_cas_key) = link {
+                // success. see `insert_inner` for why a damaged
+                // value is reported only after recording this.
+                let existing_val = existing_stored.map(|existing_stored| {
+                    self.take_value(existing_stored, &ptr, &tx)
+                });
+
+                self.record_write(new_cas_key.last_lsn(), || {
+                    LogOp::Del(IVec::from(key.as_ref()))
+                });
 
-            if link.is_ok() {
                 if let Some(res) = subscriber_reservation.take() {
-                    let event = if let Some(new) = new {
-                        subscription::Event::Set(key.as_ref().to_vec(), new)
-                    } else {
-                        subscription::Event::Del(key.as_ref().to_vec())
-                    };
+                    let event = subscription::Event::Del(key.as_ref().to_vec());
 
                     res.complete(event);
                 }
 
-                return Ok(Ok(()));
+                return existing_val.transpose();
             }
             M.tree_looped();
+            backoff.snooze();
+        }
+    }
+
+    /// Compare and swap. Capable of unique creation, conditional modification,
+    /// or deletion. If old is None, this will only set the value if it doesn't
+    /// exist yet. If new is None, will delete the value if old is correct.
+    /// If both old and new are Some, will modify the value if old is correct.
+    /// If Tree is read-only, will do nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// // unique creation
+    /// assert_eq!(t.cas(&[1], None as Option<&[u8]>, Some(&[10])), Ok(Ok(())));
+    ///
+    /// // conditional modification
+    /// assert_eq!(t.cas(&[1], Some(&[10]), Some(&[20])), Ok(Ok(())));
+    ///
+    /// // conditional deletion
+    /// assert_eq!(t.cas(&[1], Some(&[20]), None as Option<&[u8]>), Ok(Ok(())));
+    /// assert_eq!(t.get(&[1]), Ok(None));
+    /// ```
+    pub fn cas<K, OV, NV>(
+        &self,
+        key: K,
+        old: Option<OV>,
+        new: Option<NV>,
+    ) -> Result<std::result::Result<(), Option<IVec>>>
+    where
+        K: AsRef<[u8]>,
+        OV: AsRef<[u8]>,
+        IVec: From<NV>,
+    {
+        let _cc = self.concurrency_control.read();
+        self.cas_inner(key, old, new)
+    }
+
+    /// Compare and swap, like `cas`, but reporting
+    /// why the swap was not applied as a `CasOutcome`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::CasOutcome;
+    ///
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// assert_eq!(
+    ///     t.cas_outcome(&[1], Some(&[10]), Some(&[20])),
+    ///     Ok(CasOutcome::AbsentButExpectedPresent)
+    /// );
+    /// assert_eq!(
+    ///     t.cas_outcome(&[1], None as Option<&[u8]>, Some(&[10])),
+    ///     Ok(CasOutcome::Applied)
+    /// );
+    /// assert_eq!(
+    ///     t.cas_outcome(&[1], None as Option<&[u8]>, Some(&[20])),
+    ///     Ok(CasOutcome::PresentButExpectedAbsent)
+    /// );
+    /// assert_eq!(
+    ///     t.cas_outcome(&[1], Some(&[20]), Some(&[30])),
+    ///     Ok(CasOutcome::ValueMismatch(vec![10].into()))
+    /// );
+    /// ```
+    pub fn cas_outcome<K, OV, NV>(
+        &self,
+        key: K,
+        old: Option<OV>,
+        new: Option<NV>,
+    ) -> Result<CasOutcome>
+    where
+        K: AsRef<[u8]>,
+        OV: AsRef<[u8]>,
+        IVec: From<NV>,
+    {
+        let expected_present = old.is_some();
+        let outcome = match self.cas(key, old, new)? {
+            Ok(()) => CasOutcome::Applied,
+            Err(None) => CasOutcome::AbsentButExpectedPresent,
+            Err(Some(_)) if !expected_present => {
+                CasOutcome::PresentButExpectedAbsent
+            }
+            Err(Some(current)) => CasOutcome::ValueMismatch(current),
+        };
+        Ok(outcome)
+    }
+
+    pub(crate) fn cas_inner<K, OV, NV>(
+        &self,
+        key: K,
+        old: Option<OV>,
+        new: Option<NV>,
+    ) -> Result<std::result::Result<(), Option<IVec>>>
+    where
+        K: AsRef<[u8]>,
+        OV: AsRef<[u8]>,
+        IVec: From<NV>,
+    {
+        self.cas_as(key, old, new, None)
+    }
+
+    // `operand` is the operand of the merge that this
+    // swap applies, if any, which is recorded with the
+    // write when `ConfigBuilder::replication` is set.
+    fn cas_as<K, OV, NV>(
+        &self,
+        key: K,
+        old: Option<OV>,
+        new: Option<NV>,
+        operand: Option<&[u8]>,
+    ) -> Result<std::result::Result<(), Option<IVec>>>
+    where
+        K: AsRef<[u8]>,
+        OV: AsRef<[u8]>,
+        IVec: From<NV>,
+    {
+        trace!("casing key {:?}", key.as_ref());
+        let _measure = Measure::new(&M.tree_cas);
+
+        if self.context.read_only {
+            return Err(Error::Unsupported(
+                "can not perform a cas on a read-only Tree".into(),
+            ));
+        }
+
+        let new = new.map(IVec::from);
+        let mut backoff = CasBackoff::new(self.context.cas_backoff);
+
+        if let (Some(bloom_filter), Some(_)) = (&self.bloom_filter, &new) {
+            bloom_filter.insert(key.as_ref());
+        }
+
+        let stored = match new {
+            Some(ref new) => Some(self.store_value(new)?),
+            None => None,
+        };
+        let discard = |e| self.discard_value(stored.as_ref(), e);
+
+        // we need to retry caps until old != cur, since just because
+        // cap fails it doesn't mean our value was changed.
+        loop {
+            let tx = self.context.pagecache.begin().map_err(discard)?;
+            let View { ptr, pid, node, .. } =
+                self.node_for_key(key.as_ref(), &tx).map_err(discard)?;
+            let cur_stored = node.leaf_value_for_key(key.as_ref());
+            let cur = match cur_stored {
+                Some(cur_stored) => {
+                    match self
+                        .load_value(cur_stored, &ptr, &tx)
+                        .map_err(discard)?
+                    {
+                        Some(cur) => Some(cur),
+                        // replaced concurrently, so our view is stale
+                        None => continue,
+                    }
+                }
+                None => None,
+            };
+
+            let matches = match (&old, &cur) {
+                (None, None) => true,
+                (Some(ref o), Some(ref c)) => o.as_ref() == &**c,
+                _ => false,
+            };
+
+            if !matches {
+                if let Some(ref stored) = stored {
+                    self.free_value(stored, &tx)?;
+                }
+                return Ok(Err(cur));
+            }
+
+            let mut subscriber_reservation = self.subscriptions.reserve(&key);
+
+            let frag =
+                self.write_frag(node, key.as_ref(), stored.clone(), operand);
+            let link = self
+                .context
+                .pagecache
+                .link(pid, ptr, frag, &tx)
+                .map_err(discard)?;
+
+            if let Ok(new_cas_key) = link {
+                if new.is_some() {
+                    self.subscriptions.inserted();
+                }
+                if let Some(cur_stored) = cur_stored {
+                    self.free_value(cur_stored, &tx)?;
+                }
+
+                self.record_write(new_cas_key.last_lsn(), || {
+                    let key: IVec = key.as_ref().to_vec().into();
+                    if let Some(ref new) = new {
+                        LogOp::Set(key, new.clone())
+                    } else {
+                        LogOp::Del(key)
+                    }
+                });
+
+                if let Some(res) = subscriber_reservation.take() {
+                    let event = if let Some(new) = new {
+                        subscription::Event::Set(key.as_ref().to_vec(), new)
+                    } else {
+                        subscription::Event::Del(key.as_ref().to_vec())
+                    };
+
+                    res.complete(event);
+                }
+
+                return Ok(Ok(()));
+            }
+            M.tree_looped();
+            backoff.snooze();
+        }
+    }
+
+    /// Atomically compare and swap several keys at once. Each
+    /// operation is a `(key, expected, new)` triple, and the new
+    /// values are only written if every key currently holds its
+    /// expected value, with `None` meaning absent. Otherwise
+    /// nothing is written, and the first mismatched key is
+    /// returned along with its current value. The writes are
+    /// recovered atomically after a crash, just like a `Batch`.
+    /// Other writers wait while it runs, but readers don't, so
+    /// a concurrent reader may see some of the new values
+    /// before the rest are written.
+    ///
+    /// Returns an `Error::Unsupported` if a key appears more
+    /// than once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec, MultiCasError};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    ///
+    /// // move the value from a to b
+    /// let res = t.multi_cas(vec![
+    ///     (b"a", Some(vec![1]), None),
+    ///     (b"b", None, Some(vec![1])),
+    /// ]);
+    /// assert_eq!(res, Ok(Ok(())));
+    /// assert_eq!(t.get(b"a"), Ok(None));
+    /// assert_eq!(t.get(b"b"), Ok(Some(IVec::from(vec![1]))));
+    ///
+    /// // nothing is written if one expectation is stale
+    /// let res = t.multi_cas(vec![
+    ///     (b"a", Some(vec![1]), Some(vec![2])),
+    ///     (b"b", Some(vec![1]), None),
+    /// ]);
+    /// assert_eq!(res, Ok(Err(MultiCasError { key: IVec::from(b"a"), current: None })));
+    /// assert_eq!(t.get(b"b"), Ok(Some(IVec::from(vec![1]))));
+    /// ```
+    pub fn multi_cas<K, OV, NV>(
+        &self,
+        ops: Vec<(K, Option<OV>, Option<NV>)>,
+    ) -> Result<std::result::Result<(), MultiCasError>>
+    where
+        K: AsRef<[u8]>,
+        OV: AsRef<[u8]>,
+        IVec: From<NV>,
+    {
+        let mut keys: Vec<&[u8]> =
+            ops.iter().map(|(k, _, _)| k.as_ref()).collect();
+        keys.sort();
+        if keys.windows(2).any(|w| w[0] == w[1]) {
+            return Err(Error::Unsupported(
+                "each key may only appear once in a multi_cas".into(),
+            ));
+        }
+
+        let peg = self.context.pin_log()?;
+        let cc = self.concurrency_control.write();
+
+        // no other writer can proceed while we hold the write
+        // lock, so these values can't change before we apply.
+        for (key, old, _) in &ops {
+            let current = self.get_inner(key)?;
+            if current.as_ref().map(AsRef::as_ref)
+                != old.as_ref().map(AsRef::as_ref)
+            {
+                return Ok(Err(MultiCasError {
+                    key: key.as_ref().to_vec().into(),
+                    current,
+                }));
+            }
+        }
+
+        for (key, _, new) in ops {
+            if let Some(new) = new {
+                self.insert_inner(key, new)?;
+            } else {
+                self.remove_inner(key)?;
+            }
+        }
+        drop(cc);
+
+        peg.seal_batch()?;
+
+        Ok(Ok(()))
+    }
+
+    /// Runs `f` as an optimistic transaction. The closure
+    /// reads and stages writes through a `TransactionalTree`,
+    /// and when it returns, its writes are applied atomically
+    /// if none of the values that it read have changed in the
+    /// meantime. Otherwise, or if the closure returns
+    /// `TransactionError::Conflict`, the closure is run again
+    /// from the start. A `TransactionError::Storage` aborts
+    /// the transaction without writing anything.
+    ///
+    /// As with `Tree::update_and_fetch`, the closure may be
+    /// called multiple times, so it should not have side
+    /// effects outside of the `TransactionalTree`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    ///
+    /// // move the value from a to b
+    /// let moved = t
+    ///     .transaction(|tx| {
+    ///         let value = tx.remove(b"a")?;
+    ///         if let Some(value) = value.clone() {
+    ///             tx.insert(b"b", value)?;
+    ///         }
+    ///         Ok(value.is_some())
+    ///     })
+    ///     .unwrap();
+    ///
+    /// assert!(moved);
+    /// assert_eq!(t.get(b"a"), Ok(None));
+    /// assert_eq!(t.get(b"b"), Ok(Some(IVec::from(vec![1]))));
+    /// ```
+    pub fn transaction<F, A>(&self, f: F) -> Result<A>
+    where
+        F: Fn(&TransactionalTree<'_>) -> ConflictableResult<A>,
+    {
+        let mut backoff = CasBackoff::new(self.context.cas_backoff);
+        loop {
+            let tx = TransactionalTree::new(self);
+            let res = f(&tx).and_then(|ret| tx.commit().map(|()| ret));
+            match res {
+                Ok(ret) => return Ok(ret),
+                Err(TransactionError::Storage(e)) => return Err(e),
+                Err(TransactionError::Conflict) => {}
+            }
+            M.tree_looped();
+            backoff.snooze();
+        }
+    }
+
+    /// Starts a transaction that is committed explicitly,
+    /// rather than when a `Tree::transaction` closure
+    /// returns. Stage reads and writes through the returned
+    /// `TransactionalTree`, then call
+    /// `TransactionalTree::commit` to apply them, or drop it to
+    /// discard them. Unlike `Tree::transaction`, a conflict is
+    /// returned to the caller instead of being retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// let tx = t.begin_transaction();
+    /// tx.insert(b"a", vec![1]).unwrap();
+    /// tx.commit().unwrap();
+    ///
+    /// assert_eq!(t.get(b"a"), Ok(Some(IVec::from(vec![1]))));
+    /// ```
+    pub fn begin_transaction(&self) -> TransactionalTree<'_> {
+        TransactionalTree::new(self)
+    }
+
+    /// Atomically move the value of `from` to `to`, removing
+    /// `from`. If `to` already exists, it is only replaced
+    /// when `overwrite` is `true`. Returns `false` without
+    /// changing anything if `from` does not exist or `to`
+    /// exists and `overwrite` is `false`.
+    ///
+    /// The move is written as a batch, so recovery after a
+    /// crash observes either both keys as they were before
+    /// or the completed move, never both or neither.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// t.insert(b"c", vec![3]).unwrap();
+    ///
+    /// assert_eq!(t.rename(b"a", b"b", false), Ok(true));
+    /// assert_eq!(t.get(b"a"), Ok(None));
+    /// assert_eq!(t.get(b"b"), Ok(Some(IVec::from(vec![1]))));
+    ///
+    /// assert_eq!(t.rename(b"b", b"c", false), Ok(false));
+    /// assert_eq!(t.rename(b"b", b"c", true), Ok(true));
+    /// assert_eq!(t.get(b"c"), Ok(Some(IVec::from(vec![1]))));
+    /// ```
+    pub fn rename<K1, K2>(
+        &self,
+        from: K1,
+        to: K2,
+        overwrite: bool,
+    ) -> Result<bool>
+    where
+        K1: AsRef<[u8]>,
+        K2: AsRef<[u8]>,
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        let peg = self.context.pin_log()?;
+        let cc = self.concurrency_control.write();
+
+        let value = match self.get_inner(from)? {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+
+        if from == to {
+            return Ok(true);
+        }
+
+        if !overwrite && self.get_inner(to)?.is_some() {
+            return Ok(false);
+        }
+
+        self.insert_inner(to, value)?;
+        self.remove_inner(from)?;
+        drop(cc);
+
+        peg.seal_batch()?;
+
+        Ok(true)
+    }
+
+    /// Fetch the value, apply a function to it and return the result.
+    ///
+    /// The new value is stored with a compare-and-swap against the
+    /// value that was read, so it is only written if nothing else
+    /// changed the key first. Unlike a merge operator, the function
+    /// is chosen per call and is never persisted, which suits
+    /// state-based CRDTs that merge a local delta into the stored
+    /// state.
+    ///
+    /// # Note
+    ///
+    /// This may call the function multiple times if the value has been
+    /// changed from other threads in the meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use sled::{ConfigBuilder, Error, IVec};
+    ///
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let tree = sled::Db::start(config).unwrap();
+    ///
+    /// fn u64_to_ivec(number: u64) -> IVec {
+    ///     IVec::from(number.to_be_bytes().to_vec())
+    /// }
+    ///
+    /// let zero = u64_to_ivec(0);
+    /// let one = u64_to_ivec(1);
+    /// let two = u64_to_ivec(2);
+    /// let three = u64_to_ivec(3);
+    ///
+    /// fn increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
+    ///     let number = match old {
+    ///         Some(bytes) => {
+    ///             let array: [u8; 8] = bytes.try_into().unwrap();
+    ///             let number = u64::from_be_bytes(array);
+    ///             number + 1
+    ///         },
+    ///         None => 0,
+    ///     };
+    ///
+    ///     Some(number.to_be_bytes().to_vec())
+    /// }
+    ///
+    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(zero)));
+    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(one)));
+    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(two)));
+    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(three)));
+    /// ```
+    pub fn update_and_fetch<K, V, F>(
+        &self,
+        key: K,
+        mut f: F,
+    ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        F: FnMut(Option<&[u8]>) -> Option<V>,
+        IVec: From<V>,
+    {
+        let key = key.as_ref();
+        let mut current = self.get(key)?;
+
+        loop {
+            let tmp = current.as_ref().map(AsRef::as_ref);
+            let next = f(tmp).map(IVec::from);
+            match self.cas::<_, _, IVec>(key, tmp, next.clone())? {
+                Ok(()) => return Ok(next),
+                Err(new_current) => current = new_current,
+            }
+        }
+    }
+
+    /// Fetch the value, apply a function to it and return the previous value.
+    ///
+    /// # Note
+    ///
+    /// This may call the function multiple times if the value has been
+    /// changed from other threads in the meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use sled::{ConfigBuilder, Error, IVec};
+    ///
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let tree = sled::Db::start(config).unwrap();
+    ///
+    /// fn u64_to_ivec(number: u64) -> IVec {
+    ///     IVec::from(number.to_be_bytes().to_vec())
+    /// }
+    ///
+    /// let zero = u64_to_ivec(0);
+    /// let one = u64_to_ivec(1);
+    /// let two = u64_to_ivec(2);
+    ///
+    /// fn increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
+    ///     let number = match old {
+    ///         Some(bytes) => {
+    ///             let array: [u8; 8] = bytes.try_into().unwrap();
+    ///             let number = u64::from_be_bytes(array);
+    ///             number + 1
+    ///         },
+    ///         None => 0,
+    ///     };
+    ///
+    ///     Some(number.to_be_bytes().to_vec())
+    /// }
+    ///
+    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(None));
+    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(Some(zero)));
+    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(Some(one)));
+    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(Some(two)));
+    /// ```
+    pub fn fetch_and_update<K, V, F>(
+        &self,
+        key: K,
+        mut f: F,
+    ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        F: FnMut(Option<&[u8]>) -> Option<V>,
+        IVec: From<V>,
+    {
+        let key = key.as_ref();
+        let mut current = self.get(key)?;
+
+        loop {
+            let tmp = current.as_ref().map(AsRef::as_ref);
+            let next = f(tmp);
+            match self.cas(key, tmp, next)? {
+                Ok(()) => return Ok(current),
+                Err(new_current) => current = new_current,
+            }
+        }
+    }
+
+    /// Returns the value for a key, atomically inserting
+    /// `default` first if the key is absent. If a concurrent
+    /// writer inserts a value first, that value is returned
+    /// instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// assert_eq!(t.get_or_insert(b"k", vec![1]), Ok(IVec::from(vec![1])));
+    /// assert_eq!(t.get_or_insert(b"k", vec![2]), Ok(IVec::from(vec![1])));
+    /// ```
+    pub fn get_or_insert<K, V>(&self, key: K, default: V) -> Result<IVec>
+    where
+        K: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        self.get_or_insert_with(key, || default)
+    }
+
+    /// Returns the value for a key, atomically inserting the
+    /// result of `f` first if the key is absent. `f` is only
+    /// called if the key is absent. If a concurrent writer
+    /// inserts a value first, that value is returned instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"k", vec![1]).unwrap();
+    /// let value = t.get_or_insert_with(b"k", || -> Vec<u8> {
+    ///     unreachable!("the key is present")
+    /// });
+    /// assert_eq!(value, Ok(IVec::from(vec![1])));
+    /// ```
+    pub fn get_or_insert_with<K, V, F>(&self, key: K, f: F) -> Result<IVec>
+    where
+        K: AsRef<[u8]>,
+        F: FnOnce() -> V,
+        IVec: From<V>,
+    {
+        let key = key.as_ref();
+        if let Some(current) = self.get(key)? {
+            return Ok(current);
+        }
+
+        let default: IVec = f().into();
+        match self.cas::<_, _, IVec>(
+            key,
+            None as Option<&[u8]>,
+            Some(default.clone()),
+        )? {
+            Ok(()) => Ok(default),
+            // a cas expecting an absent key only fails
+            // when another writer's value is present.
+            Err(current) => Ok(current.expect("lost race to a present key")),
+        }
+    }
+
+    /// Atomically inserts `value` only if `key` is absent,
+    /// returning whether it was inserted. When many threads
+    /// race to insert the same key, exactly one succeeds,
+    /// which makes this a building block for locks and
+    /// idempotency keys. This is shorthand for a `cas` that
+    /// expects the key to be absent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// assert_eq!(t.put_if_absent(b"k", vec![1]), Ok(true));
+    /// assert_eq!(t.put_if_absent(b"k", vec![2]), Ok(false));
+    /// assert_eq!(t.get(b"k"), Ok(Some(sled::IVec::from(vec![1]))));
+    /// ```
+    pub fn put_if_absent<K, V>(&self, key: K, value: V) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        let res = self.cas(key, None as Option<&[u8]>, Some(value))?;
+        Ok(res.is_ok())
+    }
+
+    /// Returns the value for a key, calling `loader` to fetch
+    /// it if the key is absent, and storing what it returns
+    /// before returning it. This makes the `Tree` a read-through
+    /// cache in front of a slower store. `loader` is only called
+    /// on a miss, and when many threads miss the same key at
+    /// once, one of them runs its loader while the others wait
+    /// for and return its result. If `loader` returns `None`,
+    /// nothing is stored and `None` is returned. A value that
+    /// another writer stores first is returned instead of the
+    /// loaded one, as with `Tree::put_if_absent`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// let value = t.get_or_load(b"k", || Some(vec![1]));
+    /// assert_eq!(value, Ok(Some(IVec::from(vec![1]))));
+    ///
+    /// // the stored value is returned without loading it again
+    /// let value = t.get_or_load(b"k", || -> Option<Vec<u8>> {
+    ///     unreachable!("the key is present")
+    /// });
+    /// assert_eq!(value, Ok(Some(IVec::from(vec![1]))));
+    ///
+    /// // nothing is stored when the loader finds nothing
+    /// assert_eq!(t.get_or_load(b"missing", || None::<Vec<u8>>), Ok(None));
+    /// assert_eq!(t.get(b"missing"), Ok(None));
+    /// ```
+    pub fn get_or_load<K, V, F>(
+        &self,
+        key: K,
+        loader: F,
+    ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        F: FnOnce() -> Option<V>,
+        IVec: From<V>,
+    {
+        let key = key.as_ref();
+        if let Some(current) = self.get(key)? {
+            return Ok(Some(current));
+        }
+
+        let _load = self.loads.start(key);
+
+        // another caller may have loaded it while we waited
+        if let Some(current) = self.get(key)? {
+            return Ok(Some(current));
+        }
+
+        let loaded = match loader() {
+            Some(loaded) => IVec::from(loaded),
+            None => return Ok(None),
+        };
+        match self.cas::<_, _, IVec>(
+            key,
+            None as Option<&[u8]>,
+            Some(loaded.clone()),
+        )? {
+            Ok(()) => Ok(Some(loaded)),
+            Err(current) => Ok(current),
+        }
+    }
+
+    /// Subscribe to `Event`s that happen to keys that have
+    /// the specified prefix. Events for particular keys are
+    /// guaranteed to be witnessed in the same order by all
+    /// threads, but threads may witness different interleavings
+    /// of `Event`s across different keys. If subscribers don't
+    /// keep up with new writes, they will cause new writes
+    /// to block. There is a buffer of 1024 items per
+    /// `Subscriber`. This can be used to build reactive
+    /// and replicated systems.
+    ///
+    /// # Examples
+    /// ```
+    /// use sled::{Event, ConfigBuilder};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    ///
+    /// let tree = sled::Db::start(config).unwrap();
+    ///
+    /// // watch all events by subscribing to the empty prefix
+    /// let mut events = tree.watch_prefix(vec![]);
+    ///
+    /// let tree_2 = tree.clone();
+    /// let thread = std::thread::spawn(move || {
+    ///     tree.insert(vec![0], vec![1]).unwrap();
+    /// });
+    ///
+    /// // events is a blocking `Iterator` over `Event`s
+    /// for event in events.take(1) {
+    ///     match event {
+    ///         Event::Set(key, value) => assert_eq!(key, vec![0]),
+    ///         Event::Merge(key, partial_value) => {}
+    ///         Event::Del(key) => {}
+    ///     }
+    /// }
+    ///
+    /// thread.join().unwrap();
+    /// ```
+    pub fn watch_prefix(&self, prefix: Vec<u8>) -> Subscriber {
+        self.subscriptions.register(prefix)
+    }
+
+    /// Flushes all dirty IO buffers and calls fsync.
+    /// If this succeeds, it is guaranteed that
+    /// all previous writes will be recovered if
+    /// the system crashes. Returns the number
+    /// of bytes flushed during this call.
+    pub fn flush(&self) -> Result<usize> {
+        self.context.pagecache.flush()
+    }
+
+    /// Exercises the write path end to end by writing a
+    /// sentinel page, flushing it, reading it back, and
+    /// freeing it again. Returns an error if any of those
+    /// steps fail, which makes it suitable for liveness
+    /// probes. The sentinel lives outside of every tree's
+    /// keyspace, so it is never seen by readers, subscribers
+    /// or replication.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// t.health_check().unwrap();
+    /// assert!(t.is_empty());
+    /// ```
+    pub fn health_check(&self) -> Result<()> {
+        // a fresh id per call lets us tell our sentinel
+        // apart from anything else that might end up there
+        let token = self.context.generate_id()?.to_le_bytes();
+
+        let pagecache = &self.context.pagecache;
+        let tx = pagecache.begin()?;
+        let (pid, ptr) =
+            pagecache.allocate(Frag::Value(IVec::from(&token[..])), &tx)?;
+
+        let checked = match self.flush().and_then(|_| pagecache.get(pid, &tx)) {
+            Ok(Some((_, Frag::Value(value), _))) if *value == token[..] => {
+                Ok(())
+            }
+            Ok(other) => Err(Error::ReportableBug(format!(
+                "health check read back {:?} instead of {:?}",
+                other.map(|(_, frag, _)| frag),
+                token
+            ))),
+            Err(e) => Err(e),
+        };
+
+        // nothing else knows about this page, so it is
+        // still ours to free even if the check failed
+        let freed = pagecache.free(pid, ptr, &tx);
+
+        checked.and(freed.map(|_| ()))
+    }
+
+    /// Schedules a flush of all previous writes on a
+    /// background thread and returns immediately. Writes
+    /// issued before this call are durable once the returned
+    /// `FlushHandle`'s `wait` method returns `Ok`, so a
+    /// server can keep working and only wait right before
+    /// acknowledging those writes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// let handle = t.flush_async();
+    ///
+    /// // keep working while the flush runs
+    /// t.insert(b"b", vec![2]).unwrap();
+    ///
+    /// handle.wait().unwrap();
+    /// ```
+    pub fn flush_async(&self) -> FlushHandle {
+        let pagecache = self.context.pagecache.clone();
+        let lsn = pagecache.max_reserved_lsn();
+
+        if pagecache.stable_lsn() < lsn {
+            self.context.async_flusher.request(&pagecache, lsn);
+        }
+
+        FlushHandle { pagecache, lsn }
+    }
+
+    /// Blocks until every write that returned before this call,
+    /// on any thread, is durable, and returns the log sequence
+    /// number up to which the log is durable. Writes are made
+    /// durable in the order that they were logged, so a thread
+    /// that learns of a completed barrier, for example through
+    /// a channel, may rely on all writes that returned before
+    /// it having survived any later crash. Writes issued
+    /// concurrently with the barrier may or may not be covered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// let first = t.barrier().unwrap();
+    ///
+    /// t.insert(b"b", vec![2]).unwrap();
+    /// let second = t.barrier().unwrap();
+    /// assert!(second > first);
+    /// ```
+    pub fn barrier(&self) -> Result<Lsn> {
+        let pagecache = &self.context.pagecache;
+        pagecache.make_stable(pagecache.max_reserved_lsn())?;
+        Ok(pagecache.stable_lsn())
+    }
+
+    /// Returns `true` if the `Tree` contains a value for
+    /// the specified key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// t.insert(&[0], vec![0]).unwrap();
+    /// assert!(t.contains_key(&[0]).unwrap());
+    /// assert!(!t.contains_key(&[1]).unwrap());
+    /// ```
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.get(key).map(|v| v.is_some())
+    }
+
+    /// Retrieve the key and value before the provided key,
+    /// if one exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let tree = Db::start(config).unwrap();
+    ///
+    /// for i in 0..10 {
+    ///     tree.insert(&[i], vec![i]).expect("should write successfully");
+    /// }
+    ///
+    /// assert_eq!(tree.get_lt(&[]), Ok(None));
+    /// assert_eq!(tree.get_lt(&[0]), Ok(None));
+    /// assert_eq!(tree.get_lt(&[1]), Ok(Some((IVec::from(&[0]), IVec::from(&[0])))));
+    /// assert_eq!(tree.get_lt(&[9]), Ok(Some((IVec::from(&[8]), IVec::from(&[8])))));
+    /// assert_eq!(tree.get_lt(&[10]), Ok(Some((IVec::from(&[9]), IVec::from(&[9])))));
+    /// assert_eq!(tree.get_lt(&[255]), Ok(Some((IVec::from(&[9]), IVec::from(&[9])))));
+    /// ```
+    pub fn get_lt<K>(&self, key: K) -> Result<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+    {
+        let _measure = Measure::new(&M.tree_get);
+        self.range(..key).next_back().transpose()
+    }
+
+    /// Retrieve the next key and value from the `Tree` after the
+    /// provided key.
+    ///
+    /// # Note
+    /// The order follows the Ord implementation for `Vec<u8>`:
+    ///
+    /// `[] < [0] < [255] < [255, 0] < [255, 255] ...`
+    ///
+    /// To retain the ordering of numerical types use big endian reprensentation
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let tree = Db::start(config).unwrap();
+    ///
+    /// for i in 0..10 {
+    ///     tree.insert(&[i], vec![i]).expect("should write successfully");
+    /// }
+    ///
+    /// assert_eq!(tree.get_gt(&[]), Ok(Some((IVec::from(&[0]), IVec::from(&[0])))));
+    /// assert_eq!(tree.get_gt(&[0]), Ok(Some((IVec::from(&[1]), IVec::from(&[1])))));
+    /// assert_eq!(tree.get_gt(&[1]), Ok(Some((IVec::from(&[2]), IVec::from(&[2])))));
+    /// assert_eq!(tree.get_gt(&[8]), Ok(Some((IVec::from(&[9]), IVec::from(&[9])))));
+    /// assert_eq!(tree.get_gt(&[9]), Ok(None));
+    ///
+    /// tree.insert(500u16.to_be_bytes(), vec![10] );
+    /// assert_eq!(tree.get_gt(&499u16.to_be_bytes()),
+    ///            Ok(Some((IVec::from(&500u16.to_be_bytes()), IVec::from(&[10])))));
+    /// ```
+    pub fn get_gt<K>(&self, key: K) -> Result<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+    {
+        let _measure = Measure::new(&M.tree_get);
+        self.range((ops::Bound::Excluded(key), ops::Bound::Unbounded))
+            .next()
+            .transpose()
+    }
+
+    /// Retrieve the first key and value in the provided range,
+    /// or `None` if the range is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let tree = Db::start(config).unwrap();
+    ///
+    /// for i in (0..10).step_by(2) {
+    ///     tree.insert(&[i], vec![i]).unwrap();
+    /// }
+    ///
+    /// assert_eq!(
+    ///     tree.range_first(vec![3]..vec![7]),
+    ///     Ok(Some((IVec::from(&[4]), IVec::from(&[4]))))
+    /// );
+    /// assert_eq!(tree.range_first(vec![9]..), Ok(None));
+    /// ```
+    pub fn range_first<K, R>(&self, range: R) -> Result<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let _measure = Measure::new(&M.tree_get);
+        self.range(range).next().transpose()
+    }
+
+    /// Retrieve the last key and value in the provided range,
+    /// or `None` if the range is empty. This searches down from
+    /// the end of the range, rather than scanning up to it, so
+    /// it can be used to find the latest entry before a
+    /// time-ordered key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let tree = Db::start(config).unwrap();
+    ///
+    /// for i in (0..10).step_by(2) {
+    ///     tree.insert(&[i], vec![i]).unwrap();
+    /// }
+    ///
+    /// assert_eq!(
+    ///     tree.range_last(vec![3]..vec![7]),
+    ///     Ok(Some((IVec::from(&[6]), IVec::from(&[6]))))
+    /// );
+    /// assert_eq!(tree.range_last(..vec![0]), Ok(None));
+    /// ```
+    pub fn range_last<K, R>(&self, range: R) -> Result<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let _measure = Measure::new(&M.tree_get);
+        self.range(range).next_back().transpose()
+    }
+
+    /// Merge state directly into a given key's value using the
+    /// configured merge operator. This allows state to be written
+    /// into a value directly, without any read-modify-write steps.
+    /// Merge operators can be used to implement arbitrary data
+    /// structures. When the merge operator returns `None` the
+    /// key is removed, so an operator maintaining a collection
+    /// should return `None` once the collection is empty, as
+    /// `set_remove` does, rather than storing an empty encoding.
+    ///
+    /// # Panics
+    ///
+    /// Calling `merge` will panic if no merge operator has been
+    /// configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    ///
+    /// fn concatenate_merge(
+    ///   _key: &[u8],               // the key being merged
+    ///   old_value: Option<&[u8]>,  // the previous value, if one existed
+    ///   merged_bytes: &[u8]        // the new bytes being merged in
+    /// ) -> Option<Vec<u8>> {       // set the new value, return None to delete
+    ///   let mut ret = old_value
+    ///     .map(|ov| ov.to_vec())
+    ///     .unwrap_or_else(|| vec![]);
+    ///
+    ///   ret.extend_from_slice(merged_bytes);
+    ///
+    ///   Some(ret)
+    /// }
+    ///
+    /// let config = ConfigBuilder::new()
+    ///   .temporary(true)
+    ///   .build();
+    ///
+    /// let tree = Db::start(config).unwrap();
+    /// tree.set_merge_operator(concatenate_merge);
+    ///
+    /// let k = b"k1";
+    ///
+    /// tree.insert(k, vec![0]);
+    /// tree.merge(k, vec![1]);
+    /// tree.merge(k, vec![2]);
+    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![0, 1, 2]))));
+    ///
+    /// // Replace previously merged data. The merge function will not be called.
+    /// tree.insert(k, vec![3]);
+    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![3]))));
+    ///
+    /// // Merges on non-present values will cause the merge function to be called
+    /// // with `old_value == None`. If the merge function returns something (which it
+    /// // does, in this case) a new value will be inserted.
+    /// tree.remove(k);
+    /// tree.merge(k, vec![4]);
+    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![4]))));
+    /// ```
+    pub fn merge<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let _cc = self.concurrency_control.read();
+        self.merge_inner(key, value)
+    }
+
+    /// Merge `value` into the value of `key` using
+    /// `merge_operator` for this call only, instead of the
+    /// `Tree`'s merge operator, returning the value stored
+    /// afterwards. Like `merge`, the operator is applied
+    /// immediately, and retried if the value changes
+    /// concurrently, so it may be called more than once.
+    /// Returning `None` from it removes the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    ///
+    /// fn append(
+    ///     _key: &[u8],
+    ///     old_value: Option<&[u8]>,
+    ///     merged_bytes: &[u8],
+    /// ) -> Option<Vec<u8>> {
+    ///     let mut ret = old_value.map(|ov| ov.to_vec()).unwrap_or_default();
+    ///     ret.extend_from_slice(merged_bytes);
+    ///     Some(ret)
+    /// }
+    ///
+    /// fn prepend(
+    ///     _key: &[u8],
+    ///     old_value: Option<&[u8]>,
+    ///     merged_bytes: &[u8],
+    /// ) -> Option<Vec<u8>> {
+    ///     let mut ret = merged_bytes.to_vec();
+    ///     ret.extend_from_slice(old_value.unwrap_or_default());
+    ///     Some(ret)
+    /// }
+    ///
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.merge_with(b"k", vec![1], append).unwrap();
+    /// t.merge_with(b"k", vec![2], append).unwrap();
+    /// assert_eq!(
+    ///     t.merge_with(b"k", vec![0], prepend),
+    ///     Ok(Some(IVec::from(vec![0, 1, 2])))
+    /// );
+    /// ```
+    pub fn merge_with<K, V>(
+        &self,
+        key: K,
+        value: V,
+        merge_operator: MergeOperator,
+    ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        trace!("merging key {:?}", key.as_ref());
+        let _measure = Measure::new(&M.tree_merge);
+
+        if self.context.read_only {
+            return Err(Error::Unsupported(
+                "the database is in read-only mode".to_owned(),
+            ));
+        }
+
+        let _cc = self.concurrency_control.read();
+        self.merge_with_inner(key.as_ref(), value.as_ref(), merge_operator)
+    }
+
+    /// Atomically store `candidate` if it is larger than the
+    /// current value, returning the value stored afterwards.
+    /// Values are stored as big-endian `u64`s, and a value
+    /// that isn't 8 bytes long is replaced. This uses the
+    /// `max_u64` merge operator, whether or not the `Tree`
+    /// has a merge operator set, and is useful for watermarks
+    /// and monotonic clocks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// assert_eq!(t.update_max(b"high score", 10), Ok(10));
+    /// assert_eq!(t.update_max(b"high score", 7), Ok(10));
+    /// assert_eq!(t.update_max(b"high score", 12), Ok(12));
+    /// ```
+    pub fn update_max<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        candidate: u64,
+    ) -> Result<u64> {
+        self.update_extreme(key.as_ref(), candidate, max_u64)
+    }
+
+    /// Atomically store `candidate` if it is smaller than the
+    /// current value, returning the value stored afterwards.
+    /// See `Tree::update_max`, which this mirrors using the
+    /// `min_u64` merge operator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// assert_eq!(t.update_min(b"fastest", 10), Ok(10));
+    /// assert_eq!(t.update_min(b"fastest", 12), Ok(10));
+    /// assert_eq!(t.update_min(b"fastest", 7), Ok(7));
+    /// ```
+    pub fn update_min<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        candidate: u64,
+    ) -> Result<u64> {
+        self.update_extreme(key.as_ref(), candidate, min_u64)
+    }
+
+    fn update_extreme(
+        &self,
+        key: &[u8],
+        candidate: u64,
+        merge_operator: MergeOperator,
+    ) -> Result<u64> {
+        let stored =
+            self.merge_with(key, candidate.to_be_bytes(), merge_operator)?;
+
+        // the operators always store a well-formed candidate
+        Ok(stored.as_ref().and_then(|v| decode_u64(v)).unwrap())
+    }
+
+    pub(crate) fn merge_inner<K, V>(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        trace!("merging key {:?}", key.as_ref());
+        let _measure = Measure::new(&M.tree_merge);
+
+        if self.context.read_only {
+            return Err(Error::Unsupported(
+                "the database is in read-only mode".to_owned(),
+            ));
+        }
+
+        let merge_operator_opt = self.merge_operator.read();
+
+        if merge_operator_opt.is_none() {
+            return Err(Error::Unsupported(
+                "must set a merge operator on this Tree \
+                 before calling merge by calling \
+                 Tree::set_merge_operator"
+                    .to_owned(),
+            ));
+        }
+
+        let merge_operator = merge_operator_opt.unwrap();
+
+        self.merge_with_inner(key.as_ref(), value.as_ref(), merge_operator)
+    }
+
+    // applies a merge operator in a cas loop, which must
+    // happen while holding the concurrency control lock.
+    fn merge_with_inner(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        merge_operator: MergeOperator,
+    ) -> Result<Option<IVec>> {
+        let mut current = self.get_inner(key)?;
+
+        loop {
+            let tmp = current.as_ref().map(AsRef::as_ref);
+            let next = merge_operator(key, tmp, value).map(IVec::from);
+            match self.cas_as::<_, _, IVec>(
+                key,
+                tmp,
+                next.clone(),
+                Some(value),
+            )? {
+                Ok(()) => return Ok(next),
+                Err(new_current) => current = new_current,
+            }
+            M.tree_looped();
+        }
+    }
+
+    /// Sets a merge operator for use with the `merge` function.
+    ///
+    /// Merge state directly into a given key's value using the
+    /// configured merge operator. This allows state to be written
+    /// into a value directly, without any read-modify-write steps.
+    /// Merge operators can be used to implement arbitrary data
+    /// structures.
+    ///
+    /// # Panics
+    ///
+    /// Calling `merge` will panic if no merge operator has been
+    /// configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    ///
+    /// fn concatenate_merge(
+    ///   _key: &[u8],               // the key being merged
+    ///   old_value: Option<&[u8]>,  // the previous value, if one existed
+    ///   merged_bytes: &[u8]        // the new bytes being merged in
+    /// ) -> Option<Vec<u8>> {       // set the new value, return None to delete
+    ///   let mut ret = old_value
+    ///     .map(|ov| ov.to_vec())
+    ///     .unwrap_or_else(|| vec![]);
+    ///
+    ///   ret.extend_from_slice(merged_bytes);
+    ///
+    ///   Some(ret)
+    /// }
+    ///
+    /// let config = ConfigBuilder::new()
+    ///   .temporary(true)
+    ///   .build();
+    ///
+    /// let tree = Db::start(config).unwrap();
+    /// tree.set_merge_operator(concatenate_merge);
+    ///
+    /// let k = b"k1";
+    ///
+    /// tree.insert(k, vec![0]);
+    /// tree.merge(k, vec![1]);
+    /// tree.merge(k, vec![2]);
+    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![0, 1, 2]))));
+    ///
+    /// // Replace previously merged data. The merge function will not be called.
+    /// tree.insert(k, vec![3]);
+    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![3]))));
+    ///
+    /// // Merges on non-present values will cause the merge function to be called
+    /// // with `old_value == None`. If the merge function returns something (which it
+    /// // does, in this case) a new value will be inserted.
+    /// tree.remove(k);
+    /// tree.merge(k, vec![4]);
+    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![4]))));
+    /// ```
+    pub fn set_merge_operator(&self, merge_operator: MergeOperator) {
+        let mut mo_write = self.merge_operator.write();
+        *mo_write = Some(merge_operator);
+    }
+
+    /// Returns the name of the merge operator that the
+    /// database was configured with using
+    /// `ConfigBuilder::merge_operator_named`, if any. This
+    /// is the persisted name that reopening the database
+    /// must supply, and is not changed by
+    /// `set_merge_operator`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new()
+    ///     .temporary(true)
+    ///     .merge_operator_named("max_u64")
+    ///     .build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// assert_eq!(t.merge_operator_name(), Some("max_u64".to_string()));
+    /// ```
+    pub fn merge_operator_name(&self) -> Option<String> {
+        self.context.merge_operator_name.clone()
+    }
+
+    /// Atomically adds a member to the set stored under `key`,
+    /// returning `true` if it was not already a member. Sets
+    /// are stored as a bincode-encoded `Vec<Vec<u8>>` whose
+    /// members are sorted bytewise and unique, and the key
+    /// is created when its first member is added. This does
+    /// not use the `Tree`'s merge operator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// assert_eq!(t.set_add(b"tags", b"rust"), Ok(true));
+    /// assert_eq!(t.set_add(b"tags", b"db"), Ok(true));
+    /// assert_eq!(t.set_add(b"tags", b"rust"), Ok(false));
+    ///
+    /// assert_eq!(
+    ///     t.set_members(b"tags"),
+    ///     Ok(vec![IVec::from(b"db"), IVec::from(b"rust")])
+    /// );
+    /// ```
+    pub fn set_add<K, M>(&self, key: K, member: M) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+        M: AsRef<[u8]>,
+    {
+        let member = member.as_ref();
+        self.update_set(key.as_ref(), |members| {
+            match members.binary_search_by(|m| m.as_slice().cmp(member)) {
+                Ok(_) => false,
+                Err(idx) => {
+                    members.insert(idx, member.to_vec());
+                    true
+                }
+            }
+        })
+    }
+
+    /// Atomically removes a member from the set stored under
+    /// `key`, returning `true` if it was a member. The key is
+    /// removed along with the last member of its set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.set_add(b"tags", b"rust").unwrap();
+    /// assert_eq!(t.set_remove(b"tags", b"rust"), Ok(true));
+    /// assert_eq!(t.set_remove(b"tags", b"rust"), Ok(false));
+    /// assert_eq!(t.get(b"tags"), Ok(None));
+    /// ```
+    pub fn set_remove<K, M>(&self, key: K, member: M) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+        M: AsRef<[u8]>,
+    {
+        let member = member.as_ref();
+        self.update_set(key.as_ref(), |members| {
+            match members.binary_search_by(|m| m.as_slice().cmp(member)) {
+                Ok(idx) => {
+                    members.remove(idx);
+                    true
+                }
+                Err(_) => false,
+            }
+        })
+    }
+
+    /// Returns the members of the set stored under `key`
+    /// in sorted order, which is empty if the key is absent.
+    /// An empty set is never stored, so `get` returning
+    /// `Some` means the set has at least one member.
+    pub fn set_members<K: AsRef<[u8]>>(&self, key: K) -> Result<Vec<IVec>> {
+        let members = decode_set(self.get(key)?.as_ref())?;
+        Ok(members.into_iter().map(IVec::from).collect())
+    }
+
+    // applies `f` to the decoded set under `key` in a cas loop,
+    // writing the result back only if `f` returns `true`.
+    fn update_set<F>(&self, key: &[u8], f: F) -> Result<bool>
+    where
+        F: Fn(&mut Vec<Vec<u8>>) -> bool,
+    {
+        let mut current = self.get(key)?;
+
+        loop {
+            let mut members = decode_set(current.as_ref())?;
+            if !f(&mut members) {
+                return Ok(false);
+            }
+
+            let new = if members.is_empty() {
+                None
+            } else {
+                Some(bincode::serialize(&members).unwrap())
+            };
+
+            match self.cas(key, current.as_ref(), new)? {
+                Ok(()) => return Ok(true),
+                Err(new_current) => current = new_current,
+            }
+            M.tree_looped();
+        }
+    }
+
+    /// Claims an advisory lock on `key` for `owner` if nobody
+    /// holds it or the holder's lock has expired, returning
+    /// whether `owner` now holds it. Claiming a lock that
+    /// `owner` already holds extends it. The lock expires
+    /// after `ttl`, so a crashed holder can't block others
+    /// forever, and holders should call this again before
+    /// it runs out.
+    ///
+    /// Locks don't prevent any reads or writes. They are only
+    /// held in memory, so they don't appear among the `Tree`'s
+    /// keys, and they are all released when the database is
+    /// closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// let ttl = Duration::from_secs(60);
+    /// assert_eq!(t.try_lock(b"job", 1, ttl), Ok(true));
+    /// assert_eq!(t.try_lock(b"job", 2, ttl), Ok(false));
+    ///
+    /// assert_eq!(t.unlock(b"job", 1), Ok(true));
+    /// assert_eq!(t.try_lock(b"job", 2, ttl), Ok(true));
+    /// ```
+    pub fn try_lock<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        owner: u64,
+        ttl: Duration,
+    ) -> Result<bool> {
+        Ok(self.locks.try_lock(key.as_ref(), owner, ttl))
+    }
+
+    /// Releases a lock claimed with `Tree::try_lock`, returning
+    /// `false` without releasing anything if `owner` doesn't
+    /// hold it, including when another owner claimed it after
+    /// it expired.
+    pub fn unlock<K: AsRef<[u8]>>(&self, key: K, owner: u64) -> Result<bool> {
+        Ok(self.locks.unlock(key.as_ref(), owner))
+    }
+
+    /// Create a double-ended iterator over the tuples of keys and
+    /// values in this tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    /// t.insert(&[1], vec![10]);
+    /// t.insert(&[2], vec![20]);
+    /// t.insert(&[3], vec![30]);
+    /// let mut iter = t.iter();
+    /// assert_eq!(iter.next().unwrap(), Ok((IVec::from(&[1]), IVec::from(&[10]))));
+    /// assert_eq!(iter.next().unwrap(), Ok((IVec::from(&[2]), IVec::from(&[20]))));
+    /// assert_eq!(iter.next().unwrap(), Ok((IVec::from(&[3]), IVec::from(&[30]))));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn iter(&self) -> Iter<'_> {
+        self.range::<Vec<u8>, _>(..)
+    }
+
+    /// Create a double-ended iterator over tuples of keys and values,
+    /// where the keys fall within the specified range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(&[0], vec![0]).unwrap();
+    /// t.insert(&[1], vec![10]).unwrap();
+    /// t.insert(&[2], vec![20]).unwrap();
+    /// t.insert(&[3], vec![30]).unwrap();
+    /// t.insert(&[4], vec![40]).unwrap();
+    /// t.insert(&[5], vec![50]).unwrap();
+    ///
+    /// let start: &[u8] = &[2];
+    /// let end: &[u8] = &[4];
+    /// let mut r = t.range(start..end);
+    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[2]), IVec::from(&[20]))));
+    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[3]), IVec::from(&[30]))));
+    /// assert_eq!(r.next(), None);
+    ///
+    /// let mut r = t.range(start..end).rev();
+    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[3]), IVec::from(&[30]))));
+    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[2]), IVec::from(&[20]))));
+    /// assert_eq!(r.next(), None);
+    /// ```
+    pub fn range<K, R>(&self, range: R) -> Iter<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let _measure = Measure::new(&M.tree_scan);
+
+        let lo = match range.start_bound() {
+            ops::Bound::Included(ref start) => {
+                ops::Bound::Included(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Excluded(ref start) => {
+                ops::Bound::Excluded(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Included(IVec::from(&[])),
+        };
+
+        let hi = match range.end_bound() {
+            ops::Bound::Included(ref end) => {
+                ops::Bound::Included(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Excluded(ref end) => {
+                ops::Bound::Excluded(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Unbounded,
+        };
+
+        Iter {
+            tree: &self,
+            hi,
+            lo,
+            cursor: None,
+            going_forward: true,
+            prefetched_from: None,
+            key_filter: None,
+            keys_only: false,
+            remaining: None,
         }
     }
 
-    /// Fetch the value, apply a function to it and return the result.
+    /// Create a double-ended iterator over tuples of keys and
+    /// values, starting at `start`, that only yields the records
+    /// whose keys match `key_filter`.
     ///
-    /// # Note
-    ///
-    /// This may call the function multiple times if the value has been
-    /// changed from other threads in the meantime.
+    /// Unlike calling `Iterator::filter` on `Tree::range`, the
+    /// filter is checked against each key while its leaf is being
+    /// searched, so the values of skipped records are never
+    /// copied out of the leaf.
     ///
     /// # Examples
     ///
     /// ```
-    /// use std::convert::TryInto;
-    /// use sled::{ConfigBuilder, Error, IVec};
-    ///
+    /// use sled::{ConfigBuilder, Db, IVec};
     /// let config = ConfigBuilder::new().temporary(true).build();
-    /// let tree = sled::Db::start(config).unwrap();
+    /// let t = Db::start(config).unwrap();
     ///
-    /// fn u64_to_ivec(number: u64) -> IVec {
-    ///     IVec::from(number.to_be_bytes().to_vec())
+    /// for i in 0..6u8 {
+    ///     t.insert(&[i], vec![i; 1024]).unwrap();
     /// }
     ///
-    /// let zero = u64_to_ivec(0);
-    /// let one = u64_to_ivec(1);
-    /// let two = u64_to_ivec(2);
-    /// let three = u64_to_ivec(3);
+    /// let start: &[u8] = &[1];
+    /// let mut r = t.scan_filtered(start, |k| k[0] % 2 == 0).keys();
+    /// assert_eq!(r.next(), Some(Ok(IVec::from(&[2]))));
+    /// assert_eq!(r.next(), Some(Ok(IVec::from(&[4]))));
+    /// assert_eq!(r.next(), None);
+    /// ```
+    pub fn scan_filtered<'a, K, F>(
+        &'a self,
+        start: K,
+        key_filter: F,
+    ) -> Iter<'a>
+    where
+        K: AsRef<[u8]>,
+        F: 'a + Fn(&[u8]) -> bool,
+    {
+        let mut iter = self.range(start.as_ref()..);
+        iter.key_filter = Some(Box::new(key_filter));
+        iter
+    }
+
+    /// Create a double-ended iterator over tuples of keys and
+    /// values, starting at `start`, that returns at most `limit`
+    /// records. Unlike `Tree::scan(start).take(limit)`, the
+    /// iterator knows where it will stop, so with
+    /// `ConfigBuilder::scan_prefetch` it doesn't read ahead of
+    /// a leaf that holds the last records that it will return.
+    /// This is useful for reading one page of results at a time.
     ///
-    /// fn increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
-    ///     let number = match old {
-    ///         Some(bytes) => {
-    ///             let array: [u8; 8] = bytes.try_into().unwrap();
-    ///             let number = u64::from_be_bytes(array);
-    ///             number + 1
-    ///         },
-    ///         None => 0,
-    ///     };
+    /// # Examples
     ///
-    ///     Some(number.to_be_bytes().to_vec())
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    /// for i in 0..10u8 {
+    ///     t.insert(&[i], vec![i]).unwrap();
     /// }
     ///
-    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(zero)));
-    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(one)));
-    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(two)));
-    /// assert_eq!(tree.update_and_fetch("counter", increment), Ok(Some(three)));
+    /// let page: Vec<_> =
+    ///     t.scan_limited(&[2], 3).keys().map(|k| k.unwrap()).collect();
+    /// assert_eq!(page, vec![vec![2], vec![3], vec![4]]);
     /// ```
-    pub fn update_and_fetch<K, V, F>(
+    pub fn scan_limited<K: AsRef<[u8]>>(
         &self,
-        key: K,
-        mut f: F,
-    ) -> Result<Option<IVec>>
-    where
-        K: AsRef<[u8]>,
-        F: FnMut(Option<&[u8]>) -> Option<V>,
-        IVec: From<V>,
-    {
-        let key = key.as_ref();
-        let mut current = self.get(key)?;
+        start: K,
+        limit: usize,
+    ) -> Iter<'_> {
+        let mut iter = self.range(start.as_ref()..);
+        iter.remaining = Some(limit);
+        iter
+    }
 
-        loop {
-            let tmp = current.as_ref().map(AsRef::as_ref);
-            let next = f(tmp).map(IVec::from);
-            match self.cas::<_, _, IVec>(key, tmp, next.clone())? {
-                Ok(()) => return Ok(next),
-                Err(new_current) => current = new_current,
-            }
+    /// Create a scan over keys and values starting at `start`
+    /// that is consumed with `TryIter::for_each_ok`, which
+    /// stops at the first error and returns it, so that the
+    /// records themselves don't need to be unwrapped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    /// for i in 0..10u8 {
+    ///     t.insert(&[i], vec![i]).unwrap();
+    /// }
+    ///
+    /// let mut sum = 0;
+    /// t.try_scan(&[5])
+    ///     .for_each_ok(|_k, v| {
+    ///         sum += v[0];
+    ///         true
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(sum, 5 + 6 + 7 + 8 + 9);
+    /// ```
+    pub fn try_scan<K: AsRef<[u8]>>(&self, start: K) -> TryIter<'_> {
+        TryIter {
+            iter: self.range(start.as_ref()..),
+            done: false,
         }
     }
 
-    /// Fetch the value, apply a function to it and return the previous value.
+    /// Create a double-ended iterator over the keys starting at
+    /// `start`, paired with values that are only read when
+    /// `LazyValue::load` is called. When values are separated
+    /// with `ConfigBuilder::separate_values`, this avoids reading
+    /// every value while paging through keys.
     ///
-    /// # Note
+    /// # Examples
     ///
-    /// This may call the function multiple times if the value has been
-    /// changed from other threads in the meantime.
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new()
+    ///     .temporary(true)
+    ///     .separate_values(true)
+    ///     .build();
+    /// let t = Db::start(config).unwrap();
     ///
-    /// # Examples
+    /// for i in 0..5u8 {
+    ///     t.insert(&[i], vec![i; 1024]).unwrap();
+    /// }
     ///
+    /// let start: &[u8] = &[1];
+    /// let page: Vec<_> =
+    ///     t.scan_lazy(start).take(2).collect::<sled::Result<_>>().unwrap();
+    /// assert_eq!(page[0].0, IVec::from(&[1]));
+    /// assert_eq!(page[1].1.load(), Ok(Some(IVec::from(vec![2; 1024]))));
     /// ```
-    /// use std::convert::TryInto;
-    /// use sled::{ConfigBuilder, Error, IVec};
+    pub fn scan_lazy<K: AsRef<[u8]>>(&self, start: K) -> LazyIter<'_> {
+        LazyIter {
+            iter: self.range(start.as_ref()..),
+        }
+    }
+
+    /// Create an iterator over the records starting at `start`
+    /// that yields their keys as they are physically stored,
+    /// prefix-encoded relative to the `lo` bound of their leaf,
+    /// along with that bound. This is meant for tools that work
+    /// with the on-disk layout. Use `prefix_decode` to recover
+    /// the logical keys, and `prefix_reencode` to encode them
+    /// relative to another bound. See `RawRecord`.
+    ///
+    /// # Examples
     ///
+    /// ```
+    /// use sled::{prefix_decode, ConfigBuilder, Db};
     /// let config = ConfigBuilder::new().temporary(true).build();
-    /// let tree = sled::Db::start(config).unwrap();
+    /// let t = Db::start(config).unwrap();
     ///
-    /// fn u64_to_ivec(number: u64) -> IVec {
-    ///     IVec::from(number.to_be_bytes().to_vec())
+    /// t.insert(b"cat", vec![1]).unwrap();
+    /// t.insert(b"caterpillar", vec![2]).unwrap();
+    ///
+    /// for record in t.scan_raw(b"") {
+    ///     let record = record.unwrap();
+    ///     let key = prefix_decode(&record.lo, &record.key);
+    ///     assert_eq!(t.get(&key).unwrap(), Some(record.value));
     /// }
+    /// ```
+    pub fn scan_raw<K: AsRef<[u8]>>(&self, start: K) -> RawIter<'_> {
+        RawIter {
+            tree: self,
+            from: Some(IVec::from(start.as_ref())),
+            records: VecDeque::new(),
+        }
+    }
+
+    /// Create a double-ended iterator over tuples of keys and values,
+    /// where the keys fall within the specified range, that holds
+    /// its own handle to this `Tree`. Unlike `Tree::range`, the
+    /// iterator doesn't borrow the `Tree`, so it can be returned
+    /// from functions and moved to other threads. See `OwnedIter`.
     ///
-    /// let zero = u64_to_ivec(0);
-    /// let one = u64_to_ivec(1);
-    /// let two = u64_to_ivec(2);
+    /// # Examples
     ///
-    /// fn increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
-    ///     let number = match old {
-    ///         Some(bytes) => {
-    ///             let array: [u8; 8] = bytes.try_into().unwrap();
-    ///             let number = u64::from_be_bytes(array);
-    ///             number + 1
-    ///         },
-    ///         None => 0,
-    ///     };
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec, OwnedIter};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
     ///
-    ///     Some(number.to_be_bytes().to_vec())
+    /// for i in 0..5u8 {
+    ///     t.insert(&[i], vec![i]).unwrap();
     /// }
     ///
-    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(None));
-    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(Some(zero)));
-    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(Some(one)));
-    /// assert_eq!(tree.fetch_and_update("counter", increment), Ok(Some(two)));
+    /// fn from_two(t: &Db) -> OwnedIter {
+    ///     let start: &[u8] = &[2];
+    ///     t.owned_range(start..)
+    /// }
+    ///
+    /// let mut r = from_two(&t);
+    /// drop(t);
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[2]), IVec::from(&[2])))));
+    /// assert_eq!(r.next_back(), Some(Ok((IVec::from(&[4]), IVec::from(&[4])))));
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[3]), IVec::from(&[3])))));
+    /// assert_eq!(r.next(), None);
     /// ```
-    pub fn fetch_and_update<K, V, F>(
-        &self,
-        key: K,
-        mut f: F,
-    ) -> Result<Option<IVec>>
+    pub fn owned_range<K, R>(&self, range: R) -> OwnedIter
     where
         K: AsRef<[u8]>,
-        F: FnMut(Option<&[u8]>) -> Option<V>,
-        IVec: From<V>,
+        R: RangeBounds<K>,
     {
-        let key = key.as_ref();
-        let mut current = self.get(key)?;
+        let lo = match range.start_bound() {
+            ops::Bound::Included(start) => {
+                ops::Bound::Included(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Excluded(start) => {
+                ops::Bound::Excluded(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Unbounded,
+        };
 
-        loop {
-            let tmp = current.as_ref().map(AsRef::as_ref);
-            let next = f(tmp);
-            match self.cas(key, tmp, next)? {
-                Ok(()) => return Ok(current),
-                Err(new_current) => current = new_current,
+        let hi = match range.end_bound() {
+            ops::Bound::Included(end) => {
+                ops::Bound::Included(IVec::from(end.as_ref()))
             }
+            ops::Bound::Excluded(end) => {
+                ops::Bound::Excluded(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Unbounded,
+        };
+
+        OwnedIter {
+            tree: self.clone(),
+            lo,
+            hi,
         }
     }
 
-    /// Subscribe to `Event`s that happen to keys that have
-    /// the specified prefix. Events for particular keys are
-    /// guaranteed to be witnessed in the same order by all
-    /// threads, but threads may witness different interleavings
-    /// of `Event`s across different keys. If subscribers don't
-    /// keep up with new writes, they will cause new writes
-    /// to block. There is a buffer of 1024 items per
-    /// `Subscriber`. This can be used to build reactive
-    /// and replicated systems.
+    /// Read the nodes covering a range into the cache, so that
+    /// later reads of the range don't have to wait on disk.
+    /// This is useful after a restart, when the cache starts
+    /// out empty. Use `..` to warm the whole `Tree`.
     ///
-    /// # Examples
-    /// ```
-    /// use sled::{Event, ConfigBuilder};
-    /// let config = ConfigBuilder::new().temporary(true).build();
-    ///
-    /// let tree = sled::Db::start(config).unwrap();
-    ///
-    /// // watch all events by subscribing to the empty prefix
-    /// let mut events = tree.watch_prefix(vec![]);
+    /// Stops early once the pages it has read would fill
+    /// `ConfigBuilder::cache_capacity`, because reading more
+    /// would only evict the pages it read first. Returns the
+    /// number of pages read in from disk while warming, which
+    /// may include pages read concurrently by other threads.
     ///
-    /// let tree_2 = tree.clone();
-    /// let thread = std::thread::spawn(move || {
-    ///     tree.insert(vec![0], vec![1]).unwrap();
-    /// });
+    /// # Examples
     ///
-    /// // events is a blocking `Iterator` over `Event`s
-    /// for event in events.take(1) {
-    ///     match event {
-    ///         Event::Set(key, value) => assert_eq!(key, vec![0]),
-    ///         Event::Merge(key, partial_value) => {}
-    ///         Event::Del(key) => {}
-    ///     }
-    /// }
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    /// t.insert(b"a", vec![1]).unwrap();
     ///
-    /// thread.join().unwrap();
+    /// // warm a range, or use `..` for the whole tree
+    /// t.warm_cache(b"a".to_vec()..b"m".to_vec()).unwrap();
+    /// t.warm_cache::<&[u8], _>(..).unwrap();
     /// ```
-    pub fn watch_prefix(&self, prefix: Vec<u8>) -> Subscriber {
-        self.subscriptions.register(prefix)
-    }
+    pub fn warm_cache<K, R>(&self, range: R) -> Result<usize>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let tx = self.context.pagecache.begin()?;
+        let page_ins = self.context.pagecache.page_ins();
 
-    /// Flushes all dirty IO buffers and calls fsync.
-    /// If this succeeds, it is guaranteed that
-    /// all previous writes will be recovered if
-    /// the system crashes. Returns the number
-    /// of bytes flushed during this call.
-    pub fn flush(&self) -> Result<usize> {
-        self.context.pagecache.flush()
+        let lo: &[u8] = match range.start_bound() {
+            ops::Bound::Included(start) | ops::Bound::Excluded(start) => {
+                start.as_ref()
+            }
+            ops::Bound::Unbounded => &[],
+        };
+
+        let hi = match range.end_bound() {
+            ops::Bound::Included(end) => {
+                ops::Bound::Included(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Excluded(end) => {
+                ops::Bound::Excluded(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Unbounded,
+        };
+
+        // read each level of the tree from the top down, since
+        // every read needs the index nodes above its leaf.
+        let mut budget = self.context.cache_capacity;
+        let mut level = Some(self.root.load(SeqCst));
+
+        'levels: while let Some(pid) = level.take() {
+            let mut view = match self.view_for_pid(pid, &tx)? {
+                Some(view) => view,
+                None => break,
+            };
+
+            if view.node.data.is_index() {
+                level = Some(view.node.index_next_node(lo).1);
+            }
+
+            loop {
+                if view.size > budget {
+                    break 'levels;
+                }
+                budget -= view.size;
+
+                if !iter::below_bound(&view.node.hi, &hi) {
+                    break;
+                }
+                let next = match view.node.next {
+                    Some(next) => next,
+                    None => break,
+                };
+                view = match self.view_for_pid(next, &tx)? {
+                    Some(view) => view,
+                    None => break,
+                };
+            }
+        }
+
+        Ok((self.context.pagecache.page_ins() - page_ins) as usize)
     }
 
-    /// Returns `true` if the `Tree` contains a value for
-    /// the specified key.
+    /// Collect up to `limit` keys and values in a range
+    /// into a `Vec`. Unlike iterating over `Tree::range`,
+    /// concurrent writes and batches are blocked while the
+    /// window is read, so the returned items reflect a single
+    /// consistent state of the range.
     ///
     /// # Examples
     ///
     /// ```
-    /// let config = sled::ConfigBuilder::new().temporary(true).build();
-    /// let t = sled::Db::start(config).unwrap();
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
     ///
-    /// t.insert(&[0], vec![0]).unwrap();
-    /// assert!(t.contains_key(&[0]).unwrap());
-    /// assert!(!t.contains_key(&[1]).unwrap());
+    /// for i in 0..10 {
+    ///     t.insert(&[i], vec![i]).unwrap();
+    /// }
+    ///
+    /// let start: &[u8] = &[3];
+    /// let window = t.collect_range(start.., 2).unwrap();
+    /// assert_eq!(
+    ///     window,
+    ///     vec![
+    ///         (IVec::from(&[3]), IVec::from(&[3])),
+    ///         (IVec::from(&[4]), IVec::from(&[4])),
+    ///     ]
+    /// );
     /// ```
-    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
-        self.get(key).map(|v| v.is_some())
+    pub fn collect_range<K, R>(
+        &self,
+        range: R,
+        limit: usize,
+    ) -> Result<Vec<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let _cc = self.concurrency_control.write();
+
+        self.range(range).take(limit).collect()
     }
 
-    /// Retrieve the key and value before the provided key,
-    /// if one exists.
+    /// Fold a function over the keys and values in a range,
+    /// in order, without collecting them first.
     ///
     /// # Examples
     ///
     /// ```
-    /// use sled::{ConfigBuilder, Db, IVec};
+    /// use sled::{ConfigBuilder, Db};
     /// let config = ConfigBuilder::new().temporary(true).build();
-    /// let tree = Db::start(config).unwrap();
+    /// let t = Db::start(config).unwrap();
     ///
     /// for i in 0..10 {
-    ///     tree.insert(&[i], vec![i]).expect("should write successfully");
+    ///     t.insert(&[i], vec![i]).unwrap();
     /// }
     ///
-    /// assert_eq!(tree.get_lt(&[]), Ok(None));
-    /// assert_eq!(tree.get_lt(&[0]), Ok(None));
-    /// assert_eq!(tree.get_lt(&[1]), Ok(Some((IVec::from(&[0]), IVec::from(&[0])))));
-    /// assert_eq!(tree.get_lt(&[9]), Ok(Some((IVec::from(&[8]), IVec::from(&[8])))));
-    /// assert_eq!(tree.get_lt(&[10]), Ok(Some((IVec::from(&[9]), IVec::from(&[9])))));
-    /// assert_eq!(tree.get_lt(&[255]), Ok(Some((IVec::from(&[9]), IVec::from(&[9])))));
+    /// let start: &[u8] = &[2];
+    /// let end: &[u8] = &[5];
+    /// let sum = t.fold_range(start..end, 0, |acc, _k, v| acc + v[0] as u64);
+    /// assert_eq!(sum, Ok(2 + 3 + 4));
     /// ```
-    pub fn get_lt<K>(&self, key: K) -> Result<Option<(IVec, IVec)>>
+    pub fn fold_range<K, R, A, F>(
+        &self,
+        range: R,
+        init: A,
+        mut f: F,
+    ) -> Result<A>
     where
         K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+        F: FnMut(A, &[u8], &[u8]) -> A,
     {
-        let _measure = Measure::new(&M.tree_get);
-        let _ = self.concurrency_control.read();
-        self.range(..key).next_back().transpose()
+        let mut acc = init;
+        for res in self.range(range) {
+            let (k, v) = res?;
+            acc = f(acc, &k, &v);
+        }
+        Ok(acc)
     }
 
-    /// Retrieve the next key and value from the `Tree` after the
-    /// provided key.
-    ///
-    /// # Note
-    /// The order follows the Ord implementation for `Vec<u8>`:
-    ///
-    /// `[] < [0] < [255] < [255, 0] < [255, 255] ...`
-    ///
-    /// To retain the ordering of numerical types use big endian reprensentation
+    /// Create an iterator over tuples of keys and values,
+    /// where the all the keys starts with the given prefix.
     ///
     /// # Examples
     ///
     /// ```
     /// use sled::{ConfigBuilder, Db, IVec};
     /// let config = ConfigBuilder::new().temporary(true).build();
-    /// let tree = Db::start(config).unwrap();
-    ///
-    /// for i in 0..10 {
-    ///     tree.insert(&[i], vec![i]).expect("should write successfully");
-    /// }
+    /// let t = Db::start(config).unwrap();
     ///
-    /// assert_eq!(tree.get_gt(&[]), Ok(Some((IVec::from(&[0]), IVec::from(&[0])))));
-    /// assert_eq!(tree.get_gt(&[0]), Ok(Some((IVec::from(&[1]), IVec::from(&[1])))));
-    /// assert_eq!(tree.get_gt(&[1]), Ok(Some((IVec::from(&[2]), IVec::from(&[2])))));
-    /// assert_eq!(tree.get_gt(&[8]), Ok(Some((IVec::from(&[9]), IVec::from(&[9])))));
-    /// assert_eq!(tree.get_gt(&[9]), Ok(None));
+    /// t.insert(&[0, 0, 0], vec![0, 0, 0]).unwrap();
+    /// t.insert(&[0, 0, 1], vec![0, 0, 1]).unwrap();
+    /// t.insert(&[0, 0, 2], vec![0, 0, 2]).unwrap();
+    /// t.insert(&[0, 0, 3], vec![0, 0, 3]).unwrap();
+    /// t.insert(&[0, 1, 0], vec![0, 1, 0]).unwrap();
+    /// t.insert(&[0, 1, 1], vec![0, 1, 1]).unwrap();
     ///
-    /// tree.insert(500u16.to_be_bytes(), vec![10] );
-    /// assert_eq!(tree.get_gt(&499u16.to_be_bytes()),
-    ///            Ok(Some((IVec::from(&500u16.to_be_bytes()), IVec::from(&[10])))));
+    /// let prefix: &[u8] = &[0, 0];
+    /// let mut r = t.scan_prefix(prefix);
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 0]), IVec::from(&[0, 0, 0])))));
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 1]), IVec::from(&[0, 0, 1])))));
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 2]), IVec::from(&[0, 0, 2])))));
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 3]), IVec::from(&[0, 0, 3])))));
+    /// assert_eq!(r.next(), None);
     /// ```
-    pub fn get_gt<K>(&self, key: K) -> Result<Option<(IVec, IVec)>>
+    pub fn scan_prefix<P>(&self, prefix: P) -> Iter<'_>
     where
-        K: AsRef<[u8]>,
+        P: AsRef<[u8]>,
     {
-        let _measure = Measure::new(&M.tree_get);
-        let _ = self.concurrency_control.read();
-        self.range((ops::Bound::Excluded(key), ops::Bound::Unbounded))
-            .next()
-            .transpose()
+        let prefix = prefix.as_ref();
+
+        match prefix_successor(prefix) {
+            Some(upper) => self.range(prefix..&upper),
+            None => self.range(prefix..),
+        }
     }
 
-    /// Merge state directly into a given key's value using the
-    /// configured merge operator. This allows state to be written
-    /// into a value directly, without any read-modify-write steps.
-    /// Merge operators can be used to implement arbitrary data
-    /// structures.
-    ///
-    /// # Panics
-    ///
-    /// Calling `merge` will panic if no merge operator has been
-    /// configured.
+    /// Create an iterator over tuples of keys and values,
+    /// where the all the keys starts with the given prefix,
+    /// in descending key order.
     ///
     /// # Examples
     ///
     /// ```
     /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
     ///
-    /// fn concatenate_merge(
-    ///   _key: &[u8],               // the key being merged
-    ///   old_value: Option<&[u8]>,  // the previous value, if one existed
-    ///   merged_bytes: &[u8]        // the new bytes being merged in
-    /// ) -> Option<Vec<u8>> {       // set the new value, return None to delete
-    ///   let mut ret = old_value
-    ///     .map(|ov| ov.to_vec())
-    ///     .unwrap_or_else(|| vec![]);
-    ///
-    ///   ret.extend_from_slice(merged_bytes);
-    ///
-    ///   Some(ret)
-    /// }
-    ///
-    /// let config = ConfigBuilder::new()
-    ///   .temporary(true)
-    ///   .build();
+    /// t.insert(&[0, 0, 0], vec![0, 0, 0]).unwrap();
+    /// t.insert(&[0, 0, 1], vec![0, 0, 1]).unwrap();
+    /// t.insert(&[0, 0, 2], vec![0, 0, 2]).unwrap();
+    /// t.insert(&[0, 1, 0], vec![0, 1, 0]).unwrap();
     ///
-    /// let tree = Db::start(config).unwrap();
-    /// tree.set_merge_operator(concatenate_merge);
+    /// let prefix: &[u8] = &[0, 0];
+    /// let mut r = t.scan_prefix_rev(prefix);
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 2]), IVec::from(&[0, 0, 2])))));
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 1]), IVec::from(&[0, 0, 1])))));
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 0]), IVec::from(&[0, 0, 0])))));
+    /// assert_eq!(r.next(), None);
+    /// ```
+    pub fn scan_prefix_rev<P>(&self, prefix: P) -> std::iter::Rev<Iter<'_>>
+    where
+        P: AsRef<[u8]>,
+    {
+        self.scan_prefix(prefix).rev()
+    }
+
+    /// Create an iterator over the keys that start
+    /// with the given prefix, such as to list the children
+    /// of a node in a hierarchical keyspace.
     ///
-    /// let k = b"k1";
+    /// # Examples
     ///
-    /// tree.insert(k, vec![0]);
-    /// tree.merge(k, vec![1]);
-    /// tree.merge(k, vec![2]);
-    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![0, 1, 2]))));
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
     ///
-    /// // Replace previously merged data. The merge function will not be called.
-    /// tree.insert(k, vec![3]);
-    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![3]))));
+    /// t.insert(b"dir/a", vec![0; 1024]).unwrap();
+    /// t.insert(b"dir/b", vec![0; 1024]).unwrap();
+    /// t.insert(b"other", vec![0; 1024]).unwrap();
     ///
-    /// // Merges on non-present values will cause the merge function to be called
-    /// // with `old_value == None`. If the merge function returns something (which it
-    /// // does, in this case) a new value will be inserted.
-    /// tree.remove(k);
-    /// tree.merge(k, vec![4]);
-    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![4]))));
+    /// let mut r = t.scan_prefix_keys(b"dir/");
+    /// assert_eq!(r.next(), Some(Ok(IVec::from(b"dir/a"))));
+    /// assert_eq!(r.next(), Some(Ok(IVec::from(b"dir/b"))));
+    /// assert_eq!(r.next(), None);
     /// ```
-    pub fn merge<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    pub fn scan_prefix_keys<P>(
+        &self,
+        prefix: P,
+    ) -> impl '_ + DoubleEndedIterator<Item = Result<IVec>>
     where
-        K: AsRef<[u8]>,
-        V: AsRef<[u8]>,
+        P: AsRef<[u8]>,
     {
-        let _ = self.concurrency_control.read();
-        self.merge_inner(key, value)
+        self.scan_prefix(prefix).keys()
     }
 
-    pub(crate) fn merge_inner<K, V>(
+    /// List the distinct children of `prefix` in a hierarchical
+    /// keyspace, where each child is a key that starts with
+    /// `prefix`, cut off before the first `delimiter` after
+    /// `prefix`. Keys without a `delimiter` after `prefix` are
+    /// children themselves. Children are returned in ascending
+    /// order, and each is read with a single seek, skipping
+    /// over the keys below it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a/b/c", vec![]).unwrap();
+    /// t.insert(b"a/b/d", vec![]).unwrap();
+    /// t.insert(b"a/e", vec![]).unwrap();
+    ///
+    /// assert_eq!(
+    ///     t.list_children(b"a/", b'/'),
+    ///     Ok(vec![IVec::from(b"a/b"), IVec::from(b"a/e")])
+    /// );
+    /// ```
+    pub fn list_children<P>(
         &self,
-        key: K,
-        value: V,
-    ) -> Result<Option<IVec>>
+        prefix: P,
+        delimiter: u8,
+    ) -> Result<Vec<IVec>>
     where
-        K: AsRef<[u8]>,
-        V: AsRef<[u8]>,
+        P: AsRef<[u8]>,
     {
-        trace!("merging key {:?}", key.as_ref());
-        let _measure = Measure::new(&M.tree_merge);
-
-        if self.context.read_only {
-            return Err(Error::Unsupported(
-                "the database is in read-only mode".to_owned(),
-            ));
-        }
+        let prefix = prefix.as_ref();
+        let hi = match prefix_successor(prefix) {
+            Some(upper) => ops::Bound::Excluded(IVec::from(upper)),
+            None => ops::Bound::Unbounded,
+        };
 
-        let merge_operator_opt = self.merge_operator.read();
+        let mut children = vec![];
+        let mut lo = ops::Bound::Included(IVec::from(prefix));
 
-        if merge_operator_opt.is_none() {
-            return Err(Error::Unsupported(
-                "must set a merge operator on this Tree \
-                 before calling merge by calling \
-                 Tree::set_merge_operator"
-                    .to_owned(),
-            ));
-        }
+        loop {
+            let key =
+                match self.range::<IVec, _>((lo, hi.clone())).keys().next() {
+                    Some(key) => key?,
+                    None => break,
+                };
 
-        let merge_operator = merge_operator_opt.unwrap();
+            let delimited = key[prefix.len()..]
+                .iter()
+                .position(|byte| *byte == delimiter)
+                .map(|idx| prefix.len() + idx);
 
-        let key = key.as_ref();
-        let mut current = self.get(key)?;
+            match delimited {
+                Some(end) => {
+                    children.push(IVec::from(&key[..end]));
 
-        loop {
-            let tmp = current.as_ref().map(AsRef::as_ref);
-            let next = merge_operator(key, tmp, value.as_ref()).map(IVec::from);
-            match self.cas::<_, _, IVec>(key, tmp, next.clone())? {
-                Ok(()) => return Ok(next),
-                Err(new_current) => current = new_current,
+                    // skip the rest of this child's keys
+                    match prefix_successor(&key[..=end]) {
+                        Some(next) => lo = ops::Bound::Included(next.into()),
+                        None => break,
+                    }
+                }
+                None => {
+                    children.push(key.clone());
+                    lo = ops::Bound::Excluded(key);
+                }
             }
-            M.tree_looped();
         }
-    }
 
-    /// Sets a merge operator for use with the `merge` function.
-    ///
-    /// Merge state directly into a given key's value using the
-    /// configured merge operator. This allows state to be written
-    /// into a value directly, without any read-modify-write steps.
-    /// Merge operators can be used to implement arbitrary data
-    /// structures.
-    ///
-    /// # Panics
-    ///
-    /// Calling `merge` will panic if no merge operator has been
-    /// configured.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use sled::{ConfigBuilder, Db, IVec};
-    ///
-    /// fn concatenate_merge(
-    ///   _key: &[u8],               // the key being merged
-    ///   old_value: Option<&[u8]>,  // the previous value, if one existed
-    ///   merged_bytes: &[u8]        // the new bytes being merged in
-    /// ) -> Option<Vec<u8>> {       // set the new value, return None to delete
-    ///   let mut ret = old_value
-    ///     .map(|ov| ov.to_vec())
-    ///     .unwrap_or_else(|| vec![]);
-    ///
-    ///   ret.extend_from_slice(merged_bytes);
-    ///
-    ///   Some(ret)
-    /// }
-    ///
-    /// let config = ConfigBuilder::new()
-    ///   .temporary(true)
-    ///   .build();
-    ///
-    /// let tree = Db::start(config).unwrap();
-    /// tree.set_merge_operator(concatenate_merge);
-    ///
-    /// let k = b"k1";
-    ///
-    /// tree.insert(k, vec![0]);
-    /// tree.merge(k, vec![1]);
-    /// tree.merge(k, vec![2]);
-    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![0, 1, 2]))));
-    ///
-    /// // Replace previously merged data. The merge function will not be called.
-    /// tree.insert(k, vec![3]);
-    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![3]))));
-    ///
-    /// // Merges on non-present values will cause the merge function to be called
-    /// // with `old_value == None`. If the merge function returns something (which it
-    /// // does, in this case) a new value will be inserted.
-    /// tree.remove(k);
-    /// tree.merge(k, vec![4]);
-    /// assert_eq!(tree.get(k), Ok(Some(IVec::from(vec![4]))));
-    /// ```
-    pub fn set_merge_operator(&self, merge_operator: MergeOperator) {
-        let mut mo_write = self.merge_operator.write();
-        *mo_write = Some(merge_operator);
+        // a key like `a/b` sorts before keys below `a/b/`, and
+        // possibly apart from them, as with `a/b!`
+        children.sort();
+        children.dedup();
+
+        Ok(children)
     }
 
-    /// Create a double-ended iterator over the tuples of keys and
-    /// values in this tree.
+    /// Create a `PrefixView` over the keys in this `Tree`
+    /// that start with `prefix`, which accepts and returns
+    /// keys relative to the prefix.
     ///
     /// # Examples
     ///
@@ -835,87 +3489,74 @@ impl Tree {
     /// use sled::{ConfigBuilder, Db, IVec};
     /// let config = ConfigBuilder::new().temporary(true).build();
     /// let t = Db::start(config).unwrap();
-    /// t.insert(&[1], vec![10]);
-    /// t.insert(&[2], vec![20]);
-    /// t.insert(&[3], vec![30]);
-    /// let mut iter = t.iter();
-    /// assert_eq!(iter.next().unwrap(), Ok((IVec::from(&[1]), IVec::from(&[10]))));
-    /// assert_eq!(iter.next().unwrap(), Ok((IVec::from(&[2]), IVec::from(&[20]))));
-    /// assert_eq!(iter.next().unwrap(), Ok((IVec::from(&[3]), IVec::from(&[30]))));
-    /// assert_eq!(iter.next(), None);
+    ///
+    /// let users = t.prefix_view(b"users/".to_vec());
+    /// users.insert(b"alice", vec![1]).unwrap();
+    ///
+    /// assert_eq!(users.get(b"alice"), Ok(Some(IVec::from(vec![1]))));
+    /// assert_eq!(t.get(b"users/alice"), Ok(Some(IVec::from(vec![1]))));
     /// ```
-    pub fn iter(&self) -> Iter<'_> {
-        self.range::<Vec<u8>, _>(..)
+    pub fn prefix_view(&self, prefix: Vec<u8>) -> PrefixView<'_> {
+        PrefixView { tree: self, prefix }
     }
 
-    /// Create a double-ended iterator over tuples of keys and values,
-    /// where the keys fall within the specified range.
+    /// Create a `CodecView` over this `Tree`, which encodes
+    /// and decodes all keys and values with the provided
+    /// `Codec`.
     ///
     /// # Examples
     ///
     /// ```
-    /// use sled::{ConfigBuilder, Db, IVec};
+    /// use sled::{Codec, ConfigBuilder, Db, Result};
+    ///
+    /// // big-endian encoding keeps the keys in numeric order
+    /// struct U64Keys;
+    ///
+    /// impl Codec for U64Keys {
+    ///     type Key = u64;
+    ///     type Value = String;
+    ///
+    ///     fn encode_key(&self, key: &u64) -> Vec<u8> {
+    ///         key.to_be_bytes().to_vec()
+    ///     }
+    ///
+    ///     fn decode_key(&self, bytes: &[u8]) -> Result<u64> {
+    ///         let mut buf = [0; 8];
+    ///         buf.copy_from_slice(bytes);
+    ///         Ok(u64::from_be_bytes(buf))
+    ///     }
+    ///
+    ///     fn encode_value(&self, value: &String) -> Vec<u8> {
+    ///         value.as_bytes().to_vec()
+    ///     }
+    ///
+    ///     fn decode_value(&self, bytes: &[u8]) -> Result<String> {
+    ///         Ok(String::from_utf8_lossy(bytes).into_owned())
+    ///     }
+    /// }
+    ///
     /// let config = ConfigBuilder::new().temporary(true).build();
     /// let t = Db::start(config).unwrap();
+    /// let users = t.with_codec(U64Keys);
     ///
-    /// t.insert(&[0], vec![0]).unwrap();
-    /// t.insert(&[1], vec![10]).unwrap();
-    /// t.insert(&[2], vec![20]).unwrap();
-    /// t.insert(&[3], vec![30]).unwrap();
-    /// t.insert(&[4], vec![40]).unwrap();
-    /// t.insert(&[5], vec![50]).unwrap();
-    ///
-    /// let start: &[u8] = &[2];
-    /// let end: &[u8] = &[4];
-    /// let mut r = t.range(start..end);
-    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[2]), IVec::from(&[20]))));
-    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[3]), IVec::from(&[30]))));
-    /// assert_eq!(r.next(), None);
+    /// users.insert(&256, &"bob".to_owned()).unwrap();
+    /// users.insert(&1, &"alice".to_owned()).unwrap();
     ///
-    /// let mut r = t.range(start..end).rev();
-    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[3]), IVec::from(&[30]))));
-    /// assert_eq!(r.next().unwrap(), Ok((IVec::from(&[2]), IVec::from(&[20]))));
-    /// assert_eq!(r.next(), None);
+    /// assert_eq!(users.get(&1), Ok(Some("alice".to_owned())));
+    /// let ids: Vec<u64> = users.iter().map(|res| res.unwrap().0).collect();
+    /// assert_eq!(ids, vec![1, 256]);
     /// ```
-    pub fn range<K, R>(&self, range: R) -> Iter<'_>
-    where
-        K: AsRef<[u8]>,
-        R: RangeBounds<K>,
-    {
-        let _measure = Measure::new(&M.tree_scan);
-
-        let lo = match range.start_bound() {
-            ops::Bound::Included(ref start) => {
-                ops::Bound::Included(IVec::from(start.as_ref()))
-            }
-            ops::Bound::Excluded(ref start) => {
-                ops::Bound::Excluded(IVec::from(start.as_ref()))
-            }
-            ops::Bound::Unbounded => ops::Bound::Included(IVec::from(&[])),
-        };
-
-        let hi = match range.end_bound() {
-            ops::Bound::Included(ref end) => {
-                ops::Bound::Included(IVec::from(end.as_ref()))
-            }
-            ops::Bound::Excluded(ref end) => {
-                ops::Bound::Excluded(IVec::from(end.as_ref()))
-            }
-            ops::Bound::Unbounded => ops::Bound::Unbounded,
-        };
-
-        Iter {
-            tree: &self,
-            hi,
-            lo,
-            cached_node: None,
-            tx: self.context.pagecache.begin(),
-            going_forward: true,
-        }
+    pub fn with_codec<C: Codec>(&self, codec: C) -> CodecView<'_, C> {
+        CodecView { tree: self, codec }
     }
 
-    /// Create an iterator over tuples of keys and values,
-    /// where the all the keys starts with the given prefix.
+    /// Returns a secondary `Index` named `name` over this
+    /// `Tree`, which indexes each row written through it
+    /// under the value that `extract` returns for the row,
+    /// if any. Index entries live in this `Tree` under a
+    /// reserved prefix, and are kept consistent with their
+    /// rows as long as the rows are only written through
+    /// an `Index` with the same name and closure.
     ///
     /// # Examples
     ///
@@ -924,36 +3565,25 @@ impl Tree {
     /// let config = ConfigBuilder::new().temporary(true).build();
     /// let t = Db::start(config).unwrap();
     ///
-    /// t.insert(&[0, 0, 0], vec![0, 0, 0]).unwrap();
-    /// t.insert(&[0, 0, 1], vec![0, 0, 1]).unwrap();
-    /// t.insert(&[0, 0, 2], vec![0, 0, 2]).unwrap();
-    /// t.insert(&[0, 0, 3], vec![0, 0, 3]).unwrap();
-    /// t.insert(&[0, 1, 0], vec![0, 1, 0]).unwrap();
-    /// t.insert(&[0, 1, 1], vec![0, 1, 1]).unwrap();
+    /// // rows are "name:city", indexed by city
+    /// let by_city = t.index(b"city", |row: &[u8]| {
+    ///     row.iter()
+    ///         .position(|b| *b == b':')
+    ///         .map(|i| row[i + 1..].to_vec())
+    /// });
     ///
-    /// let prefix: &[u8] = &[0, 0];
-    /// let mut r = t.scan_prefix(prefix);
-    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 0]), IVec::from(&[0, 0, 0])))));
-    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 1]), IVec::from(&[0, 0, 1])))));
-    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 2]), IVec::from(&[0, 0, 2])))));
-    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[0, 0, 3]), IVec::from(&[0, 0, 3])))));
-    /// assert_eq!(r.next(), None);
+    /// by_city.insert(b"1", b"alice:paris".to_vec()).unwrap();
+    /// by_city.insert(b"2", b"bob:oslo".to_vec()).unwrap();
+    /// by_city.insert(b"3", b"carol:paris".to_vec()).unwrap();
+    ///
+    /// let ids: Vec<_> = by_city.lookup(b"paris").map(|r| r.unwrap()).collect();
+    /// assert_eq!(ids, vec![IVec::from(b"1"), IVec::from(b"3")]);
     /// ```
-    pub fn scan_prefix<P>(&self, prefix: P) -> Iter<'_>
+    pub fn index<F>(&self, name: &[u8], extract: F) -> Index<'_, F>
     where
-        P: AsRef<[u8]>,
+        F: Fn(&[u8]) -> Option<Vec<u8>>,
     {
-        let prefix = prefix.as_ref();
-        let mut upper = prefix.to_vec();
-
-        while let Some(last) = upper.pop() {
-            if last < u8::max_value() {
-                upper.push(last + 1);
-                return self.range(prefix..&upper);
-            }
-        }
-
-        self.range(prefix..)
+        Index::new(self, name, extract)
     }
 
     /// Atomically removes the maximum item in the `Tree` instance.
@@ -984,8 +3614,10 @@ impl Tree {
         loop {
             if let Some(first_res) = self.iter().next_back() {
                 let first = first_res?;
+                // only the caller whose removal succeeds
+                // claims the item
                 if self
-                    .cas(&first.0, Some(&first.1), None as Option<&[u8]>)
+                    .cas(&first.0, Some(&first.1), None as Option<&[u8]>)?
                     .is_ok()
                 {
                     return Ok(Some(first));
@@ -1025,8 +3657,10 @@ impl Tree {
         loop {
             if let Some(first_res) = self.iter().next() {
                 let first = first_res?;
+                // only the caller whose removal succeeds
+                // claims the item
                 if self
-                    .cas(&first.0, Some(&first.1), None as Option<&[u8]>)
+                    .cas(&first.0, Some(&first.1), None as Option<&[u8]>)?
                     .is_ok()
                 {
                     return Ok(Some(first));
@@ -1038,6 +3672,115 @@ impl Tree {
         }
     }
 
+    /// Atomically removes the minimum item in the `Tree` instance
+    /// like `pop_min`, waiting up to `timeout` for an item to be
+    /// inserted if the `Tree` is empty. Returns `Ok(None)` if
+    /// the `Tree` is still empty after `timeout`. Concurrent
+    /// callers never receive the same item, so this can be used
+    /// to consume a work queue from several threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// let timeout = Duration::from_millis(10);
+    /// assert_eq!(t.pop_min_blocking(timeout), Ok(None));
+    ///
+    /// t.insert(&[1], vec![10]).unwrap();
+    /// assert_eq!(
+    ///     t.pop_min_blocking(timeout),
+    ///     Ok(Some((IVec::from(&[1]), IVec::from(&[10]))))
+    /// );
+    /// ```
+    pub fn pop_min_blocking(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<(IVec, IVec)>> {
+        let deadline = Instant::now() + timeout;
+
+        let waiter = self.subscriptions.insert_waiter();
+
+        loop {
+            let seen = waiter.inserts();
+            if let Some(item) = self.pop_min()? {
+                return Ok(Some(item));
+            }
+
+            if !waiter.wait(seen, deadline) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Atomically inserts a value at the next sequential key
+    /// under `prefix`, returning the assigned key. The key is
+    /// found by incrementing the suffix of the largest key
+    /// under `prefix` as a big-endian integer, or is `prefix`
+    /// followed by 8 zero bytes if no such key exists yet.
+    /// Concurrent appends under the same prefix never receive
+    /// the same key, and never leave gaps.
+    ///
+    /// Returns an `Error::Unsupported` if the largest suffix
+    /// consists entirely of `0xFF` bytes and can't be
+    /// incremented.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new().temporary(true).build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// let first = t.append_next(b"log/", vec![1]).unwrap();
+    /// assert_eq!(&*first, b"log/\0\0\0\0\0\0\0\0");
+    ///
+    /// let second = t.append_next(b"log/", vec![2]).unwrap();
+    /// assert_eq!(&*second, b"log/\0\0\0\0\0\0\0\x01");
+    /// assert_eq!(t.get(&second), Ok(Some(IVec::from(vec![2]))));
+    /// ```
+    pub fn append_next<P, V>(&self, prefix: P, value: V) -> Result<IVec>
+    where
+        P: AsRef<[u8]>,
+        IVec: From<V>,
+    {
+        self.append_next_inner(prefix.as_ref(), IVec::from(value))
+    }
+
+    fn append_next_inner(&self, prefix: &[u8], value: IVec) -> Result<IVec> {
+        let mut backoff = CasBackoff::new(self.context.cas_backoff);
+
+        loop {
+            let last = match self.scan_prefix(prefix).next_back() {
+                Some(res) => Some(res?.0),
+                None => None,
+            };
+            let suffix = last.as_ref().map_or(&[][..], |k| &k[prefix.len()..]);
+            let next =
+                next_sequential_key(prefix, suffix).ok_or_else(|| {
+                    Error::Unsupported(format!(
+                        "the largest key under prefix {:?} \
+                         can't be incremented",
+                        prefix
+                    ))
+                })?;
+
+            if self
+                .cas(&next, None as Option<&[u8]>, Some(value.clone()))?
+                .is_ok()
+            {
+                return Ok(IVec::from(next));
+            }
+
+            // another writer claimed this key first
+            M.tree_looped();
+            backoff.snooze();
+        }
+    }
+
     /// Returns the number of elements in this tree.
     ///
     /// Beware: performs a full O(n) scan under the hood.
@@ -1060,6 +3803,187 @@ impl Tree {
         self.iter().next().is_none()
     }
 
+    /// Returns the most frequently read and written keys
+    /// of the `Tree`, hottest first, with approximate counts
+    /// of calls to `get` and `insert` for each. Up to
+    /// `ConfigBuilder::track_hot_keys` keys are tracked, and
+    /// this is empty if tracking is disabled, which it is by
+    /// default. Counts may be overestimated, but a key that
+    /// makes up more than `1 / track_hot_keys` of accesses
+    /// is always included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sled::{ConfigBuilder, Db, IVec};
+    /// let config = ConfigBuilder::new()
+    ///     .temporary(true)
+    ///     .track_hot_keys(10)
+    ///     .build();
+    /// let t = Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![1]).unwrap();
+    /// t.get(b"a").unwrap();
+    /// t.get(b"b").unwrap();
+    ///
+    /// assert_eq!(
+    ///     t.hot_keys(),
+    ///     vec![(IVec::from(b"a"), 2), (IVec::from(b"b"), 1)]
+    /// );
+    /// ```
+    pub fn hot_keys(&self) -> Vec<(IVec, u64)> {
+        self.hot_keys
+            .as_ref()
+            .map_or_else(Vec::new, |hot_keys| hot_keys.hottest())
+    }
+
+    /// Returns `n - 1` keys that divide the `Tree` into `n`
+    /// ranges holding roughly the same number of keys, for
+    /// partitioning work across threads or machines. The
+    /// first range ends before the first returned key, and
+    /// the last range starts at the last returned key. Fewer
+    /// keys are returned if the `Tree` has fewer than `n`
+    /// keys.
+    ///
+    /// This walks the leaf nodes of the `Tree` once. In the
+    /// absence of concurrent writes, the sizes of the ranges
+    /// differ by at most one key. Writes that happen during
+    /// the walk may add skew proportional to their number.
+    ///
+    /// Returns `Error::Unsupported` if `n` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// for i in 0..10u8 {
+    ///     t.insert(vec![i], vec![]).unwrap();
+    /// }
+    ///
+    /// let points = t.split_points(2).unwrap();
+    /// assert_eq!(points, vec![sled::IVec::from(vec![5])]);
+    /// ```
+    pub fn split_points(&self, n: usize) -> Result<Vec<IVec>> {
+        if n == 0 {
+            return Err(Error::Unsupported(
+                "split_points requires at least one range".to_owned(),
+            ));
+        }
+
+        let tx = self.context.pagecache.begin()?;
+        let leaves = self.leaf_nodes(&tx)?;
+
+        let total: usize = leaves.iter().map(|leaf| leaf.data.len()).sum();
+
+        let mut points = vec![];
+        let mut leaf_iter = leaves.into_iter();
+        let mut leaf = leaf_iter.next();
+        let mut skipped = 0;
+
+        for i in 1..n {
+            let target = i * total / n;
+            if target == 0 {
+                continue;
+            }
+
+            while let Some(node) = leaf {
+                let items = node.data.leaf_ref().unwrap();
+                if target < skipped + items.len() {
+                    let (ref k, _) = items[target - skipped];
+                    let key = IVec::from(prefix_decode(&node.lo, k));
+                    if points.last() != Some(&key) {
+                        points.push(key);
+                    }
+                    break;
+                }
+                skipped += items.len();
+                leaf = leaf_iter.next();
+            }
+        }
+
+        Ok(points)
+    }
+
+    /// Returns the number of keys in the `Tree` along with
+    /// the shortest, longest, and mean key lengths, which is
+    /// useful when figuring out why a `Tree` takes up more
+    /// space than expected. Like `split_points`, this walks
+    /// the leaf nodes of the `Tree` once without blocking
+    /// writers, and writes that happen during the walk may
+    /// or may not be counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let config = sled::ConfigBuilder::new().temporary(true).build();
+    /// let t = sled::Db::start(config).unwrap();
+    ///
+    /// t.insert(b"a", vec![]).unwrap();
+    /// t.insert(b"abc", vec![]).unwrap();
+    ///
+    /// let stats = t.key_stats().unwrap();
+    /// assert_eq!(stats.count, 2);
+    /// assert_eq!(stats.min_len, 1);
+    /// assert_eq!(stats.max_len, 3);
+    /// assert_eq!(stats.mean_len, 2.0);
+    /// ```
+    pub fn key_stats(&self) -> Result<KeyStats> {
+        let tx = self.context.pagecache.begin()?;
+
+        let mut stats = KeyStats::default();
+        let mut total_len = 0;
+
+        for node in self.leaf_nodes(&tx)? {
+            for (k, _) in node.data.leaf_ref().unwrap() {
+                let len = prefix_decode(&node.lo, k).len();
+                if stats.count == 0 || len < stats.min_len {
+                    stats.min_len = len;
+                }
+                stats.max_len = std::cmp::max(stats.max_len, len);
+                stats.count += 1;
+                total_len += len;
+            }
+        }
+
+        if stats.count > 0 {
+            stats.mean_len = total_len as f64 / stats.count as f64;
+        }
+
+        Ok(stats)
+    }
+
+    // descends along the left edge, then walks the leaf level
+    fn leaf_nodes<'g>(&self, tx: &'g Tx<Frag>) -> Result<Vec<&'g Node>> {
+        let mut pid = self.root.load(SeqCst);
+        let mut leaves = vec![];
+        loop {
+            let node = match self.view_for_pid(pid, tx)? {
+                Some(view) => view.node,
+                None => {
+                    return Err(Error::ReportableBug(format!(
+                        "failed to read node {} while walking leaves",
+                        pid
+                    )))
+                }
+            };
+
+            if let Data::Index(ref ptrs) = node.data {
+                pid = ptrs[0].1;
+                continue;
+            }
+
+            leaves.push(node);
+            match node.next {
+                Some(next) => pid = next,
+                None => break,
+            }
+        }
+
+        Ok(leaves)
+    }
+
     /// Clears the `Tree`, removing all values.
     ///
     /// Note that this is not atomic.
@@ -1085,7 +4009,8 @@ impl Tree {
     ) -> Result<()> {
         trace!("splitting node {}", node_view.pid);
         // split node
-        let (mut lhs, rhs) = node_view.node.clone().split();
+        let (mut lhs, rhs) =
+            node_view.node.clone().split(self.context.split_policy);
         let rhs_lo = rhs.lo.clone();
 
         // install right side
@@ -1211,11 +4136,28 @@ impl Tree {
         }
     }
 
+    pub(crate) fn reserve_cache(&self, pid: PageId) {
+        self.cache_reservation.reserve(pid, &self.context.pagecache);
+    }
+
+    /// Records every key currently in this `Tree` in its
+    /// bloom filter, if it has one. This is called whenever
+    /// a `Tree` is opened and scans all of its keys, so
+    /// without `lazy_recovery` opening a large `Tree` with a
+    /// bloom filter takes as long as a full scan.
+    pub(crate) fn fill_bloom_filter(&self) -> Result<()> {
+        match self.bloom_filter {
+            Some(ref bloom_filter) => bloom_filter.clone().fill(self),
+            None => Ok(()),
+        }
+    }
+
     pub(crate) fn view_for_pid<'g>(
         &self,
         pid: PageId,
         tx: &'g Tx<Frag>,
     ) -> Result<Option<View<'g>>> {
+        self.reserve_cache(pid);
         loop {
             let frag_opt = self.context.pagecache.get(pid, tx)?;
             if let Some((tree_ptr, Frag::Base(ref leaf), size)) = &frag_opt {
@@ -1719,6 +4661,12 @@ impl Tree {
                 )?;
 
                 if ret.is_ok() {
+                    if let Some(items) = cursor_view.data.leaf_ref() {
+                        for (_, stored) in items {
+                            self.free_value(stored, &tx)?;
+                        }
+                    }
+
                     let next_pid = if let Some(next_pid) = cursor_view.next {
                         next_pid
                     } else {
@@ -1802,3 +4750,75 @@ impl Debug for Tree {
         Ok(())
     }
 }
+
+// Decodes the id of the page that holds a separated value.
+fn value_pid(stored: &[u8]) -> Result<PageId> {
+    if stored.len() != std::mem::size_of::<PageId>() {
+        return Err(Error::ReportableBug(format!(
+            "expected a separated value's page id, but found {:?}",
+            stored
+        )));
+    }
+    let mut buf = [0; 8];
+    buf.copy_from_slice(stored);
+    Ok(PageId::from_be_bytes(buf))
+}
+
+// Increments a big-endian suffix, starting a new
+// 8-byte sequence if there is no suffix yet.
+fn next_sequential_key(prefix: &[u8], suffix: &[u8]) -> Option<Vec<u8>> {
+    let mut next = prefix.to_vec();
+
+    if suffix.is_empty() {
+        next.extend_from_slice(&[0; 8]);
+        return Some(next);
+    }
+
+    let mut suffix = suffix.to_vec();
+    for byte in suffix.iter_mut().rev() {
+        if *byte == u8::MAX {
+            *byte = 0;
+        } else {
+            *byte += 1;
+            next.extend_from_slice(&suffix);
+            return Some(next);
+        }
+    }
+
+    None
+}
+
+// lsns start at 0, but are signed
+pub(crate) fn lsn_generation(lsn: Lsn) -> u64 {
+    std::cmp::max(lsn, 0) as u64
+}
+
+// Returns the smallest key that is greater than every
+// key starting with `prefix`, if there is one.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+
+    while let Some(last) = upper.pop() {
+        if last < u8::MAX {
+            upper.push(last + 1);
+            return Some(upper);
+        }
+    }
+
+    None
+}
+
+// Decodes the owner and expiry time of a lock
+// written by `Tree::try_lock`.
+// Decodes a set written by `Tree::set_add`, where an
+// absent value is the empty set.
+fn decode_set(value: Option<&IVec>) -> Result<Vec<Vec<u8>>> {
+    value.map_or(Ok(vec![]), |value| {
+        bincode::deserialize(value).map_err(|e| {
+            Error::Unsupported(format!(
+                "the value is not a set written by set_add: {}",
+                e
+            ))
+        })
+    })
+}
@@ -213,7 +213,7 @@ pub fn prop_tree_matches_btreemap(
         .use_compression(use_compression)
         .snapshot_after_ops(u64::from(snapshot_after) + 1)
         .flush_every_ms(if flusher { Some(1) } else { None })
-        .io_buf_size(10000)
+        .io_buf_size(10240)
         .cache_capacity(256)
         .idgen_persist_interval(1)
         .build();
@@ -143,7 +143,7 @@ fn run_without_snapshot() {
         .page_consolidation_threshold(10)
         .cache_capacity(128 * 1024 * 1024)
         .flush_every_ms(Some(100))
-        .io_buf_size(1_000)
+        .io_buf_size(1024)
         .path("test_crashes".to_string())
         .snapshot_after_ops(1 << 56)
         .build();
@@ -163,7 +163,7 @@ fn run_with_snapshot() {
         .page_consolidation_threshold(10)
         .cache_capacity(128 * 1024 * 1024)
         .flush_every_ms(Some(100))
-        .io_buf_size(1_000)
+        .io_buf_size(1024)
         .path("test_crashes_with_snapshot".to_string())
         .snapshot_after_ops(5000)
         .build();
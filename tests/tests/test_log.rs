@@ -74,7 +74,7 @@ fn log_writebatch() -> pagecache::Result<()> {
 fn more_log_reservations_than_buffers() {
     let config = ConfigBuilder::new()
         .temporary(true)
-        .io_buf_size(100)
+        .io_buf_size(512)
         .segment_mode(SegmentMode::Linear)
         .build();
     let log = Log::start_raw_log(config.clone()).unwrap();
@@ -100,7 +100,7 @@ fn non_contiguous_log_flush() {
     let config = ConfigBuilder::new()
         .temporary(true)
         .segment_mode(SegmentMode::Linear)
-        .io_buf_size(1000)
+        .io_buf_size(1024)
         .build();
     let log = Log::start_raw_log(config.clone()).unwrap();
 
@@ -125,7 +125,7 @@ fn concurrent_logging() {
         let config = ConfigBuilder::new()
             .temporary(true)
             .segment_mode(SegmentMode::Linear)
-            .io_buf_size(1000)
+            .io_buf_size(1024)
             .flush_every_ms(Some(50))
             .build();
         let log = Arc::new(Log::start_raw_log(config.clone()).unwrap());
@@ -220,7 +220,7 @@ fn concurrent_logging_404() {
     let config = ConfigBuilder::new()
         .temporary(true)
         .segment_mode(SegmentMode::Linear)
-        .io_buf_size(1000)
+        .io_buf_size(1024)
         .flush_every_ms(Some(50))
         .build();
     let log_arc = Arc::new(Log::start_raw_log(config.clone()).unwrap());
@@ -338,7 +338,7 @@ fn log_iterator() {
     let config = ConfigBuilder::new()
         .temporary(true)
         .segment_mode(SegmentMode::Linear)
-        .io_buf_size(1000)
+        .io_buf_size(1024)
         .build();
     let log = Log::start_raw_log(config.clone()).unwrap();
     let (first_lsn, _) =
@@ -386,7 +386,7 @@ fn log_chunky_iterator() {
             let config = ConfigBuilder::new()
                 .temporary(true)
                 .segment_mode(SegmentMode::Linear)
-                .io_buf_size(100)
+                .io_buf_size(512)
                 .build();
 
             let log = Log::start_raw_log(config.clone()).unwrap();
@@ -448,7 +448,7 @@ fn multi_segment_log_iteration() {
     let config = ConfigBuilder::new()
         .temporary(true)
         .segment_mode(SegmentMode::Linear)
-        .io_buf_size(500)
+        .io_buf_size(512)
         .build();
 
     let total_seg_overhead = SEG_HEADER_LEN;
@@ -14,7 +14,8 @@ use {
 };
 
 use pagecache::{
-    Config, ConfigBuilder, Materializer, PageCache, MAX_SPACE_AMPLIFICATION,
+    ConfigBuilder, Error, InvariantPolicy, Materializer, PageCache,
+    MAX_SPACE_AMPLIFICATION,
 };
 
 type PageId = u64;
@@ -49,7 +50,7 @@ fn pagecache_monotonic_idgen() {
         .cache_capacity(256)
         .flush_every_ms(None)
         .snapshot_after_ops(1_000_000)
-        .io_buf_size(20000)
+        .io_buf_size(20480)
         .build();
 
     let pc: PageCache<TestMaterializer> =
@@ -77,7 +78,7 @@ fn pagecache_caching() {
         .cache_capacity(256)
         .flush_every_ms(None)
         .snapshot_after_ops(1_000_000)
-        .io_buf_size(20000)
+        .io_buf_size(20480)
         .build();
 
     let pc: PageCache<TestMaterializer> =
@@ -102,6 +103,54 @@ fn pagecache_caching() {
     }
 }
 
+// refuses to merge a fragment containing `usize::max_value()`,
+// and panics if asked to anyway
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StrictMaterializer(Vec<usize>);
+
+impl Materializer for StrictMaterializer {
+    fn merge(&mut self, other: &StrictMaterializer) {
+        assert!(self.can_merge(other), "merged an invalid fragment");
+        self.0.extend_from_slice(&other.0);
+    }
+
+    fn can_merge(&self, other: &StrictMaterializer) -> bool {
+        !other.0.contains(&usize::MAX)
+    }
+}
+
+#[test]
+fn pagecache_invariant_policy() {
+    let get_invalid_page = |policy| {
+        let config = ConfigBuilder::new()
+            .temporary(true)
+            .flush_every_ms(None)
+            .on_invariant_violation(policy)
+            .build();
+
+        let pc: PageCache<StrictMaterializer> =
+            PageCache::start(config).unwrap();
+        let tx = pc.begin().unwrap();
+
+        let (id, key) = pc.allocate(StrictMaterializer(vec![0]), &tx).unwrap();
+        pc.link(id, key, StrictMaterializer(vec![usize::MAX]), &tx)
+            .unwrap()
+            .unwrap();
+
+        pc.get(id, &tx)
+            .map(|page| page.map(|(_, frag, _)| frag.clone()))
+    };
+
+    match get_invalid_page(InvariantPolicy::Error) {
+        Err(Error::Corruption { .. }) => {}
+        other => panic!("expected Corruption, got {:?}", other),
+    }
+
+    let panicked =
+        std::panic::catch_unwind(|| get_invalid_page(InvariantPolicy::Panic));
+    assert!(panicked.is_err());
+}
+
 #[test]
 fn concurrent_pagecache() -> sled::Result<()> {
     tests::setup_logger();
@@ -113,7 +162,7 @@ fn concurrent_pagecache() -> sled::Result<()> {
         .async_io(false)
         .flush_every_ms(Some(10))
         .snapshot_after_ops(100_000_000)
-        .io_buf_size(250)
+        .io_buf_size(512)
         .page_consolidation_threshold(3)
         .build();
 
@@ -233,7 +282,7 @@ fn pagecache_strange_crash_1() {
         .cache_capacity(256)
         .flush_every_ms(None)
         .snapshot_after_ops(1_000_000)
-        .io_buf_size(20000)
+        .io_buf_size(20480)
         .build();
 
     {
@@ -271,7 +320,7 @@ fn pagecache_strange_crash_2() {
             .cache_capacity(256)
             .flush_every_ms(None)
             .snapshot_after_ops(1_000_000)
-            .io_buf_size(20000)
+            .io_buf_size(20480)
             .build();
 
         config.verify_snapshot().unwrap();
@@ -309,7 +358,7 @@ fn basic_pagecache_recovery() {
     let config = ConfigBuilder::new()
         .temporary(true)
         .flush_every_ms(None)
-        .io_buf_size(1000)
+        .io_buf_size(1024)
         .build();
 
     let pc: PageCache<TestMaterializer> =
@@ -352,6 +401,71 @@ fn basic_pagecache_recovery() {
     assert!(res.is_none());
 }
 
+#[test]
+fn replaced_blobs_are_removed() {
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .io_buf_size(20480)
+        .build();
+
+    let blobs = || {
+        std::fs::read_dir(config.get_path().join("blobs"))
+            .map(|dir| dir.count())
+            .unwrap_or(0)
+    };
+
+    let pc: PageCache<TestMaterializer> =
+        PageCache::start(config.clone()).unwrap();
+
+    // too large to be stored inline, so each version
+    // of the page is written as its own blob
+    let big = |i: usize| TestMaterializer(vec![i; 1000]);
+
+    let replace = |pid, new| {
+        // segments only become stable once no operation
+        // that may have seen them is still pinned
+        let tx = pc.begin().unwrap();
+        let (old, _, _) = pc.get(pid, &tx).unwrap().unwrap();
+        pc.replace(pid, old, new, &tx).unwrap().unwrap();
+    };
+
+    let (id, small_id) = {
+        let tx = pc.begin().unwrap();
+        let (id, _) = pc.allocate(big(0), &tx).unwrap();
+        let (small_id, _) = pc.allocate(vec![0].into(), &tx).unwrap();
+        (id, small_id)
+    };
+    for i in 1..50 {
+        replace(id, big(i));
+    }
+
+    // the blobs are removed once the segment that
+    // recorded their replacement is stable, so fill
+    // it and a few more with small updates
+    for i in 0..2000 {
+        replace(small_id, vec![i].into());
+        if i % 100 == 0 {
+            pc.flush().unwrap();
+        }
+    }
+    pc.flush().unwrap();
+
+    let before = std::time::Instant::now();
+    while blobs() > 1 {
+        assert!(
+            before.elapsed() < std::time::Duration::from_secs(5),
+            "{} of 50 blobs remain",
+            blobs()
+        );
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    let tx = pc.begin().unwrap();
+    let (_, page, _) = pc.get(id, &tx).unwrap().unwrap();
+    assert_eq!(page, &big(49));
+}
+
 #[derive(Debug, Clone)]
 enum Op {
     Replace(PageId, usize),
@@ -422,7 +536,7 @@ fn prop_pagecache_works(ops: Vec<Op>, flusher: bool) -> bool {
     use self::Op::*;
     let config = ConfigBuilder::new()
         .temporary(true)
-        .io_buf_size(1000)
+        .io_buf_size(1024)
         .flush_every_ms(if flusher { Some(1) } else { None })
         .cache_capacity(256)
         .build();
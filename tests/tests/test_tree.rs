@@ -1,7 +1,10 @@
 use std::sync::{Arc, Barrier};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use pagecache::ConfigBuilder;
+use pagecache::{
+    ConfigBuilder, SegmentMode, MAX_SPACE_AMPLIFICATION, MSG_HEADER_LEN,
+};
 use sled::*;
 use tests::tree::{
     prop_tree_matches_btreemap, Key,
@@ -40,7 +43,7 @@ fn concurrent_tree_ops() {
             .async_io(false)
             .flush_every_ms(None)
             .snapshot_after_ops(100_000_000)
-            .io_buf_size(250)
+            .io_buf_size(512)
             .build();
 
         macro_rules! par {
@@ -199,7 +202,7 @@ fn concurrent_tree_iter() -> Result<()> {
     ];
 
     for item in &INDELIBLE {
-        t.insert(item.to_vec(), item.to_vec())?;
+        t.insert(item, item.to_vec())?;
     }
 
     let barrier = Arc::new(Barrier::new(N_FORWARD + N_REVERSE + 2));
@@ -341,229 +344,4817 @@ fn concurrent_tree_iter() -> Result<()> {
 }
 
 #[test]
-fn tree_subdir() {
-    let _ = std::fs::remove_dir_all("/tmp/test_tree_subdir");
+fn concurrent_tree_iter_large_values() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u8 = 64;
+    const N_READERS: usize = 4;
+
+    // large enough that leaves are written out as blobs,
+    // and pages get rewritten and consolidated underneath
+    // the scanning threads.
+    fn value_for(key: u8, generation: u8) -> Vec<u8> {
+        let mut value = vec![generation; 64 * 1024];
+        value[0] = key;
+        value
+    }
 
     let config = ConfigBuilder::new()
-        .async_io(false)
-        .path("/tmp/test_tree_subdir/test_subdir".to_owned())
+        .temporary(true)
+        .flush_every_ms(None)
+        .page_consolidation_threshold(2)
         .build();
 
     let t = sled::Db::start(config).unwrap();
 
-    t.insert(&[1], vec![1]).unwrap();
+    for k in 0..N_KEYS {
+        t.insert(vec![k], value_for(k, 0))?;
+    }
 
-    drop(t);
+    let barrier = Arc::new(Barrier::new(N_READERS + 1));
 
-    let config = ConfigBuilder::new()
-        .path("/tmp/test_tree_subdir/test_subdir".to_owned())
-        .build();
-    let t = sled::Db::start(config).unwrap();
+    let mut threads: Vec<thread::JoinHandle<Result<()>>> = vec![];
 
-    let res = t.get(&*vec![1]);
+    for i in 0..N_READERS {
+        let t = thread::Builder::new()
+            .name(format!("scanner({})", i))
+            .spawn({
+                let t = t.clone();
+                let barrier = barrier.clone();
+                move || {
+                    barrier.wait();
+                    for _ in 0..20 {
+                        let mut seen = 0;
+                        for res in t.iter() {
+                            let (k, v) = res?;
+                            assert_eq!(v.len(), 64 * 1024);
+                            assert_eq!(v[0], k[0]);
+                            seen += 1;
+                        }
+                        assert_eq!(seen, N_KEYS as usize);
+                    }
+                    Ok(())
+                }
+            })
+            .unwrap();
+        threads.push(t);
+    }
 
-    assert_eq!(res.unwrap().unwrap(), vec![1_u8]);
+    let rewriter = thread::Builder::new()
+        .name("rewriter".into())
+        .spawn({
+            let t = t.clone();
+            let barrier = barrier.clone();
+            move || {
+                barrier.wait();
+                for generation in 1..8 {
+                    for k in 0..N_KEYS {
+                        t.insert(vec![k], value_for(k, generation))?;
+                    }
+                }
+                Ok(())
+            }
+        })
+        .unwrap();
 
-    drop(t);
+    threads.push(rewriter);
 
-    std::fs::remove_dir_all("/tmp/test_tree_subdir").unwrap();
+    for thread in threads.into_iter() {
+        thread.join().expect("thread should not have crashed")?;
+    }
+
+    Ok(())
 }
 
 #[test]
-fn tree_iterator() {
+fn contended_key_backs_off() -> Result<()> {
+    tests::setup_logger();
+
+    const N_WRITERS: usize = 16;
+    const N_OPS: usize = 200;
+
     let config = ConfigBuilder::new()
         .temporary(true)
         .flush_every_ms(None)
+        .cas_backoff(BackoffConfig {
+            spin_limit: 2,
+            base_delay_us: 1,
+            max_delay_us: 100,
+        })
         .build();
+
     let t = sled::Db::start(config).unwrap();
-    for i in 0..N_PER_THREAD {
-        let k = kv(i);
-        t.insert(&k, k.clone()).unwrap();
-    }
 
-    for (i, (k, v)) in t.iter().map(|res| res.unwrap()).enumerate() {
-        let should_be = kv(i);
-        assert_eq!(should_be, &*k);
-        assert_eq!(should_be, &*v);
-    }
+    let barrier = Arc::new(Barrier::new(N_WRITERS));
+    let mut threads: Vec<thread::JoinHandle<Result<()>>> = vec![];
 
-    for (i, (k, v)) in t.iter().map(|res| res.unwrap()).enumerate() {
-        let should_be = kv(i);
-        assert_eq!(should_be, &*k);
-        assert_eq!(should_be, &*v);
+    for i in 0..N_WRITERS {
+        let t = thread::Builder::new()
+            .name(format!("writer({})", i))
+            .spawn({
+                let t = t.clone();
+                let barrier = barrier.clone();
+                move || {
+                    barrier.wait();
+                    for j in 0..N_OPS {
+                        t.insert(b"hot", vec![i as u8, j as u8])?;
+                    }
+                    Ok(())
+                }
+            })
+            .unwrap();
+        threads.push(t);
     }
 
-    let half_way = N_PER_THREAD / 2;
-    let half_key = kv(half_way);
-    let mut tree_scan = t.range(&*half_key..);
-    let r1 = tree_scan.next().unwrap().unwrap();
-    assert_eq!((r1.0.as_ref(), &*r1.1), (half_key.as_ref(), &*half_key));
+    for thread in threads.into_iter() {
+        thread.join().expect("thread should not have crashed")?;
+    }
 
-    let first_key = kv(0);
-    let mut tree_scan = t.range(&*first_key..);
-    let r2 = tree_scan.next().unwrap().unwrap();
-    assert_eq!((r2.0.as_ref(), &*r2.1), (first_key.as_ref(), &*first_key));
+    // every write completed, and losing a race did not
+    // cause an unbounded number of retries per write.
+    let total_writes = (N_WRITERS * N_OPS) as u64;
+    assert!(
+        t.cas_retries() < total_writes * 10,
+        "{} retries for {} writes",
+        t.cas_retries(),
+        total_writes
+    );
+    assert!(t.get(b"hot")?.is_some());
 
-    let last_key = kv(N_PER_THREAD - 1);
-    let mut tree_scan = t.range(&*last_key..);
-    let r3 = tree_scan.next().unwrap().unwrap();
-    assert_eq!((r3.0.as_ref(), &*r3.1), (last_key.as_ref(), &*last_key));
-    assert_eq!(tree_scan.next(), None);
+    Ok(())
 }
 
 #[test]
-fn tree_subscriptions_and_keyspaces() -> Result<()> {
+fn replica_converges_from_log_entries() -> Result<()> {
+    tests::setup_logger();
+
+    fn concatenate(
+        _key: &[u8],
+        old: Option<&[u8]>,
+        new: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut ret = old.map(<[u8]>::to_vec).unwrap_or_default();
+        ret.extend_from_slice(new);
+        Some(ret)
+    }
+
+    // consolidating often checks that writes which are
+    // folded into a new page are still logged on their own
     let config = ConfigBuilder::new()
         .temporary(true)
-        .flush_every_ms(None)
+        .replication(true)
+        .page_consolidation_threshold(2)
         .build();
+    let primary = sled::Db::start(config).unwrap();
+    primary.set_merge_operator(concatenate);
 
-    let db = sled::Db::start(config.clone()).unwrap();
+    let config = ConfigBuilder::new().temporary(true).build();
+    let replica = sled::Db::start(config).unwrap();
+    replica.set_merge_operator(concatenate);
 
-    let t1 = db.open_tree(b"1".to_vec())?;
-    let mut s1 = t1.watch_prefix(b"".to_vec());
+    // writes to other trees aren't shipped
+    primary.open_tree(b"other")?.insert(b"x", vec![1])?;
 
-    let t2 = db.open_tree(b"2".to_vec())?;
-    let mut s2 = t2.watch_prefix(b"".to_vec());
+    let mut applied = -1;
+    for round in 0..4_u8 {
+        for i in 0..10_u8 {
+            primary.insert([i], vec![round])?;
+        }
+        primary.remove([round])?;
+        primary
+            .cas([9], Some(vec![round]), None as Option<Vec<u8>>)?
+            .unwrap();
+        primary.merge(b"merged", vec![round])?;
+
+        let entries: Vec<_> = primary.log_entries_since(applied)?.collect();
+        assert_eq!(entries.len(), 13, "every write should be shipped");
+        assert!(entries.windows(2).all(|w| w[0].lsn < w[1].lsn));
+        assert_eq!(
+            entries.last().unwrap().op,
+            LogOp::Merge(b"merged".into(), vec![round].into())
+        );
+        applied = entries.last().unwrap().lsn;
 
-    t1.insert(b"t1_a", b"t1_a".to_vec())?;
-    t2.insert(b"t2_a", b"t2_a".to_vec())?;
+        replica.apply_log_entries(&entries)?;
 
-    assert_eq!(s1.next().unwrap().key(), b"t1_a");
-    assert_eq!(s2.next().unwrap().key(), b"t2_a");
+        let expected: Vec<_> = primary.iter().collect::<Result<_>>()?;
+        let actual: Vec<_> = replica.iter().collect::<Result<_>>()?;
+        assert_eq!(expected, actual);
+    }
+    assert_eq!(replica.get(b"merged")?, Some(vec![0, 1, 2, 3].into()));
 
-    let guard = pagecache::pin();
-    guard.flush();
-    drop(guard);
+    Ok(())
+}
 
-    drop(db);
-    drop(t1);
-    drop(t2);
+#[test]
+fn log_entries_since_reads_back_the_log() -> Result<()> {
+    tests::setup_logger();
 
-    let db = sled::Db::start(config.clone()).unwrap();
+    let expect_unsupported = |res: Result<_>| match res {
+        Err(Error::Unsupported(_)) => {}
+        Err(e) => panic!("expected Unsupported, got {:?}", e),
+        Ok(_) => panic!("expected Unsupported, got entries"),
+    };
 
-    let t1 = db.open_tree(b"1".to_vec())?;
-    let mut s1 = t1.watch_prefix(b"".to_vec());
+    // writes can't be read back without replication
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+    expect_unsupported(t.log_entries_since(-1).map(|_| ()));
+    drop(t);
 
-    let t2 = db.open_tree(b"2".to_vec())?;
-    let mut s2 = t2.watch_prefix(b"".to_vec());
+    // with it, they are read back out of the log,
+    // so they survive a restart
+    let dir = "/tmp/test_log_entries_since_reads_back_the_log";
+    let _ = std::fs::remove_dir_all(dir);
+    let open = |replication| {
+        let config = ConfigBuilder::new()
+            .path(dir)
+            .replication(replication)
+            .build();
+        sled::Db::start(config)
+    };
 
-    assert!(db.is_empty());
-    assert_eq!(t1.len(), 1);
-    assert_eq!(t2.len(), 1);
+    let t = open(true)?;
+    t.insert(b"a", vec![1])?;
+    t.remove(b"a")?;
+    drop(t);
 
-    t1.insert(b"t1_b", b"t1_b".to_vec())?;
-    t2.insert(b"t2_b", b"t2_b".to_vec())?;
+    let t = open(true)?;
+    t.insert(b"b", vec![2])?;
+    let entries: Vec<_> = t.log_entries_since(-1)?.collect();
+    assert_eq!(
+        entries.iter().map(|e| e.op.clone()).collect::<Vec<_>>(),
+        vec![
+            LogOp::Set(b"a".into(), vec![1].into()),
+            LogOp::Del(b"a".into()),
+            LogOp::Set(b"b".into(), vec![2].into()),
+        ]
+    );
+    let last = entries.last().unwrap().lsn;
+    drop(t);
 
-    assert_eq!(s1.next().unwrap().key(), b"t1_b");
-    assert_eq!(s2.next().unwrap().key(), b"t2_b");
+    // writes made while it was disabled can't be read
+    // back, so they are refused rather than skipped
+    let t = open(false)?;
+    t.insert(b"c", vec![3])?;
+    let unlogged = t.generation() as i64;
+    drop(t);
 
-    let guard = pagecache::pin();
-    guard.flush();
-    drop(guard);
+    let t = open(true)?;
+    expect_unsupported(t.log_entries_since(last).map(|_| ()));
+    t.insert(b"d", vec![4])?;
+    let ops: Vec<_> = t.log_entries_since(unlogged)?.map(|e| e.op).collect();
+    assert_eq!(ops, vec![LogOp::Set(b"d".into(), vec![4].into())]);
+    drop(t);
 
-    drop(db);
-    drop(t1);
-    drop(t2);
+    std::fs::remove_dir_all(dir)?;
 
-    let db = sled::Db::start(config.clone()).unwrap();
+    Ok(())
+}
 
-    let t1 = db.open_tree(b"1".to_vec())?;
-    let t2 = db.open_tree(b"2".to_vec())?;
+#[test]
+fn log_entries_since_refuses_reclaimed_log() -> Result<()> {
+    tests::setup_logger();
 
-    assert!(db.is_empty());
-    assert_eq!(t1.len(), 2);
-    assert_eq!(t2.len(), 2);
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .replication(true)
+        .io_buf_size(1024)
+        .build();
+    let t = sled::Db::start(config)?;
 
-    db.drop_tree(b"1")?;
-    db.drop_tree(b"2")?;
+    t.insert(b"k", vec![0])?;
+    let first = t.log_entries_since(-1)?.last().unwrap().lsn;
 
-    assert_eq!(t1.get(b""), Err(Error::CollectionNotFound(b"1".to_vec())));
+    // overwriting the same key leaves nothing live in
+    // older segments, so they are reclaimed
+    for i in 0..1000_u64 {
+        t.insert(b"k", i.to_be_bytes().to_vec())?;
+    }
+    t.flush()?;
+
+    match t.log_entries_since(first) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!(
+            "expected the reclaimed log to be refused, got {:?}",
+            other.map(Iterator::count)
+        ),
+    }
 
-    assert_eq!(t2.get(b""), Err(Error::CollectionNotFound(b"2".to_vec())));
+    let recent = t.generation() as i64;
+    t.insert(b"k", vec![1])?;
+    let ops: Vec<_> = t.log_entries_since(recent)?.map(|e| e.op).collect();
+    assert_eq!(ops, vec![LogOp::Set(b"k".into(), vec![1].into())]);
 
-    let guard = pagecache::pin();
-    guard.flush();
-    drop(guard);
+    Ok(())
+}
 
-    drop(db);
-    drop(t1);
-    drop(t2);
+#[test]
+fn try_set_sheds_load_when_saturated() -> Result<()> {
+    tests::setup_logger();
 
-    let db = sled::Db::start(config.clone()).unwrap();
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .io_buf_size(8192)
+        .build();
+    let t = sled::Db::start(config).unwrap();
 
-    let t1 = db.open_tree(b"1".to_vec())?;
-    let t2 = db.open_tree(b"2".to_vec())?;
+    t.flush()?;
+    assert!(t.write_pressure() < 1.);
 
-    assert!(db.is_empty());
-    assert_eq!(t1.len(), 0);
-    assert_eq!(t2.len(), 0);
+    let mut shed = None;
+    for i in 0..1000_u16 {
+        if !t.try_set(i.to_be_bytes(), vec![0; 100])? {
+            shed = Some(i);
+            break;
+        }
+    }
+    let shed = shed.expect("try_set never observed a full IO buffer");
+    assert!(t.write_pressure() > 0.95);
+
+    // the rejected write left nothing behind
+    assert_eq!(t.get(shed.to_be_bytes())?, None);
+    assert_eq!(t.len(), shed as usize);
+
+    // flushing relieves the pressure
+    t.flush()?;
+    assert!(t.write_pressure() < 0.5);
+    assert!(t.try_set(shed.to_be_bytes(), vec![1])?);
 
     Ok(())
 }
 
 #[test]
-fn tree_range() {
+fn concurrent_append_next_is_contiguous() -> Result<()> {
     tests::setup_logger();
 
+    const N_APPENDERS: usize = 8;
+    const N_OPS: usize = 100;
+
     let config = ConfigBuilder::new()
         .temporary(true)
         .flush_every_ms(None)
         .build();
     let t = sled::Db::start(config).unwrap();
 
-    t.insert(b"0", vec![0]).unwrap();
-    t.insert(b"1", vec![10]).unwrap();
-    t.insert(b"2", vec![20]).unwrap();
-    t.insert(b"3", vec![30]).unwrap();
-    t.insert(b"4", vec![40]).unwrap();
-    t.insert(b"5", vec![50]).unwrap();
+    // a neighbouring prefix must not be counted
+    t.insert(b"log0", vec![])?;
 
-    let start: &[u8] = b"2";
-    let end: &[u8] = b"4";
-    let mut r = t.range(start..end);
-    assert_eq!(r.next().unwrap().unwrap().0, b"2");
-    assert_eq!(r.next().unwrap().unwrap().0, b"3");
-    assert_eq!(r.next(), None);
+    let barrier = Arc::new(Barrier::new(N_APPENDERS));
+    let mut threads: Vec<thread::JoinHandle<Result<()>>> = vec![];
 
-    let start = b"2".to_vec();
-    let end = b"4".to_vec();
-    let mut r = t.range(start..end).rev();
-    assert_eq!(r.next().unwrap().unwrap().0, b"3");
-    assert_eq!(r.next().unwrap().unwrap().0, b"2");
-    assert_eq!(r.next(), None);
+    for i in 0..N_APPENDERS {
+        let t = thread::Builder::new()
+            .name(format!("appender({})", i))
+            .spawn({
+                let t = t.clone();
+                let barrier = barrier.clone();
+                move || {
+                    barrier.wait();
+                    for j in 0..N_OPS {
+                        t.append_next(b"log/", vec![i as u8, j as u8])?;
+                    }
+                    Ok(())
+                }
+            })
+            .unwrap();
+        threads.push(t);
+    }
 
-    let start = b"2".to_vec();
-    let mut r = t.range(start..);
-    assert_eq!(r.next().unwrap().unwrap().0, b"2");
-    assert_eq!(r.next().unwrap().unwrap().0, b"3");
-    assert_eq!(r.next().unwrap().unwrap().0, b"4");
-    assert_eq!(r.next().unwrap().unwrap().0, b"5");
-    assert_eq!(r.next(), None);
+    for thread in threads.into_iter() {
+        thread.join().expect("thread should not have crashed")?;
+    }
 
-    let start = b"2".to_vec();
-    let mut r = t.range(..=start).rev();
-    assert_eq!(
-        r.next().unwrap().unwrap().0,
-        b"2",
-        "failed to find 2 in tree {:?}",
-        t
-    );
-    assert_eq!(r.next().unwrap().unwrap().0, b"1");
-    assert_eq!(r.next().unwrap().unwrap().0, b"0");
-    assert_eq!(r.next(), None);
+    let mut values = std::collections::HashSet::new();
+    let mut expected_seq = 0_u64;
+    for res in t.scan_prefix(b"log/") {
+        let (k, v) = res?;
+        let mut seq = [0; 8];
+        seq.copy_from_slice(&k[4..]);
+        assert_eq!(u64::from_be_bytes(seq), expected_seq);
+        assert!(values.insert(v.to_vec()), "duplicate value {:?}", v);
+        expected_seq += 1;
+    }
+    assert_eq!(expected_seq as usize, N_APPENDERS * N_OPS);
+
+    Ok(())
 }
 
 #[test]
-fn recover_tree() {
+fn scan_prefetch_reads_ahead() -> Result<()> {
     tests::setup_logger();
 
+    const N_KEYS: u32 = 1024;
+
     let config = ConfigBuilder::new()
         .temporary(true)
-        .io_buf_size(5000)
-        .flush_every_ms(None)
         .async_io(false)
-        .snapshot_after_ops(N_PER_THREAD as u64)
+        .scan_prefetch(4)
+        .build();
+
+    let t = sled::Db::start(config.clone()).unwrap();
+    for i in 0..N_KEYS {
+        t.insert(i.to_be_bytes(), vec![0; 64])?;
+    }
+    drop(t);
+
+    // recovery leaves the cache cold
+    let t = sled::Db::start(config.clone()).unwrap();
+    assert_eq!(t.prefetched_pages(), 0);
+
+    // reading within the first leaf never reads past
+    // the upper bound of the range.
+    let first_leaf: Vec<_> =
+        t.range(0_u32.to_be_bytes()..1_u32.to_be_bytes()).collect();
+    assert_eq!(first_leaf.len(), 1);
+    assert_eq!(t.prefetched_pages(), 0);
+
+    // the first item of an unbounded scan reads the
+    // following leaves before they are consumed.
+    let mut iter = t.iter();
+    assert!(iter.next().is_some());
+    let read_ahead = t.prefetched_pages();
+    assert!(read_ahead > 0 && read_ahead <= 4, "{}", read_ahead);
+
+    let scanned = 1 + iter.count();
+    assert_eq!(scanned, N_KEYS as usize);
+    assert!(t.prefetched_pages() > read_ahead);
+
+    Ok(())
+}
+
+#[test]
+fn scan_limited_stops_reading_at_its_limit() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u32 = 1024;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .async_io(false)
+        .scan_prefetch(4)
+        .build();
+
+    let t = sled::Db::start(config.clone()).unwrap();
+    for i in 0..N_KEYS {
+        t.insert(i.to_be_bytes(), vec![0; 64])?;
+    }
+    drop(t);
+
+    // recovery leaves the cache cold
+    let t = sled::Db::start(config.clone()).unwrap();
+
+    // a page that ends in the first leaf doesn't read ahead
+    let page: Vec<_> = t.scan_limited([], 1).collect::<Result<_>>()?;
+    assert_eq!(page.len(), 1);
+    assert_eq!(t.prefetched_pages(), 0);
+
+    let start = 100_u32;
+    for &limit in &[0, 1, 7, 100] {
+        let page: Vec<_> = t
+            .scan_limited(start.to_be_bytes(), limit)
+            .keys()
+            .collect::<Result<_>>()?;
+        let expected: Vec<_> = (start..start + limit as u32)
+            .map(|i| IVec::from(&i.to_be_bytes()))
+            .collect();
+        assert_eq!(page, expected);
+    }
+
+    // the limit applies to records returned from either end
+    let mut iter = t.scan_limited([], 3);
+    assert!(iter.next().is_some());
+    assert!(iter.next_back().is_some());
+    assert!(iter.next().is_some());
+    assert!(iter.next().is_none());
+    assert!(iter.next_back().is_none());
+
+    // fewer records than the limit remain
+    let tail = t.scan_limited((N_KEYS - 2).to_be_bytes(), 10).count();
+    assert_eq!(tail, 2);
+
+    Ok(())
+}
+
+#[test]
+fn scan_limited_counts_only_returned_records() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .separate_values(true)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    for i in 0..20u8 {
+        t.insert([i], vec![i])?;
+    }
+
+    // keys removed while the scan is in progress are skipped
+    // without using up the limit
+    let mut returned = vec![];
+    for res in t.scan_limited([], 5) {
+        let (key, value) = res?;
+        assert_eq!(key, value);
+        t.remove([key[0] + 1])?;
+        returned.push(key[0]);
+    }
+    assert_eq!(returned, vec![0, 2, 4, 6, 8]);
+
+    let mut returned = vec![];
+    for res in t.scan_limited([], 3).rev() {
+        let (key, value) = res?;
+        assert_eq!(key, value);
+        t.remove([key[0] - 1])?;
+        returned.push(key[0]);
+    }
+    assert_eq!(returned, vec![19, 17, 15]);
+
+    Ok(())
+}
+
+#[test]
+fn prefix_view_is_isolated() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    // neighbours on either side of the prefix
+    t.insert(b"users", vec![0])?;
+    t.insert(b"users0", vec![0])?;
+    t.insert(b"usersz", vec![0])?;
+
+    let users = t.prefix_view(b"users/".to_vec());
+    users.insert(b"alice", vec![1])?;
+    users.insert(b"bob", vec![2])?;
+    users.insert(b"bobby", vec![3])?;
+
+    assert_eq!(t.get(b"users/alice")?, Some(IVec::from(vec![1])));
+    assert_eq!(users.get(b"bob")?, Some(IVec::from(vec![2])));
+    assert_eq!(users.get(b"0")?, None);
+    assert!(users.contains_key(b"bobby")?);
+
+    let keys: Vec<IVec> = users
+        .iter()
+        .map(|res| res.map(|(k, _v)| k))
+        .collect::<Result<_>>()?;
+    assert_eq!(
+        keys,
+        vec![IVec::from(b"alice"), b"bob".into(), b"bobby".into()]
+    );
+
+    let bobs: Vec<IVec> = users
+        .scan_prefix(b"bob")
+        .rev()
+        .map(|res| res.map(|(k, _v)| k))
+        .collect::<Result<_>>()?;
+    assert_eq!(bobs, vec![IVec::from(b"bobby"), b"bob".into()]);
+
+    assert_eq!(users.remove(b"alice")?, Some(IVec::from(vec![1])));
+    assert_eq!(t.get(b"users/alice")?, None);
+    assert_eq!(t.len(), 5);
+
+    Ok(())
+}
+
+#[test]
+fn range_first_and_last_respect_bounds() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    let item = |k: u8| Some((IVec::from(&[k]), IVec::from(&[k])));
+
+    // empty tree
+    assert_eq!(t.range_first::<&[u8], _>(..)?, None);
+    assert_eq!(t.range_last::<&[u8], _>(..)?, None);
+
+    for k in (10..=50).step_by(10) {
+        t.insert([k], vec![k])?;
+    }
+
+    // bounds on present keys
+    assert_eq!(t.range_first(vec![10]..vec![50])?, item(10));
+    assert_eq!(t.range_last(vec![10]..vec![50])?, item(40));
+    assert_eq!(t.range_last(vec![10]..=vec![50])?, item(50));
+    assert_eq!(
+        t.range_first((
+            std::ops::Bound::Excluded(vec![10]),
+            std::ops::Bound::Unbounded
+        ))?,
+        item(20)
+    );
+
+    // bounds between present keys
+    assert_eq!(t.range_first(vec![15]..vec![45])?, item(20));
+    assert_eq!(t.range_last(vec![15]..vec![45])?, item(40));
+
+    // bounds outside of the present keys
+    assert_eq!(t.range_first::<&[u8], _>(..)?, item(10));
+    assert_eq!(t.range_last::<&[u8], _>(..)?, item(50));
+    assert_eq!(t.range_last(..vec![255])?, item(50));
+
+    // empty ranges
+    assert_eq!(t.range_first(vec![21]..vec![29])?, None);
+    assert_eq!(t.range_last(vec![21]..vec![29])?, None);
+    assert_eq!(t.range_first(vec![30]..vec![30])?, None);
+    assert_eq!(t.range_last(vec![30]..vec![30])?, None);
+    assert_eq!(t.range_first(vec![51]..)?, None);
+    assert_eq!(t.range_last(..vec![10])?, None);
+
+    Ok(())
+}
+
+#[test]
+fn cas_outcome_reports_each_failure() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    assert_eq!(
+        t.cas_outcome(b"k", Some(b"a"), Some(b"b".to_vec()))?,
+        CasOutcome::AbsentButExpectedPresent
+    );
+    assert_eq!(t.get(b"k")?, None);
+
+    assert_eq!(
+        t.cas_outcome(b"k", None as Option<&[u8]>, Some(b"a".to_vec()))?,
+        CasOutcome::Applied
+    );
+    assert_eq!(t.get(b"k")?, Some(IVec::from(b"a")));
+
+    assert_eq!(
+        t.cas_outcome(b"k", None as Option<&[u8]>, Some(b"b".to_vec()))?,
+        CasOutcome::PresentButExpectedAbsent
+    );
+
+    assert_eq!(
+        t.cas_outcome(b"k", Some(b"b"), Some(b"c".to_vec()))?,
+        CasOutcome::ValueMismatch(IVec::from(b"a"))
+    );
+    assert_eq!(t.get(b"k")?, Some(IVec::from(b"a")));
+
+    assert_eq!(
+        t.cas_outcome(b"k", Some(b"a"), None as Option<&[u8]>)?,
+        CasOutcome::Applied
+    );
+    assert_eq!(t.get(b"k")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn multi_cas_moves_value_atomically() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert(b"a", b"item".to_vec())?;
+
+    let res = t.multi_cas(vec![
+        (b"a", Some(b"item".to_vec()), None),
+        (b"b", None, Some(b"item".to_vec())),
+    ])?;
+    assert_eq!(res, Ok(()));
+    assert_eq!(t.get(b"a")?, None);
+    assert_eq!(t.get(b"b")?, Some(IVec::from(b"item")));
+
+    // moving it back with a stale expectation for
+    // the destination fails without writing anything.
+    t.insert(b"a", b"other".to_vec())?;
+    let res = t.multi_cas(vec![
+        (b"b", Some(b"item".to_vec()), None),
+        (b"a", None, Some(b"item".to_vec())),
+    ])?;
+    assert_eq!(
+        res,
+        Err(MultiCasError {
+            key: IVec::from(b"a"),
+            current: Some(IVec::from(b"other")),
+        })
+    );
+    assert_eq!(t.get(b"a")?, Some(IVec::from(b"other")));
+    assert_eq!(t.get(b"b")?, Some(IVec::from(b"item")));
+
+    match t.multi_cas(vec![
+        (b"a", None as Option<Vec<u8>>, None as Option<Vec<u8>>),
+        (b"a", None, None),
+    ]) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Unsupported, got {:?}", other),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn key_filters_may_read_while_multi_cas_runs() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    for i in 0..10u8 {
+        t.insert(vec![i], vec![i])?;
+    }
+
+    // the filter waits for a multi_cas on another thread and
+    // then reads, which would deadlock if the scan held a lock
+    // that multi_cas has to wait for
+    let filter = |k: &[u8]| {
+        let writer = t.clone();
+        let k = k.to_vec();
+        thread::spawn(move || {
+            writer.multi_cas(vec![(
+                vec![255],
+                None as Option<Vec<u8>>,
+                Some(k),
+            )])
+        })
+        .join()
+        .unwrap()
+        .unwrap()
+        .ok();
+        t.get(vec![255]).unwrap().is_some()
+    };
+    assert_eq!(t.scan_filtered([], filter).count(), 11);
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_multi_cas_conserves_total() -> Result<()> {
+    tests::setup_logger();
+
+    const N_MOVERS: usize = 4;
+    const N_MOVES: usize = 50;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert(b"left", vec![100])?;
+    t.insert(b"right", vec![100])?;
+
+    let mut threads: Vec<thread::JoinHandle<Result<()>>> = vec![];
+    for i in 0..N_MOVERS {
+        let t = t.clone();
+        threads.push(thread::spawn(move || {
+            let (from, to): (&[u8], &[u8]) = if i % 2 == 0 {
+                (b"left", b"right")
+            } else {
+                (b"right", b"left")
+            };
+            let mut moved = 0;
+            while moved < N_MOVES {
+                let src = t.get(from)?.unwrap()[0];
+                let dst = t.get(to)?.unwrap()[0];
+                if src == 0 {
+                    continue;
+                }
+                let res = t.multi_cas(vec![
+                    (from, Some(vec![src]), Some(vec![src - 1])),
+                    (to, Some(vec![dst]), Some(vec![dst + 1])),
+                ])?;
+                if res.is_ok() {
+                    moved += 1;
+                }
+            }
+            Ok(())
+        }));
+    }
+
+    for thread in threads.into_iter() {
+        thread.join().expect("thread should not have crashed")?;
+    }
+
+    let left = t.get(b"left")?.unwrap()[0];
+    let right = t.get(b"right")?.unwrap()[0];
+    assert_eq!(left as usize + right as usize, 200);
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_get_or_insert_agrees() -> Result<()> {
+    tests::setup_logger();
+
+    const N_THREADS: usize = 16;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    let barrier = Arc::new(Barrier::new(N_THREADS));
+    let mut threads: Vec<thread::JoinHandle<Result<IVec>>> = vec![];
+    for i in 0..N_THREADS {
+        let t = t.clone();
+        let barrier = barrier.clone();
+        threads.push(thread::spawn(move || {
+            barrier.wait();
+            t.get_or_insert(b"k", vec![i as u8])
+        }));
+    }
+
+    let observed: Vec<IVec> = threads
+        .into_iter()
+        .map(|thread| thread.join().expect("thread should not have crashed"))
+        .collect::<Result<_>>()?;
+
+    let stored = t.get(b"k")?.unwrap();
+    assert!(observed.iter().all(|value| *value == stored));
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_put_if_absent_has_one_winner() -> Result<()> {
+    tests::setup_logger();
+
+    const N_THREADS: usize = 16;
+    const N_KEYS: u8 = 20;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    let barrier = Arc::new(Barrier::new(N_THREADS));
+    let mut threads: Vec<thread::JoinHandle<Result<Vec<bool>>>> = vec![];
+    for i in 0..N_THREADS {
+        let t = t.clone();
+        let barrier = barrier.clone();
+        threads.push(thread::spawn(move || {
+            barrier.wait();
+            (0..N_KEYS)
+                .map(|k| t.put_if_absent(vec![k], vec![i as u8]))
+                .collect()
+        }));
+    }
+
+    let won: Vec<Vec<bool>> = threads
+        .into_iter()
+        .map(|thread| thread.join().expect("thread should not have crashed"))
+        .collect::<Result<_>>()?;
+
+    for k in 0..N_KEYS {
+        let winners: Vec<usize> =
+            (0..N_THREADS).filter(|&i| won[i][k as usize]).collect();
+        assert_eq!(winners.len(), 1, "key {} had winners {:?}", k, winners);
+        assert_eq!(t.get(vec![k])?, Some(IVec::from(vec![winners[0] as u8])));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_get_or_load_misses_load_once() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    tests::setup_logger();
+
+    const N_THREADS: usize = 16;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+    let loads = Arc::new(AtomicUsize::new(0));
+
+    let barrier = Arc::new(Barrier::new(N_THREADS));
+    let threads: Vec<thread::JoinHandle<Result<Option<IVec>>>> = (0..N_THREADS)
+        .map(|_| {
+            let t = t.clone();
+            let loads = loads.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                t.get_or_load(b"k", || {
+                    loads.fetch_add(1, Ordering::SeqCst);
+                    // a slow backing store
+                    thread::sleep(Duration::from_millis(50));
+                    Some(vec![7])
+                })
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        let value = thread.join().expect("thread should not have crashed")?;
+        assert_eq!(value, Some(IVec::from(vec![7])));
+    }
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+    assert_eq!(t.get(b"k")?, Some(IVec::from(vec![7])));
+
+    // hits never call the loader
+    let value = t.get_or_load(b"k", || -> Option<Vec<u8>> {
+        panic!("loaded a present key")
+    })?;
+    assert_eq!(value, Some(IVec::from(vec![7])));
+
+    Ok(())
+}
+
+#[test]
+fn fold_range_matches_scan() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    for i in 0..1000_u64 {
+        t.insert(i.to_be_bytes(), (i * 3).to_be_bytes().to_vec())?;
+    }
+
+    let decode = |v: &[u8]| {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(v);
+        u64::from_be_bytes(buf)
+    };
+
+    let start: &[u8] = &100_u64.to_be_bytes();
+    let end: &[u8] = &900_u64.to_be_bytes();
+
+    let folded = t.fold_range(start..end, 0, |acc, _k, v| acc + decode(v))?;
+
+    let mut scanned = 0;
+    for res in t.range(start..end) {
+        let (_k, v) = res?;
+        scanned += decode(&v);
+    }
+
+    assert_eq!(folded, scanned);
+    assert_eq!(folded, (100..900).map(|i| i * 3).sum());
+
+    let empty = t.fold_range(end..start, 7, |acc, _k, v| acc + decode(v))?;
+    assert_eq!(empty, 7);
+
+    Ok(())
+}
+
+#[test]
+fn max_flush_delay_bounds_durability_latency() {
+    tests::setup_logger();
+
+    const MAX_FLUSH_DELAY_MS: u64 = 100;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .min_flush_interval_ms(Some(10))
+        .max_flush_delay_ms(Some(MAX_FLUSH_DELAY_MS))
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    // let any writes from startup drain first
+    while t.write_pressure() > 0. {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let before = Instant::now();
+    t.insert(b"k", vec![1]).unwrap();
+    assert!(t.write_pressure() > 0.);
+
+    while t.write_pressure() > 0. {
+        assert!(
+            before.elapsed() < Duration::from_secs(5),
+            "write was never flushed"
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // leave some slack for scheduling delays on busy machines
+    let elapsed = before.elapsed();
+    assert!(
+        elapsed < Duration::from_millis(MAX_FLUSH_DELAY_MS * 3),
+        "write took {:?} to become durable",
+        elapsed
+    );
+}
+
+#[test]
+fn flush_on_idle_flushes_pauses_and_batches_bursts() {
+    tests::setup_logger();
+
+    const FLUSH_EVERY_MS: u64 = 4000;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(Some(FLUSH_EVERY_MS))
+        .min_flush_interval_ms(None)
+        .flush_on_idle(true)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    // let any writes from startup drain first
+    while t.write_pressure() > 0. {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // a lone write is flushed once the flusher sees no
+    // more writes arriving, well before flush_every_ms
+    let before = Instant::now();
+    t.insert(b"k", vec![1]).unwrap();
+    while t.write_pressure() > 0. {
+        assert!(
+            before.elapsed() < Duration::from_millis(FLUSH_EVERY_MS / 2),
+            "idle write waited for the scheduled flush"
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // a steady stream of writes is batched instead of
+    // being flushed one write at a time
+    let flushes_before = t.flushes();
+    let before = Instant::now();
+    let mut writes = 0_u64;
+    while before.elapsed() < Duration::from_millis(500) {
+        t.insert(writes.to_be_bytes(), vec![0]).unwrap();
+        writes += 1;
+    }
+    let flushes = t.flushes() - flushes_before;
+    assert!(
+        flushes < writes / 10,
+        "{} flushes for {} writes",
+        flushes,
+        writes
+    );
+}
+
+#[test]
+fn group_commit_shares_log_writes_between_flushes() {
+    tests::setup_logger();
+
+    const N_WRITERS: usize = 16;
+    const N_WRITES: usize = 50;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .group_commit_window_us(10_000)
+        .build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    let barrier = Arc::new(Barrier::new(N_WRITERS));
+    let log_writes_before = t.log_writes();
+    let writers: Vec<_> = (0..N_WRITERS)
+        .map(|w| {
+            let t = t.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for i in 0..N_WRITES {
+                    t.insert(vec![w as u8, i as u8], vec![0]).unwrap();
+                    t.flush().unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let writes = N_WRITERS * N_WRITES;
+    let log_writes = (t.log_writes() - log_writes_before) as usize;
+    assert!(
+        log_writes < writes / 4,
+        "{} log writes for {} durable writes",
+        log_writes,
+        writes
+    );
+    assert_eq!(t.len(), writes);
+}
+
+#[test]
+fn audit_log_retains_every_mutation() -> Result<()> {
+    tests::setup_logger();
+
+    let path = "test_audit_log_retains_every_mutation.log";
+    let _ = std::fs::remove_file(path);
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .audit_log(Some(path.into()))
+        .build();
+    let t = sled::Db::start(config)?;
+    let other = t.open_tree(b"other")?;
+
+    t.insert(b"k", vec![1])?;
+    t.insert(b"k", vec![2])?;
+    other.insert(b"k", vec![9])?;
+    t.cas(b"k", Some(vec![2]), Some(vec![3]))?.unwrap();
+    t.remove(b"k")?;
+    t.insert(b"k", vec![4])?;
+
+    // old versions are gone from the tree itself
+    assert_eq!(t.get(b"k")?, Some(IVec::from(vec![4])));
+
+    let entries: Vec<_> = t.audit_entries_since(0)?.collect();
+    let k = IVec::from(b"k");
+    assert_eq!(
+        entries.iter().map(|e| e.op.clone()).collect::<Vec<_>>(),
+        vec![
+            LogOp::Set(k.clone(), vec![1].into()),
+            LogOp::Set(k.clone(), vec![2].into()),
+            LogOp::Set(k.clone(), vec![3].into()),
+            LogOp::Del(k.clone()),
+            LogOp::Set(k.clone(), vec![4].into()),
+        ]
+    );
+    assert!(entries.windows(2).all(|w| w[0].lsn < w[1].lsn));
+
+    let since: Vec<_> = t.audit_entries_since(entries[2].lsn)?.collect();
+    assert_eq!(&since[..], &entries[3..]);
+
+    let other_entries: Vec<_> = other.audit_entries_since(0)?.collect();
+    assert_eq!(other_entries.len(), 1);
+    assert_eq!(other_entries[0].op, LogOp::Set(k, vec![9].into()));
+
+    drop(other);
+    drop(t);
+    std::fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn audit_log_truncates_a_torn_tail() -> Result<()> {
+    use std::io::Write;
+
+    tests::setup_logger();
+
+    let path = "test_audit_log_truncates_a_torn_tail.log";
+    let _ = std::fs::remove_file(path);
+    let config = || {
+        ConfigBuilder::new()
+            .temporary(true)
+            .audit_log(Some(path.into()))
+            .build()
+    };
+
+    let t = sled::Db::start(config())?;
+    t.insert(b"a", vec![1])?;
+    drop(t);
+    let len = std::fs::metadata(path)?.len();
+
+    // a crash while appending leaves part of an entry
+    let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+    file.write_all(&64_u64.to_le_bytes())?;
+    file.write_all(&[7; 10])?;
+    drop(file);
+
+    let t = sled::Db::start(config())?;
+    assert_eq!(std::fs::metadata(path)?.len(), len);
+
+    // entries appended after the torn one are readable
+    t.insert(b"b", vec![2])?;
+    let ops: Vec<_> = t.audit_entries_since(0)?.map(|e| e.op).collect();
+    assert_eq!(
+        ops,
+        vec![
+            LogOp::Set(IVec::from(b"a"), vec![1].into()),
+            LogOp::Set(IVec::from(b"b"), vec![2].into()),
+        ]
+    );
+
+    drop(t);
+    std::fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn changes_since_reports_net_changes() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .replication(true)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert(b"a", vec![0])?;
+    t.insert(b"b", vec![0])?;
+    t.insert(b"c", vec![0])?;
+
+    let lsn = t.log_entries_since(0)?.last().unwrap().lsn;
+
+    t.insert(b"a", vec![1])?;
+    t.insert(b"a", vec![2])?;
+    t.remove(b"b")?;
+    t.insert(b"d", vec![3])?;
+    t.insert(b"e", vec![4])?;
+    t.remove(b"e")?;
+
+    let changes: Vec<(IVec, Option<IVec>)> =
+        t.changes_since(lsn).collect::<Result<_>>()?;
+
+    assert_eq!(
+        changes,
+        vec![
+            (b"a".into(), Some(vec![2].into())),
+            (b"b".into(), None),
+            (b"d".into(), Some(vec![3].into())),
+            (b"e".into(), None),
+        ]
+    );
+    drop(t);
+
+    let expect_unsupported = |t: &sled::Db, lsn| match t
+        .changes_since(lsn)
+        .collect::<Result<Vec<_>>>(
+    ) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Unsupported, got {:?}", other),
+    };
+
+    fn concatenate(
+        _key: &[u8],
+        old: Option<&[u8]>,
+        new: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut ret = old.map(<[u8]>::to_vec).unwrap_or_default();
+        ret.extend_from_slice(new);
+        Some(ret)
+    }
+
+    // changes are read back out of the log, so writes from
+    // before a restart are included, and merges are reported
+    // as the values that they produced
+    let dir = "/tmp/test_changes_since_reports_net_changes";
+    let _ = std::fs::remove_dir_all(dir);
+    let open = |replication| {
+        let config = ConfigBuilder::new()
+            .path(dir)
+            .replication(replication)
+            .build();
+        let t = sled::Db::start(config)?;
+        t.set_merge_operator(concatenate);
+        Ok::<_, Error>(t)
+    };
+
+    let t = open(true)?;
+    t.insert(b"a", vec![1])?;
+    t.merge(b"m", vec![1])?;
+    drop(t);
+
+    let t = open(true)?;
+    t.merge(b"m", vec![2])?;
+    t.remove(b"a")?;
+    let changes: Vec<(IVec, Option<IVec>)> =
+        t.changes_since(-1).collect::<Result<_>>()?;
+    assert_eq!(
+        changes,
+        vec![(b"a".into(), None), (b"m".into(), Some(vec![1, 2].into()))]
+    );
+    let last = t.generation() as i64;
+    assert_eq!(t.changes_since(last).count(), 0);
+    drop(t);
+
+    // writes made without replication can't be read
+    // back, so changes must not be reported as empty
+    let t = open(false)?;
+    t.insert(b"b", vec![2])?;
+    expect_unsupported(&t, last);
+    drop(t);
+
+    let t = open(true)?;
+    expect_unsupported(&t, last);
+    drop(t);
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn reserved_tree_cache_survives_scans() -> Result<()> {
+    tests::setup_logger();
+
+    const N_SMALL: u64 = 32;
+    const N_BIG: u64 = 1_000;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .cache_capacity(256 * 64)
+        .build();
+
+    let db = sled::Db::start(config.clone())?;
+    for name in &["hot", "cold"] {
+        let tree = db.open_tree(name)?;
+        for i in 0..N_SMALL {
+            tree.insert(i.to_be_bytes(), vec![0; 8])?;
+        }
+    }
+    let big = db.open_tree("big")?;
+    for i in 0..N_BIG {
+        big.insert(i.to_be_bytes(), vec![0; 100])?;
+    }
+    drop(big);
+    drop(db);
+
+    // restart so that every page is read in from disk
+    let db = sled::Db::start(config)?;
+    let hot = db.open_tree_with_cache_capacity("hot", 1 << 20)?;
+    let cold = db.open_tree("cold")?;
+    let big = db.open_tree("big")?;
+
+    let read_all = |tree: &Tree| -> Result<()> {
+        for i in 0..N_SMALL {
+            assert!(tree.get(i.to_be_bytes())?.is_some());
+        }
+        Ok(())
+    };
+
+    read_all(&hot)?;
+    read_all(&cold)?;
+
+    for _ in 0..2 {
+        assert_eq!(big.iter().count() as u64, N_BIG);
+    }
+
+    let before = db.page_ins();
+    read_all(&hot)?;
+    assert_eq!(db.page_ins(), before, "the reserved tree was paged out");
+
+    // without a reservation, the scans evict the other small tree
+    read_all(&cold)?;
+    assert!(db.page_ins() > before);
+
+    Ok(())
+}
+
+#[test]
+fn scan_prefix_rev_is_descending_and_bounded() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    for day in 0..200_u8 {
+        t.insert(format!("events/2023/{:03}", day), vec![day])?;
+        t.insert(format!("events/2024/{:03}", day), vec![day])?;
+        t.insert(format!("events/2025/{:03}", day), vec![day])?;
+    }
+
+    // neighbors just outside of the prefix on either side
+    t.insert(b"events/2024", vec![])?;
+    t.insert(b"events/2024.", vec![])?;
+    t.insert(b"events/20240", vec![])?;
+
+    let keys: Vec<IVec> = t
+        .scan_prefix_rev(b"events/2024/")
+        .map(|res| res.map(|(k, _v)| k))
+        .collect::<Result<_>>()?;
+
+    let expected: Vec<IVec> = (0..200)
+        .rev()
+        .map(|day| format!("events/2024/{:03}", day).into_bytes().into())
+        .collect();
+    assert_eq!(keys, expected);
+
+    let latest: Vec<IVec> = t
+        .scan_prefix_rev(b"events/2024/")
+        .take(3)
+        .map(|res| res.map(|(_k, v)| v))
+        .collect::<Result<_>>()?;
+    assert_eq!(
+        latest,
+        vec![
+            IVec::from(vec![199]),
+            IVec::from(vec![198]),
+            IVec::from(vec![197])
+        ]
+    );
+
+    assert_eq!(t.scan_prefix_rev(b"events/2026/").count(), 0);
+
+    // a prefix without a successor is only bounded below
+    t.insert([0xff], vec![0])?;
+    t.insert([0xff, 0xff], vec![1])?;
+    let mut r = t.scan_prefix_rev([0xff]);
+    assert_eq!(
+        r.next(),
+        Some(Ok((IVec::from(&[0xff, 0xff]), IVec::from(&[1]))))
+    );
+    assert_eq!(r.next(), Some(Ok((IVec::from(&[0xff]), IVec::from(&[0])))));
+    assert_eq!(r.next(), None);
+
+    Ok(())
+}
+
+#[test]
+fn tree_from_provided_file() {
+    let dir = "/tmp/test_tree_from_provided_file";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .open(format!("{}/provided", dir))
+        .unwrap();
+
+    let config = ConfigBuilder::new()
+        .path(dir)
+        .try_build_with_file(file.try_clone().unwrap())
+        .unwrap();
+    let t = sled::Db::start(config).unwrap();
+    t.insert([1], vec![1]).unwrap();
+    assert_eq!(t.get([1]).unwrap(), Some(IVec::from(vec![1])));
+    t.flush().unwrap();
+    drop(t);
+
+    assert!(
+        std::fs::metadata(format!("{}/provided", dir))
+            .unwrap()
+            .len()
+            > 0
+    );
+    assert!(!std::path::Path::new(&format!("{}/db", dir)).exists());
+
+    let config = ConfigBuilder::new()
+        .path(dir)
+        .try_build_with_file(file)
+        .unwrap();
+    let t = sled::Db::start(config).unwrap();
+    assert_eq!(t.get([1]).unwrap(), Some(IVec::from(vec![1])));
+    drop(t);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn separate_values_keeps_the_log_small() -> Result<()> {
+    tests::setup_logger();
+
+    const N: usize = 200;
+
+    let dir = "/tmp/test_separate_values_keeps_the_log_small";
+
+    let config = |separate_values| {
+        ConfigBuilder::new()
+            .path(dir)
+            .io_buf_size(1 << 16)
+            .separate_values(separate_values)
+            .build()
+    };
+    let log_size = || std::fs::metadata(format!("{}/db", dir)).unwrap().len();
+    let blobs = || {
+        std::fs::read_dir(format!("{}/blobs", dir))
+            .map(|dir| dir.count())
+            .unwrap_or(0)
+    };
+    let value = |i: usize| vec![i as u8; 4096];
+
+    let mut log_sizes = vec![];
+    for &separate_values in &[false, true] {
+        let _ = std::fs::remove_dir_all(dir);
+        let t = sled::Db::start(config(separate_values))?;
+        for i in 0..N {
+            t.insert((i as u64).to_be_bytes(), value(i))?;
+        }
+        t.flush()?;
+        drop(t);
+        log_sizes.push(log_size());
+    }
+
+    // values this small are normally stored inline
+    assert!(
+        log_sizes[1] * 4 < log_sizes[0],
+        "separated log is {} bytes, inline log is {} bytes",
+        log_sizes[1],
+        log_sizes[0]
+    );
+    assert!(blobs() >= N);
+
+    // scanning keys after a restart doesn't read values
+    let t = sled::Db::start(config(true))?;
+    assert_eq!(t.iter().keys().count(), N);
+    let key_scan_page_ins = t.page_ins();
+    drop(t);
+
+    let t = sled::Db::start(config(true))?;
+    for (i, item) in t.iter().enumerate() {
+        let (k, v) = item?;
+        assert_eq!(k, IVec::from(&(i as u64).to_be_bytes()));
+        assert_eq!(v, IVec::from(value(i)));
+    }
+    let full_scan_page_ins = t.page_ins();
+    assert!(
+        full_scan_page_ins >= key_scan_page_ins + N as u64,
+        "scanning keys paged in {} pages, and values {}",
+        key_scan_page_ins,
+        full_scan_page_ins
+    );
+    assert_eq!(t.get(5_u64.to_be_bytes())?, Some(IVec::from(value(5))));
+
+    // removing values frees their blobs
+    for i in 0..N {
+        t.remove((i as u64).to_be_bytes())?;
+    }
+    // blobs are removed once the segment that recorded
+    // their removal is complete, so fill it without
+    // writing any new values
+    for _ in 0..5000 {
+        t.remove(b"missing")?;
+    }
+    t.flush()?;
+
+    let before = Instant::now();
+    while blobs() >= N / 2 {
+        assert!(
+            before.elapsed() < Duration::from_secs(5),
+            "{} blobs remain",
+            blobs()
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(t);
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn scan_lazy_reads_values_on_load() -> Result<()> {
+    tests::setup_logger();
+
+    const N: usize = 100;
+
+    let dir = "/tmp/test_scan_lazy_reads_values_on_load";
+    let _ = std::fs::remove_dir_all(dir);
+
+    let config =
+        || ConfigBuilder::new().path(dir).separate_values(true).build();
+    let value = |i: usize| IVec::from(vec![i as u8; 4096]);
+
+    let t = sled::Db::start(config())?;
+    for i in 0..N {
+        t.insert((i as u64).to_be_bytes(), value(i))?;
+    }
+    drop(t);
+
+    // after a restart nothing is cached, so every
+    // value read is a page in
+    let t = sled::Db::start(config())?;
+    let items = t.scan_lazy([]).collect::<Result<Vec<_>>>()?;
+    assert_eq!(items.len(), N);
+    let scan_page_ins = t.page_ins();
+    assert!(
+        scan_page_ins < N as u64,
+        "scanning keys paged in {} pages",
+        scan_page_ins
+    );
+
+    let (ref key, ref lazy) = items[7];
+    assert_eq!(key, &IVec::from(&7_u64.to_be_bytes()));
+    assert_eq!(lazy.load()?, Some(value(7)));
+    assert!(t.page_ins() > scan_page_ins);
+
+    // values are looked up again after they change
+    t.remove(key)?;
+    assert_eq!(lazy.load()?, None);
+    t.insert(key, vec![1])?;
+    assert_eq!(lazy.load()?, Some(IVec::from(vec![1])));
+
+    let last = t.scan_lazy([]).next_back().unwrap()?;
+    assert_eq!(last.1.load()?, Some(value(N - 1)));
+
+    drop(items);
+    drop(t);
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn blob_reads_keep_at_most_max_blob_fds_open() -> Result<()> {
+    tests::setup_logger();
+
+    const N: usize = 200;
+    const MAX_FDS: usize = 4;
+
+    let dir = "/tmp/test_blob_reads_keep_at_most_max_blob_fds_open";
+    let _ = std::fs::remove_dir_all(dir);
+
+    let config = || {
+        ConfigBuilder::new()
+            .path(dir)
+            .separate_values(true)
+            .max_blob_fds(MAX_FDS)
+            .build()
+    };
+    let value = |i: usize| IVec::from(vec![i as u8; 1024]);
+
+    let open_blobs = || {
+        let blobs = std::path::Path::new(dir).join("blobs");
+        std::fs::read_dir("/proc/self/fd")
+            .unwrap()
+            .filter_map(|fd| std::fs::read_link(fd.unwrap().path()).ok())
+            .filter(|target| target.starts_with(&blobs))
+            .count()
+    };
+
+    let t = sled::Db::start(config())?;
+    for i in 0..N {
+        t.insert((i as u64).to_be_bytes(), value(i))?;
+    }
+    drop(t);
+
+    // after a restart every value read opens its blob
+    let t = sled::Db::start(config())?;
+    let mut most_open = 0;
+    for _ in 0..2 {
+        for i in 0..N {
+            assert_eq!(t.get((i as u64).to_be_bytes())?, Some(value(i)));
+            most_open = std::cmp::max(most_open, open_blobs());
+        }
+    }
+    assert!(
+        most_open > 0 && most_open <= MAX_FDS,
+        "up to {} blob files were open",
+        most_open
+    );
+
+    drop(t);
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn iter_next_latency_stays_flat_under_writes() -> Result<()> {
+    tests::setup_logger();
+
+    const N: u64 = 2000;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    for i in 0..N {
+        t.insert(i.to_be_bytes(), vec![0; 16])?;
+    }
+
+    // keep writing to keys outside of the scanned range,
+    // so that only the first leaves see concurrent writes
+    let writing = Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let writer = {
+        let t = t.clone();
+        let writing = writing.clone();
+        thread::spawn(move || -> Result<()> {
+            let mut i = N;
+            while writing.load(std::sync::atomic::Ordering::SeqCst) {
+                t.insert((N + i % 1000).to_be_bytes(), vec![1; 16])?;
+                i += 1;
+            }
+            Ok(())
+        })
+    };
+
+    let mut latencies = Vec::with_capacity(N as usize);
+    let mut iter = t.range(0_u64.to_be_bytes()..N.to_be_bytes());
+    loop {
+        let before = Instant::now();
+        let item = iter.next();
+        latencies.push(before.elapsed());
+        if item.transpose()?.is_none() {
+            break;
+        }
+    }
+    writing.store(false, std::sync::atomic::Ordering::SeqCst);
+    writer.join().unwrap()?;
+
+    assert_eq!(latencies.len() as u64, N + 1);
+    latencies.sort();
+    let p99 = latencies[latencies.len() * 99 / 100];
+    let p50 = latencies[latencies.len() / 2];
+
+    // resuming from the cursor keeps a call from having to
+    // search down from the root, so the slowest calls stay
+    // close to the typical one
+    assert!(
+        p99 < std::cmp::max(p50 * 50, Duration::from_millis(5)),
+        "p99 next latency of {:?} with a median of {:?}",
+        p99,
+        p50
+    );
+
+    Ok(())
+}
+
+#[test]
+fn iter_resumes_after_writes_between_calls() -> Result<()> {
+    tests::setup_logger();
+
+    const N: u64 = 500;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+    let t = sled::Db::start(config)?;
+
+    for i in 0..N {
+        t.insert(i.to_be_bytes(), i.to_be_bytes().to_vec())?;
+    }
+
+    // consume from both ends, sometimes removing what was just
+    // returned so that the leaf under the cursor changes
+    let resumes_before = t.iter_resumes();
+    let mut calls = 0;
+    let mut iter = t.iter();
+    let (mut front, mut back) = (0, N);
+    while front < back {
+        calls += 1;
+        let (k, v) = if front % 3 == 2 {
+            back -= 1;
+            let item = iter.next_back().unwrap()?;
+            assert_eq!(item.0, IVec::from(&back.to_be_bytes()));
+            item
+        } else {
+            let item = iter.next().unwrap()?;
+            assert_eq!(item.0, IVec::from(&front.to_be_bytes()));
+            front += 1;
+            item
+        };
+        assert_eq!(k, v);
+        if front % 2 == 0 {
+            t.remove(&k)?;
+        }
+    }
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+    drop(iter);
+
+    // calls that follow a removal, or change direction,
+    // search from the root, but the rest resume
+    let resumed = t.iter_resumes() - resumes_before;
+    assert!(resumed > 0);
+    assert!(resumed < calls);
+
+    // without writes in between, every call after the
+    // first resumes from the cursor
+    let resumes_before = t.iter_resumes();
+    let keys: Vec<IVec> = t.iter().keys().collect::<Result<_>>()?;
+    assert_eq!(t.iter_resumes() - resumes_before, keys.len() as u64);
+
+    let resumes_before = t.iter_resumes();
+    let rev_keys: Vec<IVec> = t.iter().keys().rev().collect::<Result<_>>()?;
+    assert_eq!(t.iter_resumes() - resumes_before, rev_keys.len() as u64);
+
+    assert_eq!(keys.len(), t.len());
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(keys, rev_keys.into_iter().rev().collect::<Vec<_>>());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn full_disk_returns_out_of_space() -> Result<()> {
+    tests::setup_logger();
+
+    let dir = "/tmp/test_full_disk_returns_out_of_space";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir)?;
+
+    // every write to /dev/full fails with ENOSPC
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/full")?;
+
+    let config = ConfigBuilder::new()
+        .path(dir)
+        .flush_every_ms(None)
+        .reserve_space(4096)
+        .try_build_with_file(file)?;
+    let reserve = std::path::Path::new(dir).join("reserve");
+    assert_eq!(std::fs::metadata(&reserve)?.len(), 4096);
+
+    let t = sled::Db::start(config)?;
+
+    // fill the store, keeping track of the writes
+    // that were accepted before it ran out of space
+    let mut inserted = 0_u64;
+    let err = loop {
+        let res = t.insert(inserted.to_be_bytes(), vec![0; 1024]);
+        if let Err(e) = res {
+            break e;
+        }
+        inserted += 1;
+        if let Err(e) = t.flush() {
+            break e;
+        }
+    };
+    assert_eq!(err, Error::OutOfSpace);
+
+    // writes keep failing cleanly instead of panicking
+    assert_eq!(t.insert(b"more", vec![1]), Err(Error::OutOfSpace));
+    assert_eq!(t.flush(), Err(Error::OutOfSpace));
+
+    // the writes that were accepted can still be read
+    for i in 0..inserted {
+        assert_eq!(t.get(i.to_be_bytes())?, Some(IVec::from(vec![0; 1024])));
+    }
+
+    // the reserve was released to make room
+    assert!(!reserve.exists());
+    let released = std::path::Path::new(dir).join("reserve.released");
+    assert!(released.exists());
+
+    drop(t);
+
+    // after a restart on a device with room, the reserve is
+    // not taken back before writes succeed again
+    let reopen = || {
+        ConfigBuilder::new()
+            .path(dir)
+            .flush_every_ms(None)
+            .reserve_space(4096)
+            .build()
+    };
+    let t = sled::Db::start(reopen())?;
+    assert!(!reserve.exists());
+
+    t.insert(b"recovered", vec![2])?;
+    t.flush()?;
+    assert!(!released.exists());
+    drop(t);
+
+    // and once they have, it is set aside again
+    let t = sled::Db::start(reopen())?;
+    assert_eq!(std::fs::metadata(&reserve)?.len(), 4096);
+    assert_eq!(t.get(b"recovered")?, Some(IVec::from(vec![2])));
+    t.insert(b"more", vec![3])?;
+    t.flush()?;
+
+    drop(t);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn insert_swaps_atomically() {
+    tests::setup_logger();
+
+    const N_THREADS: u64 = 8;
+    const N_PER_THREAD: u64 = 500;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    assert_eq!(t.insert(b"absent", vec![1]).unwrap(), None);
+    assert_eq!(
+        t.insert(b"absent", vec![2]).unwrap(),
+        Some(IVec::from(vec![1]))
+    );
+
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|tn| {
+            let t = t.clone();
+            thread::spawn(move || {
+                let mut predecessors = vec![];
+                for i in 0..N_PER_THREAD {
+                    let value = (tn * N_PER_THREAD + i).to_be_bytes().to_vec();
+                    predecessors.push(t.insert(b"slot", value).unwrap());
+                }
+                predecessors
+            })
+        })
+        .collect();
+
+    let mut predecessors: Vec<Option<IVec>> = threads
+        .into_iter()
+        .flat_map(|thread| thread.join().unwrap())
+        .collect();
+    predecessors.push(t.get(b"slot").unwrap());
+
+    // every value written is replaced by exactly one other
+    // write, except for the one that remains at the end.
+    let mut seen = std::collections::HashSet::new();
+    for predecessor in &predecessors {
+        assert!(
+            seen.insert(predecessor.clone()),
+            "{:?} was seen twice",
+            predecessor
+        );
+    }
+    assert!(seen.contains(&None));
+    for v in 0..N_THREADS * N_PER_THREAD {
+        assert!(seen.contains(&Some(IVec::from(v.to_be_bytes().to_vec()))));
+    }
+}
+
+#[test]
+fn insert_returns_the_replaced_value_however_it_is_stored() -> Result<()> {
+    tests::setup_logger();
+
+    // larger than the inline limit of this io_buf_size
+    let big = |b: u8| IVec::from(vec![b; 1 << 14]);
+
+    for &separate_values in &[false, true] {
+        let config = ConfigBuilder::new()
+            .temporary(true)
+            .io_buf_size(1 << 16)
+            .separate_values(separate_values)
+            .build();
+        let t = sled::Db::start(config)?;
+
+        assert_eq!(t.insert(b"k", vec![1])?, None);
+        assert_eq!(t.insert(b"k", big(2))?, Some(IVec::from(vec![1])));
+        assert_eq!(t.insert(b"k", big(3))?, Some(big(2)));
+        assert_eq!(t.insert(b"k", vec![4])?, Some(big(3)));
+
+        // a removed key is created anew
+        assert_eq!(t.remove(b"k")?, Some(IVec::from(vec![4])));
+        assert_eq!(t.insert(b"k", vec![5])?, None);
+
+        // batches don't report predecessors, but the
+        // next insert sees their writes
+        let mut batch = t.batch();
+        batch.insert(b"k".to_vec(), vec![6]);
+        batch.apply()?;
+        assert_eq!(t.insert(b"k", vec![7])?, Some(IVec::from(vec![6])));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn value_checksums_detect_damaged_values() -> Result<()> {
+    tests::setup_logger();
+
+    for &separate_values in &[false, true] {
+        let dir = "/tmp/test_value_checksums_detect_damaged_values";
+        let _ = std::fs::remove_dir_all(dir);
+
+        let config = |value_checksums| {
+            ConfigBuilder::new()
+                .path(dir)
+                .separate_values(separate_values)
+                .value_checksums(value_checksums)
+                .try_build()
+        };
+        let is_corruption = |res: Result<Option<IVec>>| {
+            matches!(res, Err(Error::Corruption { .. }))
+        };
+
+        let t = sled::Db::start(config(true)?)?;
+        for i in 0..10_u8 {
+            t.insert(vec![i], vec![i; 10])?;
+        }
+        t.insert(b"empty", vec![])?;
+
+        // checksums are invisible until something is damaged
+        assert_eq!(t.get(vec![3])?, Some(IVec::from(vec![3; 10])));
+        assert_eq!(t.get(b"empty")?, Some(IVec::from(vec![])));
+        assert_eq!(t.get_meta(vec![3])?.unwrap().len, 10);
+        assert_eq!(t.iter().values().next(), Some(Ok(IVec::from(vec![0; 10]))));
+
+        t.corrupt_value(vec![3])?;
+        t.corrupt_value(b"empty")?;
+        assert!(is_corruption(t.get(vec![3])));
+        assert!(is_corruption(t.get(b"empty")));
+        assert!(is_corruption(
+            t.get_meta(vec![3]).map(|m| m.map(|m| m.value))
+        ));
+        assert!(t.cas(vec![3], Some(vec![3; 10]), Some(vec![0])).is_err());
+
+        // scans report the damaged value and carry on
+        let items: Vec<_> = t.range(vec![2]..vec![5]).collect();
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_ok() && items[1].is_err() && items[2].is_ok());
+        assert_eq!(t.range(vec![2]..vec![5]).keys().count(), 3);
+
+        // replacing a damaged value reports it, but still writes
+        assert!(is_corruption(t.insert(vec![3], vec![33])));
+        assert_eq!(t.get(vec![3])?, Some(IVec::from(vec![33])));
+
+        // damage is detected after recovery too
+        t.corrupt_value(vec![4])?;
+        drop(t);
+        let t = sled::Db::start(config(true)?)?;
+        assert!(is_corruption(t.get(vec![4])));
+        assert!(is_corruption(t.remove(vec![4])));
+        assert_eq!(t.get(vec![4])?, None);
+        assert_eq!(t.get(vec![5])?, Some(IVec::from(vec![5; 10])));
+        drop(t);
+
+        // the setting can't change across restarts
+        match config(false) {
+            Err(Error::Unsupported(_)) => {}
+            other => panic!("expected Error::Unsupported, got {:?}", other),
+        }
+
+        std::fs::remove_dir_all(dir)?;
+    }
+
+    // without checksums, the damage goes unnoticed
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+    t.insert(b"k", vec![2])?;
+    t.corrupt_value(b"k")?;
+    assert_eq!(t.get(b"k")?, Some(IVec::from(vec![3])));
+
+    Ok(())
+}
+
+#[test]
+fn try_scan_stops_at_the_first_error() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .value_checksums(true)
+        .build();
+    let t = sled::Db::start(config)?;
+    for i in 0..10_u8 {
+        t.insert(vec![i], vec![i])?;
+    }
+
+    // returning false pauses the scan, which resumes
+    // after the last record that was seen
+    let mut seen = vec![];
+    let mut scan = t.try_scan([]);
+    scan.for_each_ok(|k, _v| {
+        seen.push(k[0]);
+        seen.len() < 2
+    })?;
+    assert_eq!(seen, vec![0, 1]);
+
+    t.corrupt_value(vec![5])?;
+    match scan.for_each_ok(|k, _v| {
+        seen.push(k[0]);
+        true
+    }) {
+        Err(Error::Corruption { .. }) => {}
+        other => panic!("expected Corruption, got {:?}", other),
+    }
+    assert_eq!(seen, vec![0, 1, 2, 3, 4]);
+
+    // the error is only returned once
+    assert_eq!(
+        scan.for_each_ok(|_k, _v| panic!("scan was resumed")),
+        Ok(())
+    );
+
+    Ok(())
+}
+#[test]
+fn prune_snapshots_keeps_the_newest() -> Result<()> {
+    tests::setup_logger();
+
+    let dir = "/tmp/test_prune_snapshots_keeps_the_newest";
+    let _ = std::fs::remove_dir_all(dir);
+
+    let config = || {
+        ConfigBuilder::new()
+            .path(dir)
+            .async_io(false)
+            .flush_every_ms(None)
+            .snapshot_after_ops(10)
+            .build()
+    };
+
+    let t = sled::Db::start(config())?;
+    for i in 0..100_u8 {
+        t.insert([i], vec![i])?;
+    }
+    t.flush()?;
+
+    let snapshots = t.snapshots()?;
+    assert_eq!(snapshots.len(), 1);
+    let newest = snapshots[0].clone();
+    assert!(newest.size > 0);
+
+    // leave behind older snapshots, as happens
+    // when removing them after a new one fails
+    for lsn in &[1_i64, 2, 3] {
+        let path = newest.path.with_file_name(format!("snap.{:016X}", lsn));
+        std::fs::copy(&newest.path, path)?;
+    }
+
+    let snapshots = t.snapshots()?;
+    let lsns: Vec<i64> = snapshots.iter().map(|s| s.lsn).collect();
+    assert_eq!(lsns, vec![1, 2, 3, newest.lsn]);
+
+    assert!(t.prune_snapshots(0).is_err());
+    assert_eq!(t.prune_snapshots(3)?, 1);
+    assert_eq!(t.prune_snapshots(1)?, 2);
+    assert_eq!(t.snapshots()?, vec![newest]);
+    assert_eq!(t.prune_snapshots(1)?, 0);
+    drop(t);
+
+    let t = sled::Db::start(config())?;
+    assert_eq!(t.len(), 100);
+    for i in 0..100_u8 {
+        assert_eq!(t.get([i])?, Some(IVec::from(vec![i])));
+    }
+    drop(t);
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn recovery_mode_detects_and_repairs_truncated_log() {
+    tests::setup_logger();
+
+    let dir = "/tmp/test_recovery_mode";
+    let _ = std::fs::remove_dir_all(dir);
+
+    let config = |mode| {
+        ConfigBuilder::new()
+            .path(dir)
+            .async_io(false)
+            .flush_every_ms(None)
+            .snapshot_after_ops(10)
+            .recovery_mode(mode)
+            .build()
+    };
+
+    let t = sled::Db::start(config(RecoveryMode::Fast)).unwrap();
+    for i in 0..100_u8 {
+        t.insert([i], vec![i]).unwrap();
+    }
+    t.flush().unwrap();
+    drop(t);
+
+    // an intact database passes verification
+    let t = sled::Db::start(config(RecoveryMode::Verify)).unwrap();
+    assert_eq!(t.len(), 100);
+    drop(t);
+
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(format!("{}/db", dir))
+        .unwrap();
+    let len = file.metadata().unwrap().len();
+    // drop the last few kb of writes, which the
+    // latest snapshot still refers to
+    file.set_len(len - 4096).unwrap();
+    drop(file);
+
+    match sled::Db::start(config(RecoveryMode::Verify)) {
+        Err(Error::Corruption { .. }) => {}
+        other => {
+            panic!("expected verification to fail, got {:?}", other.map(|_| ()))
+        }
+    }
+
+    let t = sled::Db::start(config(RecoveryMode::Repair)).unwrap();
+    let n = t.len();
+    assert!(n > 0 && n < 100);
+    for res in t.iter() {
+        let (k, v) = res.unwrap();
+        assert_eq!(k, v);
+    }
+    drop(t);
+
+    // the repaired database is consistent with its log again
+    let t = sled::Db::start(config(RecoveryMode::Verify)).unwrap();
+    assert_eq!(t.len(), n);
+    drop(t);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn scan_prefix_keys_lists_children() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    for i in 0..50 {
+        t.insert(format!("a/{:02}", i), vec![1; 8 * 1024])?;
+        t.insert(format!("b/{:02}", i), vec![2; 8 * 1024])?;
+    }
+    t.insert(b"a", vec![0])?;
+    t.insert(b"a0", vec![0])?;
+
+    let keys: Vec<IVec> = t.scan_prefix_keys(b"b/").collect::<Result<_>>()?;
+    let expected: Vec<IVec> = (0..50)
+        .map(|i| format!("b/{:02}", i).into_bytes().into())
+        .collect();
+    assert_eq!(keys, expected);
+
+    let rev: Vec<IVec> =
+        t.scan_prefix_keys(b"a/").rev().collect::<Result<_>>()?;
+    let expected: Vec<IVec> = (0..50)
+        .rev()
+        .map(|i| format!("a/{:02}", i).into_bytes().into())
+        .collect();
+    assert_eq!(rev, expected);
+
+    assert_eq!(t.scan_prefix_keys(b"c/").count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn bulk_delete_removes_listed_keys() -> Result<()> {
+    tests::setup_logger();
+
+    const N: u64 = 500;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    for i in 0..N {
+        t.insert(i.to_be_bytes(), vec![1])?;
+    }
+
+    // every third key, listed out of order and with
+    // duplicates and keys that were never written
+    let mut keys: Vec<Vec<u8>> = (0..N * 2)
+        .rev()
+        .filter(|i| i % 3 == 0)
+        .map(|i| i.to_be_bytes().to_vec())
+        .collect();
+    keys.push(0_u64.to_be_bytes().to_vec());
+
+    let deleted = t.bulk_delete(keys)?;
+    let expected = (0..N).filter(|i| i % 3 == 0).count();
+    assert_eq!(deleted, expected);
+    assert_eq!(t.len(), N as usize - expected);
+    for i in 0..N {
+        assert_eq!(t.contains_key(i.to_be_bytes())?, i % 3 != 0);
+    }
+
+    assert_eq!(t.bulk_delete(Vec::<Vec<u8>>::new())?, 0);
+    Ok(())
+}
+
+#[test]
+fn slow_scans_let_freed_pages_be_reclaimed() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    for i in 0..500u64 {
+        t.insert(i.to_be_bytes(), vec![])?;
+    }
+
+    // inserting and then removing a band of keys splits and
+    // then merges leaves, freeing their pages
+    let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let churner = {
+        let t = t.clone();
+        let done = done.clone();
+        thread::spawn(move || -> Result<()> {
+            while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                for i in 0..50u8 {
+                    t.insert(vec![255, i], vec![])?;
+                }
+                for i in 0..50u8 {
+                    t.remove(vec![255, i])?;
+                }
+            }
+            Ok(())
+        })
+    };
+
+    // a slow scan only pins an epoch while reading a
+    // record, not for its whole duration
+    let mut scan = t.iter();
+    let before = t.freed_pages();
+    while t.freed_pages() == before {
+        if scan.next().transpose()?.is_none() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    let after = t.freed_pages();
+    drop(scan);
+
+    done.store(true, std::sync::atomic::Ordering::Relaxed);
+    churner.join().unwrap()?;
+
+    assert!(after > before, "no pages were reclaimed during the scan");
+    Ok(())
+}
+
+#[test]
+fn retain_keeps_only_matching_records() -> Result<()> {
+    tests::setup_logger();
+
+    const N: u64 = 500;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    for i in 0..N {
+        // keys and values run in opposite orders
+        t.insert(i.to_be_bytes(), (N - i).to_be_bytes().to_vec())?;
+    }
+
+    let mut seen = 0;
+    let removed = t.retain(|_k, v| {
+        seen += 1;
+        v[7] % 2 == 0
+    })?;
+
+    assert_eq!(seen, N);
+    assert_eq!(removed, N as usize / 2);
+    assert_eq!(t.len(), N as usize / 2);
+    for i in 0..N {
+        let expected = if (N - i).is_multiple_of(2) {
+            Some(IVec::from(&(N - i).to_be_bytes()))
+        } else {
+            None
+        };
+        assert_eq!(t.get(i.to_be_bytes())?, expected);
+    }
+
+    assert_eq!(t.retain(|_k, _v| true)?, 0);
+    Ok(())
+}
+
+#[test]
+fn health_check_leaves_user_data_untouched() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    t.insert(b"a", vec![1])?;
+    let mut subscriber = t.watch_prefix(vec![]);
+    for _ in 0..10 {
+        t.health_check()?;
+    }
+
+    // the sentinel never enters the keyspace, so
+    // subscribers don't hear about it either
+    assert!(subscriber.next_timeout(Duration::from_millis(50)).is_err());
+    assert_eq!(t.len(), 1);
+    assert_eq!(t.get(b"a")?, Some(IVec::from(vec![1])));
+    Ok(())
+}
+
+#[test]
+fn scan_raw_keys_decode_to_logical_keys() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    // enough keys sharing long prefixes to span many leaves
+    for i in 0..300_u32 {
+        let key = format!("users/{:04}/profile", i);
+        t.insert(key.as_bytes(), i.to_be_bytes().to_vec())?;
+    }
+
+    let start = b"users/0100";
+    let logical: Vec<(IVec, IVec)> =
+        t.range(&start[..]..).collect::<Result<_>>()?;
+    let raw: Vec<RawRecord> = t.scan_raw(start).collect::<Result<_>>()?;
+    assert_eq!(raw.len(), logical.len());
+    assert_eq!(raw.len(), 200);
+
+    let mut shared_prefix = false;
+    for (record, (key, value)) in raw.iter().zip(&logical) {
+        assert_eq!(&*prefix_decode(&record.lo, &record.key), &**key);
+        assert_eq!(record.value, *value);
+
+        let reencoded = prefix_reencode(&record.lo, b"", &record.key);
+        assert_eq!(&*prefix_decode(b"", &reencoded), &**key);
+
+        shared_prefix |= record.key[0] > 0;
+    }
+    assert!(shared_prefix, "no key was stored relative to its leaf's lo");
+
+    Ok(())
+}
+
+#[test]
+fn list_children_groups_by_delimiter() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    for i in 0..100 {
+        t.insert(format!("a/b/{:03}", i), vec![])?;
+    }
+    for key in &["a/b/c/d", "a/e", "a/b", "a/b!", "a0", "b/f", "a/"] {
+        t.insert(key, vec![])?;
+    }
+
+    let children = |prefix: &str| -> Result<Vec<String>> {
+        Ok(t.list_children(prefix, b'/')?
+            .into_iter()
+            .map(|child| String::from_utf8(child.to_vec()).unwrap())
+            .collect())
+    };
+
+    assert_eq!(children("a/")?, vec!["a/", "a/b", "a/b!", "a/e"]);
+    assert_eq!(children("a/b/")?.len(), 101);
+    assert_eq!(children("a/b/c/")?, vec!["a/b/c/d"]);
+    assert_eq!(children("")?, vec!["a", "a0", "b"]);
+    assert_eq!(children("c/")?, Vec::<String>::new());
+
+    // the delimiter needn't be '/', and may be the largest byte
+    t.insert(b"x:1:a", vec![])?;
+    t.insert(b"x:1:b", vec![])?;
+    t.insert(b"x:2", vec![])?;
+    assert_eq!(
+        t.list_children(b"x:", b':')?,
+        vec![IVec::from(b"x:1"), IVec::from(b"x:2")]
+    );
+
+    t.insert(vec![255, 255, 1], vec![])?;
+    t.insert(vec![255, 255, 2], vec![])?;
+    assert_eq!(
+        t.list_children(vec![255], 255)?,
+        vec![IVec::from(vec![255])]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn get_meta_reports_lsn_and_blobs() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .io_buf_size(1 << 16)
+        .build();
+    let t = sled::Db::start(config)?;
+
+    assert_eq!(t.get_meta(b"small")?, None);
+
+    t.insert(b"small", vec![1])?;
+    let first = t.get_meta(b"small")?.unwrap();
+    assert_eq!(first.value, IVec::from(vec![1]));
+    assert_eq!(first.len, 1);
+    assert!(!first.fragmented);
+
+    // reading again doesn't change the version
+    assert_eq!(t.get_meta(b"small")?, Some(first.clone()));
+
+    t.insert(b"small", vec![2, 2])?;
+    let second = t.get_meta(b"small")?.unwrap();
+    assert_eq!(second.len, 2);
+    assert!(second.lsn > first.lsn);
+
+    // values much larger than the io buffer are stored as blobs
+    t.insert(b"big", vec![3; 256 * 1024])?;
+    let big = t.get_meta(b"big")?.unwrap();
+    assert_eq!(big.len, 256 * 1024);
+    assert!(big.fragmented);
+    assert!(big.lsn > second.lsn);
+
+    Ok(())
+}
+
+#[test]
+fn generation_advances_only_on_writes() -> Result<()> {
+    tests::setup_logger();
+
+    let dir = "/tmp/test_generation_advances_only_on_writes";
+    let _ = std::fs::remove_dir_all(dir);
+    let config = || ConfigBuilder::new().path(dir).build();
+
+    let db = sled::Db::start(config())?;
+    let other = db.open_tree(b"other")?;
+    db.set_merge_operator(max_u64);
+
+    let mut last = db.generation();
+    let mut advanced = |db: &Db| {
+        let generation = db.generation();
+        let ret = generation > last;
+        last = generation;
+        ret
+    };
+
+    db.insert(b"a", vec![1])?;
+    assert!(advanced(&db));
+    db.merge(b"n", 7_u64.to_be_bytes())?;
+    assert!(advanced(&db));
+    assert!(db.cas(b"a", Some(vec![1]), Some(vec![2]))?.is_ok());
+    assert!(advanced(&db));
+    let mut batch = db.batch();
+    batch.insert(b"b", vec![1]);
+    batch.apply()?;
+    assert!(advanced(&db));
+    db.remove(b"a")?;
+    assert!(advanced(&db));
+
+    // reads, failed writes, and writes to other trees don't count
+    db.get(b"b")?;
+    db.iter().for_each(drop);
+    assert!(db.cas(b"b", Some(vec![9]), Some(vec![0]))?.is_err());
+    other.insert(b"a", vec![1])?;
+    assert!(!advanced(&db));
+
+    drop(other);
+    drop(db);
+
+    // and it never goes back after a restart
+    let db = sled::Db::start(config())?;
+    assert!(db.generation() >= last);
+    let reopened = db.generation();
+    db.get(b"b")?;
+    assert_eq!(db.generation(), reopened);
+    db.insert(b"c", vec![])?;
+    assert!(db.generation() > reopened);
+    drop(db);
+
+    std::fs::remove_dir_all(dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn size_on_disk_counts_blobs() -> Result<()> {
+    tests::setup_logger();
+
+    const VALUE_LEN: u64 = 256 * 1024;
+
+    // values much larger than the io buffer are stored as blobs
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .io_buf_size(1 << 16)
+        .build();
+    let t = sled::Db::start(config.clone())?;
+
+    let before = t.size_on_disk()?;
+
+    t.insert(b"big", vec![1; VALUE_LEN as usize])?;
+    t.flush()?;
+
+    let after = t.size_on_disk()?;
+    assert!(
+        after >= before + VALUE_LEN,
+        "size grew from {} to {}",
+        before,
+        after
+    );
+
+    let mut blob_dir = config.get_path();
+    blob_dir.push("blobs");
+    let blob_sizes: u64 = std::fs::read_dir(blob_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().metadata().unwrap().len())
+        .sum();
+    assert!(blob_sizes >= VALUE_LEN);
+
+    let mut db_file = config.get_path();
+    db_file.push("db");
+    let db_size = std::fs::metadata(db_file).unwrap().len();
+    assert!(after >= db_size + blob_sizes);
+
+    Ok(())
+}
+
+#[test]
+fn collect_range_is_consistent() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u64 = 150;
+    const N_GENERATIONS: u64 = 20;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    for i in 0..N_KEYS {
+        t.insert(i.to_be_bytes(), 0_u64.to_be_bytes().to_vec())?;
+    }
+
+    let start = 50_u64.to_be_bytes();
+    let start: &[u8] = &start;
+    let window = t.collect_range(start.., 64)?;
+    let expected: Vec<(IVec, IVec)> =
+        t.range(start..).take(64).collect::<Result<_>>()?;
+    assert_eq!(window.len(), 64);
+    assert_eq!(window, expected);
+    assert!(t.collect_range(start.., 0)?.is_empty());
+    assert_eq!(t.collect_range(start.., 1000)?.len() as u64, N_KEYS - 50);
+
+    // a writer moves every key to the next generation at once
+    let writer = {
+        let t = t.clone();
+        thread::spawn(move || {
+            for generation in 1..=N_GENERATIONS {
+                let mut batch = t.batch();
+                for i in 0..N_KEYS {
+                    batch.insert(
+                        i.to_be_bytes().to_vec(),
+                        generation.to_be_bytes().to_vec(),
+                    );
+                }
+                batch.apply().unwrap();
+            }
+        })
+    };
+
+    loop {
+        let window = t.collect_range(start.., 100)?;
+        assert_eq!(window.len(), 100);
+        let first = &window[0].1;
+        assert!(
+            window.iter().all(|(_k, v)| v == first),
+            "window mixed generations"
+        );
+        if first.as_ref() == &N_GENERATIONS.to_be_bytes()[..] {
+            break;
+        }
+    }
+
+    writer.join().unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn index_tracks_row_updates_and_removals() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    // rows are "name:city", indexed by city
+    let by_city = t.index(b"city", |row: &[u8]| {
+        row.iter()
+            .position(|b| *b == b':')
+            .map(|i| row[i + 1..].to_vec())
+    });
+    let lookup = |city: &str| -> Vec<IVec> {
+        by_city.lookup(city).map(|res| res.unwrap()).collect()
+    };
+
+    by_city.insert(b"1", b"alice:paris".to_vec())?;
+    by_city.insert(b"2", b"bob:oslo".to_vec())?;
+    by_city.insert(b"3", b"carol:paris".to_vec())?;
+    by_city.insert(b"4", b"no city".to_vec())?;
+
+    assert_eq!(lookup("paris"), vec![IVec::from(b"1"), IVec::from(b"3")]);
+    assert_eq!(lookup("oslo"), vec![IVec::from(b"2")]);
+    assert_eq!(lookup("par"), Vec::<IVec>::new());
+
+    // moving a row drops its old index entry
+    by_city.insert(b"3", b"carol:oslo".to_vec())?;
+    assert_eq!(lookup("paris"), vec![IVec::from(b"1")]);
+    assert_eq!(lookup("oslo"), vec![IVec::from(b"2"), IVec::from(b"3")]);
+
+    assert_eq!(by_city.remove(b"2")?, Some(IVec::from(b"bob:oslo")));
+    assert_eq!(lookup("oslo"), vec![IVec::from(b"3")]);
+    assert_eq!(t.get(b"2")?, None);
+
+    // indexes with different names don't see each other
+    let by_name = t.index(b"name", |row: &[u8]| {
+        row.iter()
+            .position(|b| *b == b':')
+            .map(|i| row[..i].to_vec())
+    });
+    assert_eq!(by_name.lookup(b"alice").count(), 0);
+
+    by_city.remove(b"1")?;
+    by_city.remove(b"3")?;
+    by_city.remove(b"4")?;
+    assert!(t.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn flush_async_handles_cover_earlier_writes() -> Result<()> {
+    tests::setup_logger();
+
+    const N_BATCHES: u64 = 5;
+    const BATCH_LEN: u64 = 100;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+    let t = sled::Db::start(config.clone()).unwrap();
+
+    let mut handles = vec![];
+    for batch in 0..N_BATCHES {
+        for i in batch * BATCH_LEN..(batch + 1) * BATCH_LEN {
+            t.insert(i.to_be_bytes(), vec![1; 32])?;
+        }
+        handles.push(t.flush_async());
+    }
+
+    // writes after the last flush_async are not covered
+    for i in N_BATCHES * BATCH_LEN..(N_BATCHES + 1) * BATCH_LEN {
+        t.insert(i.to_be_bytes(), vec![2; 32])?;
+    }
+
+    for handle in handles {
+        handle.clone().wait()?;
+        assert!(handle.is_done());
+    }
+    drop(t);
+
+    let t = sled::Db::start(config).unwrap();
+    for i in 0..N_BATCHES * BATCH_LEN {
+        assert_eq!(t.get(i.to_be_bytes())?, Some(IVec::from(vec![1; 32])));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn barrier_covers_writes_observed_across_threads() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+    let t = Arc::new(sled::Db::start(config.clone()).unwrap());
+    let (barrier_tx, barrier_rx) = std::sync::mpsc::channel();
+
+    let a = {
+        let t = t.clone();
+        thread::spawn(move || -> Result<_> {
+            t.insert(b"a", vec![1])?;
+            let lsn = t.barrier()?;
+            barrier_tx.send(lsn).unwrap();
+            Ok(lsn)
+        })
+    };
+
+    let b = {
+        let t = t.clone();
+        thread::spawn(move || -> Result<_> {
+            let a_lsn = barrier_rx.recv().unwrap();
+            t.insert(b"b", vec![2])?;
+            let b_lsn = t.barrier()?;
+            assert!(b_lsn > a_lsn, "{} <= {}", b_lsn, a_lsn);
+            Ok(b_lsn)
+        })
+    };
+
+    let a_lsn = a.join().unwrap()?;
+    let b_lsn = b.join().unwrap()?;
+    assert!(b_lsn > a_lsn);
+
+    // both barriers left nothing for a flush to write
+    assert_eq!(t.flush()?, 0);
+    drop(t);
+
+    let t = sled::Db::start(config).unwrap();
+    assert_eq!(t.get(b"a")?, Some(IVec::from(vec![1])));
+    assert_eq!(t.get(b"b")?, Some(IVec::from(vec![2])));
+
+    Ok(())
+}
+
+#[test]
+fn linear_segment_mode_never_relocates() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u64 = 64;
+    const N_ROUNDS: u64 = 40;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .segment_mode(SegmentMode::Linear)
+        .io_buf_size(8192)
+        .build();
+    let t = sled::Db::start(config.clone()).unwrap();
+
+    // written once, so only relocation could move it
+    let cold = t.open_tree(b"cold")?;
+    cold.insert(b"cold", vec![0; 256])?;
+    drop(cold);
+
+    for round in 0..N_ROUNDS {
+        for i in 0..N_KEYS {
+            t.insert(i.to_be_bytes(), vec![round as u8; 256])?;
+        }
+        t.flush()?;
+    }
+    assert_eq!(t.rewritten_pages(), 0);
+    drop(t);
+
+    let t = sled::Db::start(config).unwrap();
+    for i in 0..N_KEYS {
+        assert_eq!(
+            t.get(i.to_be_bytes())?,
+            Some(IVec::from(vec![N_ROUNDS as u8 - 1; 256]))
+        );
+    }
+    assert_eq!(t.len(), N_KEYS as usize);
+    assert_eq!(
+        t.open_tree(b"cold")?.get(b"cold")?,
+        Some(IVec::from(vec![0; 256]))
+    );
+    assert_eq!(t.rewritten_pages(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn parallel_compaction_keeps_up_with_overwrites() -> Result<()> {
+    tests::setup_logger();
+
+    const N_WRITERS: u64 = 4;
+    const N_KEYS: u64 = 64;
+    const N_ROUNDS: u64 = 40;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .io_buf_size(8192)
+        .flush_every_ms(Some(1))
+        .compaction_parallelism(4)
+        .build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    let writers: Vec<_> = (0..N_WRITERS)
+        .map(|writer| {
+            let t = t.clone();
+            thread::spawn(move || -> Result<()> {
+                for round in 0..N_ROUNDS {
+                    for i in writer * N_KEYS..(writer + 1) * N_KEYS {
+                        t.insert(i.to_be_bytes(), vec![round as u8; 256])?;
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap()?;
+    }
+
+    assert!(t.rewritten_pages() > 0);
+    let space_amplification = t.space_amplification()?;
+    assert!(
+        space_amplification < MAX_SPACE_AMPLIFICATION,
+        "space amplification was {}",
+        space_amplification
+    );
+
+    for i in 0..N_WRITERS * N_KEYS {
+        assert_eq!(
+            t.get(i.to_be_bytes())?,
+            Some(IVec::from(vec![N_ROUNDS as u8 - 1; 256]))
+        );
+    }
+
+    match ConfigBuilder::new()
+        .temporary(true)
+        .compaction_parallelism(0)
+        .try_build()
+    {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Unsupported, got {:?}", other.map(|_| ())),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn bloom_filter_skips_definite_misses() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u64 = 500;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .use_bloom_filter(true)
+        .bloom_filter_capacity(N_KEYS as usize)
+        .build();
+
+    // even keys are present, odd keys never were
+    let t = sled::Db::start(config.clone()).unwrap();
+    let other = t.open_tree(b"other")?;
+    for i in (0..N_KEYS).step_by(2) {
+        t.insert(i.to_be_bytes(), vec![1])?;
+        other.insert(i.to_be_bytes(), vec![2])?;
+    }
+    t.remove(0_u64.to_be_bytes())?;
+    drop(other);
+    drop(t);
+
+    // the filters are rebuilt from the recovered leaves
+    let t = sled::Db::start(config.clone()).unwrap();
+    let other = t.open_tree(b"other")?;
+
+    let check = |tree: &sled::Tree, value: u8| -> Result<u64> {
+        let mut definite_misses = 0;
+        for i in 0..N_KEYS {
+            let key = i.to_be_bytes();
+            let fetches = t.page_fetches();
+            let got = tree.get(key)?;
+            if i % 2 == 0 && !(value == 1 && i == 0) {
+                assert_eq!(got, Some(IVec::from(vec![value])));
+                assert!(tree.contains_key(key)?);
+            } else {
+                assert_eq!(got, None);
+                if t.page_fetches() == fetches {
+                    definite_misses += 1;
+                }
+            }
+        }
+        Ok(definite_misses)
+    };
+
+    // allow for false positives and concurrent page fetches
+    assert!(check(&t, 1)? > N_KEYS / 4);
+    assert!(check(&other, 2)? > N_KEYS / 4);
+
+    // writes after recovery are recorded too
+    t.insert(1_u64.to_be_bytes(), vec![3])?;
+    t.cas(3_u64.to_be_bytes(), None as Option<&[u8]>, Some(vec![3]))?
+        .unwrap();
+    assert_eq!(t.get(1_u64.to_be_bytes())?, Some(IVec::from(vec![3])));
+    assert_eq!(t.get(3_u64.to_be_bytes())?, Some(IVec::from(vec![3])));
+
+    // an undersized filter passes more misses, but still
+    // never hides a key that is present
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .use_bloom_filter(true)
+        .bloom_filter_capacity(1)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+    for i in 0..N_KEYS {
+        t.insert(i.to_be_bytes(), vec![1])?;
+    }
+    for i in 0..N_KEYS {
+        assert_eq!(t.get(i.to_be_bytes())?, Some(IVec::from(vec![1])));
+    }
+    drop(t);
+
+    // without the filter every miss walks the tree
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+    t.insert(b"present", vec![1])?;
+    let fetches = t.page_fetches();
+    assert_eq!(t.get(b"absent")?, None);
+    assert!(t.page_fetches() > fetches);
+
+    Ok(())
+}
+
+#[test]
+fn lazy_recovery_fills_bloom_filters_in_the_background() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u64 = 1_000;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .use_bloom_filter(true)
+        .lazy_recovery(true)
+        .build();
+
+    // even keys are present, odd keys never were
+    let t = sled::Db::start(config.clone()).unwrap();
+    let other = t.open_tree(b"other")?;
+    for i in (0..N_KEYS).step_by(2) {
+        t.insert(i.to_be_bytes(), vec![1])?;
+        other.insert(i.to_be_bytes(), vec![2])?;
+    }
+    drop(other);
+    drop(t);
+
+    // keys are found while the filters are still being filled
+    let t = sled::Db::start(config.clone()).unwrap();
+    let other = t.open_tree(b"other")?;
+    for i in (0..N_KEYS).step_by(2) {
+        assert_eq!(t.get(i.to_be_bytes())?, Some(IVec::from(vec![1])));
+        assert_eq!(other.get(i.to_be_bytes())?, Some(IVec::from(vec![2])));
+    }
+
+    let before = Instant::now();
+    while t.recovery_pending() {
+        assert!(
+            before.elapsed() < Duration::from_secs(30),
+            "bloom filters were never filled"
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(t.recovery_error(), None);
+
+    // once filled, the filters skip definite misses
+    let mut definite_misses = 0;
+    for i in (1..1000).step_by(2) {
+        let fetches = t.page_fetches();
+        assert_eq!(t.get((i as u64).to_be_bytes())?, None);
+        if t.page_fetches() == fetches {
+            definite_misses += 1;
+        }
+    }
+    assert!(definite_misses > 500 / 4);
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_set_add_and_remove() -> Result<()> {
+    tests::setup_logger();
+
+    const N_THREADS: u64 = 4;
+    const N_MEMBERS: u64 = 50;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    let member = |thread: u64, i: u64| format!("{}-{:03}", thread, i);
+
+    // each thread adds its own members and removes the odd
+    // ones, while all threads contend on the same key
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|thread| {
+            let t = t.clone();
+            thread::spawn(move || -> Result<()> {
+                for i in 0..N_MEMBERS {
+                    assert!(t.set_add(b"set", member(thread, i))?);
+                    assert!(!t.set_add(b"set", member(thread, i))?);
+                }
+                for i in (1..N_MEMBERS).step_by(2) {
+                    assert!(t.set_remove(b"set", member(thread, i))?);
+                }
+                Ok(())
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    let mut expected: Vec<IVec> = (0..N_THREADS)
+        .flat_map(|thread| {
+            (0..N_MEMBERS)
+                .step_by(2)
+                .map(move |i| IVec::from(member(thread, i).as_bytes()))
+        })
+        .collect();
+    expected.sort();
+    assert_eq!(t.set_members(b"set")?, expected);
+
+    assert!(!t.set_remove(b"set", "absent")?);
+    assert_eq!(t.set_members(b"absent")?, Vec::<IVec>::new());
+
+    t.insert(b"not a set", vec![1])?;
+    assert!(t.set_add(b"not a set", "member").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn emptied_collections_remove_their_key() -> Result<()> {
+    tests::setup_logger();
+
+    let path = "/tmp/test_tree_emptied_collections";
+    let _ = std::fs::remove_dir_all(path);
+    let config = || ConfigBuilder::new().path(path).build();
+
+    let t = sled::Db::start(config())?;
+
+    t.set_add(b"set", b"a")?;
+    t.set_add(b"set", b"b")?;
+    assert!(t.set_remove(b"set", b"a")?);
+    assert!(t.get(b"set")?.is_some());
+    assert!(t.set_remove(b"set", b"b")?);
+    assert_eq!(t.get(b"set")?, None);
+    assert!(!t.contains_key(b"set")?);
+    assert_eq!(t.set_members(b"set")?, Vec::<IVec>::new());
+
+    // a merge operator maintaining a collection removes
+    // the key by returning None once it's empty
+    fn pop_or_push(
+        _key: &[u8],
+        old: Option<&[u8]>,
+        merged: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut list = old.map(<[u8]>::to_vec).unwrap_or_default();
+        if merged.is_empty() {
+            list.pop();
+        } else {
+            list.extend_from_slice(merged);
+        }
+        if list.is_empty() {
+            None
+        } else {
+            Some(list)
+        }
+    }
+    t.set_merge_operator(pop_or_push);
+
+    t.merge(b"list", vec![1])?;
+    assert_eq!(t.merge(b"list", vec![])?, None);
+    assert_eq!(t.get(b"list")?, None);
+
+    t.set_add(b"set", b"c")?;
+    t.set_remove(b"set", b"c")?;
+    drop(t);
+
+    // and stays removed after recovery
+    let t = sled::Db::start(config())?;
+    assert_eq!(t.get(b"set")?, None);
+    assert_eq!(t.get(b"list")?, None);
+    assert_eq!(t.len(), 0);
+    drop(t);
+
+    std::fs::remove_dir_all(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn merge_with_applies_a_per_call_operator() -> Result<()> {
+    use std::convert::TryInto;
+
+    tests::setup_logger();
+
+    const N_PER_OP: u64 = 50;
+
+    fn add(_key: &[u8], old: Option<&[u8]>, n: &[u8]) -> Option<Vec<u8>> {
+        let old =
+            old.map_or(0, |old| u64::from_be_bytes(old.try_into().unwrap()));
+        let n = u64::from_be_bytes(n.try_into().unwrap());
+        Some((old + n).to_be_bytes().to_vec())
+    }
+
+    fn double(_key: &[u8], old: Option<&[u8]>, _n: &[u8]) -> Option<Vec<u8>> {
+        let old =
+            old.map_or(0, |old| u64::from_be_bytes(old.try_into().unwrap()));
+        Some((old * 2).to_be_bytes().to_vec())
+    }
+
+    fn concatenate(
+        _key: &[u8],
+        old: Option<&[u8]>,
+        new: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut ret = old.map(<[u8]>::to_vec).unwrap_or_default();
+        ret.extend_from_slice(new);
+        Some(ret)
+    }
+
+    fn clear(_key: &[u8], _old: Option<&[u8]>, _new: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config)?);
+
+    // two operators on the same key
+    assert_eq!(
+        t.merge_with(b"n", 3_u64.to_be_bytes(), add)?,
+        Some(IVec::from(&3_u64.to_be_bytes()))
+    );
+    assert_eq!(
+        t.merge_with(b"n", [], double)?,
+        Some(IVec::from(&6_u64.to_be_bytes()))
+    );
+
+    // each call is retried until it applies to the current value
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|_| {
+            let t = t.clone();
+            thread::spawn(move || {
+                for _ in 0..N_PER_OP {
+                    t.merge_with(b"n", 1_u64.to_be_bytes(), add).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let expected = 6 + N_THREADS as u64 * N_PER_OP;
+    assert_eq!(t.get(b"n")?, Some(IVec::from(&expected.to_be_bytes())));
+
+    // the tree's own merge operator is left alone
+    t.set_merge_operator(concatenate);
+    t.merge(b"s", vec![1])?;
+    t.merge_with(b"s", vec![2], clear)?;
+    assert_eq!(t.get(b"s")?, None);
+    t.merge(b"s", vec![3])?;
+    assert_eq!(t.get(b"s")?, Some(IVec::from(vec![3])));
+
+    Ok(())
+}
+
+#[test]
+fn advisory_locks_exclude_until_released_or_expired() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config)?);
+
+    let ttl = Duration::from_millis(200);
+
+    // only one of several contending owners wins
+    let barrier = Arc::new(Barrier::new(N_THREADS));
+    let threads: Vec<_> = (0..N_THREADS as u64)
+        .map(|owner| {
+            let t = t.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                t.try_lock(b"job", owner, ttl).map(|won| (owner, won))
+            })
+        })
+        .collect();
+    let winners: Vec<u64> = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|&(_, won)| won)
+        .map(|(owner, _)| owner)
+        .collect();
+    assert_eq!(winners.len(), 1);
+    let winner = winners[0];
+    let loser = (winner + 1) % N_THREADS as u64;
+
+    assert!(!t.try_lock(b"job", loser, ttl)?);
+    assert!(!t.unlock(b"job", loser)?);
+
+    // other keys are locked independently
+    assert!(t.try_lock(b"other job", loser, ttl)?);
+
+    // the holder can extend its lock, and others can
+    // claim it once it expires
+    assert!(t.try_lock(b"job", winner, ttl)?);
+    thread::sleep(ttl + Duration::from_millis(50));
+    assert!(t.try_lock(b"job", loser, ttl)?);
+    assert!(!t.try_lock(b"job", winner, ttl)?);
+
+    // the previous holder can't release the new holder's lock
+    assert!(!t.unlock(b"job", winner)?);
+    assert!(t.unlock(b"job", loser)?);
+    assert!(t.try_lock(b"job", winner, ttl)?);
+
+    // locks don't appear among the tree's keys
+    assert!(t.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn pop_min_blocking_wakes_on_insert() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    for round in 0..20_u8 {
+        let popper = {
+            let t = t.clone();
+            thread::spawn(move || {
+                let before = std::time::Instant::now();
+                let popped = t.pop_min_blocking(Duration::from_secs(30));
+                (popped, before.elapsed())
+            })
+        };
+
+        thread::sleep(Duration::from_millis(round as u64));
+        t.insert([round], vec![round])?;
+
+        let (popped, elapsed) = popper.join().unwrap();
+        assert_eq!(popped?, Some((IVec::from(&[round]), IVec::from(&[round]))));
+        assert!(elapsed < Duration::from_secs(10), "took {:?}", elapsed);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn queue_items_are_consumed_exactly_once() -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+
+    tests::setup_logger();
+
+    const N_PRODUCERS: usize = 3;
+    const N_CONSUMERS: usize = 3;
+    const N_ITEMS: usize = 200;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+    let producing = Arc::new(AtomicBool::new(true));
+
+    // consumers start first, so they block on the empty queue
+    let consumers: Vec<_> = (0..N_CONSUMERS)
+        .map(|_| {
+            let t = t.clone();
+            let producing = producing.clone();
+            thread::spawn(move || -> Result<Vec<IVec>> {
+                let mut consumed = vec![];
+                loop {
+                    let timeout = Duration::from_millis(50);
+                    match t.pop_min_blocking(timeout)? {
+                        Some((_job, value)) => consumed.push(value),
+                        None if !producing.load(SeqCst) => return Ok(consumed),
+                        None => {}
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let producers: Vec<_> = (0..N_PRODUCERS)
+        .map(|producer| {
+            let t = t.clone();
+            thread::spawn(move || -> Result<()> {
+                for i in 0..N_ITEMS {
+                    let value = format!("{}-{}", producer, i);
+                    t.append_next(b"job", value.as_bytes())?;
+                }
+                Ok(())
+            })
+        })
+        .collect();
+
+    for producer in producers {
+        producer.join().unwrap()?;
+    }
+    producing.store(false, SeqCst);
+
+    let mut consumed = vec![];
+    for consumer in consumers {
+        consumed.extend(consumer.join().unwrap()?);
+    }
+    consumed.sort();
+
+    let mut expected: Vec<IVec> = (0..N_PRODUCERS)
+        .flat_map(|producer| {
+            (0..N_ITEMS).map(move |i| {
+                IVec::from(format!("{}-{}", producer, i).as_bytes())
+            })
+        })
+        .collect();
+    expected.sort();
+
+    assert_eq!(consumed, expected);
+    assert!(t.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn consolidation_threshold_adjusts_at_runtime() -> Result<()> {
+    tests::setup_logger();
+
+    const N_UPDATES: u64 = 200;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    assert!(t.set_consolidation_threshold(0).is_err());
+    assert!(t.set_consolidation_threshold(1 << 20).is_err());
+
+    // a single key keeps every update on the same page
+    t.set_consolidation_threshold(N_UPDATES as usize * 2)?;
+    let before = t.consolidated_pages();
+    for i in 0..N_UPDATES {
+        t.insert(b"k", &i.to_be_bytes())?;
+    }
+    let bulk = t.consolidated_pages() - before;
+    assert!(bulk <= 1, "consolidated {} times during bulk load", bulk);
+
+    t.set_consolidation_threshold(2)?;
+    let before = t.consolidated_pages();
+    for i in N_UPDATES..N_UPDATES * 2 {
+        t.insert(b"k", &i.to_be_bytes())?;
+    }
+    let steady = t.consolidated_pages() - before;
+    assert!(
+        steady >= N_UPDATES / 3,
+        "consolidated only {} times with a threshold of 2",
+        steady
+    );
+
+    assert_eq!(
+        t.get(b"k")?,
+        Some(IVec::from(&(N_UPDATES * 2 - 1).to_be_bytes()))
+    );
+    Ok(())
+}
+
+#[test]
+fn default_config_consolidates_without_panicking() -> Result<()> {
+    tests::setup_logger();
+
+    const N_UPDATES: u64 = 200;
+
+    // leaves order their keys by their prefix encoding, which
+    // needs no configuration, so consolidating the pages of a
+    // tree with every option left at its default must succeed
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    let before = t.consolidated_pages();
+    for i in 0..N_UPDATES {
+        t.insert(b"k", &i.to_be_bytes())?;
+        if i % 3 == 0 {
+            t.remove(b"j")?;
+        } else {
+            t.insert(b"j", &i.to_be_bytes())?;
+        }
+    }
+    assert!(t.consolidated_pages() > before);
+
+    assert_eq!(
+        t.get(b"k")?,
+        Some(IVec::from(&(N_UPDATES - 1).to_be_bytes()))
+    );
+    assert_eq!(
+        t.get(b"j")?,
+        Some(IVec::from(&(N_UPDATES - 1).to_be_bytes()))
+    );
+    let keys: Vec<IVec> = t.iter().keys().collect::<Result<_>>()?;
+    assert_eq!(keys, vec![IVec::from(b"j"), IVec::from(b"k")]);
+    Ok(())
+}
+
+#[test]
+fn fragment_chain_len_tracks_consolidation() -> Result<()> {
+    tests::setup_logger();
+
+    const N_MERGES: usize = 50;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .merge_operator_named("max_u64")
+        .build();
+    let t = sled::Db::start(config)?;
+
+    // keep every merge linked onto the leaf's chain
+    t.set_consolidation_threshold(N_MERGES * 2)?;
+    t.insert(b"k", &0_u64.to_be_bytes())?;
+    let initial = t.fragment_chain_len(b"k")?;
+
+    for i in 1..=N_MERGES as u64 {
+        t.merge(b"k", i.to_be_bytes())?;
+    }
+    let grown = t.fragment_chain_len(b"k")?;
+    assert!(
+        grown >= initial + N_MERGES,
+        "chain grew from {} to only {} after {} merges",
+        initial,
+        grown,
+        N_MERGES
+    );
+
+    // the next merge consolidates the chain
+    t.set_consolidation_threshold(2)?;
+    t.merge(b"k", 0_u64.to_be_bytes())?;
+    assert_eq!(t.fragment_chain_len(b"k")?, 1);
+
+    assert_eq!(
+        t.get(b"k")?,
+        Some(IVec::from(&(N_MERGES as u64).to_be_bytes()))
+    );
+    Ok(())
+}
+
+#[test]
+fn concurrent_g_counter_updates_are_not_lost() -> Result<()> {
+    tests::setup_logger();
+
+    const N_THREADS: usize = 4;
+    const N_INCREMENTS: u64 = 200;
+
+    // a grow-only counter holding one big-endian u64
+    // slot per replica, which only ever increase
+    fn increment(old: Option<&[u8]>, replica: usize) -> Vec<u8> {
+        let mut slots = old
+            .map(<[u8]>::to_vec)
+            .unwrap_or_else(|| vec![0; 8 * N_THREADS]);
+        let slot = &mut slots[replica * 8..(replica + 1) * 8];
+        let mut array = [0; 8];
+        array.copy_from_slice(slot);
+        slot.copy_from_slice(&(u64::from_be_bytes(array) + 1).to_be_bytes());
+        slots
+    }
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|replica| {
+            let t = t.clone();
+            thread::spawn(move || {
+                for _ in 0..N_INCREMENTS {
+                    t.update_and_fetch(b"counter", |old| {
+                        Some(increment(old, replica))
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let slots = t.get(b"counter")?.unwrap();
+    for slot in slots.chunks(8) {
+        let mut array = [0; 8];
+        array.copy_from_slice(slot);
+        assert_eq!(u64::from_be_bytes(array), N_INCREMENTS);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_transfers_conserve_money() -> Result<()> {
+    tests::setup_logger();
+
+    const N_ACCOUNTS: u64 = 5;
+    const N_THREADS: u64 = 4;
+    const N_TRANSFERS: u64 = 50;
+    const BALANCE: u64 = 100;
+
+    fn account(i: u64) -> String {
+        format!("account-{}", i % N_ACCOUNTS)
+    }
+
+    fn balance(value: Option<IVec>) -> u64 {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&value.unwrap());
+        u64::from_be_bytes(buf)
+    }
+
+    fn total(t: &Tree) -> Result<u64> {
+        t.transaction(|tx| {
+            let mut sum = 0;
+            for i in 0..N_ACCOUNTS {
+                sum += balance(tx.get(account(i))?);
+            }
+            Ok(sum)
+        })
+    }
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    for i in 0..N_ACCOUNTS {
+        t.insert(account(i), &BALANCE.to_be_bytes())?;
+    }
+
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|thread| {
+            let t = t.clone();
+            thread::spawn(move || -> Result<()> {
+                for i in 0..N_TRANSFERS {
+                    let from = account(thread + i);
+                    let to = account(thread + i + 1 + i % (N_ACCOUNTS - 1));
+                    let amount = i % 7 + 1;
+
+                    t.transaction(|tx| {
+                        let from_balance = balance(tx.get(&from)?);
+                        if from_balance < amount {
+                            return Ok(());
+                        }
+                        let to_balance = balance(tx.get(&to)?);
+                        tx.insert(
+                            &from,
+                            &(from_balance - amount).to_be_bytes(),
+                        )?;
+                        tx.insert(&to, &(to_balance + amount).to_be_bytes())?;
+                        Ok(())
+                    })?;
+
+                    assert_eq!(total(&t)?, N_ACCOUNTS * BALANCE);
+                }
+                Ok(())
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+
+    assert_eq!(total(&t)?, N_ACCOUNTS * BALANCE);
+
+    // storage errors abort without writing anything
+    let res: Result<()> = t.transaction(|tx| {
+        tx.insert(account(0), vec![])?;
+        Err(TransactionError::Storage(Error::Unsupported(
+            "abort".into(),
+        )))
+    });
+    assert_eq!(res, Err(Error::Unsupported("abort".into())));
+    assert_eq!(total(&t)?, N_ACCOUNTS * BALANCE);
+
+    Ok(())
+}
+
+#[test]
+fn explicit_transactions_commit_or_roll_back() -> Result<()> {
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert(b"a", vec![1])?;
+
+    // dropping a transaction writes nothing
+    let tx = t.begin_transaction();
+    tx.remove(b"a").unwrap();
+    tx.insert(b"b", vec![2]).unwrap();
+    drop(tx);
+    assert_eq!(t.get(b"a")?, Some(IVec::from(vec![1])));
+    assert_eq!(t.get(b"b")?, None);
+
+    // committing applies every staged write
+    let tx = t.begin_transaction();
+    let a = tx.remove(b"a").unwrap();
+    tx.insert(b"b", a.unwrap()).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(t.get(b"a")?, None);
+    assert_eq!(t.get(b"b")?, Some(IVec::from(vec![1])));
+
+    // a conflicting write is reported by commit
+    let tx = t.begin_transaction();
+    tx.get(b"b").unwrap();
+    tx.insert(b"c", vec![3]).unwrap();
+    t.insert(b"b", vec![4])?;
+    assert_eq!(tx.commit(), Err(TransactionError::Conflict));
+    assert_eq!(t.get(b"c")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn explicit_transactions_allow_writes_before_commit() -> Result<()> {
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert(b"a", vec![1])?;
+
+    // unrelated writes and flushes don't block on, or
+    // conflict with, an open transaction
+    let tx = t.begin_transaction();
+    tx.get(b"a").unwrap();
+    tx.insert(b"b", vec![2]).unwrap();
+    t.insert(b"c", vec![3])?;
+    t.flush()?;
+    tx.commit().unwrap();
+    assert_eq!(t.get(b"b")?, Some(IVec::from(vec![2])));
+
+    Ok(())
+}
+
+#[test]
+fn scan_batches() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    for i in 0..250u64 {
+        t.insert(i.to_be_bytes(), &i.to_be_bytes())?;
+    }
+
+    let batches = t.iter().batches(100).collect::<Result<Vec<_>>>()?;
+    let sizes: Vec<usize> = batches.iter().map(Vec::len).collect();
+    assert_eq!(sizes, vec![100, 100, 50]);
+
+    let keys: Vec<IVec> =
+        batches.into_iter().flatten().map(|(k, _v)| k).collect();
+    let expected: Vec<IVec> =
+        (0..250u64).map(|i| IVec::from(&i.to_be_bytes())).collect();
+    assert_eq!(keys, expected);
+
+    Ok(())
+}
+
+#[test]
+fn split_points_divide_the_keyspace_evenly() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    let len = 1000u64;
+    for i in 0..len {
+        let k = (i * 97 % len).to_be_bytes();
+        t.insert(k, &k)?;
+    }
+
+    let n = 7;
+    let points = t.split_points(n)?;
+    assert_eq!(points.len(), n - 1);
+
+    let mut bounds = vec![None];
+    bounds.extend(points.into_iter().map(Some));
+    bounds.push(None);
+
+    let expected = len as usize / n;
+    for pair in bounds.windows(2) {
+        let count = match (&pair[0], &pair[1]) {
+            (None, Some(hi)) => t.range::<&IVec, _>(..hi).count(),
+            (Some(lo), Some(hi)) => t.range::<&IVec, _>(lo..hi).count(),
+            (Some(lo), None) => t.range::<&IVec, _>(lo..).count(),
+            (None, None) => unreachable!(),
+        };
+        assert!(
+            count == expected || count == expected + 1,
+            "range {:?} holds {} keys, expected about {}",
+            pair,
+            count,
+            expected
+        );
+    }
+
+    assert_eq!(t.split_points(1)?, Vec::<IVec>::new());
+    assert!(t.split_points(0).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn key_stats_match_inserted_key_lengths() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    assert_eq!(t.key_stats()?, KeyStats::default());
+
+    // long shared prefixes, spread over many leaves
+    let keys: Vec<Vec<u8>> = (0..500u16)
+        .map(|i| {
+            let mut k = vec![b'k'; 1 + (i % 40) as usize];
+            k.extend_from_slice(&i.to_be_bytes());
+            k
+        })
+        .collect();
+    for k in &keys {
+        t.insert(k, vec![])?;
+    }
+
+    let total: usize = keys.iter().map(Vec::len).sum();
+    let stats = t.key_stats()?;
+    assert_eq!(stats.count, 500);
+    assert_eq!(stats.min_len, 3);
+    assert_eq!(stats.max_len, 42);
+    assert!((stats.mean_len - total as f64 / 500.0).abs() < 1e-9);
+
+    for k in keys.iter().filter(|k| k.len() > 10) {
+        t.remove(k)?;
+    }
+    let stats = t.key_stats()?;
+    assert_eq!(stats.count, t.len());
+    assert_eq!(stats.max_len, 10);
+
+    Ok(())
+}
+
+#[test]
+fn append_biased_splits_keep_trees_consistent() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .split_policy(SplitPolicy::AppendBiased)
+        .build();
+    let t = sled::Db::start(config.clone())?;
+
+    let len = 1_000u64;
+    for i in 0..len {
+        t.insert(i.to_be_bytes(), &i.to_le_bytes())?;
+    }
+    // out-of-order inserts split nodes that aren't at the end
+    for i in (0..len).step_by(7) {
+        let mut k = i.to_be_bytes().to_vec();
+        k.push(0);
+        t.insert(k, vec![])?;
+    }
+    t.flush()?;
+    drop(t);
+
+    let t = sled::Db::start(config)?;
+    for i in 0..len {
+        assert_eq!(t.get(i.to_be_bytes())?, Some(IVec::from(&i.to_le_bytes())));
+    }
+    let keys: Vec<IVec> = t.iter().keys().collect::<Result<_>>()?;
+    assert_eq!(keys.len() as u64, len + len.div_ceil(7));
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+    Ok(())
+}
+
+#[test]
+fn rename_moves_a_value() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert(b"a", b"1".to_vec())?;
+    t.insert(b"b", b"2".to_vec())?;
+
+    assert!(t.rename(b"a", b"c", false)?);
+    assert_eq!(t.get(b"a")?, None);
+    assert_eq!(t.get(b"c")?, Some(IVec::from(b"1")));
+
+    // collisions are left alone unless overwriting
+    assert!(!t.rename(b"c", b"b", false)?);
+    assert_eq!(t.get(b"b")?, Some(IVec::from(b"2")));
+    assert_eq!(t.get(b"c")?, Some(IVec::from(b"1")));
+
+    assert!(t.rename(b"c", b"b", true)?);
+    assert_eq!(t.get(b"b")?, Some(IVec::from(b"1")));
+    assert_eq!(t.get(b"c")?, None);
+
+    // missing sources and renames onto themselves
+    assert!(!t.rename(b"missing", b"d", true)?);
+    assert_eq!(t.get(b"d")?, None);
+    assert!(t.rename(b"b", b"b", false)?);
+    assert_eq!(t.get(b"b")?, Some(IVec::from(b"1")));
+
+    Ok(())
+}
+
+#[test]
+fn warm_cache_reads_a_cold_tree_ahead_of_use() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u32 = 512;
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+
+    let t = sled::Db::start(config.clone())?;
+    for i in 0..N_KEYS {
+        t.insert(i.to_be_bytes(), vec![0; 64])?;
+    }
+    drop(t);
+
+    // recovery leaves the cache cold
+    let t = sled::Db::start(config.clone())?;
+
+    // a bounded range only reads the leaves it covers
+    let first = t.warm_cache(0_u32.to_be_bytes()..1_u32.to_be_bytes())?;
+    assert!(first > 0);
+
+    let warmed = t.warm_cache::<&[u8], _>(..)?;
+    assert!(warmed > first, "{} <= {}", warmed, first);
+
+    let before = t.page_ins();
+    assert_eq!(t.iter().count(), N_KEYS as usize);
+    for i in 0..N_KEYS {
+        assert!(t.get(i.to_be_bytes())?.is_some());
+    }
+    assert_eq!(t.page_ins(), before, "reads missed the warmed cache");
+
+    // warming again has nothing left to read
+    assert_eq!(t.warm_cache::<&[u8], _>(..)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn warm_cache_respects_the_cache_capacity() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u32 = 512;
+    let path = "/tmp/test_warm_cache_respects_the_cache_capacity";
+    let _ = std::fs::remove_dir_all(path);
+
+    let config = || ConfigBuilder::new().path(path).flush_every_ms(None);
+
+    let t = sled::Db::start(config().build())?;
+    for i in 0..N_KEYS {
+        t.insert(i.to_be_bytes(), vec![0; 64])?;
+    }
+    drop(t);
+
+    let t = sled::Db::start(config().build())?;
+    let all = t.warm_cache::<&[u8], _>(..)?;
+    drop(t);
+
+    // the tree is far larger than this cache, so warming
+    // stops well before reading all of it
+    let t = sled::Db::start(config().cache_capacity(256 * 16).build())?;
+    let warmed = t.warm_cache::<&[u8], _>(..)?;
+    assert!(warmed > 0);
+    assert!(warmed < all / 4, "warmed {} of {} pages", warmed, all);
+    drop(t);
+
+    std::fs::remove_dir_all(path)?;
+    Ok(())
+}
+
+#[test]
+fn hot_keys_are_ranked_first() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .track_hot_keys(16)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    let hot: Vec<u64> = vec![7, 300, 450];
+    for i in 0..500u64 {
+        t.insert(i.to_be_bytes(), vec![])?;
+        for k in &hot {
+            t.get(k.to_be_bytes())?;
+            t.get(k.to_be_bytes())?;
+        }
+    }
+
+    let hot_keys = t.hot_keys();
+    assert!(hot_keys.len() <= 16);
+
+    let mut top: Vec<IVec> =
+        hot_keys.iter().take(3).map(|(k, _)| k.clone()).collect();
+    top.sort();
+    let expected: Vec<IVec> =
+        hot.iter().map(|k| IVec::from(&k.to_be_bytes())).collect();
+    assert_eq!(top, expected);
+
+    // disabled by default
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config).unwrap();
+    t.insert(b"a", vec![])?;
+    assert!(t.hot_keys().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_extremes_are_exact() -> Result<()> {
+    use rand::Rng;
+
+    tests::setup_logger();
+
+    const N_THREADS: usize = 4;
+    const N_CANDIDATES: usize = 100;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = Arc::new(sled::Db::start(config).unwrap());
+
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|_| {
+            let t = t.clone();
+            thread::spawn(move || -> Result<Vec<u64>> {
+                let mut rng = rand::thread_rng();
+                let mut candidates = vec![];
+                for _ in 0..N_CANDIDATES {
+                    let candidate = rng.gen::<u64>();
+                    t.update_max(b"max", candidate)?;
+                    t.update_min(b"min", candidate)?;
+                    candidates.push(candidate);
+                }
+                Ok(candidates)
+            })
+        })
+        .collect();
+
+    let mut candidates = vec![];
+    for thread in threads {
+        candidates.extend(thread.join().unwrap()?);
+    }
+
+    let max = *candidates.iter().max().unwrap();
+    let min = *candidates.iter().min().unwrap();
+    assert_eq!(t.get(b"max")?, Some(IVec::from(&max.to_be_bytes())));
+    assert_eq!(t.get(b"min")?, Some(IVec::from(&min.to_be_bytes())));
+
+    // a candidate that doesn't beat the stored extreme is
+    // ignored, and the extreme is returned
+    assert_eq!(t.update_max(b"max", 0)?, max);
+    assert_eq!(t.update_min(b"min", u64::MAX)?, min);
+
+    Ok(())
+}
+
+#[test]
+fn tree_outlives_its_config_and_db() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(Some(1))
+        .build();
+    let path = config.get_path();
+
+    let db = sled::Db::start(config.clone())?;
+    let tree = db.open_tree("outlives")?;
+    drop(config);
+    drop(db);
+
+    // the tree's own reference keeps the files open
+    tree.insert(b"k", vec![1])?;
+    tree.flush()?;
+    assert_eq!(tree.get(b"k")?, Some(IVec::from(vec![1])));
+    assert!(path.exists());
+
+    // temporary files are removed once the last
+    // reference and the flusher are gone
+    drop(tree);
+    assert!(!path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn owned_range_outlives_its_tree() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u64 = 300;
+
+    fn open_and_scan() -> Result<OwnedIter> {
+        let config = ConfigBuilder::new().temporary(true).build();
+        let db = sled::Db::start(config)?;
+        for i in 0..N_KEYS {
+            db.insert(i.to_be_bytes(), &i.to_be_bytes())?;
+        }
+        let start = 100_u64.to_be_bytes();
+        Ok(db.owned_range(start..))
+    }
+
+    let iter = open_and_scan()?;
+    let keys = thread::spawn(move || {
+        iter.map(|r| r.map(|(k, _v)| k)).collect::<Result<Vec<_>>>()
+    })
+    .join()
+    .unwrap()?;
+
+    let expected: Vec<IVec> = (100..N_KEYS)
+        .map(|i| IVec::from(&i.to_be_bytes()))
+        .collect();
+    assert_eq!(keys, expected);
+
+    let rev: Vec<IVec> = open_and_scan()?
+        .rev()
+        .map(|r| r.map(|(k, _v)| k))
+        .collect::<Result<_>>()?;
+    let expected_rev: Vec<IVec> = expected.into_iter().rev().collect();
+    assert_eq!(rev, expected_rev);
+
+    Ok(())
+}
+
+#[test]
+fn scan_filtered_skips_unmatched_keys() -> Result<()> {
+    tests::setup_logger();
+
+    const N_KEYS: u64 = 200;
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    for i in 0..N_KEYS {
+        t.insert(i.to_be_bytes(), vec![i as u8; 4096])?;
+    }
+
+    let is_even = |k: &[u8]| k[7].is_multiple_of(2);
+    let checked = std::cell::Cell::new(0);
+    let filter = |k: &[u8]| {
+        checked.set(checked.get() + 1);
+        is_even(k)
+    };
+
+    let start = 10_u64.to_be_bytes();
+    let items = t.scan_filtered(start, filter).collect::<Result<Vec<_>>>()?;
+    let expected: Vec<(IVec, IVec)> = (10..N_KEYS)
+        .filter(|i| i % 2 == 0)
+        .map(|i| {
+            (
+                IVec::from(&i.to_be_bytes()),
+                IVec::from(vec![i as u8; 4096]),
+            )
+        })
+        .collect();
+    assert_eq!(items, expected);
+
+    // every key from the start was checked, including the
+    // odd ones whose values were never returned
+    assert_eq!(checked.get(), N_KEYS - 10);
+
+    let rev: Vec<IVec> = t
+        .scan_filtered(start, is_even)
+        .rev()
+        .map(|r| r.map(|(k, _v)| k))
+        .collect::<Result<_>>()?;
+    let expected_rev: Vec<IVec> =
+        expected.into_iter().rev().map(|(k, _v)| k).collect();
+    assert_eq!(rev, expected_rev);
+
+    Ok(())
+}
+
+#[test]
+fn empty_values_are_present() -> Result<()> {
+    tests::setup_logger();
+
+    let dir = "/tmp/test_tree_empty_values_are_present";
+    let empty = || IVec::from(vec![]);
+
+    for &separate_values in &[false, true] {
+        let _ = std::fs::remove_dir_all(dir);
+        let config = || {
+            ConfigBuilder::new()
+                .path(dir)
+                .separate_values(separate_values)
+                .build()
+        };
+
+        let t = sled::Db::start(config())?;
+
+        assert_eq!(t.insert(b"a", vec![])?, None);
+        assert_eq!(t.insert(b"b", vec![])?, None);
+        assert_eq!(t.insert(b"b", vec![])?, Some(empty()));
+        t.insert(b"c", vec![1])?;
+        assert_eq!(t.cas(b"c", Some(vec![1]), Some(vec![]))?, Ok(()));
+        assert_eq!(
+            t.cas::<_, _, Vec<u8>>(b"d", Some(vec![]), Some(vec![1]))?,
+            Err(None)
+        );
+
+        // a merge operator returning an empty value keeps the key
+        fn truncate(_: &[u8], _: Option<&[u8]>, _: &[u8]) -> Option<Vec<u8>> {
+            Some(vec![])
+        }
+        t.set_merge_operator(truncate);
+        assert_eq!(t.merge(b"e", vec![1])?, Some(empty()));
+
+        t.transaction(|tx| {
+            assert_eq!(tx.get(b"a")?, Some(empty()));
+            tx.insert(b"f", vec![])?;
+            assert_eq!(tx.get(b"f")?, Some(empty()));
+            assert_eq!(tx.get(b"g")?, None);
+            Ok(())
+        })?;
+
+        // an empty value is distinct from an absent key
+        // after updates are consolidated and recovered
+        for _ in 0..20 {
+            t.insert(b"churn", vec![1])?;
+        }
+        drop(t);
+        let t = sled::Db::start(config())?;
+
+        for key in &[b"a", b"b", b"c", b"e", b"f"] {
+            assert_eq!(t.get(key)?, Some(empty()));
+            assert!(t.contains_key(key)?);
+        }
+        assert_eq!(t.get(b"d")?, None);
+        assert_eq!(
+            t.iter().values().collect::<Result<Vec<_>>>()?,
+            vec![
+                empty(),
+                empty(),
+                empty(),
+                IVec::from(vec![1]),
+                empty(),
+                empty()
+            ]
+        );
+        assert_eq!(t.len(), 6);
+
+        assert_eq!(t.cas(b"a", Some(vec![]), Some(vec![2]))?, Ok(()));
+        assert_eq!(t.remove(b"b")?, Some(empty()));
+        assert_eq!(t.get(b"b")?, None);
+        assert_eq!(t.cas::<_, _, Vec<u8>>(b"c", Some(vec![]), None)?, Ok(()));
+        assert_eq!(t.get(b"c")?, None);
+
+        drop(t);
+        std::fs::remove_dir_all(dir)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn empty_key_is_the_smallest_key() -> Result<()> {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new().temporary(true).build();
+    let t = sled::Db::start(config)?;
+
+    assert_eq!(t.get(b"")?, None);
+    assert_eq!(t.insert(b"", vec![0])?, None);
+    assert_eq!(t.get(b"")?, Some(IVec::from(vec![0])));
+
+    // enough keys to split the leftmost leaf
+    // that the empty key lives in
+    for i in 0..500_u64 {
+        t.insert(i.to_be_bytes(), vec![1])?;
+    }
+    assert_eq!(t.get(b"")?, Some(IVec::from(vec![0])));
+
+    let first = t.iter().next().unwrap()?;
+    assert_eq!(first, (IVec::from(b""), IVec::from(vec![0])));
+    assert_eq!(t.range(&b""[..]..).count(), 501);
+    assert_eq!(t.scan_prefix(b"").count(), 501);
+    assert_eq!(t.iter().rev().last().unwrap()?, first);
+    assert_eq!(t.get_lt([0])?, Some(first.clone()));
+    assert_eq!(t.get_gt(b"")?.unwrap().0, IVec::from(&0_u64.to_be_bytes()));
+
+    assert_eq!(t.remove(b"")?, Some(IVec::from(vec![0])));
+    assert_eq!(t.get(b"")?, None);
+    assert_eq!(t.iter().count(), 500);
+
+    Ok(())
+}
+
+#[test]
+fn dump_log_reports_ops_in_order_and_damage() -> Result<()> {
+    use std::io::{Seek, SeekFrom, Write};
+
+    tests::setup_logger();
+
+    let path = "/tmp/test_tree_dump_log";
+    let _ = std::fs::remove_dir_all(path);
+
+    let config = ConfigBuilder::new().path(path).replication(true).build();
+    let t = sled::Db::start(config.clone())?;
+
+    t.insert(b"a", vec![1])?;
+    t.insert(b"a", vec![2])?;
+    t.remove(b"a")?;
+    t.flush()?;
+
+    let op_lsns: Vec<i64> = t.log_entries_since(0)?.map(|e| e.lsn).collect();
+    assert_eq!(op_lsns.len(), 3);
+
+    let entries = t.dump_log(op_lsns[0])?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(entries[0].lsn, op_lsns[0]);
+    assert!(entries.iter().all(|e| e.checksum_valid));
+    assert!(entries.windows(2).all(|w| w[0].lsn < w[1].lsn));
+
+    let ops: Vec<_> = entries
+        .iter()
+        .filter(|e| op_lsns.contains(&e.lsn))
+        .collect();
+    assert_eq!(
+        ops.iter().map(|e| e.lsn).collect::<Vec<_>>(),
+        op_lsns,
+        "every op should be dumped, in order"
+    );
+    for op in &ops {
+        assert_eq!(op.kind, MessageKind::InlineAppend);
+        assert_eq!(op.pid, ops[0].pid, "all ops went to the same leaf");
+    }
+    let last = *ops[2];
+
+    drop(t);
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(format!("{}/db", path))?;
+
+    // a flipped byte fails the checksum of the last op
+    file.seek(SeekFrom::Start(last.lid + MSG_HEADER_LEN as u64))?;
+    file.write_all(&[0xFF])?;
+    file.sync_all()?;
+
+    let entries = config.dump_log(op_lsns[0])?.collect::<Result<Vec<_>>>()?;
+    let damaged = entries.iter().find(|e| e.lsn == last.lsn).unwrap();
+    assert!(!damaged.checksum_valid);
+    assert_eq!(damaged.kind, MessageKind::InlineAppend);
+    assert!(entries
+        .iter()
+        .filter(|e| e.lsn < last.lsn)
+        .all(|e| e.checksum_valid));
+
+    // a file cut off partway through the last op
+    // is reported as an error after the intact ones
+    file.set_len(last.lid + MSG_HEADER_LEN as u64 + 1)?;
+
+    let results: Vec<_> = config.dump_log(op_lsns[0])?.collect();
+    let (last_result, intact) = results.split_last().unwrap();
+    assert!(last_result.is_err());
+    assert_eq!(
+        intact
+            .iter()
+            .map(|r| r.as_ref().unwrap().lsn)
+            .collect::<Vec<_>>(),
+        &op_lsns[..2]
+    );
+
+    drop(config);
+    std::fs::remove_dir_all(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn recycled_segments_bound_the_file_under_churn() -> Result<()> {
+    tests::setup_logger();
+
+    const N: u64 = 200;
+    const ROUNDS: usize = 10;
+
+    let dir = "/tmp/test_tree_recycle_segments";
+
+    let churn = |recycle_segments| -> Result<Vec<u64>> {
+        let _ = std::fs::remove_dir_all(dir);
+        let config = ConfigBuilder::new()
+            .path(dir)
+            .io_buf_size(1 << 16)
+            .recycle_segments(recycle_segments)
+            .build();
+        let t = sled::Db::start(config)?;
+
+        let mut sizes = vec![];
+        for _ in 0..ROUNDS {
+            for i in 0..N {
+                t.insert(i.to_be_bytes(), vec![0; 4096])?;
+            }
+            for i in 0..N {
+                t.remove(i.to_be_bytes())?;
+            }
+            t.flush()?;
+            sizes.push(std::fs::metadata(format!("{}/db", dir))?.len());
+        }
+
+        drop(t);
+        std::fs::remove_dir_all(dir)?;
+        Ok(sizes)
+    };
+
+    // without recycling, every round extends the file
+    let appended = churn(false)?;
+    assert!(appended.windows(2).all(|w| w[0] < w[1]), "{:?}", appended);
+    assert!(appended[ROUNDS - 1] > 4 * appended[0], "{:?}", appended);
+
+    // with it, the file levels off once the first rounds'
+    // segments are freed and written over
+    let recycled = churn(true)?;
+    let bound = 3 * recycled[1];
+    assert!(recycled.iter().all(|&size| size <= bound), "{:?}", recycled);
+
+    Ok(())
+}
+
+#[test]
+fn tree_subdir() {
+    let _ = std::fs::remove_dir_all("/tmp/test_tree_subdir");
+
+    let config = ConfigBuilder::new()
+        .async_io(false)
+        .path("/tmp/test_tree_subdir/test_subdir")
+        .build();
+
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert([1], vec![1]).unwrap();
+
+    drop(t);
+
+    let config = ConfigBuilder::new()
+        .path("/tmp/test_tree_subdir/test_subdir")
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    let res = t.get(&*vec![1]);
+
+    assert_eq!(res.unwrap().unwrap(), vec![1_u8]);
+
+    drop(t);
+
+    std::fs::remove_dir_all("/tmp/test_tree_subdir").unwrap();
+}
+
+#[test]
+fn named_merge_operator_persists() {
+    fn concatenate(
+        _key: &[u8],
+        old: Option<&[u8]>,
+        merged: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut ret = old.map(<[u8]>::to_vec).unwrap_or_default();
+        ret.extend_from_slice(merged);
+        Some(ret)
+    }
+
+    fn keep_last(
+        _key: &[u8],
+        _old: Option<&[u8]>,
+        merged: &[u8],
+    ) -> Option<Vec<u8>> {
+        Some(merged.to_vec())
+    }
+
+    sled::register_merge_operator("test_concatenate", concatenate);
+    sled::register_merge_operator("test_keep_last", keep_last);
+
+    let path = "/tmp/test_named_merge_operator";
+    let _ = std::fs::remove_dir_all(path);
+
+    let config = ConfigBuilder::new()
+        .async_io(false)
+        .path(path)
+        .merge_operator_named("test_concatenate")
+        .build();
+    let t = sled::Db::start(config).unwrap();
+    t.merge(b"k", vec![1]).unwrap();
+    t.open_tree(b"other").unwrap().merge(b"k", vec![3]).unwrap();
+    assert_eq!(
+        t.merge_operator_name(),
+        Some("test_concatenate".to_string())
+    );
+    drop(t);
+
+    let res = ConfigBuilder::new()
+        .path(path)
+        .merge_operator_named("test_keep_last")
+        .try_build();
+    match res {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Unsupported, got {:?}", other.map(|_| ())),
+    }
+
+    let config = ConfigBuilder::new()
+        .path(path)
+        .merge_operator_named("test_concatenate")
+        .build();
+    let t = sled::Db::start(config).unwrap();
+    t.merge(b"k", vec![2]).unwrap();
+    assert_eq!(t.get(b"k").unwrap(), Some(IVec::from(vec![1, 2])));
+    let other = t.open_tree(b"other").unwrap();
+    other.merge(b"k", vec![4]).unwrap();
+    assert_eq!(other.get(b"k").unwrap(), Some(IVec::from(vec![3, 4])));
+    assert_eq!(
+        other.merge_operator_name(),
+        Some("test_concatenate".to_string())
+    );
+    drop(other);
+    drop(t);
+
+    std::fs::remove_dir_all(path).unwrap();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .merge_operator_named("test_unregistered")
+        .build();
+    match sled::Db::start(config) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Unsupported, got {:?}", other.map(|_| ())),
+    }
+}
+
+// Not secure: xors with the key and nonce, and appends a
+// hash of the key, nonce, and plaintext as its tag.
+struct TestCipher(u8);
+
+impl TestCipher {
+    fn tag(&self, nonce: &[u8; NONCE_LEN], plaintext: &[u8]) -> [u8; 8] {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (self.0, nonce, plaintext).hash(&mut hasher);
+        hasher.finish().to_le_bytes()
+    }
+
+    fn xor(&self, nonce: &[u8; NONCE_LEN], buf: &[u8]) -> Vec<u8> {
+        buf.iter()
+            .enumerate()
+            .map(|(i, b)| b ^ self.0 ^ nonce[i % NONCE_LEN])
+            .collect()
+    }
+}
+
+impl Cipher for TestCipher {
+    fn encrypt(&self, nonce: &[u8; NONCE_LEN], plaintext: &[u8]) -> Vec<u8> {
+        let mut ciphertext = self.xor(nonce, plaintext);
+        ciphertext.extend_from_slice(&self.tag(nonce, plaintext));
+        ciphertext
+    }
+
+    fn decrypt(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        ciphertext: &[u8],
+    ) -> Option<Vec<u8>> {
+        if ciphertext.len() < 8 {
+            return None;
+        }
+        let (body, tag) = ciphertext.split_at(ciphertext.len() - 8);
+        let plaintext = self.xor(nonce, body);
+        if self.tag(nonce, &plaintext) == tag {
+            Some(plaintext)
+        } else {
+            None
+        }
+    }
+}
+
+fn contains_marker(path: &std::path::Path, marker: &[u8]) -> bool {
+    if path.is_dir() {
+        std::fs::read_dir(path)
+            .unwrap()
+            .any(|entry| contains_marker(&entry.unwrap().path(), marker))
+    } else {
+        let bytes = std::fs::read(path).unwrap();
+        bytes.windows(marker.len()).any(|w| w == marker)
+    }
+}
+
+#[test]
+fn encryption_round_trips_without_plaintext_on_disk() -> Result<()> {
+    tests::setup_logger();
+
+    let path = "/tmp/test_encryption_round_trip";
+    let _ = std::fs::remove_dir_all(path);
+
+    let marker = b"plaintext-marker";
+    let value = |i: u8| {
+        let mut value = marker.to_vec();
+        value.push(i);
+        value
+    };
+    let config = || {
+        ConfigBuilder::new()
+            .path(path)
+            .io_buf_size(1 << 16)
+            .encryption(Some(EncryptionConfig::new("k1", TestCipher(7))))
+    };
+
+    let t = sled::Db::start(config().build())?;
+    for i in 0..100_u8 {
+        t.insert(value(i), value(i))?;
+    }
+    // large enough to be written to a blob file
+    let big = marker.repeat(4096);
+    t.insert(b"big", big.clone())?;
+    t.flush()?;
+    drop(t);
+
+    assert!(!contains_marker(std::path::Path::new(path), marker));
+
+    let t = sled::Db::start(config().build())?;
+    assert_eq!(t.len(), 101);
+    for i in 0..100_u8 {
+        assert_eq!(t.get(value(i))?, Some(IVec::from(value(i))));
+    }
+    assert_eq!(t.get(b"big")?, Some(IVec::from(big)));
+    drop(t);
+
+    std::fs::remove_dir_all(path).unwrap();
+    Ok(())
+}
+
+#[test]
+fn encryption_rejects_a_different_key() -> Result<()> {
+    tests::setup_logger();
+
+    let path = "/tmp/test_encryption_different_key";
+    let _ = std::fs::remove_dir_all(path);
+
+    let config = ConfigBuilder::new()
+        .path(path)
+        .encryption(Some(EncryptionConfig::new("k1", TestCipher(7))))
+        .build();
+    let t = sled::Db::start(config)?;
+    t.insert(b"k", b"v".to_vec())?;
+    t.flush()?;
+    drop(t);
+
+    for encryption in [
+        // same key id, different key
+        Some(EncryptionConfig::new("k1", TestCipher(8))),
+        Some(EncryptionConfig::new("k2", TestCipher(7))),
+        None,
+    ] {
+        let res = ConfigBuilder::new()
+            .path(path)
+            .encryption(encryption.clone())
+            .try_build();
+        match res {
+            Err(Error::Unsupported(_)) => {}
+            other => panic!(
+                "expected Unsupported for {:?}, got {:?}",
+                encryption,
+                other.map(|_| ())
+            ),
+        }
+    }
+
+    let config = ConfigBuilder::new()
+        .path(path)
+        .encryption(Some(EncryptionConfig::new("k1", TestCipher(7))))
+        .build();
+    let t = sled::Db::start(config)?;
+    assert_eq!(t.get(b"k")?, Some(IVec::from(b"v")));
+    drop(t);
+
+    std::fs::remove_dir_all(path).unwrap();
+    Ok(())
+}
+
+#[test]
+fn tree_iterator() {
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+    for i in 0..N_PER_THREAD {
+        let k = kv(i);
+        t.insert(&k, k.clone()).unwrap();
+    }
+
+    for (i, (k, v)) in t.iter().map(|res| res.unwrap()).enumerate() {
+        let should_be = kv(i);
+        assert_eq!(should_be, &*k);
+        assert_eq!(should_be, &*v);
+    }
+
+    for (i, (k, v)) in t.iter().map(|res| res.unwrap()).enumerate() {
+        let should_be = kv(i);
+        assert_eq!(should_be, &*k);
+        assert_eq!(should_be, &*v);
+    }
+
+    let half_way = N_PER_THREAD / 2;
+    let half_key = kv(half_way);
+    let mut tree_scan = t.range(&*half_key..);
+    let r1 = tree_scan.next().unwrap().unwrap();
+    assert_eq!((r1.0.as_ref(), &*r1.1), (half_key.as_ref(), &*half_key));
+
+    let first_key = kv(0);
+    let mut tree_scan = t.range(&*first_key..);
+    let r2 = tree_scan.next().unwrap().unwrap();
+    assert_eq!((r2.0.as_ref(), &*r2.1), (first_key.as_ref(), &*first_key));
+
+    let last_key = kv(N_PER_THREAD - 1);
+    let mut tree_scan = t.range(&*last_key..);
+    let r3 = tree_scan.next().unwrap().unwrap();
+    assert_eq!((r3.0.as_ref(), &*r3.1), (last_key.as_ref(), &*last_key));
+    assert_eq!(tree_scan.next(), None);
+}
+
+#[test]
+fn tree_subscriptions_and_keyspaces() -> Result<()> {
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+
+    let db = sled::Db::start(config.clone()).unwrap();
+
+    let t1 = db.open_tree(b"1")?;
+    let mut s1 = t1.watch_prefix(b"".to_vec());
+
+    let t2 = db.open_tree(b"2")?;
+    let mut s2 = t2.watch_prefix(b"".to_vec());
+
+    t1.insert(b"t1_a", b"t1_a".to_vec())?;
+    t2.insert(b"t2_a", b"t2_a".to_vec())?;
+
+    assert_eq!(s1.next().unwrap().key(), b"t1_a");
+    assert_eq!(s2.next().unwrap().key(), b"t2_a");
+
+    let guard = pagecache::pin();
+    guard.flush();
+    drop(guard);
+
+    drop(db);
+    drop(t1);
+    drop(t2);
+
+    let db = sled::Db::start(config.clone()).unwrap();
+
+    let t1 = db.open_tree(b"1")?;
+    let mut s1 = t1.watch_prefix(b"".to_vec());
+
+    let t2 = db.open_tree(b"2")?;
+    let mut s2 = t2.watch_prefix(b"".to_vec());
+
+    assert!(db.is_empty());
+    assert_eq!(t1.len(), 1);
+    assert_eq!(t2.len(), 1);
+
+    t1.insert(b"t1_b", b"t1_b".to_vec())?;
+    t2.insert(b"t2_b", b"t2_b".to_vec())?;
+
+    assert_eq!(s1.next().unwrap().key(), b"t1_b");
+    assert_eq!(s2.next().unwrap().key(), b"t2_b");
+
+    let guard = pagecache::pin();
+    guard.flush();
+    drop(guard);
+
+    drop(db);
+    drop(t1);
+    drop(t2);
+
+    let db = sled::Db::start(config.clone()).unwrap();
+
+    let t1 = db.open_tree(b"1")?;
+    let t2 = db.open_tree(b"2")?;
+
+    assert!(db.is_empty());
+    assert_eq!(t1.len(), 2);
+    assert_eq!(t2.len(), 2);
+
+    db.drop_tree(b"1")?;
+    db.drop_tree(b"2")?;
+
+    assert_eq!(t1.get(b""), Err(Error::CollectionNotFound(b"1".to_vec())));
+
+    assert_eq!(t2.get(b""), Err(Error::CollectionNotFound(b"2".to_vec())));
+
+    let guard = pagecache::pin();
+    guard.flush();
+    drop(guard);
+
+    drop(db);
+    drop(t1);
+    drop(t2);
+
+    let db = sled::Db::start(config.clone()).unwrap();
+
+    let t1 = db.open_tree(b"1")?;
+    let t2 = db.open_tree(b"2")?;
+
+    assert!(db.is_empty());
+    assert_eq!(t1.len(), 0);
+    assert_eq!(t2.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn tree_range() {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .flush_every_ms(None)
+        .build();
+    let t = sled::Db::start(config).unwrap();
+
+    t.insert(b"0", vec![0]).unwrap();
+    t.insert(b"1", vec![10]).unwrap();
+    t.insert(b"2", vec![20]).unwrap();
+    t.insert(b"3", vec![30]).unwrap();
+    t.insert(b"4", vec![40]).unwrap();
+    t.insert(b"5", vec![50]).unwrap();
+
+    let start: &[u8] = b"2";
+    let end: &[u8] = b"4";
+    let mut r = t.range(start..end);
+    assert_eq!(r.next().unwrap().unwrap().0, b"2");
+    assert_eq!(r.next().unwrap().unwrap().0, b"3");
+    assert_eq!(r.next(), None);
+
+    let start = b"2".to_vec();
+    let end = b"4".to_vec();
+    let mut r = t.range(start..end).rev();
+    assert_eq!(r.next().unwrap().unwrap().0, b"3");
+    assert_eq!(r.next().unwrap().unwrap().0, b"2");
+    assert_eq!(r.next(), None);
+
+    let start = b"2".to_vec();
+    let mut r = t.range(start..);
+    assert_eq!(r.next().unwrap().unwrap().0, b"2");
+    assert_eq!(r.next().unwrap().unwrap().0, b"3");
+    assert_eq!(r.next().unwrap().unwrap().0, b"4");
+    assert_eq!(r.next().unwrap().unwrap().0, b"5");
+    assert_eq!(r.next(), None);
+
+    let start = b"2".to_vec();
+    let mut r = t.range(..=start).rev();
+    assert_eq!(
+        r.next().unwrap().unwrap().0,
+        b"2",
+        "failed to find 2 in tree {:?}",
+        t
+    );
+    assert_eq!(r.next().unwrap().unwrap().0, b"1");
+    assert_eq!(r.next().unwrap().unwrap().0, b"0");
+    assert_eq!(r.next(), None);
+}
+
+#[test]
+fn recover_tree() {
+    tests::setup_logger();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .io_buf_size(5120)
+        .flush_every_ms(None)
+        .async_io(false)
+        .snapshot_after_ops(N_PER_THREAD as u64)
         .build();
 
     let t = sled::Db::start(config.clone()).unwrap();
@@ -575,7 +5166,7 @@ fn recover_tree() {
 
     let t = sled::Db::start(config.clone()).unwrap();
     for i in 0..N_PER_THREAD {
-        let k = kv(i as usize);
+        let k = kv(i);
         assert_eq!(t.get(&*k).unwrap().unwrap(), k);
         t.remove(&*k).unwrap();
     }
@@ -583,7 +5174,7 @@ fn recover_tree() {
 
     let t = sled::Db::start(config.clone()).unwrap();
     for i in 0..N_PER_THREAD {
-        let k = kv(i as usize);
+        let k = kv(i);
         assert_eq!(t.get(&*k), Ok(None));
     }
 }
@@ -622,7 +5213,7 @@ fn tree_import_export() -> Result<()> {
         let tree = db.open_tree(tree_id.as_bytes())?;
 
         for i in 0..N_THREADS {
-            let k = kv(i as usize);
+            let k = kv(i);
             assert_eq!(tree.get(&*k).unwrap().unwrap(), k);
             tree.remove(&*k).unwrap();
         }
@@ -635,7 +5226,7 @@ fn tree_import_export() -> Result<()> {
         let tree = db.open_tree(tree_id.as_bytes())?;
 
         for i in 0..N_THREADS {
-            let k = kv(i as usize);
+            let k = kv(i);
             assert_eq!(tree.get(&*k), Ok(None));
         }
     }
@@ -80,12 +80,13 @@ fn tear_down_failpoints() {
     }
 }
 
-fn prop_tree_crashes_nicely(ops: Vec<Op>, flusher: bool) -> bool {
-    lazy_static! {
-        // forces quickcheck to run one thread at a time
-        static ref M: Mutex<()> = Mutex::new(());
-    }
+lazy_static! {
+    // failpoints are global, so this forces tests that
+    // configure them to run one thread at a time
+    static ref M: Mutex<()> = Mutex::new(());
+}
 
+fn prop_tree_crashes_nicely(ops: Vec<Op>, flusher: bool) -> bool {
     let _lock = M.lock().expect("our test lock should not be poisoned");
 
     // clear all failpoints that may be left over from the last run
@@ -117,7 +118,7 @@ fn prop_tree_crashes_nicely(ops: Vec<Op>, flusher: bool) -> bool {
 fn run_tree_crashes_nicely(ops: Vec<Op>, flusher: bool) -> bool {
     tests::setup_logger();
 
-    let io_buf_size = 300;
+    let io_buf_size = 512;
 
     let config = ConfigBuilder::new()
         .temporary(true)
@@ -298,6 +299,146 @@ fn quickcheck_tree_with_failpoints() {
         .quickcheck(prop_tree_crashes_nicely as fn(Vec<Op>, bool) -> bool);
 }
 
+#[test]
+fn failpoints_rename_is_atomic() {
+    let _lock = M.lock().expect("our test lock should not be poisoned");
+    tests::setup_logger();
+
+    let old_value = vec![1; 100];
+    let new_value = vec![2; 100];
+
+    for fp in &["buffer write", "buffer write post", "blob blob write"] {
+        tear_down_failpoints();
+
+        let config = ConfigBuilder::new()
+            .temporary(true)
+            .async_io(false)
+            .flush_every_ms(None)
+            .io_buf_size(512)
+            .cache_capacity(256)
+            .build();
+
+        let tree = sled::Db::start(config.clone()).expect("tree should start");
+        tree.insert(b"to", old_value.clone()).unwrap();
+        tree.insert(b"from", new_value.clone()).unwrap();
+        tree.flush().unwrap();
+
+        fail::cfg(*fp, "return")
+            .expect("should be able to configure failpoint");
+        let res = tree.rename(b"from", b"to", true).and_then(|_| tree.flush());
+        match res {
+            Ok(_) | Err(Error::FailPoint) => {}
+            Err(other) => panic!("rename failed with {:?} under {}", other, fp),
+        }
+        tear_down_failpoints();
+        drop(tree);
+
+        let tree = sled::Db::start(config).expect("tree should restart");
+        let from = tree.get(b"from").unwrap();
+        let to = tree.get(b"to").unwrap();
+        match (from, to) {
+            (Some(ref from), Some(ref to))
+                if *from == new_value && *to == old_value => {}
+            (None, Some(ref to)) if *to == new_value => {}
+            other => panic!(
+                "rename was partially recovered after {}: {:?}",
+                fp, other
+            ),
+        }
+    }
+}
+
+#[test]
+fn failpoints_bulk_delete_is_atomic() {
+    let _lock = M.lock().expect("our test lock should not be poisoned");
+    tests::setup_logger();
+
+    const N: u8 = 60;
+
+    for fp in &["buffer write", "buffer write post"] {
+        // let the deletion span several buffers before crashing
+        for successes in &[0, 1, 3, 6] {
+            tear_down_failpoints();
+
+            let config = ConfigBuilder::new()
+                .temporary(true)
+                .async_io(false)
+                .flush_every_ms(None)
+                .io_buf_size(512)
+                .cache_capacity(256)
+                .build();
+
+            let tree =
+                sled::Db::start(config.clone()).expect("tree should start");
+            for i in 0..N {
+                tree.insert(vec![i], vec![i; 100]).unwrap();
+            }
+            tree.flush().unwrap();
+
+            fail::cfg(*fp, &format!("{}*off->return", successes))
+                .expect("should be able to configure failpoint");
+            let evens: Vec<Vec<u8>> =
+                (0..N).filter(|i| i % 2 == 0).map(|i| vec![i]).collect();
+            let res = tree.bulk_delete(evens).and_then(|_| tree.flush());
+            match res {
+                Ok(_) | Err(Error::FailPoint) => {}
+                Err(other) => {
+                    panic!("bulk_delete failed with {:?} under {}", other, fp)
+                }
+            }
+            tear_down_failpoints();
+            drop(tree);
+
+            let tree = sled::Db::start(config).expect("tree should restart");
+            let evens_left = (0..N)
+                .filter(|i| i % 2 == 0)
+                .filter(|i| tree.contains_key(vec![*i]).unwrap())
+                .count();
+            assert!(
+                evens_left == 0 || evens_left == N as usize / 2,
+                "bulk_delete was partially recovered after {} with {} \
+                 successful writes: {} of {} keys left",
+                fp,
+                successes,
+                evens_left,
+                N / 2
+            );
+            for i in (0..N).filter(|i| i % 2 == 1) {
+                assert_eq!(
+                    tree.get(vec![i]).unwrap(),
+                    Some(vec![i; 100].into())
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn failpoints_health_check_reports_broken_flush() {
+    let _lock = M.lock().expect("our test lock should not be poisoned");
+    tests::setup_logger();
+    tear_down_failpoints();
+
+    let config = ConfigBuilder::new()
+        .temporary(true)
+        .async_io(false)
+        .flush_every_ms(None)
+        .build();
+
+    let tree = sled::Db::start(config).expect("tree should start");
+    tree.health_check().expect("health check should pass");
+
+    fail::cfg("buffer write", "return")
+        .expect("should be able to configure failpoint");
+    match tree.health_check() {
+        Err(Error::FailPoint) => {}
+        other => {
+            panic!("health check returned {:?} with a broken flush", other)
+        }
+    }
+    tear_down_failpoints();
+}
+
 #[test]
 fn failpoints_bug_01() {
     // postmortem 1: model did not account for proper reasons to fail to start